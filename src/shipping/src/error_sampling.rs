@@ -0,0 +1,253 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Keeps the traces of failed requests that head sampling would drop,
+//! enabled by `SAMPLE_ERRORS_ALWAYS`. Dropped spans are recorded anyway and
+//! held until their trace's local root ends. A trace with a span ending in
+//! an error status, which `RequestTracing` sets on 5xx responses, is
+//! exported whole, as if it had been sampled; the others are discarded.
+//!
+//! Recording costs what sampling would: every span collects its attributes
+//! and events whatever the sampling ratio, and a trace's spans stay in memory
+//! until it ends. Only what gets exported follows the ratio.
+
+use std::{
+    collections::HashMap,
+    mem,
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
+
+use opentelemetry::{
+    trace::{
+        Link, SamplingDecision, SamplingResult, Span as _, SpanContext, SpanId, SpanKind, Status,
+        TraceContextExt, TraceId,
+    },
+    Context, KeyValue,
+};
+use opentelemetry_sdk::{
+    error::OTelSdkResult,
+    trace::{ShouldSample, Span, SpanData, SpanProcessor},
+    Resource,
+};
+
+/// Sampler recording, though not sampling, every span its `inner` sampler
+/// would drop, so that `KeepErrors` can still export it.
+#[derive(Debug, Clone)]
+pub struct RecordDropped {
+    inner: Box<dyn ShouldSample>,
+}
+
+impl RecordDropped {
+    pub fn new(inner: Box<dyn ShouldSample>) -> Self {
+        RecordDropped { inner }
+    }
+}
+
+impl ShouldSample for RecordDropped {
+    fn should_sample(
+        &self,
+        parent_context: Option<&Context>,
+        trace_id: TraceId,
+        name: &str,
+        span_kind: &SpanKind,
+        attributes: &[KeyValue],
+        links: &[Link],
+    ) -> SamplingResult {
+        let mut result =
+            self.inner
+                .should_sample(parent_context, trace_id, name, span_kind, attributes, links);
+        if result.decision == SamplingDecision::Drop {
+            result.decision = SamplingDecision::RecordOnly;
+        }
+        result
+    }
+}
+
+/// Unsampled traces held at once. Traces started beyond it only keep their
+/// errored spans.
+const MAX_HELD_TRACES: usize = 1024;
+
+/// Spans held of each unsampled trace, bounding the memory of long traces.
+const MAX_HELD_SPANS: usize = 256;
+
+/// Span processor passing `inner` the sampled spans, and the whole of the
+/// unsampled traces in which a span ended with an error status, marked as
+/// sampled.
+#[derive(Debug)]
+pub struct KeepErrors<P> {
+    inner: P,
+    held: Mutex<HashMap<TraceId, HeldTrace>>,
+}
+
+/// The ended spans of an unsampled trace, until its local root ends.
+#[derive(Debug)]
+struct HeldTrace {
+    root: SpanId,
+    spans: Vec<SpanData>,
+    errored: bool,
+}
+
+impl<P> KeepErrors<P> {
+    pub fn new(inner: P) -> Self {
+        KeepErrors {
+            inner,
+            held: Mutex::default(),
+        }
+    }
+
+    /// The unsampled spans to export now that `span` ended.
+    fn release(&self, span: SpanData) -> Vec<SpanData> {
+        let errored = matches!(span.status, Status::Error { .. });
+        let trace_id = span.span_context.trace_id();
+        let mut held = self.held.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(trace) = held.get_mut(&trace_id) else {
+            return if errored { vec![span] } else { Vec::new() };
+        };
+        let is_root = span.span_context.span_id() == trace.root;
+        let released = if errored || trace.errored {
+            trace.errored = true;
+            let mut released = mem::take(&mut trace.spans);
+            released.push(span);
+            released
+        } else {
+            if trace.spans.len() < MAX_HELD_SPANS {
+                trace.spans.push(span);
+            }
+            Vec::new()
+        };
+        if is_root {
+            held.remove(&trace_id);
+        }
+        released
+    }
+}
+
+impl<P: SpanProcessor> SpanProcessor for KeepErrors<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        let span_context = span.span_context();
+        let parent = cx.span();
+        let local_root = !parent.span_context().is_valid() || parent.span_context().is_remote();
+        if !span_context.is_sampled() && local_root {
+            let mut held = self.held.lock().unwrap_or_else(PoisonError::into_inner);
+            if held.len() < MAX_HELD_TRACES {
+                held.insert(
+                    span_context.trace_id(),
+                    HeldTrace {
+                        root: span_context.span_id(),
+                        spans: Vec::new(),
+                        errored: false,
+                    },
+                );
+            }
+        }
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, span: SpanData) {
+        if span.span_context.is_sampled() {
+            self.inner.on_end(span);
+            return;
+        }
+        for mut span in self.release(span) {
+            let cx = &span.span_context;
+            span.span_context = SpanContext::new(
+                cx.trace_id(),
+                cx.span_id(),
+                cx.trace_flags().with_sampled(true),
+                cx.is_remote(),
+                cx.trace_state().clone(),
+            );
+            self.inner.on_end(span);
+        }
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.held
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::trace::{TraceFlags, Tracer, TracerProvider};
+    use opentelemetry_sdk::trace::{
+        InMemorySpanExporter, Sampler, SdkTracer, SdkTracerProvider, SimpleSpanProcessor,
+    };
+
+    use super::*;
+
+    fn never_sampled() -> (SdkTracer, InMemorySpanExporter) {
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_sampler(RecordDropped::new(Box::new(Sampler::AlwaysOff)))
+            .with_span_processor(KeepErrors::new(SimpleSpanProcessor::new(exporter.clone())))
+            .build();
+        (provider.tracer("shipping-test"), exporter)
+    }
+
+    fn exported(exporter: &InMemorySpanExporter) -> Vec<String> {
+        let spans = exporter.get_finished_spans().unwrap();
+        assert!(spans
+            .iter()
+            .all(|span| span.span_context.trace_flags() == TraceFlags::SAMPLED));
+        spans.iter().map(|span| span.name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_errored_span_is_kept_without_head_sampling() {
+        let (tracer, exporter) = never_sampled();
+
+        let mut failed = tracer.start("failed-request");
+        failed.set_status(Status::error("Internal Server Error"));
+        failed.end();
+        tracer.start("successful-request").end();
+
+        assert_eq!(exported(&exporter), ["failed-request"]);
+    }
+
+    #[test]
+    fn test_failed_requests_are_exported_whole() {
+        let (tracer, exporter) = never_sampled();
+
+        // The children end before the server span fails.
+        let failed = Context::current_with_span(tracer.start("failed-request"));
+        tracer.start_with_context("quote", &failed).end();
+        tracer.start_with_context("currency", &failed).end();
+        failed
+            .span()
+            .set_status(Status::error("Internal Server Error"));
+        failed.span().end();
+
+        let succeeded = Context::current_with_span(tracer.start("successful-request"));
+        tracer.start_with_context("quote", &succeeded).end();
+        succeeded.span().end();
+
+        assert_eq!(exported(&exporter), ["quote", "currency", "failed-request"]);
+    }
+
+    #[test]
+    fn test_spans_ending_after_an_error_are_exported() {
+        let (tracer, exporter) = never_sampled();
+
+        let request = Context::current_with_span(tracer.start("request"));
+        let mut quote = tracer.start_with_context("quote", &request);
+        quote.set_status(Status::error("Quote service unavailable"));
+        quote.end();
+        tracer.start_with_context("fallback", &request).end();
+        request.span().end();
+
+        assert_eq!(exported(&exporter), ["quote", "fallback", "request"]);
+    }
+}
@@ -1,26 +1,48 @@
 // Copyright The OpenTelemetry Authors
 // SPDX-License-Identifier: Apache-2.0
 
-use actix_web::{App, HttpServer};
+use actix_web::{middleware::from_fn, App, HttpServer};
+use futures_util::future::{select, Either};
 use opentelemetry_instrumentation_actix_web::{RequestMetrics, RequestTracing};
-use std::env;
-use tracing::info;
+use std::{env, future::Future, io, net::SocketAddr, pin::pin, time::Duration};
+use tokio::sync::oneshot;
+use tonic::transport::server::TcpIncoming;
+use tracing::{error, info, warn};
 
+mod error_sampling;
+mod prometheus;
+mod telemetry;
 mod telemetry_conf;
-use telemetry_conf::init_otel;
+use telemetry_conf::{init_otel, Telemetry};
 mod shipping_service;
-use shipping_service::{get_quote, ship_order};
+use shipping_service::{
+    admin_reset, catch_panics, compare_carriers, count_in_flight, get_order, get_quote,
+    get_quote_query, get_quotes, get_receipt, live, prometheus_metrics, ready, security_headers,
+    serve_grpc, ship_order, stop_signal, trace_headers, update_package_status, AppData,
+    ServeProtocol, ShippingConfig,
+};
+
+#[cfg(test)]
+mod test_support;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    match init_otel() {
-        Ok(_) => {
-            info!("Successfully configured OTel");
-        }
-        Err(err) => {
-            panic!("Couldn't start OTel: {0}", err);
+    let telemetry = init_otel(None);
+    if telemetry.status.is_degraded() {
+        // The log exporter may be among those that failed, so the warning
+        // goes to stderr as well.
+        for (signal, err) in &telemetry.status.failed_signals {
+            eprintln!("WARNING: running without OTel {signal}: {err}");
+            warn!(
+                name = "TelemetryDegraded",
+                signal = *signal,
+                error = err.as_str(),
+                message = "Running without telemetry signal"
+            );
         }
-    };
+    } else {
+        info!("Successfully configured OTel");
+    }
 
     let port: u16 = env::var("SHIPPING_PORT")
         .expect("$SHIPPING_PORT is not set")
@@ -33,14 +55,138 @@ async fn main() -> std::io::Result<()> {
         message = "Shipping service is running"
     );
 
-    HttpServer::new(|| {
+    let config = match ShippingConfig::from_env() {
+        Ok(config) => config,
+        Err(err) => {
+            panic!("Invalid configuration: {err:#}");
+        }
+    };
+    let data = match AppData::try_new(config) {
+        Ok(data) => data
+            .with_telemetry(telemetry.status.clone())
+            .with_prometheus(telemetry.prometheus.clone()),
+        Err(err) => {
+            panic!("Couldn't initialize state: {err:#}");
+        }
+    };
+    data.spawn_pricing_watcher();
+    data.spawn_order_reconciler();
+
+    let grace = data.config.shutdown_grace;
+    let in_flight = data.in_flight.clone();
+    // `from_env` made sure `Both` comes with a gRPC port.
+    let grpc_port = match data.config.protocol {
+        ServeProtocol::Http => None,
+        ServeProtocol::Grpc => Some(port),
+        ServeProtocol::Both => data.config.grpc_port,
+    };
+    // The gRPC server stops when the HTTP server starts draining, or when
+    // it goes away without doing so.
+    let (stop_grpc, grpc_stopped) = oneshot::channel::<()>();
+    let mut grpc = None;
+    if let Some(grpc_port) = grpc_port {
+        let incoming = TcpIncoming::bind(SocketAddr::from(([0, 0, 0, 0], grpc_port)))?;
+        info!(
+            name = "GrpcServerStarted",
+            port = grpc_port,
+            message = "Shipping gRPC service is running"
+        );
+        if !data.config.protocol.serves_http() {
+            let shutdown = stop_signal(in_flight, grace);
+            let served = serve_grpc_draining(data.clone(), incoming, shutdown, grace).await;
+            flush_telemetry(&telemetry);
+            return served;
+        }
+        let shutdown = async {
+            let _ = grpc_stopped.await;
+        };
+        let served = serve_grpc_draining(data.clone(), incoming, shutdown, grace);
+        grpc = Some(actix_web::rt::spawn(async move {
+            if let Err(err) = served.await {
+                error!(
+                    name = "GrpcServerFailed",
+                    error = %err,
+                    message = "Shipping gRPC service stopped"
+                );
+            }
+        }));
+    }
+
+    let served = HttpServer::new(move || {
         App::new()
+            .configure(|cfg| data.register(cfg))
+            .wrap(from_fn(catch_panics))
+            .wrap(from_fn(security_headers))
+            .wrap(from_fn(trace_headers))
             .wrap(RequestTracing::new())
             .wrap(RequestMetrics::default())
+            .wrap(from_fn(count_in_flight))
             .service(get_quote)
+            .service(get_quote_query)
+            .service(get_quotes)
+            .service(compare_carriers)
             .service(ship_order)
+            .service(get_receipt)
+            .service(get_order)
+            .service(update_package_status)
+            .service(live)
+            .service(ready)
+            .service(prometheus_metrics)
+            .service(admin_reset)
+    })
+    // Whole seconds, rounded up so a sub-second grace isn't cut to none.
+    .shutdown_timeout(grace.as_secs_f64().ceil() as u64)
+    .shutdown_signal(async move {
+        stop_signal(in_flight, grace).await;
+        let _ = stop_grpc.send(());
     })
     .bind(&addr)?
     .run()
-    .await
+    .await;
+    if let Some(grpc) = grpc {
+        let _ = grpc.await;
+    }
+    flush_telemetry(&telemetry);
+    served
+}
+
+/// Serves gRPC on `incoming` until `shutdown` resolves, then gives the calls
+/// in flight up to `grace` to finish, like the HTTP server's drain.
+async fn serve_grpc_draining(
+    data: AppData,
+    incoming: TcpIncoming,
+    shutdown: impl Future<Output = ()>,
+    grace: Duration,
+) -> io::Result<()> {
+    let (draining, drain_started) = oneshot::channel::<()>();
+    let server = serve_grpc(data, incoming, async move {
+        shutdown.await;
+        let _ = draining.send(());
+    });
+    let drained = async move {
+        if drain_started.await.is_ok() {
+            actix_web::rt::time::sleep(grace).await;
+        } else {
+            std::future::pending::<()>().await;
+        }
+    };
+    match select(pin!(server), pin!(drained)).await {
+        Either::Left((served, _)) => served.map_err(io::Error::other),
+        Either::Right(_) => {
+            warn!(
+                name = "GrpcDrainTimedOut",
+                grace_secs = grace.as_secs(),
+                message = "Stopped the gRPC server with calls still in flight"
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Exports the spans, metrics and logs still buffered before the process
+/// exits, reporting failures on stderr since logs may be gone by then.
+fn flush_telemetry(telemetry: &Telemetry) {
+    for (signal, err) in telemetry.shutdown() {
+        eprintln!("WARNING: failed to flush OTel {signal}: {err}");
+    }
 }
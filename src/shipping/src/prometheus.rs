@@ -0,0 +1,276 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Write},
+    sync::{Arc, Weak},
+    time::Duration,
+};
+
+use opentelemetry::KeyValue;
+use opentelemetry_sdk::{
+    error::{OTelSdkError, OTelSdkResult},
+    metrics::{
+        data::{AggregatedMetrics, Metric, MetricData, ResourceMetrics},
+        reader::MetricReader,
+        InstrumentKind, ManualReader, Pipeline, Temporality,
+    },
+};
+
+/// Content type of the Prometheus text exposition format.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Reader of the meter provider that collects its metrics on demand, for
+/// `/metrics` to render them in the Prometheus text format. Clones share
+/// the reader registered on the provider.
+#[derive(Debug, Clone, Default)]
+pub struct PrometheusReader(Arc<ManualReader>);
+
+impl PrometheusReader {
+    /// The cumulative value of every metric recorded so far.
+    pub fn render(&self) -> Result<String, OTelSdkError> {
+        let mut metrics = ResourceMetrics::default();
+        self.0.collect(&mut metrics)?;
+        Ok(render(&metrics))
+    }
+}
+
+impl MetricReader for PrometheusReader {
+    fn register_pipeline(&self, pipeline: Weak<Pipeline>) {
+        self.0.register_pipeline(pipeline)
+    }
+
+    fn collect(&self, rm: &mut ResourceMetrics) -> OTelSdkResult {
+        self.0.collect(rm)
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.0.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.0.shutdown_with_timeout(timeout)
+    }
+
+    fn temporality(&self, kind: InstrumentKind) -> Temporality {
+        self.0.temporality(kind)
+    }
+}
+
+/// The samples of one metric name, from every scope that recorded it.
+#[derive(Default)]
+struct Family {
+    kind: &'static str,
+    help: String,
+    samples: Vec<String>,
+}
+
+/// Renders `metrics` in the text format. Dots in names become underscores,
+/// counters are suffixed `_total`, and each sample is labelled with the
+/// `otel_scope_name` of its meter. Exponential histograms are left out.
+fn render(metrics: &ResourceMetrics) -> String {
+    let mut families: BTreeMap<String, Family> = BTreeMap::new();
+    for scope in metrics.scope_metrics() {
+        let scope_label = KeyValue::new("otel_scope_name", scope.scope().name().to_string());
+        for metric in scope.metrics() {
+            match metric.data() {
+                AggregatedMetrics::F64(data) => add(&mut families, metric, data, &scope_label),
+                AggregatedMetrics::U64(data) => add(&mut families, metric, data, &scope_label),
+                AggregatedMetrics::I64(data) => add(&mut families, metric, data, &scope_label),
+            }
+        }
+    }
+
+    let mut out = String::new();
+    for (name, family) in families {
+        if !family.help.is_empty() {
+            let _ = writeln!(out, "# HELP {name} {}", escape_help(&family.help));
+        }
+        let _ = writeln!(out, "# TYPE {name} {}", family.kind);
+        for sample in family.samples {
+            out.push_str(&sample);
+        }
+    }
+    out
+}
+
+fn add<T: Copy + Display>(
+    families: &mut BTreeMap<String, Family>,
+    metric: &Metric,
+    data: &MetricData<T>,
+    scope_label: &KeyValue,
+) {
+    let base = sanitize_name(metric.name());
+    let (name, kind) = match data {
+        MetricData::Sum(sum) if sum.is_monotonic() => (format!("{base}_total"), "counter"),
+        MetricData::Sum(_) | MetricData::Gauge(_) => (base.clone(), "gauge"),
+        MetricData::Histogram(_) => (base.clone(), "histogram"),
+        MetricData::ExponentialHistogram(_) => return,
+    };
+    let family = families.entry(name.clone()).or_insert_with(|| Family {
+        kind,
+        help: metric.description().to_string(),
+        ..Default::default()
+    });
+
+    match data {
+        MetricData::Sum(sum) => {
+            for point in sum.data_points() {
+                let labels = labels(scope_label, point.attributes());
+                family
+                    .samples
+                    .push(sample(&name, &labels, None, point.value()));
+            }
+        }
+        MetricData::Gauge(gauge) => {
+            for point in gauge.data_points() {
+                let labels = labels(scope_label, point.attributes());
+                family
+                    .samples
+                    .push(sample(&name, &labels, None, point.value()));
+            }
+        }
+        MetricData::Histogram(histogram) => {
+            for point in histogram.data_points() {
+                let labels = labels(scope_label, point.attributes());
+                let bucket = format!("{base}_bucket");
+                let mut cumulative = 0;
+                for (bound, count) in point.bounds().zip(point.bucket_counts()) {
+                    cumulative += count;
+                    let le = ("le", format_value(bound));
+                    family
+                        .samples
+                        .push(sample(&bucket, &labels, Some(le), cumulative));
+                }
+                let le = ("le", "+Inf".to_string());
+                family
+                    .samples
+                    .push(sample(&bucket, &labels, Some(le), point.count()));
+                family
+                    .samples
+                    .push(sample(&format!("{base}_sum"), &labels, None, point.sum()));
+                family.samples.push(sample(
+                    &format!("{base}_count"),
+                    &labels,
+                    None,
+                    point.count(),
+                ));
+            }
+        }
+        MetricData::ExponentialHistogram(_) => {}
+    }
+}
+
+/// The attributes of a data point, after the scope's label.
+fn labels<'a>(
+    scope_label: &'a KeyValue,
+    attributes: impl Iterator<Item = &'a KeyValue>,
+) -> Vec<KeyValue> {
+    std::iter::once(scope_label)
+        .chain(attributes)
+        .cloned()
+        .collect()
+}
+
+/// One line of the exposition: `name{labels} value`.
+fn sample(
+    name: &str,
+    labels: &[KeyValue],
+    le: Option<(&str, String)>,
+    value: impl Display,
+) -> String {
+    let labels = labels
+        .iter()
+        .map(|kv| (sanitize_name(kv.key.as_str()), kv.value.to_string()))
+        .chain(le.map(|(key, value)| (key.to_string(), value)))
+        .map(|(key, value)| format!("{key}=\"{}\"", escape_label(&value)))
+        .collect::<Vec<_>>()
+        .join(",");
+    format!("{name}{{{labels}}} {}\n", format_value(value))
+}
+
+/// Replaces the characters Prometheus doesn't allow in names, such as the
+/// dots of OTel names, with underscores.
+fn sanitize_name(name: &str) -> String {
+    let mut sanitized: String = name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | ':' => c,
+            _ => '_',
+        })
+        .collect();
+    if sanitized.starts_with(|c: char| c.is_ascii_digit()) {
+        sanitized.insert(0, '_');
+    }
+    sanitized
+}
+
+/// The number as Prometheus spells it, with `+Inf` and `-Inf`.
+fn format_value(value: impl Display) -> String {
+    match value.to_string().as_str() {
+        "inf" => "+Inf".to_string(),
+        "-inf" => "-Inf".to_string(),
+        other => other.to_string(),
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', r"\n")
+}
+
+fn escape_help(help: &str) -> String {
+    help.replace('\\', r"\\").replace('\n', r"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::metrics::MeterProvider;
+    use opentelemetry_sdk::metrics::SdkMeterProvider;
+
+    use super::*;
+
+    #[test]
+    fn test_metrics_render_in_the_text_format() {
+        let reader = PrometheusReader::default();
+        let provider = SdkMeterProvider::builder()
+            .with_reader(reader.clone())
+            .build();
+        let meter = provider.meter("otel_demo.shipping.test");
+        meter
+            .u64_counter("app.shipping.items_count")
+            .with_description("Items quoted")
+            .build()
+            .add(3, &[KeyValue::new("zone", "say \"west\"")]);
+        meter
+            .u64_histogram("app.shipping.quote.duration_ms")
+            .with_boundaries(vec![10.0, 100.0])
+            .build()
+            .record(42, &[]);
+        meter
+            .f64_gauge("app.shipping.ratio")
+            .build()
+            .record(f64::INFINITY, &[]);
+
+        let text = reader.render().unwrap();
+        let scope = r#"otel_scope_name="otel_demo.shipping.test""#;
+        for line in [
+            "# HELP app_shipping_items_count_total Items quoted".to_string(),
+            "# TYPE app_shipping_items_count_total counter".to_string(),
+            format!(r#"app_shipping_items_count_total{{{scope},zone="say \"west\""}} 3"#),
+            "# TYPE app_shipping_quote_duration_ms histogram".to_string(),
+            format!(r#"app_shipping_quote_duration_ms_bucket{{{scope},le="10"}} 0"#),
+            format!(r#"app_shipping_quote_duration_ms_bucket{{{scope},le="100"}} 1"#),
+            format!(r#"app_shipping_quote_duration_ms_bucket{{{scope},le="+Inf"}} 1"#),
+            format!("app_shipping_quote_duration_ms_sum{{{scope}}} 42"),
+            format!("app_shipping_quote_duration_ms_count{{{scope}}} 1"),
+            "# TYPE app_shipping_ratio gauge".to_string(),
+            format!("app_shipping_ratio{{{scope}}} +Inf"),
+        ] {
+            assert!(text.lines().any(|l| l == line), "{line} in\n{text}");
+        }
+    }
+}
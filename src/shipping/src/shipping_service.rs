@@ -1,78 +1,4272 @@
 // Copyright The OpenTelemetry Authors
 // SPDX-License-Identifier: Apache-2.0
 
-use actix_web::{post, web, HttpResponse, Responder};
-use tracing::info;
+use actix_web::{
+    body::to_bytes,
+    error::InternalError,
+    get,
+    http::header::{self, ContentType},
+    middleware::from_fn,
+    post, put, web, Error, HttpRequest, HttpResponse, Responder,
+};
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use futures_util::{stream::FuturesUnordered, StreamExt};
+use opentelemetry::{
+    context::FutureExt,
+    global,
+    trace::{get_active_span, Status, TraceContextExt, Tracer},
+    Array, Context, KeyValue, Value,
+};
+use std::{collections::BTreeSet, time::Instant};
+use tracing::{info, warn};
+
+use crate::prometheus::{self, PrometheusReader};
+use crate::telemetry::{current_trace_context, TelemetryStatus};
 
 mod quote;
-use quote::create_quote_from_count;
+use quote::{
+    create_quote_from_count, create_quote_from_items, latency_histogram, outcome, CachePolicy,
+    QuoteState,
+};
+
+mod items;
+use items::ItemCount;
+
+mod breaker;
 
 mod tracking;
-use tracking::create_tracking_id;
+use tracking::{create_order_id, create_tracking_id, validate_tracking_id};
 
 mod shipping_types;
 pub use shipping_types::*;
 
-const NANOS_MULTIPLE: u32 = 10000000u32;
+mod config;
+use config::PricingConfig;
+pub use config::ShippingConfig;
 
-#[post("/get-quote")]
-pub async fn get_quote(req: web::Json<GetQuoteRequest>) -> impl Responder {
-    let itemct: u32 = req.items.iter().map(|item| item.quantity as u32).sum();
+mod validation;
+use validation::{
+    check_zero_items, truncate_for_log, validate_address, validate_item_count,
+    validate_item_quantity, AddressRequired,
+};
 
-    let quote = match create_quote_from_count(itemct).await {
-        Ok(q) => q,
-        Err(e) => {
-            return HttpResponse::InternalServerError().body(format!("Failed to get quote: {}", e));
-        }
+mod auth;
+use auth::require_auth;
+
+mod admin;
+pub use admin::reset as admin_reset;
+
+mod debug;
+pub use debug::trace_headers;
+use debug::DebugOverrides;
+
+mod deadline;
+use deadline::RequestDeadline;
+
+mod orders;
+use orders::{Order, OrderStore};
+
+mod state;
+pub use state::AppData;
+
+mod receipt;
+use receipt::Receipt;
+
+mod timing;
+use timing::PhaseTimings;
+
+mod pricing;
+use pricing::PricingState;
+
+mod customs;
+use customs::estimate_duties;
+
+mod carbon;
+use carbon::estimate_co2_kg;
+
+mod serviceability;
+use serviceability::{check_serviceable, suggest_alternatives};
+
+mod health;
+use health::probe_quote_service;
+
+mod hazmat;
+use hazmat::check_hazmat;
+
+mod instrumentation;
+pub use instrumentation::InstrumentationLevel;
+
+mod events;
+
+mod strategy;
+use strategy::{compare_canary, sample_canary};
+
+mod money;
+use money::{convert_at, decimal_amount, from_minor_units, from_nanos, to_nanos};
+
+mod panic_guard;
+pub use panic_guard::catch_panics;
+
+mod headers;
+pub use headers::security_headers;
+
+mod shutdown;
+pub use shutdown::{count_in_flight, stop_signal};
+
+mod concurrency;
+use concurrency::{limit_get_quote, limit_ship_order};
+
+mod rate_limit;
+use rate_limit::rate_limit_quotes;
+
+mod weight;
+use weight::{billable_weight, validate_weights, BilledWeight};
+
+mod determinism;
+use determinism::{now, Entropy};
+
+mod backoff;
+
+mod currency;
+use currency::{CurrencyFailureMode, StaleRates};
+
+mod carriers;
+use carriers::carrier_quote;
+
+mod webhook;
+
+mod fees;
+use fees::{
+    billable_items, country_surcharge, dollars_to_cents, handling_fee_cents, is_free_shipping,
+};
+
+mod tax;
+use tax::TaxedTotal;
+
+mod overrides;
+use overrides::{accept_override, PricingOverride};
+
+mod idempotency;
+use idempotency::Served;
+
+mod quote_tokens;
+
+mod serialization;
+use serialization::to_json;
+
+mod reconcile;
+
+mod loyalty;
+use loyalty::LoyaltyDiscount;
+
+mod freight;
+use freight::{freight_quote, ShippingMode};
+
+mod zones;
+
+mod grpc_service;
+pub use grpc_service::{serve as serve_grpc, ServeProtocol};
+
+const CARRIER: &str = "OpenTelemetry Demo Shipping";
+const TRANSIT_DAYS: i64 = 5;
+
+const NDJSON: &str = "application/x-ndjson";
+
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+
+#[post(
+    "/get-quote",
+    wrap = "from_fn(limit_get_quote)",
+    wrap = "from_fn(rate_limit_quotes)"
+)]
+pub async fn get_quote(
+    http_req: HttpRequest,
+    req: web::Json<GetQuoteRequest>,
+    data: web::Data<AppData>,
+    debug: DebugOverrides,
+    deadline: RequestDeadline,
+) -> impl Responder {
+    let started = Instant::now();
+    let mut req = req.into_inner();
+    req.fresh |= forbids_cache(&http_req);
+    let resp = serve_quote(&req, &data, debug, deadline).await;
+    record_quote_duration(started, &resp);
+    resp
+}
+
+/// Cacheable shorthand of `POST /get-quote` for requests without an address
+/// or per-item details, e.g. `GET /get-quote?items=3&speed=express`. It is
+/// validated and priced the same way.
+#[get(
+    "/get-quote",
+    wrap = "from_fn(limit_get_quote)",
+    wrap = "from_fn(rate_limit_quotes)"
+)]
+pub async fn get_quote_query(
+    http_req: HttpRequest,
+    query: web::Query<GetQuoteQuery>,
+    data: web::Data<AppData>,
+    debug: DebugOverrides,
+    deadline: RequestDeadline,
+) -> impl Responder {
+    let started = Instant::now();
+    let mut req = GetQuoteRequest::from(query.into_inner());
+    req.fresh |= forbids_cache(&http_req);
+    let resp = serve_quote(&req, &data, debug, deadline).await;
+    record_quote_duration(started, &resp);
+    resp
+}
+
+/// Whether the request's `Cache-Control` asks for `no-cache`.
+fn forbids_cache(req: &HttpRequest) -> bool {
+    req.headers()
+        .get_all(header::CACHE_CONTROL)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|directive| directive.trim().eq_ignore_ascii_case("no-cache"))
+}
+
+/// Records the time a `get-quote` request took since `started`, by
+/// outcome, in `app.shipping.quote.duration_ms`. The quote service's share
+/// is in `app.shipping.quote.upstream_duration_ms`.
+fn record_quote_duration(started: Instant, resp: &HttpResponse) {
+    latency_histogram("app.shipping.quote.duration_ms").record(
+        started.elapsed().as_millis() as u64,
+        &[outcome(resp.status().is_success())],
+    );
+}
+
+/// Quotes `req`, answering 504 at once, without asking the quote service,
+/// when the caller's `deadline` has already passed.
+async fn serve_quote(
+    req: &GetQuoteRequest,
+    data: &AppData,
+    debug: DebugOverrides,
+    deadline: RequestDeadline,
+) -> HttpResponse {
+    let AppData {
+        config,
+        quotes,
+        pricing,
+        entropy,
+        quote_tokens,
+        stale_rates,
+        ..
+    } = data;
+    let started = Instant::now();
+    let level = config.instrumentation_level;
+    let mut timings = PhaseTimings::new(level);
+    if deadline.is_expired() {
+        return error_response(
+            ShippingError::DeadlineExceeded(
+                "The request deadline passed before it was quoted".to_string(),
+            ),
+            None,
+        );
+    }
+    deadline.record(level);
+    debug.record();
+    debug.apply_latency().await;
+
+    let (pricing, pricing_version) = pricing.current_versioned();
+    let checks = match check_quote_request(req, config, &pricing) {
+        Ok(checks) => checks,
+        Err(resp) => return resp,
     };
 
-    let reply = GetQuoteResponse {
-        cost_usd: Some(Money {
-            currency_code: "USD".into(),
-            units: quote.dollars,
-            nanos: quote.cents * NANOS_MULTIPLE,
-        }),
+    let quote_started = Instant::now();
+    let quote = match checks.mode {
+        ShippingMode::Parcel => {
+            let cache = if debug.force_fallback {
+                CachePolicy::Fallback
+            } else if req.fresh {
+                CachePolicy::Refresh
+            } else {
+                CachePolicy::Reuse
+            };
+            create_quote_from_items(
+                &checks.billable,
+                req.address.as_ref(),
+                config,
+                quotes,
+                &pricing.zones,
+                cache,
+                deadline.at,
+            )
+            .await
+        }
+        ShippingMode::Freight => Ok(freight_quote(&req.items, &pricing.freight, now(config))),
+    };
+    timings.record("quote", quote_started.elapsed());
+    let mut quote = match quote {
+        Ok(q) => q,
+        Err(e) => return quote_error_response(&e, quotes),
     };
+    if let (Some(strategy), ShippingMode::Parcel) = (config.canary_strategy, checks.mode) {
+        if sample_canary(config.canary_sample_rate, entropy) {
+            compare_canary(
+                strategy,
+                &checks.billable,
+                req.address.as_ref(),
+                &quote,
+                &pricing,
+                level,
+            );
+        }
+    }
+    checks.add_charges(&mut quote, &pricing, pricing.hazmat_surcharge);
+
+    let mut reply = quote_response(&quote, checks.speed, now(config));
+    reply.quote_token = Some(quote_tokens.issue(quote.clone(), checks.quantity, entropy));
+    if req.include_tax {
+        let taxed = TaxedTotal::for_destination(
+            quote.total_cents,
+            req.address.as_ref(),
+            &pricing.tax_rates,
+        );
+        level.set_attribute(
+            InstrumentationLevel::Standard,
+            KeyValue::new("app.shipping.tax.rate", taxed.rate),
+        );
+        reply.tax = Some(quote_tax(&taxed, &quote.currency));
+    }
+    if let Some(code) = debug.currency.as_ref().or(req.currency.as_ref()) {
+        if let Err(resp) = convert_cost(&mut reply, code, config, stale_rates).await {
+            return resp;
+        }
+    }
+    if req.include_provenance {
+        reply.provenance = Some(QuoteProvenance {
+            pricing_version: pricing_version.version,
+            pricing_strategy: match checks.mode {
+                ShippingMode::Parcel => "quote_service",
+                ShippingMode::Freight => "freight",
+            }
+            .to_string(),
+            rate_table_sha1: pricing_version.file_sha1,
+            exchange_rates_as_of: pricing_version.loaded_at,
+        });
+    }
+    if config.carbon.enabled {
+        let co2_kg = estimate_co2_kg(
+            &req.items,
+            checks.speed,
+            req.address.as_ref(),
+            &config.origin_country,
+            &config.carbon,
+        );
+        level.set_attribute(
+            InstrumentationLevel::Standard,
+            KeyValue::new("app.shipping.carbon.co2_kg", co2_kg),
+        );
+        reply.estimated_co2_kg = Some(co2_kg);
+    }
+    if checks.mode == ShippingMode::Freight {
+        reply.freight = Some(FreightEstimate {
+            transit_days: pricing.freight.transit_days,
+            estimated_delivery: reply.quoted_at
+                + Duration::days(pricing.freight.transit_days.into()),
+        });
+    }
+    level.set_attribute(
+        InstrumentationLevel::Standard,
+        KeyValue::new(
+            "app.shipping.quote.quoted_at",
+            reply.quoted_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+        ),
+    );
+    level.set_attribute(
+        InstrumentationLevel::Standard,
+        KeyValue::new(
+            "app.shipping.quote.served_at",
+            reply.served_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+        ),
+    );
 
+    let trace = current_trace_context();
+    let (trace_id, span_id) = trace.as_fields();
+    let shown = Quote::from(&quote);
     info!(
         name = "SendingQuoteValue",
-        quote.dollars = quote.dollars,
-        quote.cents = quote.cents,
+        quote.dollars = shown.dollars,
+        quote.cents = shown.cents,
+        trace_id,
+        span_id,
         message = "Sending Quote"
     );
+    if config.include_trace_id_in_response {
+        reply.trace_id = Some(trace_id.to_string());
+    }
+
+    let serialize_started = Instant::now();
+    let body = match to_json(&reply, "quote") {
+        Ok(body) => body,
+        Err(resp) => return resp,
+    };
+    timings.record("serialize", serialize_started.elapsed());
+    timings.record("total", started.elapsed());
+
+    let mut resp = HttpResponse::Ok();
+    resp.content_type(ContentType::json());
+    if config.server_timing_enabled {
+        resp.insert_header(("Server-Timing", timings.header_value()));
+    }
+    resp.body(body)
+}
+
+/// Quotes each request of the batch as `get-quote` would, concurrently. With
+/// `Accept: application/x-ndjson` each result is streamed as a line as soon
+/// as it is ready, so lines come in completion order and carry their index.
+#[post("/get-quotes", wrap = "from_fn(rate_limit_quotes)")]
+pub async fn get_quotes(
+    http_req: HttpRequest,
+    req: web::Json<BatchQuoteRequest>,
+    data: web::Data<AppData>,
+    debug: DebugOverrides,
+    deadline: RequestDeadline,
+) -> HttpResponse {
+    let requests = req.into_inner().requests;
+    let max_batch = data.config.max_quote_batch;
+    if requests.len() > max_batch {
+        return HttpResponse::PayloadTooLarge().json(api_error(
+            "batch_too_large",
+            format!(
+                "batch has {} requests, the maximum is {max_batch}",
+                requests.len()
+            ),
+        ));
+    }
+    data.config.instrumentation_level.set_attribute(
+        InstrumentationLevel::Minimal,
+        KeyValue::new("app.shipping.batch.size", requests.len() as i64),
+    );
+
+    let parent = Context::current();
+    let results: FuturesUnordered<_> = requests
+        .into_iter()
+        .enumerate()
+        .map(|(index, req)| {
+            let tracer = global::tracer("otel_demo.shipping.batch");
+            let span = tracer
+                .span_builder("shipping.batch.quote")
+                .with_attributes([KeyValue::new("app.shipping.batch.index", index as i64)])
+                .start_with_context(&tracer, &parent);
+            batch_quote(index, req, data.clone(), debug.clone(), deadline)
+                .with_context(parent.with_span(span))
+        })
+        .collect();
+
+    let ndjson = http_req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|accept| accept.contains(NDJSON));
+    if ndjson {
+        return HttpResponse::Ok()
+            .content_type(NDJSON)
+            .streaming(results.map(|result| {
+                serde_json::to_vec(&result).map(|mut line| {
+                    line.push(b'\n');
+                    web::Bytes::from(line)
+                })
+            }));
+    }
+
+    let mut results: Vec<_> = results.collect().await;
+    results.sort_by_key(|result| result.index);
+    HttpResponse::Ok().json(BatchQuoteResponse { results })
+}
+
+async fn batch_quote(
+    index: usize,
+    req: GetQuoteRequest,
+    data: web::Data<AppData>,
+    debug: DebugOverrides,
+    deadline: RequestDeadline,
+) -> BatchQuoteResult {
+    let resp = serve_quote(&req, &data, debug, deadline).await;
+    let status = resp.status();
+    let body = to_bytes(resp.into_body()).await.unwrap_or_default();
+    let (quote, error) = if status.is_success() {
+        (serde_json::from_slice(&body).ok(), None)
+    } else {
+        (None, serde_json::from_slice(&body).ok())
+    };
+    if status.is_server_error() {
+        get_active_span(|span| span.set_status(Status::error(status.to_string())));
+    }
+    get_active_span(|span| span.end());
+    BatchQuoteResult {
+        index,
+        status: status.as_u16(),
+        quote,
+        error,
+    }
+}
+
+/// Quotes the request with every configured carrier's rate table, so that
+/// clients can compare them.
+#[post("/compare-carriers")]
+pub async fn compare_carriers(
+    req: web::Json<GetQuoteRequest>,
+    config: web::Data<ShippingConfig>,
+    pricing: web::Data<PricingState>,
+) -> impl Responder {
+    let pricing = pricing.current();
+    let checks = match check_quote_request(&req, &config, &pricing) {
+        Ok(checks) => checks,
+        Err(resp) => return resp,
+    };
+
+    let quoted_at = now(&config);
+    let carriers: Vec<CarrierQuote> = pricing
+        .carriers
+        .iter()
+        .map(|carrier| {
+            let (mut quote, unpriceable_items) = carrier_quote(
+                carrier,
+                &req.items,
+                req.address.as_ref(),
+                &pricing,
+                quoted_at,
+            );
+            let hazmat_surcharge = carrier.hazmat_surcharge.unwrap_or(pricing.hazmat_surcharge);
+            checks.add_charges(&mut quote, &pricing, hazmat_surcharge);
+            CarrierQuote {
+                carrier: carrier.name.clone(),
+                quote: quote_response(&quote, checks.speed, quoted_at),
+                estimated_delivery: quoted_at + Duration::days(carrier.transit_days.into()),
+                unpriceable_items,
+            }
+        })
+        .collect();
+
+    let unpriceable: BTreeSet<&str> = carriers
+        .iter()
+        .flat_map(|quote| quote.unpriceable_items.iter().map(String::as_str))
+        .collect();
+    config.instrumentation_level.set_attribute(
+        InstrumentationLevel::Minimal,
+        KeyValue::new("app.shipping.unpriceable_count", unpriceable.len() as i64),
+    );
+    if !unpriceable.is_empty() && !config.partial_quote_allowed {
+        return HttpResponse::UnprocessableEntity().json(ApiError {
+            details: Some(serde_json::json!({ "unpriceable_items": unpriceable })),
+            ..api_error(
+                "unpriceable_items",
+                format!(
+                    "{} items can't be priced by every carrier",
+                    unpriceable.len()
+                ),
+            )
+        });
+    }
+
+    HttpResponse::Ok().json(CompareCarriersResponse { carriers })
+}
+
+#[post(
+    "/ship-order",
+    wrap = "from_fn(limit_ship_order)",
+    wrap = "from_fn(require_auth)"
+)]
+pub async fn ship_order(
+    http_req: HttpRequest,
+    req: web::Json<ShipOrderRequest>,
+    data: web::Data<AppData>,
+) -> impl Responder {
+    let AppData {
+        config,
+        quotes,
+        pricing,
+        orders,
+        entropy,
+        shipments,
+        quote_tokens,
+        ..
+    } = &**data;
+    let body_key = req.idempotency_key.clone();
+    // A token is only used up when the order ships, not when the result of
+    // an earlier request with the same idempotency key is replayed, or when
+    // the order is then rejected.
+    let create = || async {
+        let req = req.into_inner();
+        let token = req.quote_token.clone();
+        let locked = match &token {
+            Some(token) => {
+                let quantity = ItemCount::total(req.shipped_items())
+                    .map_err(|msg| error_response(ShippingError::InvalidItemCount(msg), None))?;
+                match quote_tokens.redeem(token, quantity, config.quote_token_max_uses) {
+                    Ok(quote) => Some(quote),
+                    Err(rejection) => return Err(rejection.response()),
+                }
+            }
+            None => None,
+        };
+        let shipped = create_order(
+            req,
+            locked,
+            config,
+            &pricing.current(),
+            quotes,
+            orders,
+            entropy,
+        )
+        .await;
+        if let (Err(_), Some(token)) = (&shipped, &token) {
+            quote_tokens.refund(token);
+        }
+        shipped.map_err(|err| error_response(err, None))
+    };
+    let key = http_req
+        .headers()
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .or(body_key.as_deref())
+        .map(str::trim)
+        .filter(|key| !key.is_empty());
+
+    let result = match key {
+        Some(key) if key.len() > MAX_IDEMPOTENCY_KEY_LEN => {
+            return HttpResponse::BadRequest().json(api_error(
+                "invalid_idempotency_key",
+                format!("Idempotency keys are limited to {MAX_IDEMPOTENCY_KEY_LEN} characters"),
+            ));
+        }
+        Some(key) => {
+            let (result, served) = shipments
+                .run(key, config.ship_order_coalescing, create)
+                .await;
+            let level = config.instrumentation_level;
+            let served_attr = KeyValue::new("app.shipping.idempotency.served", served.as_str());
+            level.set_attribute(InstrumentationLevel::Minimal, served_attr.clone());
+            if served != Served::Created {
+                level.add_event(
+                    InstrumentationLevel::Minimal,
+                    "Idempotent Replay",
+                    vec![served_attr],
+                );
+            }
+            result
+        }
+        None => create().await,
+    };
+    let mut shipped = match result {
+        Ok(shipped) => shipped,
+        Err(resp) => return resp,
+    };
+    if config.include_trace_id_in_response {
+        shipped.trace_id = Some(current_trace_context().trace_id);
+    }
+    match to_json(&shipped, "shipped order") {
+        Ok(body) => HttpResponse::Ok()
+            .insert_header((header::LINK, order_links(&shipped.order_id, config)))
+            .content_type(ContentType::json())
+            .body(body),
+        Err(resp) => resp,
+    }
+}
 
-    HttpResponse::Ok().json(reply)
+/// RFC 8288 links to the status and receipt of a shipped order, absolute
+/// under `PUBLIC_BASE_URL` when it is set.
+fn order_links(order_id: &str, config: &ShippingConfig) -> String {
+    let base = config
+        .public_base_url
+        .as_deref()
+        .unwrap_or_default()
+        .trim_end_matches('/');
+    format!(
+        r#"<{base}/order/{order_id}>; rel="status", <{base}/order/{order_id}/receipt>; rel="receipt""#
+    )
 }
 
-#[post("/ship-order")]
-pub async fn ship_order(_req: web::Json<ShipOrderRequest>) -> impl Responder {
-    let tid = create_tracking_id();
+/// Ships the order: assigns its ids, quotes it unless `locked` carries the
+/// quote of a token, and stores it. Errors are all the client's fault.
+async fn create_order(
+    req: ShipOrderRequest,
+    locked: Option<ShippingQuote>,
+    config: &ShippingConfig,
+    pricing: &PricingConfig,
+    quotes: &QuoteState,
+    orders: &OrderStore,
+    entropy: &Entropy,
+) -> Result<ShipOrderResponse, ShippingError> {
+    let item_entries = req.items.len()
+        + req
+            .packages
+            .iter()
+            .map(|package| package.items.len())
+            .sum::<usize>();
+    validate_item_count(item_entries, config.max_items_in_request)
+        .map_err(ShippingError::TooManyItems)?;
+    let order_id = create_order_id(entropy);
+    let package_items = if req.packages.is_empty() {
+        vec![req.items]
+    } else {
+        req.packages
+            .into_iter()
+            .map(|package| package.items)
+            .collect()
+    };
+    let packages: Vec<Package> = package_items
+        .into_iter()
+        .map(|items| Package {
+            tracking_id: create_tracking_id(entropy, &config.tracking_id),
+            items,
+            status: DeliveryStatus::InTransit,
+        })
+        .collect();
+
+    // The quote is kept with the order for its receipt; shipping goes ahead
+    // without it if the quote service is unavailable.
+    let itemct = ItemCount::total(packages.iter().flat_map(|package| &package.items))
+        .map_err(ShippingError::InvalidItemCount)?;
+    validate_item_quantity(itemct, config.max_item_count, config.instrumentation_level)
+        .map_err(ShippingError::TooManyItems)?;
+    check_zero_items(
+        itemct,
+        config.zero_items_policy,
+        config.instrumentation_level,
+    )
+    .map_err(ShippingError::NoItems)?;
+    let quote = match locked {
+        Some(quote) => Ok(quote),
+        None => create_quote_from_count(itemct, config, quotes, pricing).await,
+    };
+    let quote = match quote {
+        Ok(q) => Some(q),
+        Err(e) => {
+            let trace = current_trace_context();
+            let (trace_id, span_id) = trace.as_fields();
+            warn!(
+                name = "ShipOrderQuoteFailed",
+                order_id = order_id.as_str(),
+                error = %e,
+                trace_id,
+                span_id,
+                message = "Shipping order without a stored quote"
+            );
+            None
+        }
+    };
+
+    let package_tracking_ids: Vec<String> = packages
+        .iter()
+        .map(|package| package.tracking_id.clone())
+        .collect();
+    let shipped_at = now(config);
+    orders.insert(Order {
+        order_id: order_id.clone(),
+        packages,
+        address: req.address,
+        quote,
+        carrier: CARRIER.to_string(),
+        shipped_at,
+        estimated_delivery: shipped_at + Duration::days(TRANSIT_DAYS),
+        origin: get_active_span(|span| span.span_context().clone()),
+        stuck_since: None,
+    });
+
+    let trace = current_trace_context();
+    let (trace_id, span_id) = trace.as_fields();
     info!(
         name = "CreatingTrackingId",
-        tracking_id = tid.as_str(),
+        order_id = order_id.as_str(),
+        tracking_ids = package_tracking_ids.join(",").as_str(),
+        trace_id,
+        span_id,
         message = "Tracking ID Created"
     );
-    HttpResponse::Ok().json(ShipOrderResponse { tracking_id: tid })
+    Ok(ShipOrderResponse {
+        order_id,
+        tracking_id: package_tracking_ids.first().cloned().unwrap_or_default(),
+        package_tracking_ids,
+        trace_id: None,
+    })
+}
+
+#[get("/order/{order_id}")]
+pub async fn get_order(path: web::Path<String>, orders: web::Data<OrderStore>) -> impl Responder {
+    let order_id = path.into_inner();
+    match orders.get(&order_id) {
+        Some(order) => {
+            order.link_origin();
+            HttpResponse::Ok().json(OrderResponse::from(&order))
+        }
+        None => order_not_found(&order_id),
+    }
+}
+
+#[put("/package/{tracking_id}/status", wrap = "from_fn(require_auth)")]
+pub async fn update_package_status(
+    path: web::Path<String>,
+    req: web::Json<PackageStatusUpdate>,
+    config: web::Data<ShippingConfig>,
+    orders: web::Data<OrderStore>,
+) -> impl Responder {
+    let tracking_id = path.into_inner();
+    if !validate_tracking_id(&tracking_id, &config.tracking_id) {
+        return HttpResponse::BadRequest().json(api_error(
+            "invalid_tracking_id",
+            format!(
+                "{} is not a valid {} tracking id",
+                truncate_for_log(&tracking_id),
+                config.tracking_id.encoding
+            ),
+        ));
+    }
+    let Some(order) = orders.set_package_status(&tracking_id, req.status) else {
+        return HttpResponse::NotFound().json(api_error(
+            "package_not_found",
+            format!(
+                "No package with tracking id {}",
+                truncate_for_log(&tracking_id)
+            ),
+        ));
+    };
+
+    let trace = current_trace_context();
+    let (trace_id, span_id) = trace.as_fields();
+    info!(
+        name = "PackageStatusUpdated",
+        order_id = order.order_id.as_str(),
+        tracking_id = tracking_id.as_str(),
+        status = ?req.status,
+        trace_id,
+        span_id,
+        message = "Package status updated"
+    );
+    order.link_origin();
+    if let Some(url) = &config.order_webhook_url {
+        webhook::notify(url.clone(), &order);
+    }
+    HttpResponse::Ok().json(OrderResponse::from(&order))
+}
+
+/// Serves the receipt of an order, looked up by order id or, for clients of
+/// single-package orders, by a package tracking id.
+#[get("/order/{order_id}/receipt")]
+pub async fn get_receipt(
+    req: HttpRequest,
+    path: web::Path<String>,
+    orders: web::Data<OrderStore>,
+) -> impl Responder {
+    let order_id = path.into_inner();
+    let order = orders
+        .get(&order_id)
+        .or_else(|| orders.find_by_tracking_id(&order_id));
+    let Some(order) = order else {
+        return order_not_found(&order_id);
+    };
+    order.link_origin();
+
+    let receipt = Receipt::from(&order);
+    let wants_text = req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/plain"));
+
+    if wants_text {
+        HttpResponse::Ok()
+            .content_type(ContentType::plaintext())
+            .body(receipt.to_string())
+    } else {
+        HttpResponse::Ok().json(receipt)
+    }
+}
+
+/// Liveness probe: the service answers whatever the state of its
+/// dependencies, which `/ready` checks.
+#[get("/health")]
+pub async fn live() -> impl Responder {
+    HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
+}
+
+/// Readiness probe: ready only while the quote service answers in time.
+/// Telemetry signals that failed to start don't make the service unready,
+/// but are listed under `telemetry`.
+#[get("/ready")]
+pub async fn ready(
+    config: web::Data<ShippingConfig>,
+    telemetry: web::Data<TelemetryStatus>,
+) -> impl Responder {
+    match probe_quote_service(&config.quote_addr, config.readiness_probe_timeout).await {
+        Ok(()) if telemetry.is_degraded() => HttpResponse::Ok().json(serde_json::json!({
+            "status": "ready",
+            "observability": "degraded",
+            "telemetry": &**telemetry,
+        })),
+        Ok(()) => HttpResponse::Ok().json(serde_json::json!({ "status": "ready" })),
+        Err(reason) => {
+            let trace = current_trace_context();
+            let (trace_id, span_id) = trace.as_fields();
+            warn!(
+                name = "NotReady",
+                dependency = "quote",
+                reason = reason.as_str(),
+                trace_id,
+                span_id,
+                message = "Readiness probe failed"
+            );
+            HttpResponse::ServiceUnavailable().json(ApiError {
+                details: Some(serde_json::json!({ "dependency": "quote" })),
+                ..api_error("not_ready", format!("Quote service {}", reason))
+            })
+        }
+    }
+}
+
+/// The service's metrics in the Prometheus text format, for scrapers that
+/// can't take OTLP. Answers 404 unless `PROMETHEUS_ENABLED` is true.
+#[get("/metrics")]
+pub async fn prometheus_metrics(reader: Option<web::Data<PrometheusReader>>) -> impl Responder {
+    let Some(reader) = reader else {
+        return HttpResponse::NotFound().json(api_error(
+            "metrics_disabled",
+            "The metrics endpoint is disabled, set PROMETHEUS_ENABLED=true to enable it"
+                .to_string(),
+        ));
+    };
+    match reader.render() {
+        Ok(text) => HttpResponse::Ok()
+            .content_type(prometheus::CONTENT_TYPE)
+            .body(text),
+        Err(err) => HttpResponse::InternalServerError().json(api_error(
+            "metrics_unavailable",
+            format!("Failed to collect metrics: {err}"),
+        )),
+    }
+}
+
+/// Outcome of the checks every quote request goes through, and what they
+/// add to the price.
+struct QuoteChecks {
+    level: InstrumentationLevel,
+    speed: ShippingSpeed,
+    /// Items in the request, free-shipping ones included.
+    quantity: ItemCount,
+    /// The request's items less those that ship free.
+    billable: Vec<CartItem>,
+    hazmat: bool,
+    /// Dollars added for the destination country.
+    country_surcharge: f64,
+    duties: Option<u64>,
+    weight: Option<BilledWeight>,
+    mode: ShippingMode,
+    free_shipping: bool,
+    pricing_override: Option<PricingOverride>,
+    loyalty: Option<LoyaltyDiscount>,
+}
+
+impl QuoteChecks {
+    /// Prices the base cost at the requested speed and waives or discounts
+    /// it, then adds the surcharges, duties, weight charge and handling fee
+    /// owed by the request.
+    fn add_charges(
+        &self,
+        quote: &mut ShippingQuote,
+        pricing: &PricingConfig,
+        hazmat_surcharge: f64,
+    ) {
+        // Freight rates have no speed tiers.
+        if self.mode == ShippingMode::Parcel {
+            let multiplier = pricing.speed_multipliers.get(self.speed);
+            quote.total_cents = (quote.total_cents as f64 * multiplier).round() as u64;
+        }
+        if self.free_shipping {
+            quote.total_cents -= quote.base_cents();
+        } else {
+            if let Some(terms) = &self.pricing_override {
+                quote.total_cents -= terms.discount_cents(quote.base_cents());
+            }
+            if let Some(loyalty) = &self.loyalty {
+                let discount = loyalty.discount_cents(quote.base_cents());
+                quote.total_cents -= discount;
+                self.level.set_attribute(
+                    InstrumentationLevel::Standard,
+                    KeyValue::new("app.shipping.loyalty_discount", discount as f64 / 100.0),
+                );
+            }
+        }
+        if self.hazmat {
+            quote.add_charge(
+                "Hazmat surcharge",
+                (hazmat_surcharge * 100.0).round() as u64,
+            );
+        }
+        if self.country_surcharge > 0.0 {
+            quote.add_charge(
+                "Country surcharge",
+                dollars_to_cents(self.country_surcharge),
+            );
+        }
+        if let Some(duties) = self.duties {
+            quote.add_charge("Estimated customs duties", duties);
+        }
+        // Freight rates already price the weight.
+        let parcel = self.mode == ShippingMode::Parcel;
+        if let Some(weight) = self.weight.filter(|_| parcel && pricing.per_kg_rate > 0.0) {
+            quote.add_charge(
+                "Weight charge",
+                (weight.billed_kg * pricing.per_kg_rate * 100.0).round() as u64,
+            );
+        }
+
+        let waived = self.free_shipping && pricing.waive_handling_with_free_shipping;
+        if pricing.handling_fee > 0.0 && !waived {
+            let cents = handling_fee_cents(pricing);
+            quote.add_charge("Handling fee", cents);
+            self.level.set_attribute(
+                InstrumentationLevel::Standard,
+                KeyValue::new("app.shipping.handling_fee", cents as f64 / 100.0),
+            );
+        }
+    }
+}
+
+/// Validates a quote request and records it on the active span, returning
+/// the response to send instead when it is rejected.
+fn check_quote_request(
+    req: &GetQuoteRequest,
+    config: &ShippingConfig,
+    pricing: &PricingConfig,
+) -> Result<QuoteChecks, HttpResponse> {
+    let level = config.instrumentation_level;
+    let rejected = |err| error_response(err, None);
+    validate_item_count(req.items.len(), config.max_items_in_request)
+        .map_err(|msg| rejected(ShippingError::TooManyItems(msg)))?;
+    let quantity = ItemCount::total(&req.items)
+        .map_err(|msg| rejected(ShippingError::InvalidItemCount(msg)))?;
+    validate_item_quantity(quantity, config.max_item_count, level)
+        .map_err(|msg| rejected(ShippingError::TooManyItems(msg)))?;
+    check_zero_items(quantity, config.zero_items_policy, level)
+        .map_err(|msg| rejected(ShippingError::NoItems(msg)))?;
+    validate_weights(&req.items).map_err(|msg| rejected(ShippingError::InvalidWeight(msg)))?;
+    let speed = req.speed.speed().map_err(rejected)?;
+    level.set_attribute(
+        InstrumentationLevel::Minimal,
+        KeyValue::new("app.shipping.speed", speed.as_str()),
+    );
+    if let Some(rule) =
+        AddressRequired::for_currency(req.currency.as_deref(), &config.address_required_currencies)
+    {
+        level.set_attribute(
+            InstrumentationLevel::Minimal,
+            KeyValue::new("app.shipping.market_rule", rule.name()),
+        );
+        rule.check(req.address.as_ref())
+            .map_err(|msg| rejected(ShippingError::AddressRequired(msg)))?;
+    }
+
+    if let Some(address) = &req.address {
+        if let Err(msg) = validate_address(address, &config.address_limits) {
+            let trace = current_trace_context();
+            let (trace_id, span_id) = trace.as_fields();
+            warn!(
+                name = "InvalidAddress",
+                reason = msg.as_str(),
+                trace_id,
+                span_id,
+                message = "Rejecting quote request"
+            );
+            return Err(rejected(ShippingError::InvalidAddress(msg)));
+        }
+        record_address(address, level);
+
+        if let Err(reason) = check_serviceable(address, &config.serviceable_countries) {
+            let details = config
+                .suggest_alternatives
+                .then(|| {
+                    suggest_alternatives(reason.clone(), address, &config.serviceable_countries)
+                })
+                .and_then(|alternatives| serde_json::to_value(alternatives).ok());
+            return Err(error_response(
+                ShippingError::UnserviceableDestination(format!(
+                    "Destination not serviceable: {}",
+                    reason
+                )),
+                details,
+            ));
+        }
+    }
+    if level.records(InstrumentationLevel::Verbose) {
+        level.set_attribute(
+            InstrumentationLevel::Verbose,
+            KeyValue::new(
+                "app.shipping.items.product_ids",
+                Value::Array(Array::String(
+                    req.items
+                        .iter()
+                        .map(|item| truncate_for_log(&item.product_id).into_owned().into())
+                        .collect(),
+                )),
+            ),
+        );
+    }
+
+    let hazmat = check_hazmat(&req.items, speed)
+        .map_err(|msg| rejected(ShippingError::HazmatSpeedUnavailable(msg)))?;
+    if hazmat {
+        level.set_attribute(
+            InstrumentationLevel::Minimal,
+            KeyValue::new("app.shipping.hazmat", true),
+        );
+    }
+
+    let country_surcharge =
+        country_surcharge(pricing, req.address.as_ref(), &config.origin_country);
+    level.set_attribute(
+        InstrumentationLevel::Standard,
+        KeyValue::new("app.shipping.surcharge.country", country_surcharge),
+    );
+
+    let duties = estimate_duties(
+        req.customs_value.as_ref(),
+        req.address.as_ref(),
+        &config.origin_country,
+        pricing.customs_duty_rate,
+    )
+    .map_err(|msg| rejected(ShippingError::InvalidCustomsValue(msg)))?;
+
+    let billable = billable_items(&req.items);
+    let free_items = quantity.get() - ItemCount::total(&billable).map_or(0, ItemCount::get);
+    if free_items > 0 {
+        level.set_attribute(
+            InstrumentationLevel::Standard,
+            KeyValue::new("app.shipping.free_items_count", i64::from(free_items)),
+        );
+    }
+
+    let weight = billable_weight(&billable, config.weight_billing_increment_kg);
+    if let Some(weight) = weight {
+        level.set_attribute(
+            InstrumentationLevel::Standard,
+            KeyValue::new("app.shipping.weight.actual_kg", weight.actual_kg),
+        );
+        level.set_attribute(
+            InstrumentationLevel::Standard,
+            KeyValue::new("app.shipping.weight.billed_kg", weight.billed_kg),
+        );
+    }
+
+    let mode = ShippingMode::for_items(&req.items, &config.parcel_limits);
+    level.set_attribute(
+        InstrumentationLevel::Minimal,
+        KeyValue::new("app.shipping.mode", mode.as_str()),
+    );
+
+    let all_free = free_items > 0 && free_items == quantity.get();
+    let free_shipping = all_free || is_free_shipping(quantity, pricing);
+    if free_shipping {
+        level.set_attribute(
+            InstrumentationLevel::Minimal,
+            KeyValue::new("app.shipping.free_shipping", true),
+        );
+    }
+
+    let pricing_override = accept_override(req.pricing_override.as_deref(), config);
+
+    let loyalty = LoyaltyDiscount::from_baggage(&pricing.loyalty_discounts);
+    if let Some(loyalty) = &loyalty {
+        level.set_attribute(
+            InstrumentationLevel::Minimal,
+            KeyValue::new("app.shipping.loyalty_tier", loyalty.tier.clone()),
+        );
+    }
+
+    Ok(QuoteChecks {
+        level,
+        speed,
+        quantity,
+        billable,
+        hazmat,
+        country_surcharge,
+        duties,
+        weight,
+        mode,
+        free_shipping,
+        pricing_override,
+        loyalty,
+    })
+}
+
+/// Converts `quote` into `Money` in the quote's currency.
+fn quote_money(quote: &ShippingQuote) -> Money {
+    from_minor_units(quote.total_cents, &quote.currency)
+}
+
+/// Itemizes `quote` as its base shipping cost followed by each charge.
+fn quote_lines(quote: &ShippingQuote) -> Vec<QuoteLine> {
+    let base = QuoteLine {
+        label: "Shipping".to_string(),
+        amount: from_minor_units(quote.base_cents(), &quote.currency),
+    };
+    let charges = quote.charges.iter().map(|charge| QuoteLine {
+        label: charge.label.to_string(),
+        amount: from_minor_units(charge.cents, &quote.currency),
+    });
+    std::iter::once(base).chain(charges).collect()
+}
+
+/// Builds the response for `quote`, keeping its original computation time as
+/// `quoted_at` while stamping `served_at` with the time it is sent.
+fn quote_response(
+    quote: &ShippingQuote,
+    speed: ShippingSpeed,
+    served_at: DateTime<Utc>,
+) -> GetQuoteResponse {
+    let cost = quote_money(quote);
+    GetQuoteResponse {
+        amount_decimal: Some(decimal_amount(&cost)),
+        cost_usd: Some(cost),
+        quoted_at: quote.quoted_at,
+        served_at,
+        speed,
+        breakdown: if quote.charges.is_empty() {
+            vec![]
+        } else {
+            quote_lines(quote)
+        },
+        tax: None,
+        freight: None,
+        quote_token: None,
+        provenance: None,
+        trace_id: None,
+        conversion: None,
+        estimated_co2_kg: None,
+    }
+}
+
+/// Converts the cost of `reply` into `code`, its breakdown and tax at the
+/// same rate. When there is no currency service or the conversion fails,
+/// `CURRENCY_FAILURE_MODE` decides whether the cost is converted at a stale
+/// rate, left in dollars, or the quote is answered with the returned 503.
+async fn convert_cost(
+    reply: &mut GetQuoteResponse,
+    code: &str,
+    config: &ShippingConfig,
+    stale_rates: &StaleRates,
+) -> Result<(), HttpResponse> {
+    let code = code.trim().to_ascii_uppercase();
+    let Some(cost) = reply
+        .cost_usd
+        .clone()
+        .filter(|cost| cost.currency_code != code)
+    else {
+        return Ok(());
+    };
+    let level = config.instrumentation_level;
+    let mode = config.currency_failure_mode;
+    level.set_attribute(
+        InstrumentationLevel::Standard,
+        KeyValue::new("app.shipping.currency.failure_mode", mode.to_string()),
+    );
+    let result = match &config.currency_addr {
+        Some(addr) => currency::convert(addr, cost.clone(), &code).await,
+        None => Err(anyhow::anyhow!("CURRENCY_ADDR is not set")),
+    };
+
+    let record_outcome = |outcome: &'static str| {
+        level.set_attribute(
+            InstrumentationLevel::Standard,
+            KeyValue::new("app.shipping.currency.outcome", outcome),
+        )
+    };
+
+    let converted = match result {
+        Ok(converted) => {
+            stale_rates.record(&cost, &converted);
+            record_outcome("converted");
+            converted
+        }
+        Err(err) => {
+            let trace = current_trace_context();
+            let (trace_id, span_id) = trace.as_fields();
+            warn!(
+                name = "CurrencyConversionFailed",
+                currency = code.as_str(),
+                mode = %mode,
+                error = format!("{err:#}"),
+                trace_id,
+                span_id,
+                message = "Currency conversion failed"
+            );
+            let stale = match mode {
+                CurrencyFailureMode::Reject => None,
+                _ => stale_rates.convert(&cost, &code),
+            };
+            match (stale, mode) {
+                (Some(converted), _) => {
+                    record_outcome("stale_rate");
+                    reply.conversion = Some(ConversionFallback::StaleRate);
+                    converted
+                }
+                (None, CurrencyFailureMode::UsdFallback) => {
+                    record_outcome("usd_fallback");
+                    reply.conversion = Some(ConversionFallback::UsdFallback);
+                    return Ok(());
+                }
+                (None, _) => {
+                    record_outcome("rejected");
+                    return Err(error_response(
+                        ShippingError::CurrencyUnavailable(format!(
+                            "Can't quote in {code} while the currency service is unavailable"
+                        )),
+                        None,
+                    ));
+                }
+            }
+        }
+    };
+    restate_in(reply, &cost, converted);
+    Ok(())
+}
+
+/// Restates `reply`, whose cost `cost` converts to `converted`, in the
+/// currency of `converted`. Charges and tax are converted at the same rate;
+/// the base shipping line takes what remains of the converted cost, so the
+/// breakdown still adds up to it.
+fn restate_in(reply: &mut GetQuoteResponse, cost: &Money, converted: Money) {
+    let code = converted.currency_code.as_str();
+    let rate = match to_nanos(cost) {
+        0 => 0.0,
+        nanos => to_nanos(&converted) as f64 / nanos as f64,
+    };
+    if let Some((base, charges)) = reply.breakdown.split_first_mut() {
+        for charge in charges.iter_mut() {
+            charge.amount = convert_at(&charge.amount, rate, code);
+        }
+        let charged: u128 = charges.iter().map(|charge| to_nanos(&charge.amount)).sum();
+        base.amount = from_nanos(to_nanos(&converted).saturating_sub(charged), code);
+    }
+    if let Some(tax) = &mut reply.tax {
+        tax.amount = convert_at(&tax.amount, rate, code);
+        tax.total_exclusive = converted.clone();
+        tax.total_inclusive = from_nanos(to_nanos(&converted) + to_nanos(&tax.amount), code);
+    }
+    reply.amount_decimal = Some(decimal_amount(&converted));
+    reply.cost_usd = Some(converted);
+}
+
+fn quote_tax(taxed: &TaxedTotal, currency: &str) -> QuoteTax {
+    QuoteTax {
+        rate: taxed.rate,
+        amount: from_minor_units(taxed.tax_cents, currency),
+        total_exclusive: from_minor_units(taxed.exclusive_cents, currency),
+        total_inclusive: from_minor_units(taxed.inclusive_cents, currency),
+    }
+}
+
+/// Logs the destination and records it on the active span, truncating each
+/// field so untrusted input can't bloat either.
+fn record_address(address: &Address, level: InstrumentationLevel) {
+    let city = truncate_for_log(&address.city);
+    let state = truncate_for_log(&address.state);
+    let country = truncate_for_log(&address.country);
+    let zip_code = truncate_for_log(&address.zip_code);
+
+    let trace = current_trace_context();
+    let (trace_id, span_id) = trace.as_fields();
+    info!(
+        name = "QuoteDestination",
+        address.city = city.as_ref(),
+        address.state = state.as_ref(),
+        address.country = country.as_ref(),
+        address.zip_code = zip_code.as_ref(),
+        trace_id,
+        span_id,
+        message = "Quoting shipment"
+    );
+
+    for (key, value) in [
+        ("app.shipping.address.city", city),
+        ("app.shipping.address.state", state),
+        ("app.shipping.address.country", country),
+        ("app.shipping.address.zip_code", zip_code),
+    ] {
+        level.set_attribute(
+            InstrumentationLevel::Standard,
+            KeyValue::new(key, value.into_owned()),
+        );
+    }
+}
+
+/// Maps a failed quote to its HTTP response. A quote turned away by the open
+/// circuit breaker answers 503 with the breaker's state in `details`, and
+/// one the quote service didn't answer in time 504.
+fn quote_error_response(e: &tonic::Status, quotes: &QuoteState) -> HttpResponse {
+    let err = ShippingError::from_quote_failure(e);
+    let details = match err {
+        ShippingError::QuoteServiceUnavailable(_) => {
+            serde_json::to_value(quotes.breaker_snapshot()).ok()
+        }
+        _ => None,
+    };
+    error_response(err, details)
+}
+
+fn order_not_found(order_id: &str) -> HttpResponse {
+    HttpResponse::NotFound().json(api_error(
+        "order_not_found",
+        format!("No order with id {}", truncate_for_log(order_id)),
+    ))
+}
+
+/// Answers a body or query string that doesn't parse into the handler's
+/// request type.
+pub fn malformed_request(err: impl std::fmt::Display + std::fmt::Debug + 'static) -> Error {
+    let resp = error_response(ShippingError::MalformedRequest(err.to_string()), None);
+    InternalError::from_response(err, resp).into()
+}
+
+/// Answers `err` with its status, adding `details` to its body.
+fn error_response(err: ShippingError, details: Option<serde_json::Value>) -> HttpResponse {
+    HttpResponse::build(err.status()).json(ApiError {
+        details,
+        ..api_error(err.code(), err.into_message())
+    })
+}
+
+fn api_error(code: &str, message: String) -> ApiError {
+    ApiError {
+        code: code.to_string(),
+        message,
+        trace_id: current_trace_context().trace_id,
+        details: None,
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use actix_web::{http::header::ContentType, test, App};
+    use actix_web::{http::StatusCode, test, App};
 
     use super::*;
+    use crate::shipping_service::config::{
+        BreakerConfig, CarbonConfig, CarrierRates, FallbackConfig, SpeedMultipliers,
+    };
+    use crate::shipping_service::strategy::PricingStrategy;
+    use crate::shipping_service::validation::ZeroItemsPolicy;
+    use crate::test_support::{
+        in_test_span, spawn_mock, spawn_quote_mock, test_spans, CapturedLogs, TestMetrics,
+    };
+    use opentelemetry::trace::TraceId;
+    use opentelemetry_instrumentation_actix_web::RequestTracing;
+    use opentelemetry_sdk::trace::SpanData;
+    use std::collections::HashMap;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
 
     #[actix_web::test]
-    async fn test_ship_order() {
-        let app = test::init_service(App::new().service(ship_order)).await;
+    async fn test_get_quote_rejects_long_city() {
+        let config = ShippingConfig::default();
+        let address = Address {
+            city: "x".repeat(config.address_limits.city + 1),
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote),
+        )
+        .await;
         let req = test::TestRequest::post()
-            .uri("/ship-order")
-            .insert_header(ContentType::json())
-            .set_json(&ShipOrderRequest {})
+            .uri("/get-quote")
+            .set_json(GetQuoteRequest {
+                address: Some(address),
+                ..Default::default()
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let err: ApiError = test::read_body_json(resp).await;
+        assert_eq!(err.code, "invalid_address");
+        assert!(err.message.contains("address.city"));
+    }
+
+    #[actix_web::test]
+    async fn test_logs_share_the_handler_trace_id() {
+        test_spans();
+        let (logs, _guard) = CapturedLogs::install();
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .wrap(RequestTracing::new())
+                .service(get_quote),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(GetQuoteRequest {
+                items: vec![CartItem {
+                    product_id: "OLJCESPC7Z".into(),
+                    quantity: 2,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            })
             .to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
 
-        let order: ShipOrderResponse = test::read_body_json(resp).await;
-        assert!(!order.tracking_id.is_empty());
+        let requesting = &logs.named("RequestingQuote")[0];
+        let sending = &logs.named("SendingQuoteValue")[0];
+        assert_ne!(sending["trace_id"], TraceId::INVALID.to_string());
+        assert_eq!(requesting["trace_id"], sending["trace_id"]);
+        assert_eq!(requesting["span_id"], sending["span_id"]);
+    }
+
+    #[actix_web::test]
+    async fn test_get_quote_happy_path() {
+        test_spans();
+        let (logs, _guard) = CapturedLogs::install();
+        // Only a request for the cart's three items gets a price.
+        let quote_addr = spawn_mock(|cfg| {
+            cfg.route(
+                "/getquote",
+                web::post().to(|body: web::Json<serde_json::Value>| async move {
+                    if body["numberOfItems"] == 3 {
+                        HttpResponse::Ok().body("10.99")
+                    } else {
+                        HttpResponse::BadRequest().finish()
+                    }
+                }),
+            );
+        });
+        let config = ShippingConfig {
+            quote_addr,
+            fallback: no_fallback(),
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .wrap(RequestTracing::new())
+                .service(get_quote),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(GetQuoteRequest {
+                items: vec![
+                    CartItem {
+                        product_id: "OLJCESPC7Z".into(),
+                        quantity: 2,
+                        ..Default::default()
+                    },
+                    CartItem {
+                        product_id: "66VCHSJNUP".into(),
+                        quantity: 1,
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let quote: GetQuoteResponse = test::read_body_json(resp).await;
+        let cost = quote.cost_usd.unwrap();
+        assert_eq!(cost.currency_code, "USD");
+        assert_eq!(cost.units, 10);
+        assert_eq!(cost.nanos, 990_000_000);
+        assert_eq!(quote.amount_decimal.as_deref(), Some("10.99"));
+
+        let sending = logs.named("SendingQuoteValue");
+        assert_eq!(sending.len(), 1);
+        assert_ne!(sending[0]["trace_id"], TraceId::INVALID.to_string());
+        assert_eq!(sending[0]["quote.cents"], "99");
+    }
+
+    #[actix_web::test]
+    async fn test_get_quote_from_query() {
+        // Only a request for three items gets a price.
+        let quote_addr = spawn_mock(|cfg| {
+            cfg.route(
+                "/getquote",
+                web::post().to(|body: web::Json<serde_json::Value>| async move {
+                    if body["numberOfItems"] == 3 {
+                        HttpResponse::Ok().body("10.99")
+                    } else {
+                        HttpResponse::BadRequest().finish()
+                    }
+                }),
+            );
+        });
+        let config = ShippingConfig {
+            quote_addr,
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote_query),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/get-quote?items=3&currency=USD&speed=express")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let quote: GetQuoteResponse = test::read_body_json(resp).await;
+        assert_eq!(quote.amount_decimal.as_deref(), Some("10.99"));
+
+        let req = test::TestRequest::get()
+            .uri("/get-quote?speed=express")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_get_quote_from_query_is_validated_like_post() {
+        let config = ShippingConfig {
+            address_required_currencies: vec!["EUR".to_string()],
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote)
+                .service(get_quote_query),
+        )
+        .await;
+        let get = test::TestRequest::get()
+            .uri("/get-quote?items=2&currency=EUR")
+            .to_request();
+        let post = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(GetQuoteRequest {
+                items: vec![CartItem {
+                    quantity: 2,
+                    ..Default::default()
+                }],
+                currency: Some("EUR".to_string()),
+                ..Default::default()
+            })
+            .to_request();
+
+        let mut errors = Vec::new();
+        for req in [get, post] {
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+            let err: ApiError = test::read_body_json(resp).await;
+            errors.push((err.code, err.message));
+        }
+        assert_eq!(errors[0].0, "address_required");
+        assert_eq!(errors[0], errors[1]);
+    }
+
+    async fn quote_in_currency(
+        currency_addr: Option<String>,
+        currency_code: &str,
+    ) -> GetQuoteResponse {
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            currency_addr,
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(GetQuoteRequest {
+                currency: Some(currency_code.to_string()),
+                ..single_item_request()
+            })
+            .to_request();
+        test::call_and_read_body_json(&app, req).await
+    }
+
+    #[actix_web::test]
+    async fn test_usd_quote_is_passed_through() {
+        // No currency service is needed for dollars.
+        let quote = quote_in_currency(None, "USD").await;
+        let cost = quote.cost_usd.unwrap();
+        assert_eq!(cost.currency_code, "USD");
+        assert_eq!(decimal_amount(&cost), "10.99");
+    }
+
+    #[actix_web::test]
+    async fn test_quote_is_converted_to_requested_currency() {
+        let quote = quote_in_currency(Some(currency::spawn_mock(0.9)), "eur").await;
+        let cost = quote.cost_usd.unwrap();
+        assert_eq!(cost.currency_code, "EUR");
+        assert_eq!((cost.units, cost.nanos), (9, 891_000_000));
+        assert_eq!(quote.amount_decimal.as_deref(), Some("9.891"));
+    }
+
+    #[actix_web::test]
+    async fn test_get_quote_from_query_is_converted() {
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            currency_addr: Some(currency::spawn_mock(0.9)),
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote_query),
+        )
+        .await;
+        for uri in [
+            "/get-quote?items=1&currency=EUR",
+            "/get-quote?items=1&currency_code=EUR",
+        ] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            let quote: GetQuoteResponse = test::call_and_read_body_json(&app, req).await;
+            let cost = quote.cost_usd.unwrap();
+            assert_eq!(cost.currency_code, "EUR", "{uri}");
+            assert_eq!((cost.units, cost.nanos), (9, 891_000_000), "{uri}");
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_failed_conversion_falls_back_to_usd() {
+        let (logs, _guard) = CapturedLogs::install();
+        let quote = quote_in_currency(None, "EUR").await;
+        assert_eq!(quote.cost_usd.unwrap().currency_code, "USD");
+        let failed = logs.named("CurrencyConversionFailed");
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0]["currency"], "EUR");
+    }
+
+    /// Status and body of a quote in euros while the currency service is
+    /// down, with or without a stale rate of 0.9 euros to the dollar.
+    async fn quote_in_euros_during_outage(
+        mode: CurrencyFailureMode,
+        stale_rate: bool,
+    ) -> (StatusCode, serde_json::Value, SpanData) {
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            // Nothing listens on port 1, so connecting is refused at once.
+            currency_addr: Some("127.0.0.1:1".into()),
+            currency_failure_mode: mode,
+            ..Default::default()
+        };
+        let data = AppData::new(config);
+        if stale_rate {
+            data.stale_rates
+                .record(&from_minor_units(100, "USD"), &from_minor_units(90, "EUR"));
+        }
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| data.register(cfg))
+                .service(get_quote),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(GetQuoteRequest {
+                currency: Some("EUR".to_string()),
+                ..single_item_request()
+            })
+            .to_request();
+        let (resp, span) = in_test_span("get-quote", test::call_service(&app, req)).await;
+        let status = resp.status();
+        (status, test::read_body_json(resp).await, span)
+    }
+
+    #[actix_web::test]
+    async fn test_currency_failure_modes_during_outage() {
+        use CurrencyFailureMode::*;
+        for (mode, stale_rate, expected) in [
+            (Reject, true, None),
+            (Reject, false, None),
+            (UsdFallback, true, Some(("EUR", "9.891", "stale_rate"))),
+            (UsdFallback, false, Some(("USD", "10.99", "usd_fallback"))),
+            (StaleOnly, true, Some(("EUR", "9.891", "stale_rate"))),
+            (StaleOnly, false, None),
+        ] {
+            let (status, body, span) = quote_in_euros_during_outage(mode, stale_rate).await;
+            let outcome = match expected {
+                Some((currency, amount, outcome)) => {
+                    assert_eq!(status, StatusCode::OK, "{mode} {stale_rate}");
+                    assert_eq!(body["cost_usd"]["currency_code"], currency);
+                    assert_eq!(body["amount_decimal"], amount);
+                    assert_eq!(body["conversion"], outcome);
+                    outcome
+                }
+                None => {
+                    assert_eq!(
+                        status,
+                        StatusCode::SERVICE_UNAVAILABLE,
+                        "{mode} {stale_rate}"
+                    );
+                    assert_eq!(body["code"], "currency_unavailable");
+                    "rejected"
+                }
+            };
+            assert!(span.attributes.contains(&KeyValue::new(
+                "app.shipping.currency.failure_mode",
+                mode.to_string()
+            )));
+            assert!(span
+                .attributes
+                .contains(&KeyValue::new("app.shipping.currency.outcome", outcome)));
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_no_cache_bypasses_and_refreshes_the_quote_cache() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let hits = calls.clone();
+        let quote_addr = spawn_mock(move |cfg| {
+            let hits = hits.clone();
+            cfg.route(
+                "/getquote",
+                web::post().to(move || {
+                    let call = hits.fetch_add(1, Ordering::SeqCst);
+                    async move { ["10.99", "12.99"][call.min(1)] }
+                }),
+            );
+        });
+        let config = ShippingConfig {
+            quote_addr,
+            quote_cache_ttl: std::time::Duration::from_secs(60),
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote)
+                .service(get_quote_query),
+        )
+        .await;
+        let quote = |cache_control: Option<&str>| {
+            let mut req = test::TestRequest::post()
+                .uri("/get-quote")
+                .set_json(single_item_request());
+            if let Some(value) = cache_control {
+                req = req.insert_header((header::CACHE_CONTROL, value));
+            }
+            in_test_span(
+                "get-quote",
+                test::call_and_read_body_json(&app, req.to_request()),
+            )
+        };
+        let served = |resp: &GetQuoteResponse, span: &SpanData| {
+            let cache = span
+                .attributes
+                .iter()
+                .find(|kv| kv.key.as_str() == "app.shipping.quote.cache")
+                .map(|kv| kv.value.to_string());
+            (money_cents(resp.cost_usd.as_ref().unwrap()), cache.unwrap())
+        };
+
+        let (resp, span) = quote(None).await;
+        assert_eq!(served(&resp, &span), (1099, "miss".to_string()));
+        let (resp, span) = quote(None).await;
+        assert_eq!(served(&resp, &span), (1099, "hit".to_string()));
+        let (resp, span) = quote(Some("max-age=0, No-Cache")).await;
+        assert_eq!(served(&resp, &span), (1299, "bypass".to_string()));
+        let (resp, span) = quote(None).await;
+        assert_eq!(served(&resp, &span), (1299, "hit".to_string()));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        let req = test::TestRequest::get()
+            .uri("/get-quote?items=1&fresh=true")
+            .to_request();
+        let (resp, span) =
+            in_test_span("get-quote", test::call_and_read_body_json(&app, req)).await;
+        assert_eq!(served(&resp, &span), (1299, "bypass".to_string()));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    fn no_fallback() -> FallbackConfig {
+        FallbackConfig {
+            enabled: false,
+            ..Default::default()
+        }
+    }
+
+    fn speed_priced_app_config() -> ShippingConfig {
+        ShippingConfig {
+            quote_addr: spawn_quote_mock("10.00"),
+            pricing: PricingConfig {
+                speed_multipliers: SpeedMultipliers {
+                    standard: 1.0,
+                    express: 1.5,
+                    overnight: 2.5,
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_speed_multiplies_the_quote() {
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(speed_priced_app_config()).register(cfg))
+                .service(get_quote),
+        )
+        .await;
+
+        for (requested, speed, amount) in [
+            (None, ShippingSpeed::Standard, "10.00"),
+            (Some("standard"), ShippingSpeed::Standard, "10.00"),
+            (Some("express"), ShippingSpeed::Express, "15.00"),
+            (Some("Overnight"), ShippingSpeed::Overnight, "25.00"),
+        ] {
+            let mut body =
+                serde_json::json!({ "items": [{ "product_id": "OLJCESPC7Z", "quantity": 1 }] });
+            if let Some(requested) = requested {
+                body["speed"] = requested.into();
+            }
+            let req = test::TestRequest::post()
+                .uri("/get-quote")
+                .set_json(body)
+                .to_request();
+            let (quote, span): (GetQuoteResponse, _) =
+                in_test_span("get-quote", test::call_and_read_body_json(&app, req)).await;
+            assert_eq!(
+                quote.amount_decimal.as_deref(),
+                Some(amount),
+                "{requested:?}"
+            );
+            assert_eq!(quote.speed, speed);
+            assert!(span
+                .attributes
+                .contains(&KeyValue::new("app.shipping.speed", speed.as_str())));
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_unknown_speed_is_rejected() {
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(speed_priced_app_config()).register(cfg))
+                .service(get_quote)
+                .service(get_quote_query),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(serde_json::json!({
+                "items": [{ "product_id": "OLJCESPC7Z", "quantity": 1 }],
+                "speed": "teleport",
+            }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let err: ApiError = test::read_body_json(resp).await;
+        assert_eq!(err.code, "invalid_speed");
+        assert!(err.message.contains("\"teleport\""), "{}", err.message);
+
+        let req = test::TestRequest::get()
+            .uri("/get-quote?items=1&speed=teleport")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let err: ApiError = test::read_body_json(resp).await;
+        assert_eq!(err.code, "invalid_speed");
+    }
+
+    fn single_item_request() -> GetQuoteRequest {
+        GetQuoteRequest {
+            items: vec![CartItem {
+                product_id: "OLJCESPC7Z".into(),
+                quantity: 1,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    /// Cost and breakdown of a quote for `items` from a quote service
+    /// charging a dollar per item, at a dollar per kilogram.
+    async fn quote_per_item_and_kg(items: Vec<CartItem>) -> (Money, Vec<QuoteLine>) {
+        let config = ShippingConfig {
+            quote_addr: spawn_mock(|cfg| {
+                cfg.route(
+                    "/getquote",
+                    web::post().to(|body: web::Json<serde_json::Value>| async move {
+                        format!("{}.00", body["numberOfItems"])
+                    }),
+                );
+            }),
+            pricing: PricingConfig {
+                per_kg_rate: 1.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(GetQuoteRequest {
+                items,
+                ..Default::default()
+            })
+            .to_request();
+        let quote: GetQuoteResponse = test::call_and_read_body_json(&app, req).await;
+        (quote.cost_usd.unwrap(), quote.breakdown)
+    }
+
+    fn weighed_item(product_id: &str, quantity: u32, free_shipping: bool) -> CartItem {
+        CartItem {
+            product_id: product_id.into(),
+            quantity,
+            weight_kg: Some(1.5),
+            free_shipping: Some(free_shipping),
+            ..Default::default()
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_free_items_are_not_billed() {
+        let (cost, breakdown) = quote_per_item_and_kg(vec![
+            weighed_item("OLJCESPC7Z", 2, false),
+            weighed_item("66VCHSJNUP", 3, true),
+        ])
+        .await;
+        // Two billed items at a dollar each, plus their 3 kg.
+        assert_eq!((cost.units, cost.nanos), (5, 0));
+        let weight = breakdown
+            .iter()
+            .find(|line| line.label == "Weight charge")
+            .unwrap();
+        assert_eq!((weight.amount.units, weight.amount.nanos), (3, 0));
+    }
+
+    #[actix_web::test]
+    async fn test_all_free_order_is_quoted_zero() {
+        let (cost, breakdown) = quote_per_item_and_kg(vec![
+            weighed_item("OLJCESPC7Z", 2, true),
+            weighed_item("66VCHSJNUP", 3, true),
+        ])
+        .await;
+        assert_eq!((cost.units, cost.nanos), (0, 0));
+        assert!(breakdown.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_large_carts_record_their_exact_item_count() {
+        let metrics = TestMetrics::install();
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            max_item_count: u32::MAX,
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote),
+        )
+        .await;
+        // Past i32::MAX, where a cast to a signed 32-bit value would wrap.
+        let quantity = 3_000_000_000;
+        let req = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(GetQuoteRequest {
+                items: vec![CartItem {
+                    product_id: "OLJCESPC7Z".into(),
+                    quantity,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            })
+            .to_request();
+        let (resp, span) = in_test_span("get-quote", test::call_service(&app, req)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        assert_eq!(
+            metrics.counter("app.shipping.items_count", &[]),
+            u64::from(quantity)
+        );
+        let received = span
+            .events
+            .iter()
+            .find(|event| event.name == "Received Quote")
+            .unwrap();
+        assert!(received.attributes.contains(&KeyValue::new(
+            "app.shipping.items.count",
+            i64::from(quantity)
+        )));
+    }
+
+    #[actix_web::test]
+    async fn test_quote_durations_are_recorded_by_outcome() {
+        let metrics = TestMetrics::install();
+        for body in ["10.99", "not a number"] {
+            let config = ShippingConfig {
+                quote_addr: spawn_quote_mock(body),
+                fallback: no_fallback(),
+                ..Default::default()
+            };
+            let app = test::init_service(
+                App::new()
+                    .configure(|cfg| AppData::new(config).register(cfg))
+                    .service(get_quote),
+            )
+            .await;
+            let req = test::TestRequest::post()
+                .uri("/get-quote")
+                .set_json(single_item_request())
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+
+        for name in [
+            "app.shipping.quote.duration_ms",
+            "app.shipping.quote.upstream_duration_ms",
+        ] {
+            for outcome in ["success", "error"] {
+                assert_eq!(
+                    metrics.histogram_count(name, &[KeyValue::new("outcome", outcome)]),
+                    1,
+                    "{name} {outcome}"
+                );
+            }
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_get_quote_upstream_failure_returns_500() {
+        test_spans();
+        let quote_addr = spawn_mock(|cfg| {
+            cfg.route(
+                "/getquote",
+                web::post().to(|| async { HttpResponse::InternalServerError().body("oops") }),
+            );
+        });
+        let config = ShippingConfig {
+            quote_addr,
+            fallback: no_fallback(),
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .wrap(RequestTracing::new())
+                .service(get_quote),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(single_item_request())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        let fields: Vec<&str> = body
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        assert_eq!(fields, ["code", "message", "trace_id"]);
+        assert_eq!(body["code"], "quote_failed");
+        assert!(body["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("Failed to get quote"));
+        assert_ne!(body["trace_id"], TraceId::INVALID.to_string());
+    }
+
+    #[actix_web::test]
+    async fn test_server_timing_header_reports_upstream_quote() {
+        let quote_addr = spawn_mock(|cfg| {
+            cfg.route(
+                "/getquote",
+                web::post().to(|| async {
+                    actix_web::rt::time::sleep(std::time::Duration::from_millis(50)).await;
+                    "10.99"
+                }),
+            );
+        });
+        let config = ShippingConfig {
+            quote_addr,
+            server_timing_enabled: true,
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(single_item_request())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let header = resp
+            .headers()
+            .get("Server-Timing")
+            .unwrap()
+            .to_str()
+            .unwrap();
+        let durations: HashMap<&str, f64> = header
+            .split(", ")
+            .filter_map(|metric| metric.split_once(";dur="))
+            .map(|(name, dur)| (name, dur.parse().unwrap()))
+            .collect();
+        let quote_ms = durations["quote"];
+        assert!(
+            (50.0..5000.0).contains(&quote_ms),
+            "quote took {quote_ms}ms"
+        );
+        assert!(durations.contains_key("serialize"));
+        assert!(durations["total"] >= quote_ms);
+    }
+
+    #[actix_web::test]
+    async fn test_server_timing_header_is_opt_in() {
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(single_item_request())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        assert!(!resp.headers().contains_key("Server-Timing"));
+    }
+
+    #[actix_web::test]
+    async fn test_open_breaker_returns_503_with_details() {
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("not a number"),
+            breaker: BreakerConfig {
+                failure_threshold: 1,
+                open_for: std::time::Duration::from_secs(60),
+                ..Default::default()
+            },
+            fallback: no_fallback(),
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote),
+        )
+        .await;
+        let quote_request = || {
+            test::TestRequest::post()
+                .uri("/get-quote")
+                .set_json(single_item_request())
+                .to_request()
+        };
+
+        let resp = test::call_service(&app, quote_request()).await;
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let resp = test::call_service(&app, quote_request()).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let err: ApiError = test::read_body_json(resp).await;
+        assert_eq!(err.code, "quote_service_unavailable");
+        let details = err.details.unwrap();
+        assert_eq!(details["state"], "open");
+        assert_eq!(details["consecutive_failures"], 1);
+        let cooldown = details["cooldown_remaining_ms"].as_u64().unwrap();
+        assert!(cooldown > 0 && cooldown <= 60_000);
+    }
+
+    #[actix_web::test]
+    async fn test_reused_quote_keeps_original_quoted_at() {
+        let quoted_at = Utc::now() - chrono::Duration::seconds(30);
+        let quote = ShippingQuote {
+            total_cents: 1099,
+            charges: vec![],
+            currency: "USD".into(),
+            source: QuoteSource::QuoteService,
+            confidence: QuoteConfidence::Exact,
+            quoted_at,
+        };
+
+        let first = quote_response(&quote, ShippingSpeed::Standard, Utc::now());
+        let second = quote_response(&quote, ShippingSpeed::Standard, Utc::now());
+
+        assert_eq!(first.quoted_at, quoted_at);
+        assert_eq!(second.quoted_at, quoted_at);
+        assert!(first.served_at > quoted_at);
+        assert!(second.served_at >= first.served_at);
+    }
+
+    #[actix_web::test]
+    async fn test_quote_money_keeps_every_cent() {
+        let quote = ShippingQuote {
+            total_cents: 123_456,
+            charges: vec![],
+            currency: "EUR".into(),
+            source: QuoteSource::QuoteService,
+            confidence: QuoteConfidence::Exact,
+            quoted_at: Utc::now(),
+        };
+
+        let money = quote_money(&quote);
+        assert_eq!(money.currency_code, "EUR");
+        assert_eq!(money.units, 1234);
+        assert_eq!(money.nanos, 560_000_000);
+        assert_eq!(Quote::from(&quote).to_string(), "1234.56");
+    }
+
+    #[actix_web::test]
+    async fn test_quote_money_uses_the_currency_exponent() {
+        let quote_in = |currency: &str| ShippingQuote {
+            total_cents: 123_456,
+            charges: vec![],
+            currency: currency.into(),
+            source: QuoteSource::QuoteService,
+            confidence: QuoteConfidence::Exact,
+            quoted_at: Utc::now(),
+        };
+
+        let yen = quote_money(&quote_in("JPY"));
+        assert_eq!((yen.units, yen.nanos), (123_456, 0));
+        let dinars = quote_money(&quote_in("KWD"));
+        assert_eq!((dinars.units, dinars.nanos), (123, 456_000_000));
+    }
+
+    #[actix_web::test]
+    async fn test_record_address_truncates_long_fields() {
+        let (logs, _guard) = CapturedLogs::install();
+        let address = Address {
+            city: "y".repeat(100),
+            ..Default::default()
+        };
+        assert!(validate_address(&address, &Default::default()).is_ok());
+
+        record_address(&address, InstrumentationLevel::Standard);
+
+        let events = logs.named("QuoteDestination");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["address.city"], format!("{}...", "y".repeat(64)));
+    }
+
+    #[actix_web::test]
+    async fn test_ship_order() {
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(ship_order),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/ship-order")
+            .insert_header(ContentType::json())
+            .set_json(ShipOrderRequest::default())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let order: ShipOrderResponse = test::read_body_json(resp).await;
+        assert!(!order.order_id.is_empty());
+        assert_eq!(order.package_tracking_ids, vec![order.tracking_id]);
+    }
+
+    #[actix_web::test]
+    async fn test_ship_order_links_to_status_and_receipt() {
+        let config = ShippingConfig {
+            public_base_url: Some("https://shop.example/api/shipping/".into()),
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(ship_order),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/ship-order")
+            .set_json(ShipOrderRequest::default())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let link = resp
+            .headers()
+            .get(header::LINK)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        let order: ShipOrderResponse = test::read_body_json(resp).await;
+        let base = format!("https://shop.example/api/shipping/order/{}", order.order_id);
+        assert_eq!(
+            link,
+            format!(r#"<{base}>; rel="status", <{base}/receipt>; rel="receipt""#)
+        );
+    }
+
+    /// Statuses of shipping `orders` orders with the token of one quote.
+    async fn ship_with_one_token(max_uses: u32, orders: usize) -> Vec<StatusCode> {
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            quote_token_max_uses: max_uses,
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote)
+                .service(ship_order),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(single_item_request())
+            .to_request();
+        let quote: GetQuoteResponse = test::call_and_read_body_json(&app, req).await;
+
+        let mut statuses = Vec::new();
+        for _ in 0..orders {
+            let req = test::TestRequest::post()
+                .uri("/ship-order")
+                .set_json(ShipOrderRequest {
+                    items: single_item_request().items,
+                    quote_token: quote.quote_token.clone(),
+                    ..Default::default()
+                })
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            statuses.push(resp.status());
+            if resp.status() == StatusCode::CONFLICT {
+                let err: ApiError = test::read_body_json(resp).await;
+                assert_eq!(err.code, "quote_token_exhausted");
+            }
+        }
+        statuses
+    }
+
+    #[actix_web::test]
+    async fn test_quote_token_is_single_use_by_default() {
+        assert_eq!(
+            ship_with_one_token(1, 2).await,
+            [StatusCode::OK, StatusCode::CONFLICT]
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_quote_token_binds_items_and_survives_rejected_orders() {
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            max_items_in_request: 1,
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote)
+                .service(ship_order),
+        )
+        .await;
+        let item = |quantity| CartItem {
+            product_id: "OLJCESPC7Z".into(),
+            quantity,
+            ..Default::default()
+        };
+        let req = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(GetQuoteRequest {
+                items: vec![item(2)],
+                ..Default::default()
+            })
+            .to_request();
+        let quote: GetQuoteResponse = test::call_and_read_body_json(&app, req).await;
+        let ship = |items: Vec<CartItem>| {
+            test::TestRequest::post()
+                .uri("/ship-order")
+                .set_json(ShipOrderRequest {
+                    items,
+                    quote_token: quote.quote_token.clone(),
+                    ..Default::default()
+                })
+                .to_request()
+        };
+
+        let resp = test::call_service(&app, ship(vec![item(5)])).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        let err: ApiError = test::read_body_json(resp).await;
+        assert_eq!(err.code, "quote_token_mismatch");
+
+        let resp = test::call_service(&app, ship(vec![item(1), item(1)])).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let err: ApiError = test::read_body_json(resp).await;
+        assert_eq!(err.code, "too_many_items");
+
+        let resp = test::call_service(&app, ship(vec![item(2)])).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_quote_token_allows_configured_uses() {
+        assert_eq!(
+            ship_with_one_token(2, 3).await,
+            [StatusCode::OK, StatusCode::OK, StatusCode::CONFLICT]
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_concurrent_ship_orders_with_one_key_coalesce() {
+        let config = ShippingConfig {
+            quote_addr: spawn_mock(|cfg| {
+                cfg.route(
+                    "/getquote",
+                    web::post().to(|| async {
+                        actix_web::rt::time::sleep(std::time::Duration::from_millis(100)).await;
+                        "10.99"
+                    }),
+                );
+            }),
+            ..Default::default()
+        };
+        let data = AppData::new(config);
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| data.register(cfg))
+                .service(ship_order),
+        )
+        .await;
+        let request = || {
+            test::TestRequest::post()
+                .uri("/ship-order")
+                .insert_header(("Idempotency-Key", "checkout-42"))
+                .set_json(ShipOrderRequest {
+                    items: single_item_request().items,
+                    ..Default::default()
+                })
+                .to_request()
+        };
+
+        let (first, second) = tokio::join!(
+            test::call_service(&app, request()),
+            test::call_service(&app, request())
+        );
+        let first: ShipOrderResponse = test::read_body_json(first).await;
+        let second: ShipOrderResponse = test::read_body_json(second).await;
+        assert_eq!(first.order_id, second.order_id);
+        assert_eq!(first.package_tracking_ids, second.package_tracking_ids);
+        assert_eq!(data.orders.order_count(), 1);
+    }
+
+    #[actix_web::test]
+    async fn test_body_idempotency_key_replays_the_tracking_id() {
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            ..Default::default()
+        };
+        let data = AppData::new(config);
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| data.register(cfg))
+                .service(ship_order),
+        )
+        .await;
+        let ship = |key: &str| {
+            let req = test::TestRequest::post()
+                .uri("/ship-order")
+                .set_json(ShipOrderRequest {
+                    items: single_item_request().items,
+                    idempotency_key: Some(key.to_string()),
+                    ..Default::default()
+                })
+                .to_request();
+            in_test_span("ship-order", test::call_and_read_body_json(&app, req))
+        };
+        let replays = |span: &SpanData| {
+            span.events
+                .iter()
+                .filter(|event| event.name == "Idempotent Replay")
+                .count()
+        };
+
+        let (first, span): (ShipOrderResponse, _) = ship("checkout-1").await;
+        assert_eq!(replays(&span), 0);
+        let (again, span): (ShipOrderResponse, _) = ship("checkout-1").await;
+        assert_eq!(replays(&span), 1);
+        assert_eq!(again.tracking_id, first.tracking_id);
+        assert_eq!(data.orders.order_count(), 1);
+
+        let (other, _): (ShipOrderResponse, _) = ship("checkout-2").await;
+        assert_ne!(other.tracking_id, first.tracking_id);
+        assert_eq!(data.orders.order_count(), 2);
+    }
+
+    #[actix_web::test]
+    async fn test_idempotent_replay_survives_restart() {
+        let journal =
+            std::env::temp_dir().join(format!("shipping-idempotency-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&journal);
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            idempotency_store_file: Some(journal.clone()),
+            ..Default::default()
+        };
+        let ship = |data: AppData| async move {
+            let app = test::init_service(
+                App::new()
+                    .configure(|cfg| data.register(cfg))
+                    .service(ship_order),
+            )
+            .await;
+            let req = test::TestRequest::post()
+                .uri("/ship-order")
+                .insert_header(("Idempotency-Key", "checkout-42"))
+                .set_json(ShipOrderRequest {
+                    items: single_item_request().items,
+                    ..Default::default()
+                })
+                .to_request();
+            let resp: ShipOrderResponse = test::call_and_read_body_json(&app, req).await;
+            (resp, data.orders.order_count())
+        };
+
+        let (original, _) = ship(AppData::new(config.clone())).await;
+        let (replayed, created) = ship(AppData::new(config)).await;
+        std::fs::remove_file(&journal).unwrap();
+        assert_eq!(replayed.order_id, original.order_id);
+        assert_eq!(replayed.package_tracking_ids, original.package_tracking_ids);
+        assert_eq!(created, 0);
+    }
+
+    #[actix_web::test]
+    async fn test_order_status_aggregates_its_packages() {
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(ship_order)
+                .service(get_order)
+                .service(update_package_status),
+        )
+        .await;
+        let package = |product_id: &str| PackageRequest {
+            items: vec![CartItem {
+                product_id: product_id.into(),
+                quantity: 1,
+                ..Default::default()
+            }],
+        };
+        let req = test::TestRequest::post()
+            .uri("/ship-order")
+            .set_json(ShipOrderRequest {
+                packages: vec![package("OLJCESPC7Z"), package("66VCHSJNUP")],
+                ..Default::default()
+            })
+            .to_request();
+        let shipped: ShipOrderResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(shipped.package_tracking_ids.len(), 2);
+
+        let get_order_req = || {
+            test::TestRequest::get()
+                .uri(&format!("/order/{}", shipped.order_id))
+                .to_request()
+        };
+        let order: OrderResponse = test::call_and_read_body_json(&app, get_order_req()).await;
+        assert_eq!(order.status, DeliveryStatus::InTransit);
+        assert_eq!(order.packages[1].items[0].product_id, "66VCHSJNUP");
+
+        let mut statuses = vec![];
+        for tracking_id in &shipped.package_tracking_ids {
+            let req = test::TestRequest::put()
+                .uri(&format!("/package/{}/status", tracking_id))
+                .set_json(PackageStatusUpdate {
+                    status: DeliveryStatus::Delivered,
+                })
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert!(resp.status().is_success());
+
+            let order: OrderResponse = test::call_and_read_body_json(&app, get_order_req()).await;
+            statuses.push(order.status);
+        }
+        assert_eq!(
+            statuses,
+            vec![DeliveryStatus::InTransit, DeliveryStatus::Delivered]
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_receipt_contains_stored_quote_and_items() {
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(ship_order)
+                .service(get_receipt),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/ship-order")
+            .set_json(ShipOrderRequest {
+                items: vec![
+                    CartItem {
+                        product_id: "OLJCESPC7Z".into(),
+                        quantity: 2,
+                        ..Default::default()
+                    },
+                    CartItem {
+                        product_id: "66VCHSJNUP".into(),
+                        quantity: 1,
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            })
+            .to_request();
+        let order: ShipOrderResponse = test::call_and_read_body_json(&app, req).await;
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/order/{}/receipt", order.order_id))
+            .to_request();
+        let receipt: Receipt = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(receipt.order_id, order.order_id);
+        assert_eq!(receipt.tracking_ids, vec![order.tracking_id.clone()]);
+        assert_eq!(receipt.items.len(), 2);
+        assert_eq!(receipt.items[0].product_id, "OLJCESPC7Z");
+        let total = receipt.total.unwrap();
+        assert_eq!((total.units, total.nanos), (10, 990_000_000));
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/order/{}/receipt", order.tracking_id))
+            .insert_header((header::ACCEPT, "text/plain"))
+            .to_request();
+        let text = test::call_and_read_body(&app, req).await;
+        let text = std::str::from_utf8(&text).unwrap();
+        assert!(text.contains("2 x OLJCESPC7Z"));
+        assert!(text.contains("Total: 10.99 USD"));
+    }
+
+    fn hazmat_quote_request(speed: ShippingSpeed) -> GetQuoteRequest {
+        GetQuoteRequest {
+            items: vec![CartItem {
+                product_id: "HQTGWGPNH4".into(),
+                quantity: 1,
+                hazmat: Some(true),
+                ..Default::default()
+            }],
+            speed: speed.into(),
+            ..Default::default()
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_hazmat_surcharge_is_applied() {
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            pricing: PricingConfig {
+                hazmat_surcharge: 15.5,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(hazmat_quote_request(ShippingSpeed::Express))
+            .to_request();
+
+        let (resp, span) = in_test_span("get-quote", test::call_service(&app, req)).await;
+        assert!(resp.status().is_success());
+        let quote: GetQuoteResponse = test::read_body_json(resp).await;
+        let cost = quote.cost_usd.unwrap();
+        assert_eq!((cost.units, cost.nanos), (26, 490_000_000));
+        assert!(span
+            .attributes
+            .contains(&KeyValue::new("app.shipping.hazmat", true)));
+    }
+
+    #[actix_web::test]
+    async fn test_country_surcharge_is_added_to_international_quotes() {
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            origin_country: "US".to_string(),
+            pricing: PricingConfig {
+                country_surcharges: [("CA".to_string(), 7.5)].into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote),
+        )
+        .await;
+
+        for (country, surcharge, total) in [
+            ("US", 0.0, (10, 990_000_000)),
+            ("CA", 7.5, (18, 490_000_000)),
+            ("DE", 0.0, (10, 990_000_000)),
+        ] {
+            let req = test::TestRequest::post()
+                .uri("/get-quote")
+                .set_json(GetQuoteRequest {
+                    address: Some(Address {
+                        country: country.to_string(),
+                        ..Default::default()
+                    }),
+                    items: vec![CartItem {
+                        quantity: 1,
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                })
+                .to_request();
+            let (resp, span) = in_test_span("get-quote", test::call_service(&app, req)).await;
+            assert!(resp.status().is_success(), "{country}");
+            let quote: GetQuoteResponse = test::read_body_json(resp).await;
+            let cost = quote.cost_usd.unwrap();
+            assert_eq!((cost.units, cost.nanos), total, "{country}");
+            assert!(
+                span.attributes
+                    .contains(&KeyValue::new("app.shipping.surcharge.country", surcharge)),
+                "{country}"
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_compare_carriers_prices_each_rate_table() {
+        let carrier = |name: &str, per_item_rate, hazmat_surcharge, transit_days| CarrierRates {
+            name: name.into(),
+            strategy: PricingStrategy::PerItem,
+            per_item_rate,
+            sku_rates: Default::default(),
+            hazmat_surcharge,
+            transit_days,
+        };
+        let config = ShippingConfig {
+            pricing: PricingConfig {
+                carriers: vec![
+                    carrier("Ground Co", 4.0, None, 5),
+                    carrier("Air Co", 2.5, Some(10.0), 2),
+                ],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(compare_carriers),
+        )
+        .await;
+        let mut request = hazmat_quote_request(ShippingSpeed::Express);
+        request.items[0].quantity = 3;
+        let req = test::TestRequest::post()
+            .uri("/compare-carriers")
+            .set_json(request)
+            .to_request();
+
+        let resp: CompareCarriersResponse = test::call_and_read_body_json(&app, req).await;
+        let totals: Vec<_> = resp
+            .carriers
+            .iter()
+            .map(|quote| {
+                let cost = quote.quote.cost_usd.as_ref().unwrap();
+                (quote.carrier.as_str(), cost.units, cost.nanos)
+            })
+            .collect();
+        assert_eq!(totals, [("Ground Co", 37, 0), ("Air Co", 17, 500_000_000)]);
+
+        let [ground, air] = &resp.carriers[..] else {
+            panic!("expected two carriers");
+        };
+        assert_eq!(
+            ground.estimated_delivery - air.estimated_delivery,
+            Duration::days(3)
+        );
+        assert_eq!(air.quote.breakdown[1].label, "Hazmat surcharge");
+    }
+
+    async fn compare_with_unpriceable_item(
+        partial_quote_allowed: bool,
+    ) -> actix_web::dev::ServiceResponse {
+        let config = ShippingConfig {
+            partial_quote_allowed,
+            pricing: PricingConfig {
+                carriers: vec![CarrierRates {
+                    name: "Sku Co".into(),
+                    strategy: PricingStrategy::PerSku,
+                    per_item_rate: 0.0,
+                    sku_rates: [("OLJCESPC7Z".to_string(), 2.0)].into(),
+                    hazmat_surcharge: None,
+                    transit_days: 3,
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(compare_carriers),
+        )
+        .await;
+        let item = |product_id: &str| CartItem {
+            product_id: product_id.into(),
+            quantity: 2,
+            ..Default::default()
+        };
+        let req = test::TestRequest::post()
+            .uri("/compare-carriers")
+            .set_json(GetQuoteRequest {
+                items: vec![item("OLJCESPC7Z"), item("UNKNOWNSKU")],
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await
+    }
+
+    #[actix_web::test]
+    async fn test_partial_quote_lists_unpriceable_items() {
+        let (resp, span) =
+            in_test_span("compare-carriers", compare_with_unpriceable_item(true)).await;
+        assert!(resp.status().is_success());
+        let resp: CompareCarriersResponse = test::read_body_json(resp).await;
+        let quote = &resp.carriers[0];
+        let cost = quote.quote.cost_usd.as_ref().unwrap();
+        assert_eq!((cost.units, cost.nanos), (4, 0));
+        assert_eq!(quote.unpriceable_items, ["UNKNOWNSKU"]);
+        assert!(span
+            .attributes
+            .contains(&KeyValue::new("app.shipping.unpriceable_count", 1)));
+    }
+
+    #[actix_web::test]
+    async fn test_unpriceable_items_fail_the_request_by_default() {
+        let resp = compare_with_unpriceable_item(false).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let err: ApiError = test::read_body_json(resp).await;
+        assert_eq!(err.code, "unpriceable_items");
+        assert_eq!(
+            err.details.unwrap()["unpriceable_items"],
+            serde_json::json!(["UNKNOWNSKU"])
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_zero_items_policies() {
+        for policy in [ZeroItemsPolicy::ZeroQuote, ZeroItemsPolicy::Reject] {
+            // The default quote address is unreachable, so only a request that
+            // skips the quote service can succeed.
+            let config = ShippingConfig {
+                zero_items_policy: policy,
+                ..Default::default()
+            };
+            let app = test::init_service(
+                App::new()
+                    .configure(|cfg| AppData::new(config).register(cfg))
+                    .service(get_quote),
+            )
+            .await;
+            let req = test::TestRequest::post()
+                .uri("/get-quote")
+                .set_json(GetQuoteRequest::default())
+                .to_request();
+
+            let (resp, span) = in_test_span("get-quote", test::call_service(&app, req)).await;
+            assert!(span.attributes.contains(&KeyValue::new(
+                "app.shipping.zero_items.policy",
+                policy.to_string()
+            )));
+            match policy {
+                ZeroItemsPolicy::ZeroQuote => {
+                    assert!(resp.status().is_success());
+                    let quote: GetQuoteResponse = test::read_body_json(resp).await;
+                    let cost = quote.cost_usd.unwrap();
+                    assert_eq!((cost.units, cost.nanos), (0, 0));
+                }
+                ZeroItemsPolicy::Reject => {
+                    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+                    let err: ApiError = test::read_body_json(resp).await;
+                    assert_eq!(err.code, "no_items");
+                }
+            }
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_carbon_estimate_is_opt_in() {
+        for enabled in [false, true] {
+            let config = ShippingConfig {
+                quote_addr: spawn_quote_mock("10.99"),
+                carbon: CarbonConfig {
+                    enabled,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let app = test::init_service(
+                App::new()
+                    .configure(|cfg| AppData::new(config).register(cfg))
+                    .service(get_quote),
+            )
+            .await;
+            let req = test::TestRequest::post()
+                .uri("/get-quote")
+                .set_json(GetQuoteRequest {
+                    speed: ShippingSpeed::Overnight.into(),
+                    ..single_item_request()
+                })
+                .to_request();
+
+            let (resp, span) = in_test_span("get-quote", test::call_service(&app, req)).await;
+            let quote: GetQuoteResponse = test::read_body_json(resp).await;
+            // One unweighed item of 0.5 kg flown 800 km at 0.8 kg per tonne-km.
+            let expected = enabled.then_some(0.32);
+            assert_eq!(quote.estimated_co2_kg, expected);
+            assert_eq!(
+                span.attributes
+                    .iter()
+                    .find(|kv| kv.key.as_str() == "app.shipping.carbon.co2_kg")
+                    .map(|kv| kv.value.clone()),
+                expected.map(Value::from)
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_item_count_is_bounded_before_quoting() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let hits = calls.clone();
+        let quote_addr = spawn_mock(move |cfg| {
+            let hits = hits.clone();
+            cfg.route(
+                "/getquote",
+                web::post().to(move || {
+                    hits.fetch_add(1, Ordering::SeqCst);
+                    async { "10.99" }
+                }),
+            );
+        });
+        let config = ShippingConfig {
+            quote_addr,
+            max_item_count: 100,
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote),
+        )
+        .await;
+        let quote = |quantities: &[u32]| {
+            test::TestRequest::post()
+                .uri("/get-quote")
+                .set_json(GetQuoteRequest {
+                    items: quantities
+                        .iter()
+                        .map(|&quantity| CartItem {
+                            quantity,
+                            ..Default::default()
+                        })
+                        .collect(),
+                    ..Default::default()
+                })
+                .to_request()
+        };
+
+        let resp = test::call_service(&app, quote(&[60, 40])).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let (resp, span) =
+            in_test_span("get-quote", test::call_service(&app, quote(&[60, 41]))).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let err: ApiError = test::read_body_json(resp).await;
+        assert_eq!(err.code, "too_many_items");
+        assert_eq!(
+            err.message,
+            "request has 101 items in total, the maximum is 100"
+        );
+        let event = span
+            .events
+            .iter()
+            .find(|event| event.name == "Item Count Rejected")
+            .unwrap();
+        assert!(event
+            .attributes
+            .contains(&KeyValue::new("app.shipping.items.count", 101)));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[actix_web::test]
+    async fn test_market_rules_require_address_by_currency() {
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            address_required_currencies: vec!["EUR".into()],
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote),
+        )
+        .await;
+        let quote_in = |currency: &str| {
+            test::TestRequest::post()
+                .uri("/get-quote")
+                .set_json(GetQuoteRequest {
+                    currency: Some(currency.into()),
+                    ..single_item_request()
+                })
+                .to_request()
+        };
+
+        let (resp, span) =
+            in_test_span("get-quote", test::call_service(&app, quote_in("EUR"))).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let err: ApiError = test::read_body_json(resp).await;
+        assert_eq!(err.code, "address_required");
+        assert!(err.message.contains("EUR"));
+        assert!(span.attributes.contains(&KeyValue::new(
+            "app.shipping.market_rule",
+            "address_required:EUR"
+        )));
+
+        let resp = test::call_service(&app, quote_in("USD")).await;
+        assert!(resp.status().is_success());
+
+        // The currency to convert to is the one the rules are chosen by.
+        let req = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(serde_json::json!({
+                "items": [{ "quantity": 1 }],
+                "currency_code": "EUR",
+            }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let err: ApiError = test::read_body_json(resp).await;
+        assert_eq!(err.code, "address_required");
+    }
+
+    #[actix_web::test]
+    async fn test_hazmat_overnight_is_rejected() {
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(ShippingConfig::default()).register(cfg))
+                .service(get_quote),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(hazmat_quote_request(ShippingSpeed::Overnight))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let err: ApiError = test::read_body_json(resp).await;
+        assert_eq!(err.code, "hazmat_speed_unavailable");
+    }
+
+    #[actix_web::test]
+    async fn test_weight_charge_uses_billed_weight() {
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            weight_billing_increment_kg: Some(0.5),
+            pricing: PricingConfig {
+                per_kg_rate: 2.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(GetQuoteRequest {
+                items: vec![CartItem {
+                    product_id: "OLJCESPC7Z".into(),
+                    quantity: 1,
+                    weight_kg: Some(1.2),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            })
+            .to_request();
+
+        let (resp, span) = in_test_span("get-quote", test::call_service(&app, req)).await;
+        let quote: GetQuoteResponse = test::read_body_json(resp).await;
+        let cost = quote.cost_usd.unwrap();
+        assert_eq!((cost.units, cost.nanos), (13, 990_000_000));
+        assert!(span
+            .attributes
+            .contains(&KeyValue::new("app.shipping.weight.actual_kg", 1.2)));
+        assert!(span
+            .attributes
+            .contains(&KeyValue::new("app.shipping.weight.billed_kg", 1.5)));
+    }
+
+    #[actix_web::test]
+    async fn test_weights_that_arent_positive_are_rejected() {
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(ShippingConfig::default()).register(cfg))
+                .service(get_quote),
+        )
+        .await;
+        for kg in [0.0, -5.0] {
+            let req = test::TestRequest::post()
+                .uri("/get-quote")
+                .set_json(GetQuoteRequest {
+                    items: vec![CartItem {
+                        product_id: "OLJCESPC7Z".into(),
+                        quantity: 1,
+                        weight_kg: Some(kg),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                })
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+            let err: ApiError = test::read_body_json(resp).await;
+            assert_eq!(err.code, "invalid_weight");
+        }
+    }
+
+    async fn quote_item(config: ShippingConfig, item: CartItem) -> (GetQuoteResponse, SpanData) {
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(GetQuoteRequest {
+                items: vec![item],
+                ..Default::default()
+            })
+            .to_request();
+
+        let (resp, span) = in_test_span("get-quote", test::call_service(&app, req)).await;
+        assert!(resp.status().is_success());
+        (test::read_body_json(resp).await, span)
+    }
+
+    #[actix_web::test]
+    async fn test_oversized_item_is_quoted_as_freight() {
+        // No quote service is running: freight never asks it.
+        let (quote, span) = quote_item(
+            ShippingConfig::default(),
+            CartItem {
+                product_id: "OLJCESPC7Z".into(),
+                quantity: 1,
+                weight_kg: Some(39.2),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert_eq!(
+            quote_lines_of(&quote),
+            [("Shipping", 129, 0), ("Freight surcharge", 35, 0)]
+        );
+        let freight = quote.freight.unwrap();
+        assert_eq!(freight.transit_days, 7);
+        assert_eq!(
+            freight.estimated_delivery - quote.quoted_at,
+            Duration::days(7)
+        );
+        assert!(span
+            .attributes
+            .contains(&KeyValue::new("app.shipping.mode", "freight")));
+    }
+
+    #[actix_web::test]
+    async fn test_normal_item_is_quoted_as_parcel() {
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            ..Default::default()
+        };
+        let (quote, span) = quote_item(
+            config,
+            CartItem {
+                product_id: "OLJCESPC7Z".into(),
+                quantity: 1,
+                weight_kg: Some(2.0),
+                dimensions_cm: Some([40.0, 30.0, 20.0]),
+                ..Default::default()
+            },
+        )
+        .await;
+        let cost = quote.cost_usd.unwrap();
+        assert_eq!((cost.units, cost.nanos), (10, 990_000_000));
+        assert!(quote.freight.is_none());
+        assert!(span
+            .attributes
+            .contains(&KeyValue::new("app.shipping.mode", "parcel")));
+    }
+
+    async fn quote_with_pricing(pricing: PricingConfig) -> (GetQuoteResponse, SpanData) {
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            pricing,
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(single_item_request())
+            .to_request();
+
+        let (resp, span) = in_test_span("get-quote", test::call_service(&app, req)).await;
+        assert!(resp.status().is_success());
+        (test::read_body_json(resp).await, span)
+    }
+
+    fn quote_lines_of(quote: &GetQuoteResponse) -> Vec<(&str, u64, u32)> {
+        quote
+            .breakdown
+            .iter()
+            .map(|line| (line.label.as_str(), line.amount.units, line.amount.nanos))
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn test_handling_fee_is_its_own_line() {
+        let (quote, span) = quote_with_pricing(PricingConfig {
+            handling_fee: 2.5,
+            ..Default::default()
+        })
+        .await;
+        let cost = quote.cost_usd.as_ref().unwrap();
+        assert_eq!((cost.units, cost.nanos), (13, 490_000_000));
+        assert_eq!(
+            quote_lines_of(&quote),
+            [
+                ("Shipping", 10, 990_000_000),
+                ("Handling fee", 2, 500_000_000)
+            ]
+        );
+        assert!(span
+            .attributes
+            .contains(&KeyValue::new("app.shipping.handling_fee", 2.5)));
+    }
+
+    #[actix_web::test]
+    async fn test_free_shipping_waives_handling_only_when_configured() {
+        for (waive, expected_cents) in [(true, 0), (false, 250)] {
+            let (quote, span) = quote_with_pricing(PricingConfig {
+                handling_fee: 2.5,
+                free_shipping_min_items: Some(1),
+                waive_handling_with_free_shipping: waive,
+                ..Default::default()
+            })
+            .await;
+            let cost = quote.cost_usd.unwrap();
+            assert_eq!(
+                (cost.units, cost.nanos),
+                (
+                    expected_cents / 100,
+                    (expected_cents % 100) as u32 * 10_000_000
+                )
+            );
+            assert!(span
+                .attributes
+                .contains(&KeyValue::new("app.shipping.free_shipping", true)));
+        }
+    }
+
+    async fn quote_with_override(token: String) -> (GetQuoteResponse, SpanData) {
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            pricing_override_secret: Some("partner-key".into()),
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(GetQuoteRequest {
+                pricing_override: Some(token),
+                ..single_item_request()
+            })
+            .to_request();
+
+        let (resp, span) = in_test_span("get-quote", test::call_service(&app, req)).await;
+        assert!(resp.status().is_success());
+        (test::read_body_json(resp).await, span)
+    }
+
+    fn partner_terms(expires_in: Duration) -> PricingOverride {
+        PricingOverride {
+            partner: "acme".into(),
+            discount_rate: 0.1,
+            expires_at: Utc::now() + expires_in,
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_signed_pricing_override_is_applied() {
+        let token = partner_terms(Duration::hours(1)).sign("partner-key");
+        let (quote, span) = quote_with_override(token).await;
+        let cost = quote.cost_usd.unwrap();
+        assert_eq!((cost.units, cost.nanos), (9, 890_000_000));
+        assert!(span.attributes.contains(&KeyValue::new(
+            "app.shipping.pricing_override_applied",
+            true
+        )));
+    }
+
+    #[actix_web::test]
+    async fn test_expired_and_forged_overrides_are_ignored() {
+        let metrics = TestMetrics::install();
+        let expired = partner_terms(-Duration::hours(1)).sign("partner-key");
+        let forged = partner_terms(Duration::hours(1)).sign("guessed-key");
+
+        for (token, reason) in [(expired, "expired"), (forged, "invalid_signature")] {
+            let (quote, span) = quote_with_override(token).await;
+            let cost = quote.cost_usd.unwrap();
+            assert_eq!((cost.units, cost.nanos), (10, 990_000_000));
+            assert!(span.attributes.contains(&KeyValue::new(
+                "app.shipping.pricing_override_applied",
+                false
+            )));
+            assert_eq!(
+                metrics.counter(
+                    "app.shipping.pricing_override.ignored",
+                    &[KeyValue::new("reason", reason)]
+                ),
+                1
+            );
+        }
+    }
+
+    async fn quote_with_baggage(trace_id: &str, baggage: Option<&str>) -> (Money, SpanData) {
+        let exporter = test_spans();
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            pricing: PricingConfig {
+                loyalty_discounts: [("gold".to_string(), 0.2)].into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .wrap(RequestTracing::new())
+                .service(get_quote),
+        )
+        .await;
+        let mut req = test::TestRequest::post()
+            .uri("/get-quote")
+            .insert_header(("traceparent", format!("00-{trace_id}-00f067aa0ba902b7-01")))
+            .set_json(single_item_request());
+        if let Some(baggage) = baggage {
+            req = req.insert_header(("baggage", baggage));
+        }
+        let resp = test::call_service(&app, req.to_request()).await;
+        assert!(resp.status().is_success());
+        let quote: GetQuoteResponse = test::read_body_json(resp).await;
+
+        let trace_id = TraceId::from_hex(trace_id).unwrap();
+        let span = exporter
+            .get_finished_spans()
+            .unwrap()
+            .into_iter()
+            .find(|span| {
+                span.span_context.trace_id() == trace_id
+                    && span.span_kind == opentelemetry::trace::SpanKind::Server
+            })
+            .unwrap();
+        (quote.cost_usd.unwrap(), span)
+    }
+
+    #[actix_web::test]
+    async fn test_loyalty_tier_from_baggage_is_discounted() {
+        let (cost, span) = quote_with_baggage(
+            "5b8efff798038103d269b633813fc60c",
+            Some("user.loyalty_tier=gold"),
+        )
+        .await;
+        assert_eq!((cost.units, cost.nanos), (8, 790_000_000));
+        assert!(span
+            .attributes
+            .contains(&KeyValue::new("app.shipping.loyalty_tier", "gold")));
+        assert!(span
+            .attributes
+            .contains(&KeyValue::new("app.shipping.loyalty_discount", 2.2)));
+    }
+
+    #[actix_web::test]
+    async fn test_no_baggage_means_no_loyalty_discount() {
+        let (cost, span) = quote_with_baggage("5b8efff798038103d269b633813fc60d", None).await;
+        assert_eq!((cost.units, cost.nanos), (10, 990_000_000));
+        assert!(!span
+            .attributes
+            .iter()
+            .any(|kv| kv.key.as_str().starts_with("app.shipping.loyalty")));
+    }
+
+    async fn quote_span_at(level: InstrumentationLevel) -> SpanData {
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            instrumentation_level: level,
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(GetQuoteRequest {
+                items: vec![CartItem {
+                    product_id: "OLJCESPC7Z".into(),
+                    quantity: 1,
+                    ..Default::default()
+                }],
+                address: Some(Address {
+                    city: "Seattle".into(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .to_request();
+        let (resp, span) = in_test_span("get-quote", test::call_service(&app, req)).await;
+        assert!(resp.status().is_success());
+        span
+    }
+
+    fn has_attribute(span: &SpanData, key: &str) -> bool {
+        span.attributes.iter().any(|kv| kv.key.as_str() == key)
+    }
+
+    #[actix_web::test]
+    async fn test_minimal_instrumentation_keeps_only_required_detail() {
+        let span = quote_span_at(InstrumentationLevel::Minimal).await;
+        assert!(has_attribute(&span, "app.shipping.cost.total"));
+        assert!(!has_attribute(&span, "app.shipping.address.city"));
+        assert!(!has_attribute(&span, "app.shipping.quote.quoted_at"));
+        assert!(!has_attribute(&span, "app.shipping.timing.quote_ms"));
+        assert!(span.events.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_instrumentation_levels_add_detail() {
+        let span = quote_span_at(InstrumentationLevel::Standard).await;
+        assert!(has_attribute(&span, "app.shipping.cost.total"));
+        assert!(has_attribute(&span, "app.shipping.address.city"));
+        assert!(has_attribute(&span, "app.shipping.timing.quote_ms"));
+        assert!(!has_attribute(&span, "app.shipping.items.product_ids"));
+        assert_eq!(span.events.len(), 1);
+
+        let span = quote_span_at(InstrumentationLevel::Verbose).await;
+        assert!(has_attribute(&span, "app.shipping.items.product_ids"));
+    }
+
+    async fn quote_to_country(country: &str) -> GetQuoteResponse {
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            origin_country: "US".into(),
+            pricing: PricingConfig {
+                customs_duty_rate: 0.1,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(GetQuoteRequest {
+                address: Some(Address {
+                    country: country.into(),
+                    ..Default::default()
+                }),
+                items: single_item_request().items,
+                customs_value: Some(Money {
+                    currency_code: "USD".into(),
+                    units: 150,
+                    nanos: 0,
+                }),
+                ..Default::default()
+            })
+            .to_request();
+        test::call_and_read_body_json(&app, req).await
+    }
+
+    #[actix_web::test]
+    async fn test_international_quote_itemizes_customs_duties() {
+        let quote = quote_to_country("CA").await;
+        let cost = quote.cost_usd.unwrap();
+        assert_eq!((cost.units, cost.nanos), (25, 990_000_000));
+
+        let lines: Vec<(&str, u64, u32)> = quote
+            .breakdown
+            .iter()
+            .map(|line| (line.label.as_str(), line.amount.units, line.amount.nanos))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                ("Shipping", 10, 990_000_000),
+                ("Estimated customs duties", 15, 0)
+            ]
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_domestic_quote_has_no_customs_line() {
+        let quote = quote_to_country("US").await;
+        let cost = quote.cost_usd.unwrap();
+        assert_eq!((cost.units, cost.nanos), (10, 990_000_000));
+        assert!(quote.breakdown.is_empty());
+    }
+
+    async fn taxed_quote_to(country: &str, include_tax: bool) -> GetQuoteResponse {
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            pricing: PricingConfig {
+                tax_rates: [("DE".to_string(), 0.19)].into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(GetQuoteRequest {
+                address: Some(Address {
+                    country: country.into(),
+                    ..Default::default()
+                }),
+                include_tax,
+                ..single_item_request()
+            })
+            .to_request();
+        test::read_body_json(test::call_service(&app, req).await).await
+    }
+
+    fn money_cents(money: &Money) -> u64 {
+        money::to_minor_units(money)
+    }
+
+    #[actix_web::test]
+    async fn test_taxed_destination_totals_are_consistent() {
+        let quote = taxed_quote_to("DE", true).await;
+        let tax = quote.tax.unwrap();
+        assert_eq!(tax.rate, 0.19);
+        assert_eq!(money_cents(&tax.total_exclusive), 1099);
+        assert_eq!(money_cents(&tax.amount), 209);
+        assert_eq!(
+            money_cents(&tax.total_inclusive),
+            money_cents(&tax.total_exclusive) + money_cents(&tax.amount)
+        );
+        // The headline cost stays tax-exclusive.
+        assert_eq!(money_cents(&quote.cost_usd.unwrap()), 1099);
+    }
+
+    #[actix_web::test]
+    async fn test_untaxed_destination_owes_no_tax() {
+        let quote = taxed_quote_to("US", true).await;
+        let tax = quote.tax.unwrap();
+        assert_eq!(money_cents(&tax.amount), 0);
+        assert_eq!(money_cents(&tax.total_inclusive), 1099);
+
+        assert!(taxed_quote_to("DE", false).await.tax.is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_converted_quote_is_in_one_currency() {
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            currency_addr: Some(currency::spawn_mock(0.9)),
+            pricing: PricingConfig {
+                handling_fee: 2.5,
+                tax_rates: [("DE".to_string(), 0.19)].into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(GetQuoteRequest {
+                address: Some(Address {
+                    country: "DE".into(),
+                    ..Default::default()
+                }),
+                include_tax: true,
+                currency: Some("EUR".into()),
+                ..single_item_request()
+            })
+            .to_request();
+        let quote: GetQuoteResponse = test::call_and_read_body_json(&app, req).await;
+
+        let cost = quote.cost_usd.clone().unwrap();
+        let tax = quote.tax.as_ref().unwrap();
+        let amounts = [
+            &cost,
+            &tax.amount,
+            &tax.total_exclusive,
+            &tax.total_inclusive,
+        ]
+        .into_iter()
+        .chain(quote.breakdown.iter().map(|line| &line.amount));
+        for amount in amounts {
+            assert_eq!(amount.currency_code, "EUR", "{amount:?}");
+        }
+        // 13.49 USD at 0.9, split the way the dollar quote was.
+        assert_eq!((cost.units, cost.nanos), (12, 141_000_000));
+        assert_eq!(
+            quote_lines_of(&quote),
+            [
+                ("Shipping", 9, 891_000_000),
+                ("Handling fee", 2, 250_000_000)
+            ]
+        );
+        assert_eq!(tax.total_exclusive, cost);
+        assert_eq!(
+            money::to_nanos(&tax.total_inclusive),
+            money::to_nanos(&cost) + money::to_nanos(&tax.amount)
+        );
+    }
+
+    async fn quote_unserviceable(country: &str, suggest_alternatives: bool) -> ApiError {
+        let config = ShippingConfig {
+            serviceable_countries: vec!["US".into(), "CA".into()],
+            suggest_alternatives,
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(GetQuoteRequest {
+                address: Some(Address {
+                    country: country.into(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        test::read_body_json(resp).await
+    }
+
+    #[actix_web::test]
+    async fn test_unserviceable_destination_suggests_alternatives() {
+        let err = quote_unserviceable("MX", true).await;
+        assert_eq!(err.code, "unserviceable_destination");
+        let details = err.details.unwrap();
+        assert!(details["reason"].as_str().unwrap().contains("MX"));
+        let countries: Vec<&str> = details["suggestions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|suggestion| suggestion["country"].as_str().unwrap())
+            .collect();
+        assert_eq!(countries, ["US", "CA"]);
+        assert_eq!(details["suggestions"][0]["region"], "north_america");
+
+        // Nothing served is anywhere near Antarctica.
+        let details = quote_unserviceable("AQ", true).await.details.unwrap();
+        assert_eq!(details["suggestions"], serde_json::json!([]));
+    }
+
+    #[actix_web::test]
+    async fn test_unserviceable_destination_without_suggestions() {
+        let err = quote_unserviceable("MX", false).await;
+        assert_eq!(err.code, "unserviceable_destination");
+        assert!(err.details.is_none());
+    }
+
+    /// Quotes a batch of three requests, the second to an unserviceable
+    /// destination.
+    async fn quote_batch(accept: &str) -> actix_web::dev::ServiceResponse {
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            serviceable_countries: vec!["US".into()],
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quotes),
+        )
+        .await;
+        let unserviceable = GetQuoteRequest {
+            address: Some(Address {
+                country: "AQ".into(),
+                ..Default::default()
+            }),
+            ..single_item_request()
+        };
+        let req = test::TestRequest::post()
+            .uri("/get-quotes")
+            .insert_header((header::ACCEPT, accept))
+            .set_json(BatchQuoteRequest {
+                requests: vec![single_item_request(), unserviceable, single_item_request()],
+            })
+            .to_request();
+        test::call_service(&app, req).await
+    }
+
+    #[actix_web::test]
+    async fn test_batch_quote_streams_one_line_per_request() {
+        let resp = quote_batch(NDJSON).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get(header::CONTENT_TYPE).unwrap(), NDJSON);
+
+        let body = test::read_body(resp).await;
+        let mut results: Vec<BatchQuoteResult> = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(results.len(), 3);
+        results.sort_by_key(|result| result.index);
+        let outcomes: Vec<_> = results
+            .iter()
+            .map(|result| (result.index, result.status, result.quote.is_some()))
+            .collect();
+        assert_eq!(outcomes, [(0, 200, true), (1, 422, false), (2, 200, true)]);
+        assert_eq!(
+            results[1].error.as_ref().unwrap().code,
+            "unserviceable_destination"
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_batch_quote_as_json_keeps_request_order() {
+        let batch: BatchQuoteResponse = test::read_body_json(quote_batch("*/*").await).await;
+        let indices: Vec<_> = batch.results.iter().map(|result| result.index).collect();
+        assert_eq!(indices, [0, 1, 2]);
+        let cost = batch.results[0].quote.as_ref().unwrap().cost_usd.as_ref();
+        assert_eq!(decimal_amount(cost.unwrap()), "10.99");
+    }
+
+    #[actix_web::test]
+    async fn test_oversized_batch_is_rejected() {
+        let config = ShippingConfig {
+            max_quote_batch: 2,
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quotes),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/get-quotes")
+            .set_json(BatchQuoteRequest {
+                requests: (0..3).map(|_| single_item_request()).collect(),
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let err: ApiError = test::read_body_json(resp).await;
+        assert_eq!(err.code, "batch_too_large");
+        assert_eq!(err.message, "batch has 3 requests, the maximum is 2");
+    }
+
+    #[actix_web::test]
+    async fn test_malformed_requests_are_bad_requests() {
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(ShippingConfig::default()).register(cfg))
+                .service(get_quote)
+                .service(get_quote_query)
+                .service(ship_order),
+        )
+        .await;
+        let json = |uri, body: &'static str| {
+            test::TestRequest::post()
+                .uri(uri)
+                .insert_header(ContentType::json())
+                .set_payload(body)
+                .to_request()
+        };
+        for req in [
+            json("/get-quote", "{"),
+            json("/get-quote", r#"{"items": "many"}"#),
+            json("/ship-order", r#"{"items": [{"quantity": "two"}]}"#),
+            test::TestRequest::get()
+                .uri("/get-quote?items=-1")
+                .to_request(),
+        ] {
+            let uri = req.uri().to_string();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "{uri}");
+            let err: ApiError = test::read_body_json(resp).await;
+            assert_eq!(err.code, "malformed_request", "{uri}");
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_canary_is_recorded_but_not_returned() {
+        let metrics = TestMetrics::install();
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            canary_strategy: Some(PricingStrategy::PerItem),
+            canary_sample_rate: 1.0,
+            pricing: PricingConfig {
+                per_item_rate: 4.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(GetQuoteRequest {
+                items: vec![CartItem {
+                    product_id: "OLJCESPC7Z".into(),
+                    quantity: 2,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            })
+            .to_request();
+
+        let (resp, span) = in_test_span("get-quote", test::call_service(&app, req)).await;
+        let quote: GetQuoteResponse = test::read_body_json(resp).await;
+        let cost = quote.cost_usd.unwrap();
+        assert_eq!((cost.units, cost.nanos), (10, 990_000_000));
+
+        // The canary charges 2 x 4.00 against the quote service's 10.99.
+        assert!(span
+            .attributes
+            .contains(&KeyValue::new("app.shipping.canary.delta_cents", -299)));
+        assert!(span
+            .attributes
+            .contains(&KeyValue::new("app.shipping.canary.total_cents", 800)));
+        assert_eq!(
+            metrics.counter(
+                "app.shipping.canary.comparisons",
+                &[KeyValue::new("strategy", "per_item")]
+            ),
+            1
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_too_many_items_is_rejected_early() {
+        let config = ShippingConfig {
+            max_items_in_request: 3,
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote),
+        )
+        .await;
+        let item = CartItem {
+            product_id: "OLJCESPC7Z".into(),
+            quantity: 1,
+            ..Default::default()
+        };
+        let req = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(GetQuoteRequest {
+                items: vec![item.clone(); 4],
+                ..Default::default()
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let err: ApiError = test::read_body_json(resp).await;
+        assert_eq!(err.code, "too_many_items");
+        assert_eq!(err.message, "request has 4 items, the maximum is 3");
+
+        let huge = CartItem {
+            quantity: u32::MAX,
+            ..item
+        };
+        let req = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(GetQuoteRequest {
+                items: vec![huge; 2],
+                ..Default::default()
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let err: ApiError = test::read_body_json(resp).await;
+        assert_eq!(err.code, "invalid_item_count");
+    }
+
+    #[actix_web::test]
+    async fn test_ready_fails_promptly_on_slow_dependency() {
+        let quote_addr = spawn_mock(|cfg| {
+            cfg.default_service(web::to(|| async {
+                actix_web::rt::time::sleep(std::time::Duration::from_secs(5)).await;
+                HttpResponse::Ok().finish()
+            }));
+        });
+        let config = ShippingConfig {
+            quote_addr,
+            readiness_probe_timeout: std::time::Duration::from_millis(100),
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(ready),
+        )
+        .await;
+
+        let started = Instant::now();
+        let req = test::TestRequest::get().uri("/ready").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+        let err: ApiError = test::read_body_json(resp).await;
+        assert_eq!(err.code, "not_ready");
+    }
+
+    #[actix_web::test]
+    async fn test_health_ignores_dependencies_unlike_ready() {
+        let config = ShippingConfig {
+            // Nothing listens on port 1, so connecting is refused at once.
+            quote_addr: "http://127.0.0.1:1".into(),
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(live)
+                .service(ready),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/health").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["status"], "ok");
+
+        let req = test::TestRequest::get().uri("/ready").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    /// Trace ids of a quote and a shipped order, and of the spans they were
+    /// served in.
+    async fn success_trace_ids(include: bool) -> [(Option<String>, String); 2] {
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            include_trace_id_in_response: include,
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote)
+                .service(ship_order),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(single_item_request())
+            .to_request();
+        let (resp, span) = in_test_span("get-quote", test::call_service(&app, req)).await;
+        let quote: GetQuoteResponse = test::read_body_json(resp).await;
+        let quoted = (quote.trace_id, span.span_context.trace_id().to_string());
+
+        let req = test::TestRequest::post()
+            .uri("/ship-order")
+            .set_json(ShipOrderRequest {
+                items: single_item_request().items,
+                ..Default::default()
+            })
+            .to_request();
+        let (resp, span) = in_test_span("ship-order", test::call_service(&app, req)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let shipped: ShipOrderResponse = test::read_body_json(resp).await;
+        let shipped = (shipped.trace_id, span.span_context.trace_id().to_string());
+        [quoted, shipped]
+    }
+
+    #[actix_web::test]
+    async fn test_trace_id_in_successful_responses_follows_flag() {
+        for (trace_id, span_trace_id) in success_trace_ids(true).await {
+            assert_eq!(trace_id, Some(span_trace_id));
+        }
+        for (trace_id, _) in success_trace_ids(false).await {
+            assert_eq!(trace_id, None);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_ready_when_dependency_answers() {
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(ready),
+        )
+        .await;
+        let req = test::TestRequest::get().uri("/ready").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_metrics_endpoint_serves_recorded_metrics() {
+        let reader = PrometheusReader::default();
+        let _metrics = TestMetrics::install_with_reader(reader.clone());
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            ..Default::default()
+        };
+        let data = AppData::new(config).with_prometheus(Some(reader));
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| data.register(cfg))
+                .service(get_quote)
+                .service(prometheus_metrics),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(GetQuoteRequest {
+                items: vec![CartItem {
+                    quantity: 3,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let req = test::TestRequest::get().uri("/metrics").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers().get(header::CONTENT_TYPE).unwrap(),
+            prometheus::CONTENT_TYPE
+        );
+        let body = test::read_body(resp).await;
+        let text = std::str::from_utf8(&body).unwrap();
+        assert!(text.contains("# TYPE app_shipping_items_count_total counter"));
+        assert!(text.contains(
+            r#"app_shipping_items_count_total{otel_scope_name="otel_demo.shipping.quote"} 3"#
+        ));
+        assert!(text.contains("# TYPE app_shipping_quote_duration_ms histogram"));
+        assert!(text.contains(r#"app_shipping_quote_duration_ms_bucket{"#));
+    }
+
+    #[actix_web::test]
+    async fn test_metrics_endpoint_is_off_by_default() {
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(ShippingConfig::default()).register(cfg))
+                .service(prometheus_metrics),
+        )
+        .await;
+        let req = test::TestRequest::get().uri("/metrics").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let err: ApiError = test::read_body_json(resp).await;
+        assert_eq!(err.code, "metrics_disabled");
+    }
+
+    #[actix_web::test]
+    async fn test_receipt_for_unknown_order_is_not_found() {
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(ShippingConfig::default()).register(cfg))
+                .service(get_receipt),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/order/unknown/receipt")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let err: ApiError = test::read_body_json(resp).await;
+        assert_eq!(err.code, "order_not_found");
     }
 }
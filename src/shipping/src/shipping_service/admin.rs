@@ -0,0 +1,220 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use actix_web::{middleware::from_fn, post, web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::auth::require_admin;
+use super::AppData;
+use crate::telemetry::current_trace_context;
+
+/// What `/admin/reset` cleared.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResetSummary {
+    /// Circuit breakers that were open or half-open.
+    pub breakers_closed: usize,
+    pub quote_cache_entries: usize,
+    /// Exchange rates kept for quoting while the currency service is down.
+    pub stale_currency_rates: usize,
+}
+
+/// Closes every circuit breaker and empties the quote and currency caches,
+/// so that a demo recovers from an injected outage at once instead of
+/// waiting out cooldowns and TTLs.
+#[post("/admin/reset", wrap = "from_fn(require_admin)")]
+pub async fn reset(data: web::Data<AppData>) -> impl Responder {
+    let summary = ResetSummary {
+        breakers_closed: data
+            .quotes
+            .reset_breakers(data.config.instrumentation_level),
+        quote_cache_entries: data.quotes.clear_cache(),
+        stale_currency_rates: data.stale_rates.clear(),
+    };
+
+    let trace = current_trace_context();
+    let (trace_id, span_id) = trace.as_fields();
+    warn!(
+        name = "AdminReset",
+        breakers_closed = summary.breakers_closed,
+        quote_cache_entries = summary.quote_cache_entries,
+        stale_currency_rates = summary.stale_currency_rates,
+        trace_id,
+        span_id,
+        message = "Breakers and caches reset"
+    );
+    HttpResponse::Ok().json(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use actix_web::{http::header, http::StatusCode, test, App};
+
+    use super::*;
+    use crate::shipping_service::{
+        config::{AuthConfig, BreakerConfig, FallbackConfig},
+        get_quote, ApiError, CartItem, GetQuoteRequest, Money, ShippingConfig,
+    };
+    use crate::test_support::spawn_mock;
+
+    /// A quote service answering its second call with an error and every
+    /// other one with a price.
+    fn spawn_second_call_failing_mock() -> (String, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let hits = calls.clone();
+        let addr = spawn_mock(move |cfg| {
+            let hits = hits.clone();
+            cfg.route(
+                "/getquote",
+                web::post().to(move || {
+                    let call = hits.fetch_add(1, Ordering::SeqCst);
+                    async move {
+                        if call == 1 {
+                            HttpResponse::BadRequest().finish()
+                        } else {
+                            HttpResponse::Ok().body("10.99")
+                        }
+                    }
+                }),
+            );
+        });
+        (addr, calls)
+    }
+
+    fn quote_request(quantity: u32) -> test::TestRequest {
+        test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(GetQuoteRequest {
+                items: vec![CartItem {
+                    quantity,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            })
+    }
+
+    fn reset_request(token: Option<&str>) -> test::TestRequest {
+        let req = test::TestRequest::post().uri("/admin/reset");
+        match token {
+            Some(token) => req.insert_header((header::AUTHORIZATION, format!("Bearer {token}"))),
+            None => req,
+        }
+    }
+
+    fn admin_config(admin_token: Option<&str>) -> ShippingConfig {
+        ShippingConfig {
+            auth: AuthConfig {
+                admin_token: admin_token.map(str::to_string),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_reset_closes_breakers_and_clears_caches() {
+        let (quote_addr, calls) = spawn_second_call_failing_mock();
+        let config = ShippingConfig {
+            quote_addr,
+            quote_cache_ttl: Duration::from_secs(60),
+            breaker: BreakerConfig {
+                failure_threshold: 1,
+                open_for: Duration::from_secs(60),
+                ..Default::default()
+            },
+            fallback: FallbackConfig {
+                enabled: false,
+                ..Default::default()
+            },
+            ..admin_config(Some("admin-secret"))
+        };
+        let data = AppData::new(config);
+        let usd = |units| Money {
+            currency_code: "USD".to_string(),
+            units,
+            nanos: 0,
+        };
+        data.stale_rates.record(
+            &usd(10),
+            &Money {
+                currency_code: "EUR".to_string(),
+                ..usd(9)
+            },
+        );
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| data.register(cfg))
+                .service(get_quote)
+                .service(reset),
+        )
+        .await;
+
+        let resp = test::call_service(&app, quote_request(1).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp = test::call_service(&app, quote_request(2).to_request()).await;
+        assert!(resp.status().is_server_error());
+        let resp = test::call_service(&app, quote_request(2).to_request()).await;
+        let err: ApiError = test::read_body_json(resp).await;
+        assert_eq!(err.code, "quote_service_unavailable");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        let resp = test::call_service(&app, reset_request(Some("admin-secret")).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let summary: ResetSummary = test::read_body_json(resp).await;
+        assert_eq!(
+            summary,
+            ResetSummary {
+                breakers_closed: 1,
+                quote_cache_entries: 1,
+                stale_currency_rates: 1,
+            }
+        );
+
+        let resp = test::call_service(&app, quote_request(2).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[actix_web::test]
+    async fn test_reset_requires_the_admin_token() {
+        for (admin_token, token, status, code) in [
+            (
+                None,
+                Some("anything"),
+                StatusCode::NOT_FOUND,
+                "admin_disabled",
+            ),
+            (
+                Some("admin-secret"),
+                None,
+                StatusCode::UNAUTHORIZED,
+                "missing_token",
+            ),
+            (
+                Some("admin-secret"),
+                Some("guessed"),
+                StatusCode::FORBIDDEN,
+                "invalid_token",
+            ),
+        ] {
+            let app = test::init_service(
+                App::new()
+                    .configure(|cfg| AppData::new(admin_config(admin_token)).register(cfg))
+                    .service(reset),
+            )
+            .await;
+            let resp = test::call_service(&app, reset_request(token).to_request()).await;
+            assert_eq!(resp.status(), status);
+            let err: ApiError = test::read_body_json(resp).await;
+            assert_eq!(err.code, code);
+        }
+    }
+}
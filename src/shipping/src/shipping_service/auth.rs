@@ -0,0 +1,228 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{self, HeaderValue},
+    middleware::Next,
+    web, Error, HttpResponse,
+};
+use opentelemetry::{global, KeyValue};
+use subtle::{Choice, ConstantTimeEq};
+use tracing::warn;
+
+use super::{api_error, ShippingConfig};
+use crate::telemetry::current_trace_context;
+
+/// Middleware guarding write endpoints with a bearer token from the
+/// `AUTH_TOKENS` allowlist. It lets every request through unless
+/// `AUTH_ENABLED` is set.
+pub async fn require_auth(
+    config: web::Data<ShippingConfig>,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let auth = &config.auth;
+    if !auth.enabled {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+
+    match bearer_token(&req) {
+        None => Ok(reject(req, "missing_token", "A bearer token is required")),
+        Some(token) if !is_allowed(token, &auth.tokens) => Ok(reject(
+            req,
+            "invalid_token",
+            "The bearer token is not allowed",
+        )),
+        Some(_) => Ok(next.call(req).await?.map_into_left_body()),
+    }
+}
+
+/// Middleware guarding the `/admin` endpoints with the `ADMIN_TOKEN` bearer
+/// token, whatever `AUTH_ENABLED` says. Without a token they answer 404.
+pub async fn require_admin(
+    config: web::Data<ShippingConfig>,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let Some(admin_token) = &config.auth.admin_token else {
+        let resp = HttpResponse::NotFound().json(api_error(
+            "admin_disabled",
+            "Admin endpoints are disabled, set ADMIN_TOKEN to enable them".to_string(),
+        ));
+        return Ok(req.into_response(resp).map_into_right_body());
+    };
+
+    match bearer_token(&req) {
+        None => Ok(reject(req, "missing_token", "A bearer token is required")),
+        Some(token) if !is_allowed(token, [admin_token]) => Ok(reject(
+            req,
+            "invalid_token",
+            "The bearer token is not the admin token",
+        )),
+        Some(_) => Ok(next.call(req).await?.map_into_left_body()),
+    }
+}
+
+/// Whether `token` is one of `allowed`. Every allowed token is compared in
+/// constant time, so the answer's timing doesn't tell how much of a guess
+/// was right or which token it came close to.
+fn is_allowed<'a>(token: &str, allowed: impl IntoIterator<Item = &'a String>) -> bool {
+    allowed
+        .into_iter()
+        .fold(Choice::from(0), |found, allowed| {
+            found | token.as_bytes().ct_eq(allowed.as_bytes())
+        })
+        .into()
+}
+
+fn bearer_token(req: &ServiceRequest) -> Option<&str> {
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+/// Logs, counts and answers a request turned away for `code`: 401 without a
+/// token, 403 with the wrong one.
+fn reject<B>(
+    req: ServiceRequest,
+    code: &'static str,
+    message: &str,
+) -> ServiceResponse<EitherBody<B>> {
+    let trace = current_trace_context();
+    let (trace_id, span_id) = trace.as_fields();
+    warn!(
+        name = "AuthRejected",
+        path = req.path(),
+        reason = code,
+        trace_id,
+        span_id,
+        message = "Rejecting unauthenticated request"
+    );
+    let meter = global::meter("otel_demo.shipping.auth");
+    let counter = meter.u64_counter("app.shipping.auth.rejected").build();
+    counter.add(1, &[KeyValue::new("reason", code)]);
+
+    let body = api_error(code, message.to_string());
+    let resp = if bearer_token(&req).is_none() {
+        HttpResponse::Unauthorized()
+            .insert_header((header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer")))
+            .json(body)
+    } else {
+        HttpResponse::Forbidden().json(body)
+    };
+    req.into_response(resp).map_into_right_body()
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::StatusCode, test, App};
+
+    use super::*;
+    use crate::shipping_service::{
+        config::AuthConfig, get_quote, ship_order, Address, ApiError, AppData, GetQuoteRequest,
+        ShipOrderRequest,
+    };
+    use crate::test_support::spawn_quote_mock;
+
+    fn auth_enabled_config() -> ShippingConfig {
+        ShippingConfig {
+            auth: AuthConfig {
+                enabled: true,
+                tokens: ["secret".to_string()].into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_is_allowed_checks_every_token() {
+        let tokens: Vec<String> = ["first", "second"].map(String::from).into();
+        assert!(is_allowed("first", &tokens));
+        assert!(is_allowed("second", &tokens));
+        assert!(!is_allowed("secon", &tokens));
+        assert!(!is_allowed("second!", &tokens));
+        assert!(!is_allowed("", &tokens));
+        assert!(!is_allowed("first", &Vec::new()));
+    }
+
+    #[actix_web::test]
+    async fn test_valid_token_passes() {
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            ..auth_enabled_config()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(ship_order),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/ship-order")
+            .insert_header((header::AUTHORIZATION, "Bearer secret"))
+            .set_json(ShipOrderRequest::default())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_missing_and_invalid_tokens_rejected() {
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(auth_enabled_config()).register(cfg))
+                .service(ship_order),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/ship-order")
+            .set_json(ShipOrderRequest::default())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let err: ApiError = test::read_body_json(resp).await;
+        assert_eq!(err.code, "missing_token");
+
+        let req = test::TestRequest::post()
+            .uri("/ship-order")
+            .insert_header((header::AUTHORIZATION, "Bearer guessed"))
+            .set_json(ShipOrderRequest::default())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        let err: ApiError = test::read_body_json(resp).await;
+        assert_eq!(err.code, "invalid_token");
+    }
+
+    #[actix_web::test]
+    async fn test_quoting_remains_unauthenticated() {
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(auth_enabled_config()).register(cfg))
+                .service(get_quote),
+        )
+        .await;
+        // An over-long address is rejected before any upstream call, which is
+        // enough to show the request reached the handler without a token.
+        let req = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(GetQuoteRequest {
+                address: Some(Address {
+                    zip_code: "9".repeat(100),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let err: ApiError = test::read_body_json(resp).await;
+        assert_eq!(err.code, "invalid_address");
+    }
+}
@@ -0,0 +1,86 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{fmt, str::FromStr, time::Duration};
+
+use super::determinism::Entropy;
+
+/// Randomization of the retry backoff, set by `QUOTE_BACKOFF_JITTER`. The
+/// formulas are the AWS ones: jitter spreads out the retries of callers
+/// that failed together.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Jitter {
+    /// The exponential delay itself.
+    #[default]
+    None,
+    /// Anywhere between zero and the exponential delay.
+    Full,
+    /// At least half the exponential delay, plus up to the other half.
+    Equal,
+}
+
+impl FromStr for Jitter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Jitter::None),
+            "full" => Ok(Jitter::Full),
+            "equal" => Ok(Jitter::Equal),
+            _ => Err(format!(
+                "unknown jitter {s:?}, expected none, full or equal"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Jitter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Jitter::None => "none",
+            Jitter::Full => "full",
+            Jitter::Equal => "equal",
+        })
+    }
+}
+
+/// Wait before retry number `retry`, counted from 0: `base` doubled for
+/// each earlier retry, then jittered.
+pub fn backoff_delay(base: Duration, retry: u32, jitter: Jitter, entropy: &Entropy) -> Duration {
+    let ceiling = base.saturating_mul(2u32.saturating_pow(retry));
+    match jitter {
+        Jitter::None => ceiling,
+        Jitter::Full => ceiling.mul_f64(entropy.fraction()),
+        Jitter::Equal => ceiling / 2 + (ceiling / 2).mul_f64(entropy.fraction()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: Duration = Duration::from_millis(100);
+
+    #[test]
+    fn test_no_jitter_doubles_exactly() {
+        let entropy = Entropy::default();
+        let delays: Vec<_> = (0..4)
+            .map(|retry| backoff_delay(BASE, retry, Jitter::None, &entropy))
+            .collect();
+        assert_eq!(delays, [100, 200, 400, 800].map(Duration::from_millis));
+    }
+
+    #[test]
+    fn test_jitter_stays_within_bounds() {
+        let entropy = Entropy::default();
+        for retry in 0..4 {
+            let ceiling = BASE * 2u32.pow(retry);
+            for _ in 0..100 {
+                let full = backoff_delay(BASE, retry, Jitter::Full, &entropy);
+                assert!(full <= ceiling, "{full:?} > {ceiling:?}");
+                let equal = backoff_delay(BASE, retry, Jitter::Equal, &entropy);
+                assert!(equal >= ceiling / 2 && equal <= ceiling, "{equal:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,310 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl BreakerState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BreakerState::Closed => "closed",
+            BreakerState::Open => "open",
+            BreakerState::HalfOpen => "half_open",
+        }
+    }
+
+    /// Value of the state in the `app.shipping.quote.circuit_state` gauge.
+    pub fn gauge_value(&self) -> u64 {
+        match self {
+            BreakerState::Closed => 0,
+            BreakerState::Open => 1,
+            BreakerState::HalfOpen => 2,
+        }
+    }
+}
+
+/// What a breaker guards, set by `CB_SCOPE`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BreakerScope {
+    /// One breaker for all quote service replicas: failures of any of them
+    /// can turn every request away.
+    #[default]
+    Global,
+    /// A breaker per replica, so that one failing replica is skipped while
+    /// the others keep serving.
+    PerBackend,
+}
+
+impl FromStr for BreakerScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "global" => Ok(BreakerScope::Global),
+            "per_backend" => Ok(BreakerScope::PerBackend),
+            _ => Err(format!(
+                "unknown breaker scope {s:?}, expected global or per_backend"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for BreakerScope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            BreakerScope::Global => "global",
+            BreakerScope::PerBackend => "per_backend",
+        })
+    }
+}
+
+/// Health of the guarded dependency: unhealthy from its first failure after
+/// a success until its next success.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Health {
+    Healthy,
+    Unhealthy,
+}
+
+impl Health {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Health::Healthy => "healthy",
+            Health::Unhealthy => "unhealthy",
+        }
+    }
+}
+
+/// Point-in-time view of a breaker, reported to clients it turns away.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BreakerSnapshot {
+    pub state: BreakerState,
+    pub consecutive_failures: u32,
+    pub cooldown_remaining_ms: u64,
+}
+
+/// Circuit breaker guarding calls to the quote service. It opens after
+/// `failure_threshold` consecutive failures, rejects calls for `open_for`,
+/// then lets a single probe through to decide whether to close again.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    open_for: Duration,
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    state: BreakerState,
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    /// Number of the probe in flight while half-open.
+    probe: Option<u64>,
+    probes: u64,
+}
+
+/// A call let through by `try_acquire`. A probe dropped before its outcome
+/// is recorded, as when the request is cancelled, is let go so that the
+/// next call probes instead.
+#[must_use]
+#[derive(Debug)]
+pub struct Permit<'a> {
+    breaker: &'a CircuitBreaker,
+    probe: Option<u64>,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if self.probe.is_some() {
+            let mut inner = self.breaker.lock();
+            if inner.probe == self.probe {
+                inner.probe = None;
+            }
+        }
+    }
+}
+
+impl CircuitBreaker {
+    /// Creates a closed breaker. A `failure_threshold` of 0 disables it.
+    pub fn new(failure_threshold: u32, open_for: Duration) -> Self {
+        CircuitBreaker {
+            failure_threshold,
+            open_for,
+            inner: Mutex::new(Inner {
+                state: BreakerState::Closed,
+                consecutive_failures: 0,
+                opened_at: None,
+                probe: None,
+                probes: 0,
+            }),
+        }
+    }
+
+    /// Checks whether a call may go ahead, returning the breaker's state
+    /// when it must be rejected instead. The permit is held until the
+    /// call's outcome is recorded.
+    pub fn try_acquire(&self) -> Result<Permit<'_>, BreakerSnapshot> {
+        let mut inner = self.lock();
+        match inner.state {
+            BreakerState::Closed => Ok(Permit {
+                breaker: self,
+                probe: None,
+            }),
+            BreakerState::Open if self.cooldown_remaining(&inner).is_zero() => {
+                inner.state = BreakerState::HalfOpen;
+                Ok(self.start_probe(&mut inner))
+            }
+            BreakerState::HalfOpen if inner.probe.is_none() => Ok(self.start_probe(&mut inner)),
+            BreakerState::Open | BreakerState::HalfOpen => Err(self.snapshot_of(&inner)),
+        }
+    }
+
+    fn start_probe(&self, inner: &mut Inner) -> Permit<'_> {
+        inner.probes += 1;
+        inner.probe = Some(inner.probes);
+        Permit {
+            breaker: self,
+            probe: inner.probe,
+        }
+    }
+
+    /// Records a successful call, returning the dependency's new health if
+    /// this call ended an outage.
+    pub fn record_success(&self) -> Option<Health> {
+        let mut inner = self.lock();
+        let recovered = inner.consecutive_failures > 0;
+        inner.state = BreakerState::Closed;
+        inner.consecutive_failures = 0;
+        inner.opened_at = None;
+        inner.probe = None;
+        recovered.then_some(Health::Healthy)
+    }
+
+    /// Records a failed call, returning the dependency's new health if this
+    /// call started an outage.
+    pub fn record_failure(&self) -> Option<Health> {
+        let mut inner = self.lock();
+        let started_outage = inner.consecutive_failures == 0;
+        inner.consecutive_failures = inner.consecutive_failures.saturating_add(1);
+        inner.probe = None;
+
+        let trips = inner.state == BreakerState::HalfOpen
+            || (self.failure_threshold > 0 && inner.consecutive_failures >= self.failure_threshold);
+        if trips {
+            inner.state = BreakerState::Open;
+            inner.opened_at = Some(Instant::now());
+        }
+        started_outage.then_some(Health::Unhealthy)
+    }
+
+    /// Closes the breaker and forgets its failures, returning whether it
+    /// was open or half-open.
+    pub fn reset(&self) -> bool {
+        let mut inner = self.lock();
+        let was_tripped = inner.state != BreakerState::Closed;
+        inner.state = BreakerState::Closed;
+        inner.consecutive_failures = 0;
+        inner.opened_at = None;
+        inner.probe = None;
+        was_tripped
+    }
+
+    pub fn snapshot(&self) -> BreakerSnapshot {
+        self.snapshot_of(&self.lock())
+    }
+
+    /// Locks the state, recovering it if a panicking request poisoned the
+    /// lock: every update leaves it consistent.
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn snapshot_of(&self, inner: &Inner) -> BreakerSnapshot {
+        BreakerSnapshot {
+            state: inner.state,
+            consecutive_failures: inner.consecutive_failures,
+            cooldown_remaining_ms: self.cooldown_remaining(inner).as_millis() as u64,
+        }
+    }
+
+    fn cooldown_remaining(&self, inner: &Inner) -> Duration {
+        match (inner.state, inner.opened_at) {
+            (BreakerState::Open, Some(opened_at)) => {
+                self.open_for.saturating_sub(opened_at.elapsed())
+            }
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opens_after_threshold_and_reports_cooldown() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(30));
+        breaker.record_failure();
+        assert!(breaker.try_acquire().is_ok());
+        breaker.record_failure();
+
+        let snapshot = breaker.try_acquire().unwrap_err();
+        assert_eq!(snapshot.state, BreakerState::Open);
+        assert_eq!(snapshot.consecutive_failures, 2);
+        assert!(snapshot.cooldown_remaining_ms > 29_000);
+    }
+
+    #[test]
+    fn test_dropped_probe_lets_the_next_call_probe() {
+        let breaker = CircuitBreaker::new(1, Duration::ZERO);
+        breaker.record_failure();
+
+        let probe = breaker.try_acquire().unwrap();
+        assert_eq!(
+            breaker.try_acquire().err().map(|snapshot| snapshot.state),
+            Some(BreakerState::HalfOpen)
+        );
+        drop(probe);
+        let probe = breaker.try_acquire().unwrap();
+
+        // A permit outliving its recorded outcome leaves later probes be.
+        breaker.record_failure();
+        let next = breaker.try_acquire().unwrap();
+        drop(probe);
+        assert!(breaker.try_acquire().is_err());
+        drop(next);
+    }
+
+    #[test]
+    fn test_disabled_with_zero_threshold() {
+        let breaker = CircuitBreaker::new(0, Duration::from_secs(30));
+        for _ in 0..10 {
+            breaker.record_failure();
+        }
+        assert!(breaker.try_acquire().is_ok());
+    }
+
+    #[test]
+    fn test_reports_health_transitions_only() {
+        let breaker = CircuitBreaker::new(0, Duration::from_secs(30));
+        assert_eq!(breaker.record_success(), None);
+        assert_eq!(breaker.record_failure(), Some(Health::Unhealthy));
+        assert_eq!(breaker.record_failure(), None);
+        assert_eq!(breaker.record_success(), Some(Health::Healthy));
+        assert_eq!(breaker.record_success(), None);
+    }
+}
@@ -0,0 +1,92 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::config::CarbonConfig;
+use super::customs::is_international;
+use super::shipping_types::{Address, CartItem, ShippingSpeed};
+
+/// Estimates the CO2, in kilograms, emitted shipping `items` to
+/// `destination` at `speed`: their weight times the distance times the
+/// emission factor of the speed. Items without a declared weight count as
+/// `default_item_kg`, and the distance is the domestic or the international
+/// one. Rounded to the gram.
+pub fn estimate_co2_kg(
+    items: &[CartItem],
+    speed: ShippingSpeed,
+    destination: Option<&Address>,
+    origin_country: &str,
+    carbon: &CarbonConfig,
+) -> f64 {
+    let weight_kg: f64 = items
+        .iter()
+        .map(|item| item.weight_kg.unwrap_or(carbon.default_item_kg) * f64::from(item.quantity))
+        .sum();
+    let distance_km = match destination {
+        Some(address) if is_international(address, origin_country) => carbon.international_km,
+        _ => carbon.domestic_km,
+    };
+    let kg_per_tonne_km = match speed {
+        ShippingSpeed::Standard => carbon.standard_kg_per_tonne_km,
+        ShippingSpeed::Express => carbon.express_kg_per_tonne_km,
+        ShippingSpeed::Overnight => carbon.overnight_kg_per_tonne_km,
+    };
+    let co2_kg = weight_kg / 1000.0 * distance_km * kg_per_tonne_km;
+    (co2_kg * 1000.0).round() / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(weight_kg: f64) -> Vec<CartItem> {
+        vec![CartItem {
+            quantity: 2,
+            weight_kg: Some(weight_kg),
+            ..Default::default()
+        }]
+    }
+
+    fn to_country(country: &str) -> Address {
+        Address {
+            country: country.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_air_emits_more_than_ground_on_the_same_route() {
+        let carbon = CarbonConfig::default();
+        let destination = to_country("DE");
+        let co2 = |speed| estimate_co2_kg(&items(5.0), speed, Some(&destination), "US", &carbon);
+
+        assert!(co2(ShippingSpeed::Overnight) > co2(ShippingSpeed::Express));
+        assert!(co2(ShippingSpeed::Express) > co2(ShippingSpeed::Standard));
+        // 10 kg over 6000 km by truck at 0.1 kg per tonne-km.
+        assert_eq!(co2(ShippingSpeed::Standard), 6.0);
+    }
+
+    #[test]
+    fn test_co2_scales_with_weight_and_distance() {
+        let carbon = CarbonConfig::default();
+        let co2 = |weight_kg, country| {
+            estimate_co2_kg(
+                &items(weight_kg),
+                ShippingSpeed::Standard,
+                Some(&to_country(country)),
+                "US",
+                &carbon,
+            )
+        };
+
+        assert_eq!(co2(10.0, "US"), 2.0 * co2(5.0, "US"));
+        assert!(co2(5.0, "DE") > co2(5.0, "US"));
+        let unweighed = [CartItem {
+            quantity: 4,
+            ..Default::default()
+        }];
+        assert_eq!(
+            estimate_co2_kg(&unweighed, ShippingSpeed::Standard, None, "US", &carbon),
+            co2(1.0, "US")
+        );
+    }
+}
@@ -0,0 +1,34 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use chrono::{DateTime, Utc};
+
+use super::config::{CarrierRates, PricingConfig};
+use super::shipping_types::{Address, CartItem, QuoteConfidence, QuoteSource, ShippingQuote};
+
+/// Prices `items` from `carrier`'s rate table, before any charges. Also
+/// returns the product ids of the items the table can't price, which are
+/// left out of the quote.
+pub fn carrier_quote(
+    carrier: &CarrierRates,
+    items: &[CartItem],
+    destination: Option<&Address>,
+    pricing: &PricingConfig,
+    quoted_at: DateTime<Utc>,
+) -> (ShippingQuote, Vec<String>) {
+    let rates = PricingConfig {
+        per_item_rate: carrier.per_item_rate,
+        sku_rates: carrier.sku_rates.clone(),
+        ..pricing.clone()
+    };
+    let priced = carrier.strategy.price(items, destination, &rates);
+    let quote = ShippingQuote {
+        total_cents: priced.cents,
+        charges: vec![],
+        currency: "USD".to_string(),
+        source: QuoteSource::RateTable,
+        confidence: QuoteConfidence::Exact,
+        quoted_at,
+    };
+    (quote, priced.unpriceable)
+}
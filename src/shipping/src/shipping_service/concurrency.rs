@@ -0,0 +1,248 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header,
+    middleware::Next,
+    web, Error,
+};
+use opentelemetry::{global, KeyValue};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::warn;
+
+use super::config::ConcurrencyConfig;
+use super::{error_response, ShippingError};
+use crate::telemetry::current_trace_context;
+
+/// Requests an endpoint is serving, and how many it may serve at once.
+#[derive(Debug)]
+struct Limiter {
+    endpoint: &'static str,
+    /// `None` when the endpoint is unbounded.
+    permits: Option<Arc<Semaphore>>,
+    in_flight: AtomicU64,
+}
+
+impl Limiter {
+    fn new(endpoint: &'static str, max: Option<usize>) -> Self {
+        Limiter {
+            endpoint,
+            permits: max.map(|max| Arc::new(Semaphore::new(max))),
+            in_flight: AtomicU64::new(0),
+        }
+    }
+
+    /// Counts a request in, unless the endpoint is saturated.
+    fn enter(&self) -> Option<Entered<'_>> {
+        let permit = match &self.permits {
+            Some(permits) => Some(permits.clone().try_acquire_owned().ok()?),
+            None => None,
+        };
+        self.record(self.in_flight.fetch_add(1, Ordering::SeqCst) + 1);
+        Some(Entered {
+            limiter: self,
+            _permit: permit,
+        })
+    }
+
+    fn record(&self, in_flight: u64) {
+        global::meter("otel_demo.shipping.concurrency")
+            .u64_gauge("app.shipping.concurrency.in_flight")
+            .build()
+            .record(in_flight, &[KeyValue::new("endpoint", self.endpoint)]);
+    }
+}
+
+/// A request being served, counted out when dropped.
+struct Entered<'a> {
+    limiter: &'a Limiter,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Drop for Entered<'_> {
+    fn drop(&mut self) {
+        let limiter = self.limiter;
+        limiter.record(limiter.in_flight.fetch_sub(1, Ordering::SeqCst) - 1);
+    }
+}
+
+/// The concurrency limits of the endpoints, gauged in
+/// `app.shipping.concurrency.in_flight`.
+#[derive(Debug)]
+pub struct ConcurrencyLimits {
+    get_quote: Limiter,
+    ship_order: Limiter,
+    retry_after_secs: u64,
+}
+
+impl ConcurrencyLimits {
+    pub fn new(config: &ConcurrencyConfig) -> Self {
+        ConcurrencyLimits {
+            get_quote: Limiter::new("get_quote", config.get_quote),
+            ship_order: Limiter::new("ship_order", config.ship_order),
+            retry_after_secs: config.retry_after.as_secs(),
+        }
+    }
+}
+
+/// Middleware holding `/get-quote` to `GET_QUOTE_MAX_CONCURRENCY` requests.
+pub async fn limit_get_quote(
+    limits: web::Data<ConcurrencyLimits>,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    limited(&limits.get_quote, limits.retry_after_secs, req, next).await
+}
+
+/// Middleware holding `/ship-order` to `SHIP_ORDER_MAX_CONCURRENCY`
+/// requests.
+pub async fn limit_ship_order(
+    limits: web::Data<ConcurrencyLimits>,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    limited(&limits.ship_order, limits.retry_after_secs, req, next).await
+}
+
+/// Serves `req` within the limit of `limiter`, answering 503 with a
+/// `Retry-After` when it is reached instead of queueing the request.
+async fn limited(
+    limiter: &Limiter,
+    retry_after_secs: u64,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let Some(_entered) = limiter.enter() else {
+        let trace = current_trace_context();
+        let (trace_id, span_id) = trace.as_fields();
+        warn!(
+            name = "EndpointSaturated",
+            endpoint = limiter.endpoint,
+            trace_id,
+            span_id,
+            message = "Rejecting request over the concurrency limit"
+        );
+        global::meter("otel_demo.shipping.concurrency")
+            .u64_counter("app.shipping.concurrency.rejected")
+            .build()
+            .add(1, &[KeyValue::new("endpoint", limiter.endpoint)]);
+
+        let mut resp = error_response(
+            ShippingError::EndpointSaturated(format!(
+                "Too many concurrent {} requests, retry later",
+                limiter.endpoint
+            )),
+            None,
+        );
+        resp.headers_mut()
+            .insert(header::RETRY_AFTER, retry_after_secs.into());
+        return Ok(req.into_response(resp).map_into_right_body());
+    };
+    Ok(next.call(req).await?.map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use actix_web::{http::StatusCode, test, App, HttpResponse};
+    use futures_util::future::join;
+
+    use super::*;
+    use crate::shipping_service::{
+        get_quote, ship_order, ApiError, AppData, CartItem, GetQuoteRequest, ShipOrderRequest,
+        ShippingConfig,
+    };
+    use crate::test_support::{spawn_mock, TestMetrics};
+
+    fn items() -> Vec<CartItem> {
+        vec![CartItem {
+            quantity: 1,
+            ..Default::default()
+        }]
+    }
+
+    #[actix_web::test]
+    async fn test_saturated_quotes_leave_orders_available() {
+        let metrics = TestMetrics::install();
+        let quote_addr = spawn_mock(|cfg| {
+            cfg.route(
+                "/getquote",
+                web::post().to(|| async {
+                    actix_web::rt::time::sleep(Duration::from_millis(300)).await;
+                    HttpResponse::Ok().body("10.99")
+                }),
+            );
+        });
+        let config = ShippingConfig {
+            quote_addr,
+            concurrency: ConcurrencyConfig {
+                get_quote: Some(1),
+                ship_order: Some(1),
+                retry_after: Duration::from_secs(2),
+            },
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote)
+                .service(ship_order),
+        )
+        .await;
+        let quote = || {
+            test::TestRequest::post()
+                .uri("/get-quote")
+                .set_json(GetQuoteRequest {
+                    items: items(),
+                    ..Default::default()
+                })
+                .to_request()
+        };
+
+        let (slow_quote, (saturated, order)) = join(test::call_service(&app, quote()), async {
+            actix_web::rt::time::sleep(Duration::from_millis(50)).await;
+            let saturated = test::call_service(&app, quote()).await;
+            let order = test::TestRequest::post()
+                .uri("/ship-order")
+                .set_json(ShipOrderRequest {
+                    items: items(),
+                    ..Default::default()
+                })
+                .to_request();
+            (saturated, test::call_service(&app, order).await)
+        })
+        .await;
+
+        assert_eq!(slow_quote.status(), StatusCode::OK);
+        assert_eq!(order.status(), StatusCode::OK);
+        assert_eq!(saturated.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(saturated.headers().get(header::RETRY_AFTER).unwrap(), "2");
+        let err: ApiError = test::read_body_json(saturated).await;
+        assert_eq!(err.code, "endpoint_saturated");
+
+        let endpoint = |name| [KeyValue::new("endpoint", name)];
+        assert_eq!(
+            metrics.counter("app.shipping.concurrency.rejected", &endpoint("get_quote")),
+            1
+        );
+        assert_eq!(
+            metrics.counter("app.shipping.concurrency.rejected", &endpoint("ship_order")),
+            0
+        );
+        assert_eq!(
+            metrics.gauge("app.shipping.concurrency.in_flight", &endpoint("get_quote")),
+            Some(0)
+        );
+
+        let resp = test::call_service(&app, quote()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,1376 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    env,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use anyhow::Context;
+use serde::{de::DeserializeOwned, Deserialize};
+use tracing::warn;
+
+use super::backoff::Jitter;
+use super::breaker::BreakerScope;
+use super::currency::CurrencyFailureMode;
+use super::grpc_service::ServeProtocol;
+use super::idempotency::Retention;
+use super::shipping_types::ShippingSpeed;
+use super::strategy::PricingStrategy;
+use super::tracking::{TrackingIdEncoding, TrackingIdFormat};
+use super::validation::ZeroItemsPolicy;
+use super::zones::ShippingZone;
+use super::InstrumentationLevel;
+
+/// Runtime configuration of the shipping service, read once from the
+/// environment at startup and shared with the handlers.
+#[derive(Debug, Clone)]
+pub struct ShippingConfig {
+    /// Base URL of the quote service.
+    pub quote_addr: String,
+    /// Base URLs of further quote service replicas, sharing quote requests
+    /// with `quote_addr` in turn.
+    pub quote_replica_addrs: Vec<String>,
+    pub address_limits: AddressLimits,
+    pub auth: AuthConfig,
+    /// Enables debug-only behavior such as `X-Debug-*` request overrides.
+    pub debug_endpoints_enabled: bool,
+    /// Quotes above this many dollars are flagged, but still returned.
+    pub quote_warn_above: Option<f64>,
+    /// Adds a `Server-Timing` header with the phases of each quote.
+    pub server_timing_enabled: bool,
+    /// Adds `nosniff`, `X-Frame-Options` and, on HTML, a CSP to responses.
+    pub security_headers_enabled: bool,
+    /// Adds the trace id to successful quote and ship-order responses, not
+    /// only to errors.
+    pub include_trace_id_in_response: bool,
+    pub breaker: BreakerConfig,
+    pub fallback: FallbackConfig,
+    pub carbon: CarbonConfig,
+    pub concurrency: ConcurrencyConfig,
+    pub retry: RetryConfig,
+    pub quote_pool: QuotePoolConfig,
+    /// Decimal separator the quote service uses in its responses.
+    pub quote_decimal_separator: char,
+    /// Time the `/ready` probe waits for the quote service.
+    pub readiness_probe_timeout: Duration,
+    /// Time in-flight requests are given to complete once shutdown starts.
+    pub shutdown_grace: Duration,
+    /// Longest wait for the quote service to answer a quote request.
+    pub quote_timeout: Duration,
+    /// Share of quote service calls made to hang until they time out, to
+    /// demo the timeout handling and circuit breaker.
+    pub quote_inject_timeout_rate: f64,
+    /// How long a price fetched from the quote service is reused for the
+    /// same item count; zero, the default, keeps every quote in the trace.
+    pub quote_cache_ttl: Duration,
+    /// Pricing at startup. Handlers read the live copy in `PricingState`,
+    /// which picks up changes to `pricing_config_file`.
+    pub pricing: PricingConfig,
+    pub pricing_config_file: Option<PathBuf>,
+    /// How often to check `pricing_config_file` for changes; unset disables
+    /// hot reload.
+    pub pricing_reload_interval: Option<Duration>,
+    pub instrumentation_level: InstrumentationLevel,
+    /// Country shipments leave from; any other destination is international.
+    pub origin_country: String,
+    /// Destination countries served; empty serves every country.
+    pub serviceable_countries: Vec<String>,
+    /// Lists alternatives in the error for an unserviceable destination.
+    pub suggest_alternatives: bool,
+    /// Strategy priced alongside the quote service for comparison.
+    pub canary_strategy: Option<PricingStrategy>,
+    /// Fraction of quotes, between 0 and 1, that also run the canary.
+    pub canary_sample_rate: f64,
+    /// Most entries accepted in a request's item list.
+    pub max_items_in_request: usize,
+    /// Most items, summing their quantities, quoted or shipped at once.
+    pub max_item_count: u32,
+    /// Most requests in a `get-quotes` batch.
+    pub max_quote_batch: usize,
+    /// Quote requests a second the process accepts; zero is unlimited.
+    pub quote_rate_limit_rps: u32,
+    /// Step, in kilograms, the billed weight is rounded up to; unset bills
+    /// the actual weight.
+    pub weight_billing_increment_kg: Option<f64>,
+    /// Freezes the clock and seeds the id generator so that responses are
+    /// reproducible. For tests and demos only.
+    pub deterministic_mode: bool,
+    pub zero_items_policy: ZeroItemsPolicy,
+    /// Receives the order after each package status change.
+    pub order_webhook_url: Option<String>,
+    /// Quotes the priceable items of a request instead of rejecting it when
+    /// a rate table can't price some of them.
+    pub partial_quote_allowed: bool,
+    /// Currencies whose market requires an address to quote.
+    pub address_required_currencies: Vec<String>,
+    pub tracking_id: TrackingIdFormat,
+    /// Key partners sign `pricing_override` tokens with. Tokens are ignored
+    /// when unset.
+    pub pricing_override_secret: Option<String>,
+    /// Makes a ship-order request wait for a concurrent one with the same
+    /// `Idempotency-Key` instead of shipping the order twice.
+    pub ship_order_coalescing: bool,
+    /// Journal of ship-order results by idempotency key, read back on start
+    /// so replays survive restarts. Without it replays are best-effort.
+    pub idempotency_store_file: Option<PathBuf>,
+    /// How long and how many ship-order results are replayed by key.
+    pub idempotency_retention: Retention,
+    /// Times each quote token can ship an order.
+    pub quote_token_max_uses: u32,
+    pub parcel_limits: ParcelLimits,
+    /// Orders in transit for longer are flagged as stuck; unset disables the
+    /// reconciliation job.
+    pub stuck_order_max_age: Option<Duration>,
+    pub stuck_order_scan_interval: Duration,
+    /// Where clients reach the service, for the links in responses. Links
+    /// are relative when unset.
+    pub public_base_url: Option<String>,
+    /// Demo currency service converting quotes into the currency clients
+    /// ask for. Quotes stay in dollars when unset.
+    pub currency_addr: Option<String>,
+    pub currency_failure_mode: CurrencyFailureMode,
+    pub protocol: ServeProtocol,
+    /// Port of the gRPC server when it runs alongside the HTTP one, which
+    /// keeps `SHIPPING_PORT`.
+    pub grpc_port: Option<u16>,
+}
+
+const DEFAULT_QUOTE_ADDR: &str = "http://quote:8090";
+const DEFAULT_ORIGIN_COUNTRY: &str = "United States";
+
+impl Default for ShippingConfig {
+    fn default() -> Self {
+        ShippingConfig {
+            quote_addr: DEFAULT_QUOTE_ADDR.to_string(),
+            quote_replica_addrs: Vec::new(),
+            address_limits: AddressLimits::default(),
+            auth: AuthConfig::default(),
+            debug_endpoints_enabled: false,
+            quote_warn_above: None,
+            server_timing_enabled: false,
+            security_headers_enabled: false,
+            include_trace_id_in_response: false,
+            breaker: BreakerConfig::default(),
+            fallback: FallbackConfig::default(),
+            carbon: CarbonConfig::default(),
+            concurrency: ConcurrencyConfig::default(),
+            quote_pool: QuotePoolConfig::default(),
+            retry: RetryConfig::default(),
+            quote_decimal_separator: '.',
+            readiness_probe_timeout: Duration::from_millis(1000),
+            shutdown_grace: Duration::from_secs(10),
+            quote_timeout: Duration::from_millis(5000),
+            quote_inject_timeout_rate: 0.0,
+            quote_cache_ttl: Duration::ZERO,
+            pricing: PricingConfig::default(),
+            pricing_config_file: None,
+            pricing_reload_interval: None,
+            instrumentation_level: InstrumentationLevel::default(),
+            origin_country: DEFAULT_ORIGIN_COUNTRY.to_string(),
+            serviceable_countries: Vec::new(),
+            suggest_alternatives: false,
+            canary_strategy: None,
+            canary_sample_rate: 0.1,
+            max_items_in_request: 500,
+            max_item_count: 10_000,
+            max_quote_batch: 20,
+            quote_rate_limit_rps: 0,
+            weight_billing_increment_kg: None,
+            deterministic_mode: false,
+            zero_items_policy: ZeroItemsPolicy::default(),
+            order_webhook_url: None,
+            partial_quote_allowed: false,
+            address_required_currencies: Vec::new(),
+            tracking_id: TrackingIdFormat::default(),
+            pricing_override_secret: None,
+            ship_order_coalescing: true,
+            idempotency_store_file: None,
+            idempotency_retention: Retention::default(),
+            quote_token_max_uses: 1,
+            parcel_limits: ParcelLimits::default(),
+            stuck_order_max_age: None,
+            stuck_order_scan_interval: Duration::from_secs(60),
+            public_base_url: None,
+            currency_addr: None,
+            currency_failure_mode: CurrencyFailureMode::default(),
+            protocol: ServeProtocol::default(),
+            grpc_port: None,
+        }
+    }
+}
+
+impl ShippingConfig {
+    /// Reads the configuration, failing when `PRICING_CONFIG_FILE` can't be
+    /// loaded or `SHIPPING_PROTOCOL=both` has no `SHIPPING_GRPC_PORT`.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let pricing_config_file = env::var_os("PRICING_CONFIG_FILE").map(PathBuf::from);
+        let config = ShippingConfig {
+            quote_addr: env::var("QUOTE_ADDR").unwrap_or_else(|_| DEFAULT_QUOTE_ADDR.to_string()),
+            quote_replica_addrs: env_list("QUOTE_REPLICA_ADDRS"),
+            address_limits: AddressLimits::from_env(),
+            auth: AuthConfig::from_env(),
+            debug_endpoints_enabled: env_or("DEBUG_ENDPOINTS_ENABLED", false),
+            quote_warn_above: env_opt("QUOTE_WARN_ABOVE"),
+            server_timing_enabled: env_or("SERVER_TIMING_ENABLED", false),
+            security_headers_enabled: env_or("SECURITY_HEADERS_ENABLED", false),
+            include_trace_id_in_response: env_or("INCLUDE_TRACE_ID_IN_RESPONSE", false),
+            breaker: BreakerConfig::from_env(),
+            fallback: FallbackConfig::from_env(),
+            carbon: CarbonConfig::from_env(),
+            concurrency: ConcurrencyConfig::from_env(),
+            quote_pool: QuotePoolConfig::from_env(),
+            retry: RetryConfig::from_env(),
+            quote_decimal_separator: env_or("QUOTE_DECIMAL_SEPARATOR", '.'),
+            readiness_probe_timeout: Duration::from_millis(env_or(
+                "READINESS_PROBE_TIMEOUT_MS",
+                1000,
+            )),
+            shutdown_grace: Duration::from_secs(env_or("SHUTDOWN_GRACE_SECS", 10)),
+            quote_timeout: Duration::from_millis(env_or("QUOTE_TIMEOUT_MS", 5000)),
+            quote_inject_timeout_rate: env_or("QUOTE_INJECT_TIMEOUT_RATE", 0.0),
+            quote_cache_ttl: Duration::from_millis(env_or("QUOTE_CACHE_TTL_MS", 0)),
+            pricing: PricingConfig::load(pricing_config_file.as_deref())?,
+            pricing_reload_interval: interval_from_env("PRICING_RELOAD_INTERVAL_MS"),
+            pricing_config_file,
+            instrumentation_level: env_or("INSTRUMENTATION_LEVEL", InstrumentationLevel::default()),
+            origin_country: env_or("ORIGIN_COUNTRY", DEFAULT_ORIGIN_COUNTRY.to_string()),
+            serviceable_countries: env_list("SERVICEABLE_COUNTRIES"),
+            suggest_alternatives: env_or("SUGGEST_ALTERNATIVES", false),
+            canary_strategy: env_opt("CANARY_PRICING_STRATEGY"),
+            canary_sample_rate: env_or("CANARY_SAMPLE_RATE", 0.1),
+            max_items_in_request: env_or("MAX_ITEMS_IN_REQUEST", 500),
+            max_item_count: env_or("MAX_ITEM_COUNT", 10_000),
+            max_quote_batch: env_or("MAX_QUOTE_BATCH", 20),
+            quote_rate_limit_rps: env_or("QUOTE_RATE_LIMIT_RPS", 0),
+            weight_billing_increment_kg: env_opt("WEIGHT_BILLING_INCREMENT_KG")
+                .filter(|increment: &f64| increment.is_finite() && *increment > 0.0),
+            deterministic_mode: env_or("DETERMINISTIC_MODE", false),
+            zero_items_policy: zero_items_policy_from_env(),
+            order_webhook_url: env::var("ORDER_WEBHOOK_URL").ok(),
+            partial_quote_allowed: env_or("PARTIAL_QUOTE_ALLOWED", false),
+            address_required_currencies: env_list("ADDRESS_REQUIRED_CURRENCIES"),
+            tracking_id: TrackingIdFormat {
+                encoding: env_or("TRACKING_ID_ENCODING", TrackingIdEncoding::default()),
+                prefix: env_opt("TRACKING_ID_PREFIX").unwrap_or_default(),
+                check_digit: env_or("TRACKING_ID_CHECKSUM", false),
+            },
+            pricing_override_secret: env::var("PRICING_OVERRIDE_SECRET").ok(),
+            ship_order_coalescing: env_or("SHIP_ORDER_COALESCING", true),
+            idempotency_store_file: env::var_os("IDEMPOTENCY_STORE_FILE").map(PathBuf::from),
+            idempotency_retention: {
+                let default = Retention::default();
+                Retention {
+                    ttl: Duration::from_secs(env_or("IDEMPOTENCY_TTL_SECS", default.ttl.as_secs())),
+                    max_keys: env_or("IDEMPOTENCY_MAX_KEYS", default.max_keys),
+                }
+            },
+            quote_token_max_uses: env_or("QUOTE_TOKEN_MAX_USES", 1),
+            parcel_limits: ParcelLimits::from_env(),
+            stuck_order_max_age: env_opt("STUCK_ORDER_MAX_AGE_SECS").map(Duration::from_secs),
+            stuck_order_scan_interval: interval_from_env("STUCK_ORDER_SCAN_INTERVAL_MS")
+                .unwrap_or(Duration::from_secs(60)),
+            public_base_url: env::var("PUBLIC_BASE_URL").ok(),
+            currency_addr: env::var("CURRENCY_ADDR").ok(),
+            currency_failure_mode: env_or("CURRENCY_FAILURE_MODE", CurrencyFailureMode::default()),
+            protocol: env_or("SHIPPING_PROTOCOL", ServeProtocol::default()),
+            grpc_port: env_opt("SHIPPING_GRPC_PORT"),
+        };
+        if config.protocol == ServeProtocol::Both && config.grpc_port.is_none() {
+            anyhow::bail!("SHIPPING_PROTOCOL=both needs SHIPPING_GRPC_PORT for the gRPC server");
+        }
+        Ok(config)
+    }
+}
+
+/// Maximum accepted length, in characters, of each `Address` field.
+#[derive(Debug, Clone)]
+pub struct AddressLimits {
+    pub street_address: usize,
+    pub city: usize,
+    pub state: usize,
+    pub country: usize,
+    pub zip_code: usize,
+}
+
+impl Default for AddressLimits {
+    fn default() -> Self {
+        AddressLimits {
+            street_address: 256,
+            city: 128,
+            state: 64,
+            country: 64,
+            zip_code: 32,
+        }
+    }
+}
+
+impl AddressLimits {
+    fn from_env() -> Self {
+        let default = AddressLimits::default();
+        AddressLimits {
+            street_address: env_or("ADDRESS_MAX_STREET_LEN", default.street_address),
+            city: env_or("ADDRESS_MAX_CITY_LEN", default.city),
+            state: env_or("ADDRESS_MAX_STATE_LEN", default.state),
+            country: env_or("ADDRESS_MAX_COUNTRY_LEN", default.country),
+            zip_code: env_or("ADDRESS_MAX_ZIP_LEN", default.zip_code),
+        }
+    }
+}
+
+/// Bearer-token authentication of the write endpoints.
+#[derive(Debug, Clone, Default)]
+pub struct AuthConfig {
+    pub enabled: bool,
+    pub tokens: HashSet<String>,
+    /// Token of the `/admin` endpoints, which are disabled without one.
+    pub admin_token: Option<String>,
+}
+
+impl AuthConfig {
+    fn from_env() -> Self {
+        AuthConfig {
+            enabled: env_or("AUTH_ENABLED", false),
+            tokens: env_list("AUTH_TOKENS").into_iter().collect(),
+            admin_token: env_opt("ADMIN_TOKEN"),
+        }
+    }
+}
+
+/// Retries of failed quote service calls.
+#[derive(Debug, Clone)]
+pub struct RetryConfig {
+    /// Retries of a call that failed to connect or got a 5xx or 429; 0
+    /// disables them.
+    pub max_retries: u32,
+    /// Wait before the first retry, doubled before each further one.
+    pub backoff: Duration,
+    pub jitter: Jitter,
+    /// Wall-clock cap on all attempts and backoff together. An attempt is
+    /// cut short when the budget runs out, and no retry starts that would
+    /// only begin after it.
+    pub budget: Option<Duration>,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_retries: 3,
+            backoff: Duration::from_millis(100),
+            jitter: Jitter::default(),
+            budget: None,
+        }
+    }
+}
+
+impl RetryConfig {
+    fn from_env() -> Self {
+        let default = RetryConfig::default();
+        RetryConfig {
+            // `QUOTE_MAX_ATTEMPTS` predates `QUOTE_MAX_RETRIES` and counts
+            // the first call too.
+            max_retries: env_opt("QUOTE_MAX_RETRIES")
+                .or_else(|| {
+                    env_opt("QUOTE_MAX_ATTEMPTS").map(|attempts: u32| attempts.saturating_sub(1))
+                })
+                .unwrap_or(default.max_retries),
+            backoff: Duration::from_millis(env_or(
+                "QUOTE_RETRY_BACKOFF_MS",
+                default.backoff.as_millis() as u64,
+            )),
+            jitter: env_or("QUOTE_BACKOFF_JITTER", default.jitter),
+            budget: env_opt("QUOTE_RETRY_BUDGET_MS").map(Duration::from_millis),
+        }
+    }
+}
+
+/// Largest item parcel carriers take. Shipments with an item beyond either
+/// limit are quoted as freight.
+#[derive(Debug, Clone)]
+pub struct ParcelLimits {
+    /// Weight of one unit, in kilograms.
+    pub max_weight_kg: f64,
+    /// Longest side of one unit, in centimeters.
+    pub max_side_cm: f64,
+}
+
+impl Default for ParcelLimits {
+    fn default() -> Self {
+        ParcelLimits {
+            max_weight_kg: 30.0,
+            max_side_cm: 150.0,
+        }
+    }
+}
+
+impl ParcelLimits {
+    fn from_env() -> Self {
+        let default = ParcelLimits::default();
+        ParcelLimits {
+            max_weight_kg: env_or("PARCEL_MAX_WEIGHT_KG", default.max_weight_kg),
+            max_side_cm: env_or("PARCEL_MAX_SIDE_CM", default.max_side_cm),
+        }
+    }
+}
+
+/// Circuit breaker around the quote service client.
+#[derive(Debug, Clone)]
+pub struct BreakerConfig {
+    /// Consecutive failures that open the breaker; 0 disables it.
+    pub failure_threshold: u32,
+    /// How long the breaker stays open before probing the quote service.
+    pub open_for: Duration,
+    /// Whether one breaker guards every quote service replica, or each has
+    /// its own.
+    pub scope: BreakerScope,
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        BreakerConfig {
+            failure_threshold: 5,
+            open_for: Duration::from_secs(30),
+            scope: BreakerScope::Global,
+        }
+    }
+}
+
+impl BreakerConfig {
+    fn from_env() -> Self {
+        let default = BreakerConfig::default();
+        BreakerConfig {
+            failure_threshold: env_or("QUOTE_CB_FAILURE_THRESHOLD", default.failure_threshold),
+            open_for: Duration::from_millis(env_or(
+                "QUOTE_CB_OPEN_MS",
+                default.open_for.as_millis() as u64,
+            )),
+            scope: env_or("CB_SCOPE", default.scope),
+        }
+    }
+}
+
+/// Local pricing of quotes the quote service fails to answer, so that
+/// checkout keeps working through its outages.
+#[derive(Debug, Clone)]
+pub struct FallbackConfig {
+    /// When off, quote service failures fail the quote.
+    pub enabled: bool,
+    pub base_fee_usd: f64,
+    pub per_item_usd: f64,
+}
+
+impl Default for FallbackConfig {
+    fn default() -> Self {
+        FallbackConfig {
+            enabled: true,
+            base_fee_usd: 5.0,
+            per_item_usd: 0.5,
+        }
+    }
+}
+
+impl FallbackConfig {
+    fn from_env() -> Self {
+        let default = FallbackConfig::default();
+        FallbackConfig {
+            enabled: env_or("QUOTE_FALLBACK_ENABLED", default.enabled),
+            base_fee_usd: env_or("FALLBACK_BASE_FEE_USD", default.base_fee_usd),
+            per_item_usd: env_or("FALLBACK_RATE_USD", default.per_item_usd),
+        }
+    }
+}
+
+/// Most requests each endpoint serves at once, so that a flood of quotes
+/// can't starve orders. Unset limits leave an endpoint unbounded.
+#[derive(Debug, Clone)]
+pub struct ConcurrencyConfig {
+    pub get_quote: Option<usize>,
+    pub ship_order: Option<usize>,
+    /// Wait suggested to the requests turned away.
+    pub retry_after: Duration,
+}
+
+impl Default for ConcurrencyConfig {
+    fn default() -> Self {
+        ConcurrencyConfig {
+            get_quote: None,
+            ship_order: None,
+            retry_after: Duration::from_secs(1),
+        }
+    }
+}
+
+impl ConcurrencyConfig {
+    fn from_env() -> Self {
+        let default = ConcurrencyConfig::default();
+        ConcurrencyConfig {
+            get_quote: env_opt("GET_QUOTE_MAX_CONCURRENCY"),
+            ship_order: env_opt("SHIP_ORDER_MAX_CONCURRENCY"),
+            retry_after: Duration::from_secs(env_or(
+                "CONCURRENCY_RETRY_AFTER_SECS",
+                default.retry_after.as_secs(),
+            )),
+        }
+    }
+}
+
+/// Connections each worker keeps to the quote services, reused across
+/// quote requests instead of connecting for each.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuotePoolConfig {
+    /// Most connections open at once; 0 leaves them unbounded.
+    pub max_connections: usize,
+    /// How long an unused connection stays open.
+    pub keep_alive: Duration,
+}
+
+impl Default for QuotePoolConfig {
+    fn default() -> Self {
+        QuotePoolConfig {
+            max_connections: 100,
+            keep_alive: Duration::from_secs(15),
+        }
+    }
+}
+
+impl QuotePoolConfig {
+    fn from_env() -> Self {
+        let default = QuotePoolConfig::default();
+        QuotePoolConfig {
+            max_connections: env_or("QUOTE_POOL_SIZE", default.max_connections),
+            keep_alive: Duration::from_secs(env_or(
+                "QUOTE_KEEP_ALIVE_SECS",
+                default.keep_alive.as_secs(),
+            )),
+        }
+    }
+}
+
+/// Estimate of the CO2 a shipment emits, added to quotes when enabled.
+/// Emission factors are in kilograms of CO2 per tonne-kilometer.
+#[derive(Debug, Clone)]
+pub struct CarbonConfig {
+    pub enabled: bool,
+    /// Standard shipments go by truck.
+    pub standard_kg_per_tonne_km: f64,
+    /// Express shipments go by truck, less consolidated than standard ones.
+    pub express_kg_per_tonne_km: f64,
+    /// Overnight shipments go by air.
+    pub overnight_kg_per_tonne_km: f64,
+    /// Distance assumed to destinations in the origin country.
+    pub domestic_km: f64,
+    /// Distance assumed to destinations abroad.
+    pub international_km: f64,
+    /// Weight assumed of an item that declares none.
+    pub default_item_kg: f64,
+}
+
+impl Default for CarbonConfig {
+    fn default() -> Self {
+        CarbonConfig {
+            enabled: false,
+            standard_kg_per_tonne_km: 0.1,
+            express_kg_per_tonne_km: 0.15,
+            overnight_kg_per_tonne_km: 0.8,
+            domestic_km: 800.0,
+            international_km: 6000.0,
+            default_item_kg: 0.5,
+        }
+    }
+}
+
+impl CarbonConfig {
+    fn from_env() -> Self {
+        let default = CarbonConfig::default();
+        CarbonConfig {
+            enabled: env_or("INCLUDE_CARBON", default.enabled),
+            standard_kg_per_tonne_km: env_or(
+                "CARBON_FACTOR_STANDARD",
+                default.standard_kg_per_tonne_km,
+            ),
+            express_kg_per_tonne_km: env_or(
+                "CARBON_FACTOR_EXPRESS",
+                default.express_kg_per_tonne_km,
+            ),
+            overnight_kg_per_tonne_km: env_or(
+                "CARBON_FACTOR_OVERNIGHT",
+                default.overnight_kg_per_tonne_km,
+            ),
+            domestic_km: env_or("CARBON_DOMESTIC_KM", default.domestic_km),
+            international_km: env_or("CARBON_INTERNATIONAL_KM", default.international_km),
+            default_item_kg: env_or("CARBON_DEFAULT_ITEM_KG", default.default_item_kg),
+        }
+    }
+}
+
+/// Pricing settings. They can be loaded from the JSON file named by
+/// `PRICING_CONFIG_FILE`, with the matching environment variables taking
+/// precedence over the file.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PricingConfig {
+    /// Dollars added to quotes containing hazardous items.
+    pub hazmat_surcharge: f64,
+    /// Share of the declared customs value charged as estimated duties on
+    /// international shipments.
+    pub customs_duty_rate: f64,
+    /// Dollars per item charged by the `per_item` pricing strategy.
+    pub per_item_rate: f64,
+    /// Dollars charged per billed kilogram of declared weight.
+    pub per_kg_rate: f64,
+    /// Dollars per unit of each product, for the `per_sku` strategy.
+    pub sku_rates: BTreeMap<String, f64>,
+    /// Rate tables of the carriers offered by `/compare-carriers`.
+    pub carriers: Vec<CarrierRates>,
+    /// Dollars added to every quote as its own line.
+    pub handling_fee: f64,
+    /// Shipments of at least this many items ship free.
+    pub free_shipping_min_items: Option<u32>,
+    /// Waives the handling fee on free shipments too.
+    pub waive_handling_with_free_shipping: bool,
+    /// Tax rate of each destination country, by ISO code. Countries missing
+    /// from the table are untaxed.
+    pub tax_rates: BTreeMap<String, f64>,
+    /// Rate table of shipments too large for parcel carriers.
+    pub freight: FreightRates,
+    /// Rates of the components of the `composite` pricing strategy.
+    pub composite: CompositeRates,
+    /// Share of the base shipping cost taken off for each loyalty tier, as
+    /// named by the `user.loyalty_tier` baggage entry.
+    pub loyalty_discounts: BTreeMap<String, f64>,
+    /// Price multipliers of destination zones, chosen by zip code.
+    pub zones: Vec<ShippingZone>,
+    /// Price multipliers of the shipping speeds.
+    pub speed_multipliers: SpeedMultipliers,
+    /// Dollars added to international quotes to each destination country,
+    /// by ISO code. Countries missing from the table pay none.
+    pub country_surcharges: BTreeMap<String, f64>,
+}
+
+impl Default for PricingConfig {
+    fn default() -> Self {
+        PricingConfig {
+            hazmat_surcharge: 25.0,
+            customs_duty_rate: 0.05,
+            per_item_rate: 3.99,
+            per_kg_rate: 0.0,
+            sku_rates: BTreeMap::new(),
+            carriers: Vec::new(),
+            handling_fee: 0.0,
+            free_shipping_min_items: None,
+            waive_handling_with_free_shipping: false,
+            tax_rates: BTreeMap::new(),
+            freight: FreightRates::default(),
+            composite: CompositeRates::default(),
+            loyalty_discounts: BTreeMap::new(),
+            zones: Vec::new(),
+            speed_multipliers: SpeedMultipliers::default(),
+            country_surcharges: BTreeMap::new(),
+        }
+    }
+}
+
+impl PricingConfig {
+    /// Loads the pricing file at `path`, if any, then applies the
+    /// environment overrides.
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        let file = match path {
+            Some(path) => PricingConfig::from_file(path)?,
+            None => PricingConfig::default(),
+        };
+        Ok(file.with_env_overrides())
+    }
+
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let raw = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read pricing config {}", path.display()))?;
+        PricingConfig::parse(&raw, path)
+    }
+
+    /// Parses and validates the contents of the pricing file at `path`.
+    pub fn parse(raw: &str, path: &Path) -> anyhow::Result<Self> {
+        let pricing: PricingConfig = serde_json::from_str(raw)
+            .with_context(|| format!("Malformed pricing config {}", path.display()))?;
+        pricing
+            .validate()
+            .with_context(|| format!("Invalid pricing config {}", path.display()))?;
+        Ok(pricing)
+    }
+
+    pub fn with_env_overrides(self) -> Self {
+        PricingConfig {
+            hazmat_surcharge: env_opt_checked("HAZMAT_SURCHARGE", |amount| {
+                non_negative_amount("hazmat_surcharge", *amount)
+            })
+            .unwrap_or(self.hazmat_surcharge),
+            customs_duty_rate: env_opt_checked("CUSTOMS_DUTY_RATE", |rate| {
+                share("customs_duty_rate", *rate)
+            })
+            .unwrap_or(self.customs_duty_rate),
+            per_item_rate: env_opt_checked("PER_ITEM_RATE", |amount| {
+                non_negative_amount("per_item_rate", *amount)
+            })
+            .unwrap_or(self.per_item_rate),
+            per_kg_rate: env_opt_checked("PER_KG_RATE", |amount| {
+                non_negative_amount("per_kg_rate", *amount)
+            })
+            .unwrap_or(self.per_kg_rate),
+            sku_rates: self.sku_rates,
+            carriers: self.carriers,
+            handling_fee: ["HANDLING_FEE_USD", "HANDLING_FEE"]
+                .into_iter()
+                .find_map(|key| {
+                    env_opt_checked(key, |amount| non_negative_amount("handling_fee", *amount))
+                })
+                .unwrap_or(self.handling_fee),
+            free_shipping_min_items: env_opt("FREE_SHIPPING_MIN_ITEMS")
+                .or(self.free_shipping_min_items),
+            waive_handling_with_free_shipping: env_or(
+                "WAIVE_HANDLING_WITH_FREE_SHIPPING",
+                self.waive_handling_with_free_shipping,
+            ),
+            tax_rates: self.tax_rates,
+            freight: self.freight,
+            composite: self.composite.with_env_overrides(),
+            loyalty_discounts: self.loyalty_discounts,
+            zones: self.zones,
+            speed_multipliers: self.speed_multipliers.with_env_overrides(),
+            country_surcharges: env_json("COUNTRY_SURCHARGES", validate_country_surcharges)
+                .unwrap_or(self.country_surcharges),
+        }
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        non_negative_amount("hazmat_surcharge", self.hazmat_surcharge)?;
+        non_negative_amount("per_item_rate", self.per_item_rate)?;
+        non_negative_amount("per_kg_rate", self.per_kg_rate)?;
+        share("customs_duty_rate", self.customs_duty_rate)?;
+        validate_sku_rates(&self.sku_rates)?;
+        non_negative_amount("handling_fee", self.handling_fee)?;
+        for (country, rate) in &self.tax_rates {
+            if !(0.0..=1.0).contains(rate) {
+                anyhow::bail!("tax_rates.{country} must be between 0 and 1, got {rate}");
+            }
+        }
+        validate_country_surcharges(&self.country_surcharges)?;
+        self.freight.validate().context("Invalid freight rates")?;
+        self.composite
+            .validate()
+            .context("Invalid composite rates")?;
+        self.speed_multipliers
+            .validate()
+            .context("Invalid speed multipliers")?;
+        for (tier, rate) in &self.loyalty_discounts {
+            if !(0.0..=1.0).contains(rate) {
+                anyhow::bail!("loyalty_discounts.{tier} must be between 0 and 1, got {rate}");
+            }
+        }
+        for zone in &self.zones {
+            zone.validate()
+                .with_context(|| format!("Invalid zone {:?}", zone.name))?;
+        }
+        for carrier in &self.carriers {
+            carrier
+                .validate()
+                .with_context(|| format!("Invalid rates for carrier {:?}", carrier.name))?;
+        }
+        Ok(())
+    }
+}
+
+/// A carrier's rate table, priced with one of the pricing strategies.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CarrierRates {
+    pub name: String,
+    pub strategy: PricingStrategy,
+    /// Dollars per item, for the `per_item` strategy.
+    #[serde(default)]
+    pub per_item_rate: f64,
+    /// Dollars per unit of each product, for the `per_sku` strategy.
+    #[serde(default)]
+    pub sku_rates: BTreeMap<String, f64>,
+    /// Replaces the service-wide `hazmat_surcharge` for this carrier.
+    #[serde(default)]
+    pub hazmat_surcharge: Option<f64>,
+    pub transit_days: u32,
+}
+
+impl CarrierRates {
+    fn validate(&self) -> anyhow::Result<()> {
+        if !self.per_item_rate.is_finite() || self.per_item_rate < 0.0 {
+            anyhow::bail!(
+                "per_item_rate must be a non-negative amount, got {}",
+                self.per_item_rate
+            );
+        }
+        validate_sku_rates(&self.sku_rates)?;
+        if let Some(surcharge) = self.hazmat_surcharge {
+            if !surcharge.is_finite() || surcharge < 0.0 {
+                anyhow::bail!(
+                    "hazmat_surcharge must be a non-negative amount, got {}",
+                    surcharge
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Freight rate table, in dollars.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FreightRates {
+    pub base_rate: f64,
+    pub per_kg_rate: f64,
+    pub surcharge: f64,
+    pub transit_days: u32,
+}
+
+impl Default for FreightRates {
+    fn default() -> Self {
+        FreightRates {
+            base_rate: 95.0,
+            per_kg_rate: 0.85,
+            surcharge: 35.0,
+            transit_days: 7,
+        }
+    }
+}
+
+impl FreightRates {
+    fn validate(&self) -> anyhow::Result<()> {
+        for (name, amount) in [
+            ("base_rate", self.base_rate),
+            ("per_kg_rate", self.per_kg_rate),
+            ("surcharge", self.surcharge),
+        ] {
+            if !amount.is_finite() || amount < 0.0 {
+                anyhow::bail!("{name} must be a non-negative amount, got {amount}");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Rates of the `composite` pricing strategy, in dollars, which adds up a
+/// component for the item count, the declared weight and the distance.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CompositeRates {
+    pub per_item_rate: f64,
+    pub per_kg_rate: f64,
+    /// Rate per shipment to each destination country, by ISO code, which
+    /// stands in for the distance. Countries missing from the table add
+    /// nothing.
+    pub distance_rates: BTreeMap<String, f64>,
+    /// Warehouses orders ship from. When set, the distance is priced from
+    /// the nearest one with capacity instead of with `distance_rates`.
+    pub warehouses: Vec<Warehouse>,
+}
+
+impl Default for CompositeRates {
+    fn default() -> Self {
+        CompositeRates {
+            per_item_rate: 3.99,
+            per_kg_rate: 0.0,
+            distance_rates: BTreeMap::new(),
+            warehouses: Vec::new(),
+        }
+    }
+}
+
+/// Factors the quote service's price is multiplied by at each shipping
+/// speed. All are 1 unless configured, so every speed costs the same.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpeedMultipliers {
+    pub standard: f64,
+    pub express: f64,
+    pub overnight: f64,
+}
+
+impl Default for SpeedMultipliers {
+    fn default() -> Self {
+        SpeedMultipliers {
+            standard: 1.0,
+            express: 1.0,
+            overnight: 1.0,
+        }
+    }
+}
+
+impl SpeedMultipliers {
+    pub fn get(&self, speed: ShippingSpeed) -> f64 {
+        match speed {
+            ShippingSpeed::Standard => self.standard,
+            ShippingSpeed::Express => self.express,
+            ShippingSpeed::Overnight => self.overnight,
+        }
+    }
+
+    /// Applies the `SPEED_*_MULT` overrides, keeping the multipliers as they
+    /// were when the overridden ones aren't all positive.
+    fn with_env_overrides(self) -> Self {
+        let overridden = SpeedMultipliers {
+            standard: env_or("SPEED_STANDARD_MULT", self.standard),
+            express: env_or("SPEED_EXPRESS_MULT", self.express),
+            overnight: env_or("SPEED_OVERNIGHT_MULT", self.overnight),
+        };
+        match overridden.validate() {
+            Ok(()) => overridden,
+            Err(err) => {
+                warn!(
+                    name = "InvalidConfigValue",
+                    key = "SPEED_*_MULT",
+                    error = %err,
+                    message = "Invalid speed multipliers, ignoring the overrides"
+                );
+                self
+            }
+        }
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        for speed in ShippingSpeed::ALL {
+            let multiplier = self.get(speed);
+            if !multiplier.is_finite() || multiplier <= 0.0 {
+                anyhow::bail!(
+                    "{} must be a positive factor, got {multiplier}",
+                    speed.as_str()
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A warehouse and its distance to the countries it ships to.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Warehouse {
+    pub name: String,
+    /// Rate per shipment from the warehouse to each destination country, by
+    /// ISO code. The warehouse doesn't ship to countries missing from it.
+    pub distance_rates: BTreeMap<String, f64>,
+    /// Takes no more orders, which then ship from the next-nearest
+    /// warehouse. Also set by listing the warehouse in
+    /// `WAREHOUSES_AT_CAPACITY`.
+    #[serde(default)]
+    pub at_capacity: bool,
+}
+
+impl CompositeRates {
+    fn with_env_overrides(mut self) -> Self {
+        let at_capacity = env_list("WAREHOUSES_AT_CAPACITY");
+        for warehouse in &mut self.warehouses {
+            warehouse.at_capacity |= at_capacity
+                .iter()
+                .any(|name| name.eq_ignore_ascii_case(&warehouse.name));
+        }
+        self
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        for (name, amount) in [
+            ("per_item_rate", self.per_item_rate),
+            ("per_kg_rate", self.per_kg_rate),
+        ] {
+            if !amount.is_finite() || amount < 0.0 {
+                anyhow::bail!("{name} must be a non-negative amount, got {amount}");
+            }
+        }
+        for (country, rate) in &self.distance_rates {
+            if !rate.is_finite() || *rate < 0.0 {
+                anyhow::bail!("distance_rates.{country} must be a non-negative amount, got {rate}");
+            }
+        }
+        for warehouse in &self.warehouses {
+            for (country, rate) in &warehouse.distance_rates {
+                if !rate.is_finite() || *rate < 0.0 {
+                    anyhow::bail!(
+                        "warehouses.{}.distance_rates.{country} must be a non-negative amount, got {rate}",
+                        warehouse.name
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn non_negative_amount(name: &str, amount: f64) -> anyhow::Result<()> {
+    if !amount.is_finite() || amount < 0.0 {
+        anyhow::bail!("{name} must be a non-negative amount, got {amount}");
+    }
+    Ok(())
+}
+
+fn share(name: &str, rate: f64) -> anyhow::Result<()> {
+    if !(0.0..=1.0).contains(&rate) {
+        anyhow::bail!("{name} must be between 0 and 1, got {rate}");
+    }
+    Ok(())
+}
+
+fn validate_sku_rates(rates: &BTreeMap<String, f64>) -> anyhow::Result<()> {
+    for (sku, rate) in rates {
+        if !rate.is_finite() || *rate < 0.0 {
+            anyhow::bail!("sku_rates.{sku} must be a non-negative amount, got {rate}");
+        }
+    }
+    Ok(())
+}
+
+fn validate_country_surcharges(surcharges: &BTreeMap<String, f64>) -> anyhow::Result<()> {
+    for (country, surcharge) in surcharges {
+        if !surcharge.is_finite() || *surcharge < 0.0 {
+            anyhow::bail!(
+                "country_surcharges.{country} must be a non-negative amount, got {surcharge}"
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Reads the JSON setting `key` from the environment, treating a value that
+/// cannot be parsed or fails `validate` as unset.
+fn env_json<T: DeserializeOwned>(
+    key: &str,
+    validate: impl Fn(&T) -> anyhow::Result<()>,
+) -> Option<T> {
+    let raw = env::var(key).ok()?;
+    let parsed = serde_json::from_str(&raw)
+        .map_err(anyhow::Error::from)
+        .and_then(|value| validate(&value).map(|()| value));
+    match parsed {
+        Ok(value) => Some(value),
+        Err(err) => {
+            warn!(
+                name = "InvalidConfigValue",
+                key = key,
+                value = raw.as_str(),
+                error = %err,
+                message = "Invalid configuration value, ignoring it"
+            );
+            None
+        }
+    }
+}
+
+/// Reads `key` from the environment, falling back to `default` when it is
+/// unset or cannot be parsed.
+pub(crate) fn env_or<T>(key: &str, default: T) -> T
+where
+    T: FromStr + Display,
+    T::Err: Display,
+{
+    match env::var(key) {
+        Ok(raw) => raw.trim().parse().unwrap_or_else(|err| {
+            warn!(
+                name = "InvalidConfigValue",
+                key = key,
+                value = raw.as_str(),
+                error = %err,
+                default = %default,
+                message = "Invalid configuration value, using default"
+            );
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+/// Reads the zero-items policy from `ZERO_ITEMS_POLICY`, or else from the
+/// boolean `ALLOW_ZERO_ITEM_QUOTE`.
+fn zero_items_policy_from_env() -> ZeroItemsPolicy {
+    env_opt("ZERO_ITEMS_POLICY")
+        .or_else(|| {
+            env_opt("ALLOW_ZERO_ITEM_QUOTE").map(|allow: bool| match allow {
+                true => ZeroItemsPolicy::ZeroQuote,
+                false => ZeroItemsPolicy::Reject,
+            })
+        })
+        .unwrap_or_default()
+}
+
+/// Reads a comma-separated list from `key`, dropping empty entries.
+fn env_list(key: &str) -> Vec<String> {
+    env::var(key)
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Reads an optional setting `key` from the environment like `env_opt`, also
+/// treating a value that fails `validate` as unset, so that an override can't
+/// let through what the pricing file would be rejected for.
+fn env_opt_checked<T>(key: &str, validate: impl Fn(&T) -> anyhow::Result<()>) -> Option<T>
+where
+    T: FromStr + Display,
+    T::Err: Display,
+{
+    let value = env_opt(key)?;
+    match validate(&value) {
+        Ok(()) => Some(value),
+        Err(err) => {
+            warn!(
+                name = "InvalidConfigValue",
+                key = key,
+                value = %value,
+                error = %err,
+                message = "Invalid configuration value, ignoring it"
+            );
+            None
+        }
+    }
+}
+
+/// Reads an interval in milliseconds from `key`, warning about and ignoring
+/// 0, which a timer can't tick at.
+fn interval_from_env(key: &str) -> Option<Duration> {
+    env_opt_checked(key, |millis: &u64| {
+        if *millis == 0 {
+            anyhow::bail!("the interval must be at least 1 ms");
+        }
+        Ok(())
+    })
+    .map(Duration::from_millis)
+}
+
+/// Reads an optional setting `key` from the environment, treating a value that
+/// cannot be parsed as unset.
+pub(crate) fn env_opt<T>(key: &str) -> Option<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let raw = env::var(key).ok()?;
+    match raw.trim().parse() {
+        Ok(value) => Some(value),
+        Err(err) => {
+            warn!(
+                name = "InvalidConfigValue",
+                key = key,
+                value = raw.as_str(),
+                error = %err,
+                message = "Invalid configuration value, ignoring it"
+            );
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{env_lock, CapturedLogs};
+
+    fn write_pricing_file(name: &str, contents: &str) -> std::path::PathBuf {
+        let path = env::temp_dir().join(format!("shipping-{}-{}.json", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_pricing_config_from_file() {
+        let path = write_pricing_file("valid", r#"{"hazmat_surcharge": 12.5}"#);
+        let pricing = PricingConfig::from_file(&path).unwrap();
+        assert_eq!(pricing.hazmat_surcharge, 12.5);
+    }
+
+    #[test]
+    fn test_malformed_pricing_config_is_rejected() {
+        for (name, contents) in [
+            ("syntax", r#"{"hazmat_surcharge": }"#),
+            ("unknown", r#"{"hazmat_surcharg": 12.5}"#),
+            ("negative", r#"{"hazmat_surcharge": -1}"#),
+            ("rate", r#"{"customs_duty_rate": 5}"#),
+            (
+                "carrier",
+                r#"{"carriers": [{"name": "A", "strategy": "per_item", "per_item_rate": -1, "transit_days": 2}]}"#,
+            ),
+        ] {
+            let path = write_pricing_file(name, contents);
+            let err = PricingConfig::from_file(&path).unwrap_err();
+            assert!(format!("{err:#}").contains(&path.display().to_string()));
+        }
+    }
+
+    #[test]
+    fn test_env_overrides_pricing_file() {
+        let _env = env_lock();
+        let path = write_pricing_file("override", r#"{"hazmat_surcharge": 12.5}"#);
+        let load = || PricingConfig::load(Some(&path)).unwrap().hazmat_surcharge;
+        assert_eq!(load(), 12.5);
+
+        env::set_var("HAZMAT_SURCHARGE", "30");
+        assert_eq!(load(), 30.0);
+        env::remove_var("HAZMAT_SURCHARGE");
+    }
+
+    #[test]
+    fn test_serving_both_protocols_needs_a_grpc_port() {
+        let _env = env_lock();
+        env::set_var("SHIPPING_PROTOCOL", "both");
+        let err = ShippingConfig::from_env().unwrap_err();
+        assert!(err.to_string().contains("SHIPPING_GRPC_PORT"));
+
+        env::set_var("SHIPPING_GRPC_PORT", "50051");
+        assert_eq!(ShippingConfig::from_env().unwrap().grpc_port, Some(50051));
+        env::remove_var("SHIPPING_GRPC_PORT");
+        env::remove_var("SHIPPING_PROTOCOL");
+    }
+
+    #[test]
+    fn test_zero_items_policy_takes_the_boolean_switch() {
+        let _env = env_lock();
+        assert_eq!(zero_items_policy_from_env(), ZeroItemsPolicy::ZeroQuote);
+        env::set_var("ALLOW_ZERO_ITEM_QUOTE", "false");
+        assert_eq!(zero_items_policy_from_env(), ZeroItemsPolicy::Reject);
+        env::set_var("ZERO_ITEMS_POLICY", "zero_quote");
+        assert_eq!(zero_items_policy_from_env(), ZeroItemsPolicy::ZeroQuote);
+        env::remove_var("ZERO_ITEMS_POLICY");
+        env::remove_var("ALLOW_ZERO_ITEM_QUOTE");
+    }
+
+    #[test]
+    fn test_speed_multipliers_from_file_and_env() {
+        let _env = env_lock();
+        let path = write_pricing_file(
+            "speeds",
+            r#"{"speed_multipliers": {"express": 1.5, "overnight": 3}}"#,
+        );
+        let load = || PricingConfig::load(Some(&path)).unwrap().speed_multipliers;
+        assert_eq!(
+            load(),
+            SpeedMultipliers {
+                standard: 1.0,
+                express: 1.5,
+                overnight: 3.0,
+            }
+        );
+        env::set_var("SPEED_EXPRESS_MULT", "2");
+        assert_eq!(load().get(ShippingSpeed::Express), 2.0);
+        env::remove_var("SPEED_EXPRESS_MULT");
+
+        let (logs, _guard) = CapturedLogs::install();
+        for bad in ["0", "-1"] {
+            env::set_var("SPEED_EXPRESS_MULT", bad);
+            assert_eq!(load().get(ShippingSpeed::Express), 1.5, "{bad}");
+        }
+        env::remove_var("SPEED_EXPRESS_MULT");
+        assert_eq!(logs.named("InvalidConfigValue").len(), 2);
+
+        let path = write_pricing_file("speeds-zero", r#"{"speed_multipliers": {"express": 0}}"#);
+        assert!(PricingConfig::from_file(&path).is_err());
+    }
+
+    #[test]
+    fn test_handling_fee_usd_takes_precedence() {
+        let _env = env_lock();
+        let fee = || PricingConfig::load(None).unwrap().handling_fee;
+        env::set_var("HANDLING_FEE", "1.5");
+        assert_eq!(fee(), 1.5);
+        env::set_var("HANDLING_FEE_USD", "2.5");
+        assert_eq!(fee(), 2.5);
+        env::remove_var("HANDLING_FEE_USD");
+        env::remove_var("HANDLING_FEE");
+        assert_eq!(fee(), 0.0);
+    }
+
+    #[test]
+    fn test_invalid_pricing_overrides_keep_the_file_values() {
+        let _env = env_lock();
+        let (logs, _guard) = CapturedLogs::install();
+        let path = write_pricing_file(
+            "overrides",
+            r#"{"customs_duty_rate": 0.1, "hazmat_surcharge": 20, "per_kg_rate": 1.5, "per_item_rate": 2, "handling_fee": 1}"#,
+        );
+        let overrides = [
+            ("CUSTOMS_DUTY_RATE", "5"),
+            ("HAZMAT_SURCHARGE", "-5"),
+            ("PER_KG_RATE", "-1"),
+            ("PER_ITEM_RATE", "NaN"),
+            ("HANDLING_FEE_USD", "inf"),
+        ];
+        for (key, value) in overrides {
+            env::set_var(key, value);
+        }
+        let pricing = PricingConfig::load(Some(&path)).unwrap();
+        for (key, _) in overrides {
+            env::remove_var(key);
+        }
+
+        assert_eq!(pricing.customs_duty_rate, 0.1);
+        assert_eq!(pricing.hazmat_surcharge, 20.0);
+        assert_eq!(pricing.per_kg_rate, 1.5);
+        assert_eq!(pricing.per_item_rate, 2.0);
+        assert_eq!(pricing.handling_fee, 1.0);
+        let warned: HashSet<String> = logs
+            .named("InvalidConfigValue")
+            .into_iter()
+            .map(|fields| fields["key"].clone())
+            .collect();
+        assert_eq!(
+            warned,
+            overrides.iter().map(|(key, _)| key.to_string()).collect()
+        );
+    }
+
+    #[test]
+    fn test_zero_intervals_are_ignored() {
+        let _env = env_lock();
+        let (logs, _guard) = CapturedLogs::install();
+        let keys = ["PRICING_RELOAD_INTERVAL_MS", "STUCK_ORDER_SCAN_INTERVAL_MS"];
+        for key in keys {
+            env::set_var(key, "0");
+            let zero = interval_from_env(key);
+            env::set_var(key, "250");
+            let set = interval_from_env(key);
+            env::remove_var(key);
+
+            assert_eq!(zero, None, "{key}");
+            assert_eq!(set, Some(Duration::from_millis(250)), "{key}");
+        }
+        let warned: Vec<_> = logs
+            .named("InvalidConfigValue")
+            .into_iter()
+            .map(|fields| fields["key"].clone())
+            .collect();
+        assert_eq!(warned, keys);
+    }
+
+    #[test]
+    fn test_country_surcharges_from_env() {
+        let _env = env_lock();
+        let (logs, _guard) = CapturedLogs::install();
+        let surcharges = || PricingConfig::load(None).unwrap().country_surcharges;
+        assert!(surcharges().is_empty());
+        env::set_var("COUNTRY_SURCHARGES", r#"{"CA": 7.5, "DE": 12}"#);
+        assert_eq!(
+            surcharges(),
+            [("CA".to_string(), 7.5), ("DE".to_string(), 12.0)].into()
+        );
+        assert!(logs.named("InvalidConfigValue").is_empty());
+
+        for malformed in ["CA=7.5", r#"{"CA": -1}"#] {
+            env::set_var("COUNTRY_SURCHARGES", malformed);
+            assert!(surcharges().is_empty(), "{malformed}");
+        }
+        env::remove_var("COUNTRY_SURCHARGES");
+        let warnings = logs.named("InvalidConfigValue");
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings
+            .iter()
+            .all(|fields| fields["key"] == "COUNTRY_SURCHARGES"));
+    }
+}
@@ -0,0 +1,251 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    collections::HashMap,
+    fmt,
+    str::FromStr,
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+use anyhow::Context as _;
+use opentelemetry::{
+    context::FutureExt,
+    global,
+    trace::{SpanKind, Status as SpanStatus, TraceContextExt, Tracer},
+    Context, KeyValue,
+};
+use tonic::{client::Grpc, codegen::http::uri::PathAndQuery, transport::Endpoint};
+
+use super::grpc_service::{pb, MetadataInjector, ProstCodec};
+use super::shipping_types::Money;
+
+const SERVICE: &str = "oteldemo.CurrencyService";
+
+/// Bound on connecting to the currency service and on each conversion.
+const CONVERT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// What to quote when the currency service can't convert a quote, set by
+/// `CURRENCY_FAILURE_MODE`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CurrencyFailureMode {
+    /// Fail the quote with a 503, even when a stale rate is known.
+    Reject,
+    /// Convert at the stale rate if one is known, or else quote in dollars.
+    #[default]
+    UsdFallback,
+    /// Convert at the stale rate, failing the quote with a 503 if none is
+    /// known.
+    StaleOnly,
+}
+
+impl FromStr for CurrencyFailureMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "reject" => Ok(CurrencyFailureMode::Reject),
+            "usd_fallback" => Ok(CurrencyFailureMode::UsdFallback),
+            "stale_only" => Ok(CurrencyFailureMode::StaleOnly),
+            _ => Err(format!(
+                "unknown currency failure mode {s:?}, expected reject, usd_fallback or stale_only"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for CurrencyFailureMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            CurrencyFailureMode::Reject => "reject",
+            CurrencyFailureMode::UsdFallback => "usd_fallback",
+            CurrencyFailureMode::StaleOnly => "stale_only",
+        })
+    }
+}
+
+/// Rate of the last successful conversion into each currency, for
+/// converting while the currency service is down. Rates are kept until a
+/// newer conversion replaces them.
+#[derive(Debug, Default)]
+pub struct StaleRates {
+    rates: Mutex<HashMap<String, f64>>,
+}
+
+impl StaleRates {
+    /// Locks the rates, recovering them if a panicking request poisoned the
+    /// lock: every update leaves them consistent.
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, f64>> {
+        self.rates.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Remembers the rate at which `from` was converted into `to`. A zero
+    /// amount has no rate to learn.
+    pub fn record(&self, from: &Money, to: &Money) {
+        let from_nanos = total_nanos(from);
+        if from_nanos > 0.0 {
+            self.lock()
+                .insert(to.currency_code.clone(), total_nanos(to) / from_nanos);
+        }
+    }
+
+    /// Forgets every rate, returning how many were known.
+    pub fn clear(&self) -> usize {
+        let mut rates = self.lock();
+        let known = rates.len();
+        rates.clear();
+        known
+    }
+
+    /// Converts `from` into `to_code` at the last known rate, if any.
+    pub fn convert(&self, from: &Money, to_code: &str) -> Option<Money> {
+        let rate = *self.lock().get(to_code)?;
+        let nanos = (total_nanos(from) * rate).round();
+        Some(Money {
+            currency_code: to_code.to_string(),
+            units: (nanos / 1e9) as u64,
+            nanos: (nanos % 1e9) as u32,
+        })
+    }
+}
+
+fn total_nanos(money: &Money) -> f64 {
+    money.units as f64 * 1e9 + money.nanos as f64
+}
+
+/// Converts `from` into `to_code` with the demo's currency service at
+/// `addr`, e.g. `currency:7001`, in a client span of the current trace.
+pub async fn convert(addr: &str, from: Money, to_code: &str) -> anyhow::Result<Money> {
+    let tracer = global::tracer("otel_demo.shipping.currency");
+    let span = tracer
+        .span_builder(format!("{SERVICE}/Convert"))
+        .with_kind(SpanKind::Client)
+        .with_attributes([
+            KeyValue::new("rpc.system", "grpc"),
+            KeyValue::new("rpc.service", SERVICE),
+            KeyValue::new("rpc.method", "Convert"),
+            KeyValue::new("app.currency.conversion.to", to_code.to_string()),
+        ])
+        .start(&tracer);
+    let cx = Context::current_with_span(span);
+
+    let result = request_conversion(addr, from, to_code)
+        .with_context(cx.clone())
+        .await;
+    if let Err(err) = &result {
+        cx.span().set_status(SpanStatus::error(format!("{err:#}")));
+    }
+    cx.span().end();
+    result
+}
+
+async fn request_conversion(addr: &str, from: Money, to_code: &str) -> anyhow::Result<Money> {
+    let uri = if addr.contains("://") {
+        addr.to_string()
+    } else {
+        format!("http://{addr}")
+    };
+    let channel = Endpoint::from_shared(uri)
+        .context("Invalid currency service address")?
+        .connect_timeout(CONVERT_TIMEOUT)
+        .timeout(CONVERT_TIMEOUT)
+        .connect()
+        .await
+        .context("Failed to connect to the currency service")?;
+
+    let mut request = tonic::Request::new(pb::CurrencyConversionRequest {
+        from: Some(from.into()),
+        to_code: to_code.to_string(),
+    });
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(
+            &Context::current(),
+            &mut MetadataInjector(request.metadata_mut()),
+        )
+    });
+
+    let mut client = Grpc::new(channel);
+    client
+        .ready()
+        .await
+        .context("Currency service is unavailable")?;
+    let converted: pb::Money = client
+        .unary(
+            request,
+            PathAndQuery::from_static("/oteldemo.CurrencyService/Convert"),
+            ProstCodec::default(),
+        )
+        .await
+        .context("Currency conversion failed")?
+        .into_inner();
+    if !converted.currency_code.eq_ignore_ascii_case(to_code) {
+        anyhow::bail!(
+            "Currency service converted to {:?} instead of {to_code:?}",
+            converted.currency_code
+        );
+    }
+    Money::try_from(converted).map_err(anyhow::Error::msg)
+}
+
+/// Starts a mock currency service converting at `rate` units of the target
+/// currency per dollar, returning its address.
+#[cfg(test)]
+pub fn spawn_mock(rate: f64) -> String {
+    use std::convert::Infallible;
+
+    use tonic::{
+        body::Body,
+        codegen::{http, BoxFuture, Service},
+        server::NamedService,
+        transport::{server::TcpIncoming, Server},
+    };
+
+    use super::grpc_service::unary;
+
+    #[derive(Clone)]
+    struct MockCurrency(f64);
+
+    impl Service<http::Request<Body>> for MockCurrency {
+        type Response = http::Response<Body>;
+        type Error = Infallible;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+
+        fn poll_ready(
+            &mut self,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Result<(), Self::Error>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, req: http::Request<Body>) -> Self::Future {
+            let rate = self.0;
+            unary(
+                req,
+                move |request: tonic::Request<pb::CurrencyConversionRequest>| async move {
+                    let request = request.into_inner();
+                    let from = request.from.unwrap_or_default();
+                    let nanos = ((from.units as f64 * 1e9 + from.nanos as f64) * rate).round();
+                    Ok(tonic::Response::new(pb::Money {
+                        currency_code: request.to_code,
+                        units: (nanos / 1e9) as i64,
+                        nanos: (nanos % 1e9) as i32,
+                    }))
+                },
+            )
+        }
+    }
+
+    impl NamedService for MockCurrency {
+        const NAME: &'static str = SERVICE;
+    }
+
+    let incoming = TcpIncoming::bind(([127, 0, 0, 1], 0).into()).unwrap();
+    let addr = incoming.local_addr().unwrap();
+    actix_web::rt::spawn(
+        Server::builder()
+            .add_service(MockCurrency(rate))
+            .serve_with_incoming(incoming),
+    );
+    addr.to_string()
+}
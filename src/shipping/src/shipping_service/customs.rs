@@ -0,0 +1,88 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::money::to_minor_units;
+use super::shipping_types::{Address, Money};
+
+/// Customs values must be declared in the currency quotes are priced in.
+const CUSTOMS_CURRENCY: &str = "USD";
+
+/// Estimates the duties, in cents, on `customs_value` for a shipment to
+/// `address`. Domestic shipments and shipments without a declared value owe
+/// none.
+pub fn estimate_duties(
+    customs_value: Option<&Money>,
+    address: Option<&Address>,
+    origin_country: &str,
+    duty_rate: f64,
+) -> Result<Option<u64>, String> {
+    let (Some(value), Some(address)) = (customs_value, address) else {
+        return Ok(None);
+    };
+    if !is_international(address, origin_country) {
+        return Ok(None);
+    }
+
+    if value.currency_code != CUSTOMS_CURRENCY {
+        return Err(format!(
+            "customs_value must be in {}, got {:?}",
+            CUSTOMS_CURRENCY, value.currency_code
+        ));
+    }
+    if value.nanos >= 1_000_000_000 {
+        return Err(format!(
+            "customs_value.nanos must be below 1000000000, got {}",
+            value.nanos
+        ));
+    }
+
+    let value_cents = to_minor_units(value);
+    Ok(Some((value_cents as f64 * duty_rate).round() as u64))
+}
+
+/// A shipment is international when its destination names a country other
+/// than `origin_country`.
+pub fn is_international(address: &Address, origin_country: &str) -> bool {
+    let country = address.country.trim();
+    !country.is_empty() && !country.eq_ignore_ascii_case(origin_country.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usd(units: u64, nanos: u32) -> Money {
+        Money {
+            currency_code: "USD".into(),
+            units,
+            nanos,
+        }
+    }
+
+    fn to(country: &str) -> Address {
+        Address {
+            country: country.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_estimate_duties() {
+        let value = usd(200, 500_000_000);
+        let duties = |country: &str| estimate_duties(Some(&value), Some(&to(country)), "US", 0.1);
+
+        assert_eq!(duties("CA"), Ok(Some(2005)));
+        assert_eq!(duties("us"), Ok(None));
+        assert_eq!(duties(""), Ok(None));
+        assert_eq!(estimate_duties(None, Some(&to("CA")), "US", 0.1), Ok(None));
+    }
+
+    #[test]
+    fn test_customs_currency_is_validated() {
+        let value = Money {
+            currency_code: "EUR".into(),
+            ..usd(200, 0)
+        };
+        assert!(estimate_duties(Some(&value), Some(&to("CA")), "US", 0.1).is_err());
+    }
+}
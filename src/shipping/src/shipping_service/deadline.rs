@@ -0,0 +1,277 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    convert::Infallible,
+    future::{ready, Ready},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use actix_web::{dev::Payload, http::header::HeaderMap, FromRequest, HttpRequest};
+use opentelemetry::KeyValue;
+
+use super::InstrumentationLevel;
+
+const REQUEST_DEADLINE_HEADER: &str = "x-request-deadline";
+const GRPC_TIMEOUT_HEADER: &str = "grpc-timeout";
+
+/// Longest `grpc-timeout` value the gRPC spec allows, in digits.
+const MAX_GRPC_TIMEOUT_DIGITS: usize = 8;
+
+/// When the caller stops waiting for a response: the `X-Request-Deadline`
+/// header, in milliseconds since the Unix epoch, or a `grpc-timeout` such
+/// as `250m` counted from arrival. The earlier one wins when both are
+/// sent; malformed values are ignored.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RequestDeadline {
+    pub at: Option<Instant>,
+}
+
+impl RequestDeadline {
+    fn from_headers(headers: &HeaderMap, arrived: Instant, now: SystemTime) -> Self {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+        };
+
+        let absolute = header(REQUEST_DEADLINE_HEADER)
+            .and_then(|value| value.parse::<u64>().ok())
+            .map(|epoch_ms| {
+                let since_epoch = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+                Duration::from_millis(epoch_ms).saturating_sub(since_epoch)
+            });
+        let relative = header(GRPC_TIMEOUT_HEADER).and_then(parse_grpc_timeout);
+
+        RequestDeadline {
+            at: absolute
+                .into_iter()
+                .chain(relative)
+                .min()
+                .and_then(|remaining| arrived.checked_add(remaining)),
+        }
+    }
+
+    /// Time left before the deadline, `None` without one.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.at
+            .map(|at| at.saturating_duration_since(Instant::now()))
+    }
+
+    /// Whether the deadline has already passed.
+    pub fn is_expired(&self) -> bool {
+        self.remaining()
+            .is_some_and(|remaining| remaining.is_zero())
+    }
+
+    /// Records the time left on the current span.
+    pub fn record(&self, level: InstrumentationLevel) {
+        if let Some(remaining) = self.remaining() {
+            level.set_attribute(
+                InstrumentationLevel::Standard,
+                KeyValue::new(
+                    "app.shipping.deadline.remaining_ms",
+                    remaining.as_millis() as i64,
+                ),
+            );
+        }
+    }
+}
+
+/// A `grpc-timeout` value: at most eight digits followed by one of the
+/// units `H`, `M`, `S`, `m`, `u` or `n`.
+fn parse_grpc_timeout(value: &str) -> Option<Duration> {
+    let unit = value.chars().last()?;
+    let digits = &value[..value.len() - unit.len_utf8()];
+    if digits.is_empty()
+        || digits.len() > MAX_GRPC_TIMEOUT_DIGITS
+        || !digits.bytes().all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+    let n: u64 = digits.parse().ok()?;
+    Some(match unit {
+        'H' => Duration::from_secs(n * 3600),
+        'M' => Duration::from_secs(n * 60),
+        'S' => Duration::from_secs(n),
+        'm' => Duration::from_millis(n),
+        'u' => Duration::from_micros(n),
+        'n' => Duration::from_nanos(n),
+        _ => return None,
+    })
+}
+
+impl FromRequest for RequestDeadline {
+    type Error = Infallible;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Ok(RequestDeadline::from_headers(
+            req.headers(),
+            Instant::now(),
+            SystemTime::now(),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use actix_web::{http::StatusCode, test, web, App, HttpResponse};
+
+    use super::*;
+    use crate::shipping_service::{
+        config::FallbackConfig, get_quote, ApiError, AppData, CartItem, GetQuoteRequest,
+        ShippingConfig,
+    };
+    use crate::test_support::spawn_mock;
+
+    fn deadline(headers: &[(&str, &str)]) -> Option<Duration> {
+        let arrived = Instant::now();
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut req = test::TestRequest::default();
+        for header in headers {
+            req = req.insert_header(*header);
+        }
+        RequestDeadline::from_headers(req.to_http_request().headers(), arrived, now)
+            .at
+            .map(|at| at - arrived)
+    }
+
+    #[actix_web::test]
+    async fn test_deadline_headers_are_parsed() {
+        assert_eq!(deadline(&[]), None);
+        assert_eq!(
+            deadline(&[("grpc-timeout", "250m")]),
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(
+            deadline(&[("grpc-timeout", "2S")]),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(
+            deadline(&[("grpc-timeout", "1H")]),
+            Some(Duration::from_secs(3600))
+        );
+        assert_eq!(
+            deadline(&[("grpc-timeout", "1500u")]),
+            Some(Duration::from_micros(1500))
+        );
+        assert_eq!(
+            deadline(&[("x-request-deadline", "1700000000400")]),
+            Some(Duration::from_millis(400))
+        );
+        assert_eq!(
+            deadline(&[("x-request-deadline", "1699999999000")]),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(
+            deadline(&[
+                ("x-request-deadline", "1700000000400"),
+                ("grpc-timeout", "100m")
+            ]),
+            Some(Duration::from_millis(100))
+        );
+        for malformed in ["", "m", "250", "250x", "-5m", "123456789m", "1.5S"] {
+            assert_eq!(
+                deadline(&[("grpc-timeout", malformed)]),
+                None,
+                "{malformed}"
+            );
+        }
+        assert_eq!(deadline(&[("x-request-deadline", "soon")]), None);
+    }
+
+    /// A quote service answering after `delay`, counting its calls.
+    fn spawn_slow_quote_mock(delay: Duration) -> (String, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let hits = calls.clone();
+        let addr = spawn_mock(move |cfg| {
+            let hits = hits.clone();
+            cfg.route(
+                "/getquote",
+                web::post().to(move || {
+                    hits.fetch_add(1, Ordering::SeqCst);
+                    async move {
+                        actix_web::rt::time::sleep(delay).await;
+                        HttpResponse::Ok().body("10.99")
+                    }
+                }),
+            );
+        });
+        (addr, calls)
+    }
+
+    fn quote_request() -> test::TestRequest {
+        test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(GetQuoteRequest {
+                items: vec![CartItem {
+                    quantity: 1,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            })
+    }
+
+    fn config(quote_addr: String) -> ShippingConfig {
+        ShippingConfig {
+            quote_addr,
+            quote_timeout: Duration::from_secs(5),
+            fallback: FallbackConfig {
+                enabled: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_deadline_shrinks_the_quote_timeout() {
+        let (quote_addr, calls) = spawn_slow_quote_mock(Duration::from_secs(2));
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config(quote_addr)).register(cfg))
+                .service(get_quote),
+        )
+        .await;
+
+        let started = Instant::now();
+        let req = quote_request().insert_header(("grpc-timeout", "100m"));
+        let resp = test::call_service(&app, req.to_request()).await;
+        assert_eq!(resp.status(), StatusCode::GATEWAY_TIMEOUT);
+        let err: ApiError = test::read_body_json(resp).await;
+        assert_eq!(err.code, "quote_timeout");
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[actix_web::test]
+    async fn test_expired_deadline_skips_the_quote_service() {
+        let (quote_addr, calls) = spawn_slow_quote_mock(Duration::ZERO);
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config(quote_addr)).register(cfg))
+                .service(get_quote),
+        )
+        .await;
+
+        let past = SystemTime::now().duration_since(UNIX_EPOCH).unwrap() - Duration::from_secs(1);
+        let req =
+            quote_request().insert_header(("x-request-deadline", past.as_millis().to_string()));
+        let resp = test::call_service(&app, req.to_request()).await;
+        assert_eq!(resp.status(), StatusCode::GATEWAY_TIMEOUT);
+        let err: ApiError = test::read_body_json(resp).await;
+        assert_eq!(err.code, "quote_timeout");
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+        let resp = test::call_service(&app, quote_request().to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
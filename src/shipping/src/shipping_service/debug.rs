@@ -0,0 +1,335 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    convert::Infallible,
+    future::{ready, Ready},
+    time::Duration,
+};
+
+use actix_web::{
+    body::MessageBody,
+    dev::{Payload, ServiceRequest, ServiceResponse},
+    http::header::{HeaderMap, HeaderName, HeaderValue},
+    middleware::Next,
+    web, Error, FromRequest, HttpRequest,
+};
+use opentelemetry::{trace::get_active_span, KeyValue};
+
+use super::ShippingConfig;
+
+const FORCE_FALLBACK_HEADER: &str = "x-debug-force-fallback";
+const FORCE_LATENCY_HEADER: &str = "x-debug-force-latency-ms";
+const CURRENCY_HEADER: &str = "x-debug-currency";
+const TRACE_SAMPLED_HEADER: HeaderName = HeaderName::from_static("x-trace-sampled");
+const TRACEPARENT_HEADER: HeaderName = HeaderName::from_static("traceparent");
+
+/// Upper bound on the injected latency so a typo can't park a worker.
+const MAX_FORCED_LATENCY_MS: u64 = 30_000;
+
+/// Per-request behavior overrides taken from `X-Debug-*` headers, letting
+/// demo presenters trigger specific paths without a restart. Extraction
+/// yields no overrides unless `DEBUG_ENDPOINTS_ENABLED` is set.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DebugOverrides {
+    /// Prices parcels by the fallback formula without asking the quote
+    /// service.
+    pub force_fallback: bool,
+    /// Delay before the request is quoted.
+    pub latency: Option<Duration>,
+    /// Currency the quote is converted into, in place of the request's.
+    pub currency: Option<String>,
+}
+
+impl DebugOverrides {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+        };
+
+        DebugOverrides {
+            force_fallback: header(FORCE_FALLBACK_HEADER)
+                .is_some_and(|value| value.eq_ignore_ascii_case("true") || value == "1"),
+            latency: header(FORCE_LATENCY_HEADER)
+                .and_then(|value| value.parse::<u64>().ok())
+                .map(|ms| Duration::from_millis(ms.min(MAX_FORCED_LATENCY_MS))),
+            currency: header(CURRENCY_HEADER)
+                .filter(|code| code.len() == 3 && code.chars().all(|c| c.is_ascii_alphabetic()))
+                .map(str::to_ascii_uppercase),
+        }
+    }
+
+    /// Records the active overrides on the current span.
+    pub fn record(&self) {
+        if *self == DebugOverrides::default() {
+            return;
+        }
+        get_active_span(|span| {
+            span.set_attribute(KeyValue::new(
+                "app.shipping.debug.force_fallback",
+                self.force_fallback,
+            ));
+            if let Some(latency) = self.latency {
+                span.set_attribute(KeyValue::new(
+                    "app.shipping.debug.latency_ms",
+                    latency.as_millis() as i64,
+                ));
+            }
+            if let Some(currency) = &self.currency {
+                span.set_attribute(KeyValue::new(
+                    "app.shipping.debug.currency",
+                    currency.clone(),
+                ));
+            }
+        });
+    }
+
+    /// Sleeps for the forced latency, if any.
+    pub async fn apply_latency(&self) {
+        if let Some(latency) = self.latency {
+            actix_web::rt::time::sleep(latency).await;
+        }
+    }
+}
+
+impl FromRequest for DebugOverrides {
+    type Error = Infallible;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let enabled = req
+            .app_data::<web::Data<ShippingConfig>>()
+            .is_some_and(|config| config.debug_endpoints_enabled);
+
+        ready(Ok(if enabled {
+            DebugOverrides::from_headers(req.headers())
+        } else {
+            DebugOverrides::default()
+        }))
+    }
+}
+
+/// Middleware telling presenters which trace a response belongs to: under
+/// `DEBUG_ENDPOINTS_ENABLED`, responses carry `X-Trace-Sampled` and the
+/// W3C `traceparent` of the request's span.
+pub async fn trace_headers(
+    config: web::Data<ShippingConfig>,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let span_context = get_active_span(|span| span.span_context().clone());
+    let mut resp = next.call(req).await?;
+    if !config.debug_endpoints_enabled {
+        return Ok(resp);
+    }
+
+    let headers = resp.headers_mut();
+    headers.insert(
+        TRACE_SAMPLED_HEADER,
+        HeaderValue::from_static(if span_context.is_sampled() {
+            "true"
+        } else {
+            "false"
+        }),
+    );
+    if span_context.is_valid() {
+        let traceparent = format!(
+            "00-{}-{}-{:02x}",
+            span_context.trace_id(),
+            span_context.span_id(),
+            span_context.trace_flags().to_u8()
+        );
+        if let Ok(value) = HeaderValue::from_str(&traceparent) {
+            headers.insert(TRACEPARENT_HEADER, value);
+        }
+    }
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use actix_web::{http::StatusCode, middleware::from_fn, test, App};
+    use opentelemetry::{
+        trace::{FutureExt, SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState},
+        Context,
+    };
+
+    use super::*;
+    use crate::shipping_service::{
+        currency, get_order, get_quote, money::decimal_amount, Address, AppData, CartItem,
+        GetQuoteRequest, GetQuoteResponse,
+    };
+    use crate::test_support::{in_test_span, spawn_quote_mock};
+
+    fn debug_request(debug_enabled: bool) -> test::TestRequest {
+        let config = ShippingConfig {
+            debug_endpoints_enabled: debug_enabled,
+            ..Default::default()
+        };
+        test::TestRequest::default()
+            .app_data(web::Data::new(config))
+            .insert_header((FORCE_FALLBACK_HEADER, "true"))
+            .insert_header((FORCE_LATENCY_HEADER, "250"))
+            .insert_header((CURRENCY_HEADER, "eur"))
+    }
+
+    #[actix_web::test]
+    async fn test_overrides_extracted_under_debug_flag() {
+        let req = debug_request(true).to_http_request();
+        let overrides = DebugOverrides::extract(&req).await.unwrap();
+        assert_eq!(
+            overrides,
+            DebugOverrides {
+                force_fallback: true,
+                latency: Some(Duration::from_millis(250)),
+                currency: Some("EUR".to_string()),
+            }
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_overrides_ignored_without_debug_flag() {
+        let req = debug_request(false).to_http_request();
+        let overrides = DebugOverrides::extract(&req).await.unwrap();
+        assert_eq!(overrides, DebugOverrides::default());
+    }
+
+    #[actix_web::test]
+    async fn test_forced_latency_only_applies_under_debug_flag() {
+        for debug_enabled in [true, false] {
+            let config = ShippingConfig {
+                debug_endpoints_enabled: debug_enabled,
+                ..Default::default()
+            };
+            let app = test::init_service(
+                App::new()
+                    .configure(|cfg| AppData::new(config).register(cfg))
+                    .service(get_quote),
+            )
+            .await;
+            // An invalid address keeps the request away from the quote service.
+            let req = test::TestRequest::post()
+                .uri("/get-quote")
+                .insert_header((FORCE_LATENCY_HEADER, "200"))
+                .set_json(GetQuoteRequest {
+                    address: Some(Address {
+                        city: "c".repeat(1000),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                })
+                .to_request();
+
+            let start = Instant::now();
+            let resp = test::call_service(&app, req).await;
+            let elapsed = start.elapsed();
+
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+            assert_eq!(elapsed >= Duration::from_millis(200), debug_enabled);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_overrides_force_the_fallback_and_the_currency() {
+        let config = ShippingConfig {
+            debug_endpoints_enabled: true,
+            quote_addr: spawn_quote_mock("10.99"),
+            currency_addr: Some(currency::spawn_mock(0.9)),
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote),
+        )
+        .await;
+        let quote = |headers: &[(&'static str, &'static str)]| {
+            let mut req = test::TestRequest::post().uri("/get-quote");
+            for header in headers {
+                req = req.insert_header(*header);
+            }
+            req.set_json(GetQuoteRequest {
+                items: vec![CartItem {
+                    product_id: "p1".into(),
+                    quantity: 3,
+                    ..Default::default()
+                }],
+                currency: Some("USD".into()),
+                ..Default::default()
+            })
+            .to_request()
+        };
+        let cost = |quote: GetQuoteResponse| {
+            let cost = quote.cost_usd.unwrap();
+            (cost.currency_code.clone(), decimal_amount(&cost))
+        };
+
+        let plain: GetQuoteResponse = test::call_and_read_body_json(&app, quote(&[])).await;
+        assert_eq!(cost(plain), ("USD".to_string(), "10.99".to_string()));
+
+        // 5.00 plus 0.50 for each of the 3 items, by the default formula.
+        let forced: GetQuoteResponse =
+            test::call_and_read_body_json(&app, quote(&[(FORCE_FALLBACK_HEADER, "1")])).await;
+        assert_eq!(cost(forced), ("USD".to_string(), "6.50".to_string()));
+
+        let converted: GetQuoteResponse =
+            test::call_and_read_body_json(&app, quote(&[(CURRENCY_HEADER, "eur")])).await;
+        assert_eq!(cost(converted), ("EUR".to_string(), "9.891".to_string()));
+    }
+
+    #[actix_web::test]
+    async fn test_trace_headers_reflect_sampling_decision() {
+        let config = ShippingConfig {
+            debug_endpoints_enabled: true,
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .wrap(from_fn(trace_headers))
+                .service(get_order),
+        )
+        .await;
+        let header = |resp: &ServiceResponse<_>, name| {
+            resp.headers()
+                .get(name)
+                .map(|value: &HeaderValue| value.to_str().unwrap().to_string())
+        };
+
+        let req = test::TestRequest::get().uri("/order/missing").to_request();
+        let (resp, span) = in_test_span("get-order", test::call_service(&app, req)).await;
+        let span_context = span.span_context;
+        assert_eq!(header(&resp, TRACE_SAMPLED_HEADER).as_deref(), Some("true"));
+        assert_eq!(
+            header(&resp, TRACEPARENT_HEADER).unwrap(),
+            format!(
+                "00-{}-{}-01",
+                span_context.trace_id(),
+                span_context.span_id()
+            )
+        );
+
+        let unsampled = Context::new().with_remote_span_context(SpanContext::new(
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+            SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+            TraceFlags::default(),
+            true,
+            TraceState::default(),
+        ));
+        let req = test::TestRequest::get().uri("/order/missing").to_request();
+        let resp = test::call_service(&app, req).with_context(unsampled).await;
+        assert_eq!(
+            header(&resp, TRACE_SAMPLED_HEADER).as_deref(),
+            Some("false")
+        );
+        assert_eq!(
+            header(&resp, TRACEPARENT_HEADER).as_deref(),
+            Some("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00")
+        );
+    }
+}
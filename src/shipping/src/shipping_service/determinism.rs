@@ -0,0 +1,166 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use chrono::{DateTime, TimeZone, Utc};
+use uuid::{Builder, Uuid};
+
+use super::ShippingConfig;
+
+/// Seed of the id generator in deterministic mode.
+const SEED: u64 = 0x5eed_5eed_5eed_5eed;
+
+/// Increment of the SplitMix64 generator.
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// Current time, frozen at 2024-01-01T00:00:00Z under `DETERMINISTIC_MODE`.
+pub fn now(config: &ShippingConfig) -> DateTime<Utc> {
+    if config.deterministic_mode {
+        Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap()
+    } else {
+        Utc::now()
+    }
+}
+
+/// Source of the ids and samples handed out by the service. Under
+/// `DETERMINISTIC_MODE` it is a fixed-seed SplitMix64 generator, so that
+/// the same sequence of requests gets the same ids on every run.
+#[derive(Debug, Default)]
+pub struct Entropy {
+    seeded: Option<AtomicU64>,
+}
+
+impl Entropy {
+    pub fn new(config: &ShippingConfig) -> Self {
+        Entropy {
+            seeded: config.deterministic_mode.then(|| AtomicU64::new(SEED)),
+        }
+    }
+
+    /// 16 random bytes.
+    pub fn bytes(&self) -> [u8; 16] {
+        match &self.seeded {
+            Some(state) => {
+                let high = next(state) as u128;
+                let low = next(state) as u128;
+                ((high << 64) | low).to_be_bytes()
+            }
+            // All but the 6 version and variant bits of a v4 UUID are random.
+            None => Uuid::new_v4().into_bytes(),
+        }
+    }
+
+    /// A random v4 UUID.
+    pub fn uuid(&self) -> Uuid {
+        match &self.seeded {
+            Some(_) => Builder::from_random_bytes(self.bytes()).into_uuid(),
+            None => Uuid::new_v4(),
+        }
+    }
+
+    /// A uniformly distributed fraction in `[0, 1)`.
+    pub fn fraction(&self) -> f64 {
+        let bits = match &self.seeded {
+            Some(state) => next(state),
+            // The top 64 bits of a v4 UUID hold 60 random bits past its
+            // version field; the top 48 are enough here.
+            None => (Uuid::new_v4().as_u128() >> 64) as u64,
+        };
+        (bits >> 16) as f64 / (1u64 << 48) as f64
+    }
+}
+
+/// Advances the SplitMix64 generator at `state`.
+fn next(state: &AtomicU64) -> u64 {
+    let mut z = state
+        .fetch_add(GOLDEN_GAMMA, Ordering::Relaxed)
+        .wrapping_add(GOLDEN_GAMMA);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, App};
+
+    use super::*;
+    use crate::shipping_service::{
+        get_order, get_quote, ship_order, AppData, CartItem, GetQuoteRequest, ShipOrderRequest,
+        ShipOrderResponse,
+    };
+    use crate::test_support::spawn_quote_mock;
+
+    async fn run_flow(quote_addr: &str) -> Vec<Vec<u8>> {
+        let config = ShippingConfig {
+            quote_addr: quote_addr.to_string(),
+            deterministic_mode: true,
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote)
+                .service(ship_order)
+                .service(get_order),
+        )
+        .await;
+        let items = vec![CartItem {
+            product_id: "OLJCESPC7Z".into(),
+            quantity: 2,
+            ..Default::default()
+        }];
+
+        let req = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(GetQuoteRequest {
+                items: items.clone(),
+                ..Default::default()
+            })
+            .to_request();
+        let quote = test::call_and_read_body(&app, req).await;
+
+        let req = test::TestRequest::post()
+            .uri("/ship-order")
+            .set_json(ShipOrderRequest {
+                items,
+                ..Default::default()
+            })
+            .to_request();
+        let shipped = test::call_and_read_body(&app, req).await;
+
+        let order_id = serde_json::from_slice::<ShipOrderResponse>(&shipped)
+            .unwrap()
+            .order_id;
+        let req = test::TestRequest::get()
+            .uri(&format!("/order/{order_id}"))
+            .to_request();
+        let order = test::call_and_read_body(&app, req).await;
+
+        vec![quote.to_vec(), shipped.to_vec(), order.to_vec()]
+    }
+
+    #[actix_web::test]
+    async fn test_deterministic_runs_are_identical() {
+        let quote_addr = spawn_quote_mock("10.99");
+        let first = run_flow(&quote_addr).await;
+        let second = run_flow(&quote_addr).await;
+        assert_eq!(first, second);
+
+        let order = std::str::from_utf8(&first[2]).unwrap();
+        assert!(order.contains("2024-01-01T00:00:00"));
+    }
+
+    #[actix_web::test]
+    async fn test_seeded_ids_are_distinct_v4() {
+        let entropy = Entropy::new(&ShippingConfig {
+            deterministic_mode: true,
+            ..Default::default()
+        });
+        let (a, b) = (entropy.uuid(), entropy.uuid());
+        assert_ne!(a, b);
+        assert_eq!(a.get_version_num(), 4);
+        assert!((0.0..1.0).contains(&entropy.fraction()));
+    }
+}
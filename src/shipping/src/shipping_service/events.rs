@@ -0,0 +1,124 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use opentelemetry::KeyValue;
+
+use super::breaker::{BreakerState, Health};
+use super::items::ItemCount;
+use super::shipping_types::Quote;
+use super::InstrumentationLevel;
+
+const COST_TOTAL: &str = "app.shipping.cost.total";
+const ITEMS_COUNT: &str = "app.shipping.items.count";
+const WARN_ABOVE: &str = "app.shipping.quote.warn_above";
+const HEALTH: &str = "app.shipping.quote.health";
+const CIRCUIT_STATE: &str = "app.shipping.quote.circuit_state";
+const BACKEND: &str = "app.shipping.quote.backend";
+
+/// A span event of the quote path. Constructors own the event names and
+/// attribute keys, so every call site records them the same way.
+#[derive(Debug)]
+pub struct QuoteEvent {
+    name: &'static str,
+    detail: InstrumentationLevel,
+    attributes: Vec<KeyValue>,
+}
+
+impl QuoteEvent {
+    /// The quote service priced `count` items at `quote`.
+    pub fn received(quote: &Quote, count: ItemCount) -> Self {
+        QuoteEvent {
+            name: "Received Quote",
+            detail: InstrumentationLevel::Standard,
+            attributes: vec![
+                KeyValue::new(COST_TOTAL, quote.to_string()),
+                KeyValue::new(ITEMS_COUNT, count.as_attr()),
+            ],
+        }
+    }
+
+    /// `quote` exceeds the `QUOTE_WARN_ABOVE` threshold.
+    pub fn high_value(quote: &Quote, threshold: f64) -> Self {
+        QuoteEvent {
+            name: "High Value Quote",
+            detail: InstrumentationLevel::Standard,
+            attributes: vec![
+                KeyValue::new(COST_TOTAL, quote.to_string()),
+                KeyValue::new(WARN_ABOVE, threshold),
+            ],
+        }
+    }
+
+    /// The price of `count` items was reused from an earlier quote.
+    pub fn cache_hit(count: ItemCount) -> Self {
+        QuoteEvent {
+            name: "Quote Cache Hit",
+            detail: InstrumentationLevel::Standard,
+            attributes: vec![KeyValue::new(ITEMS_COUNT, count.as_attr())],
+        }
+    }
+
+    /// The quote service became `health`, marking the start or end of an
+    /// outage. Recorded at every level so traces show outages even when
+    /// detail is trimmed.
+    pub fn health_changed(health: Health) -> Self {
+        QuoteEvent {
+            name: match health {
+                Health::Healthy => "Quote Service Recovered",
+                Health::Unhealthy => "Quote Service Unhealthy",
+            },
+            detail: InstrumentationLevel::Minimal,
+            attributes: vec![KeyValue::new(HEALTH, health.as_str())],
+        }
+    }
+
+    /// The breaker guarding `backend`, or `global`, moved to `state`.
+    /// Recorded at every level, like health changes.
+    pub fn circuit_changed(backend: &str, state: BreakerState) -> Self {
+        QuoteEvent {
+            name: match state {
+                BreakerState::Closed => "Quote Circuit Closed",
+                BreakerState::Open => "Quote Circuit Opened",
+                BreakerState::HalfOpen => "Quote Circuit Half-Opened",
+            },
+            detail: InstrumentationLevel::Minimal,
+            attributes: vec![
+                KeyValue::new(CIRCUIT_STATE, state.as_str()),
+                KeyValue::new(BACKEND, backend.to_string()),
+            ],
+        }
+    }
+
+    /// Adds the event to the active span, if `level` records it.
+    pub fn emit(self, level: InstrumentationLevel) {
+        level.add_event(self.detail, self.name, self.attributes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::in_test_span;
+
+    #[actix_web::test]
+    async fn test_received_event_attributes() {
+        let quote = Quote {
+            dollars: 10,
+            cents: 99,
+        };
+        let ((), span) = in_test_span("quote", async {
+            QuoteEvent::received(&quote, ItemCount::new(3)).emit(InstrumentationLevel::Standard)
+        })
+        .await;
+
+        let event = span.events.iter().next().unwrap();
+        assert_eq!(event.name, "Received Quote");
+        assert_eq!(
+            event.attributes,
+            [
+                KeyValue::new("app.shipping.cost.total", "10.99"),
+                KeyValue::new("app.shipping.items.count", 3),
+            ]
+        );
+    }
+}
@@ -0,0 +1,87 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::config::PricingConfig;
+use super::customs::is_international;
+use super::items::ItemCount;
+use super::shipping_types::{Address, CartItem};
+
+/// The items billed for shipping: all but those flagged `free_shipping`.
+pub fn billable_items(items: &[CartItem]) -> Vec<CartItem> {
+    items
+        .iter()
+        .filter(|item| item.free_shipping != Some(true))
+        .cloned()
+        .collect()
+}
+
+/// Whether a shipment of `item_count` items ships free.
+pub fn is_free_shipping(item_count: ItemCount, pricing: &PricingConfig) -> bool {
+    pricing
+        .free_shipping_min_items
+        .is_some_and(|min_items| item_count.get() >= min_items)
+}
+
+/// The handling fee in cents. Quotes are priced in dollars and converted
+/// into the requested currency as a whole, fee included.
+pub fn handling_fee_cents(pricing: &PricingConfig) -> u64 {
+    dollars_to_cents(pricing.handling_fee)
+}
+
+/// `dollars` in cents; a negative amount rounds to nothing.
+pub fn dollars_to_cents(dollars: f64) -> u64 {
+    (dollars * 100.0).round() as u64
+}
+
+/// Dollars added for shipping to `destination` from `origin_country`, its
+/// country's entry in `country_surcharges`. Domestic shipments, and those
+/// to countries missing from the table, pay none.
+pub fn country_surcharge(
+    pricing: &PricingConfig,
+    destination: Option<&Address>,
+    origin_country: &str,
+) -> f64 {
+    let Some(address) = destination.filter(|address| is_international(address, origin_country))
+    else {
+        return 0.0;
+    };
+    let country = address.country.trim();
+    pricing
+        .country_surcharges
+        .iter()
+        .find(|(code, _)| code.eq_ignore_ascii_case(country))
+        .map_or(0.0, |(_, surcharge)| *surcharge)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handling_fee_is_in_cents() {
+        let pricing = PricingConfig {
+            handling_fee: 2.499,
+            ..Default::default()
+        };
+        assert_eq!(handling_fee_cents(&pricing), 250);
+        assert_eq!(dollars_to_cents(-1.0), 0);
+    }
+
+    #[test]
+    fn test_country_surcharge_applies_to_listed_international_destinations() {
+        let pricing = PricingConfig {
+            country_surcharges: [("CA".to_string(), 7.5), ("US".to_string(), 3.0)].into(),
+            ..Default::default()
+        };
+        let to = |country: &str| Address {
+            country: country.to_string(),
+            ..Default::default()
+        };
+        let surcharge = |country| country_surcharge(&pricing, Some(&to(country)), "US");
+        assert_eq!(surcharge("US"), 0.0);
+        assert_eq!(surcharge("ca"), 7.5);
+        assert_eq!(surcharge("DE"), 0.0);
+        assert_eq!(surcharge(""), 0.0);
+        assert_eq!(country_surcharge(&pricing, None, "US"), 0.0);
+    }
+}
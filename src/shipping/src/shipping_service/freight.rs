@@ -0,0 +1,97 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use chrono::{DateTime, Utc};
+
+use super::config::{FreightRates, ParcelLimits};
+use super::shipping_types::{CartItem, QuoteConfidence, QuoteSource, ShippingQuote};
+use super::weight::billable_weight;
+
+/// Freight is billed by the started kilogram.
+const FREIGHT_BILLING_INCREMENT_KG: f64 = 1.0;
+
+/// How a shipment moves: as parcels, or as freight once an item is too
+/// heavy or too large for parcel carriers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShippingMode {
+    Parcel,
+    Freight,
+}
+
+impl ShippingMode {
+    pub fn for_items(items: &[CartItem], limits: &ParcelLimits) -> Self {
+        let oversized = |item: &CartItem| {
+            item.weight_kg.is_some_and(|kg| kg > limits.max_weight_kg)
+                || item
+                    .dimensions_cm
+                    .is_some_and(|sides| sides.iter().any(|side| *side > limits.max_side_cm))
+        };
+        if items.iter().any(oversized) {
+            ShippingMode::Freight
+        } else {
+            ShippingMode::Parcel
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ShippingMode::Parcel => "parcel",
+            ShippingMode::Freight => "freight",
+        }
+    }
+}
+
+/// Prices `items` from the freight rate table: a base rate plus the billed
+/// weight, with the freight surcharge as its own charge.
+pub fn freight_quote(
+    items: &[CartItem],
+    rates: &FreightRates,
+    quoted_at: DateTime<Utc>,
+) -> ShippingQuote {
+    let billed_kg = billable_weight(items, Some(FREIGHT_BILLING_INCREMENT_KG))
+        .map_or(0.0, |weight| weight.billed_kg);
+    let mut quote = ShippingQuote {
+        total_cents: ((rates.base_rate + billed_kg * rates.per_kg_rate) * 100.0).round() as u64,
+        charges: vec![],
+        currency: "USD".to_string(),
+        source: QuoteSource::RateTable,
+        confidence: QuoteConfidence::Exact,
+        quoted_at,
+    };
+    quote.add_charge(
+        "Freight surcharge",
+        (rates.surcharge * 100.0).round() as u64,
+    );
+    quote
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mode_follows_parcel_limits() {
+        let limits = ParcelLimits {
+            max_weight_kg: 30.0,
+            max_side_cm: 150.0,
+        };
+        let item = |weight_kg, dimensions_cm| CartItem {
+            product_id: "OLJCESPC7Z".into(),
+            quantity: 1,
+            weight_kg: Some(weight_kg),
+            dimensions_cm,
+            ..Default::default()
+        };
+
+        let mode = |items: &[CartItem]| ShippingMode::for_items(items, &limits);
+        assert_eq!(
+            mode(&[item(2.0, Some([40.0, 30.0, 20.0]))]),
+            ShippingMode::Parcel
+        );
+        assert_eq!(mode(&[item(31.0, None)]), ShippingMode::Freight);
+        assert_eq!(
+            mode(&[item(2.0, None), item(2.0, Some([40.0, 200.0, 20.0]))]),
+            ShippingMode::Freight
+        );
+    }
+}
@@ -0,0 +1,683 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+//! The `oteldemo.ShippingService` gRPC API of the upstream demo, served from
+//! the same business logic as the HTTP handlers.
+
+use std::{
+    convert::Infallible,
+    fmt,
+    future::Future,
+    marker::PhantomData,
+    str::FromStr,
+    task::{Context as TaskContext, Poll},
+};
+
+use actix_rt::{Arbiter, ArbiterHandle};
+use opentelemetry::{
+    context::FutureExt,
+    global,
+    propagation::{Extractor, Injector},
+    trace::{SpanKind, Status as SpanStatus, TraceContextExt, Tracer},
+    KeyValue,
+};
+use tokio::sync::oneshot;
+use tonic::{
+    body::Body as GrpcBody,
+    codec::{Codec, DecodeBuf, Decoder, EncodeBuf, Encoder},
+    codegen::{http, Body, BoxFuture, Service, StdError},
+    metadata::{KeyRef, MetadataKey, MetadataMap, MetadataValue},
+    server::{Grpc, NamedService, UnaryService},
+    transport::{server::TcpIncoming, Server},
+    Status,
+};
+
+use super::items::ItemCount;
+use super::quote::create_quote_from_count;
+use super::validation::{check_zero_items, validate_item_count, validate_item_quantity};
+use super::{
+    create_order, quote_money, Address, AppData, CartItem, Money, ShipOrderRequest, ShippingError,
+};
+
+const SERVICE: &str = "oteldemo.ShippingService";
+
+/// Which servers to run, set by `SHIPPING_PROTOCOL`. The gRPC server takes
+/// `SHIPPING_PORT` when it runs alone and `SHIPPING_GRPC_PORT` otherwise.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ServeProtocol {
+    #[default]
+    Http,
+    Grpc,
+    Both,
+}
+
+impl ServeProtocol {
+    pub fn serves_http(&self) -> bool {
+        matches!(self, ServeProtocol::Http | ServeProtocol::Both)
+    }
+}
+
+impl FromStr for ServeProtocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "http" => Ok(ServeProtocol::Http),
+            "grpc" => Ok(ServeProtocol::Grpc),
+            "both" => Ok(ServeProtocol::Both),
+            _ => Err(format!(
+                "unknown protocol {s:?}, expected http, grpc or both"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ServeProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ServeProtocol::Http => "http",
+            ServeProtocol::Grpc => "grpc",
+            ServeProtocol::Both => "both",
+        })
+    }
+}
+
+/// Messages of `pb/demo.proto` used by the shipping service.
+pub mod pb {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct CartItem {
+        #[prost(string, tag = "1")]
+        pub product_id: String,
+        #[prost(int32, tag = "2")]
+        pub quantity: i32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Address {
+        #[prost(string, tag = "1")]
+        pub street_address: String,
+        #[prost(string, tag = "2")]
+        pub city: String,
+        #[prost(string, tag = "3")]
+        pub state: String,
+        #[prost(string, tag = "4")]
+        pub country: String,
+        #[prost(string, tag = "5")]
+        pub zip_code: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Money {
+        #[prost(string, tag = "1")]
+        pub currency_code: String,
+        #[prost(int64, tag = "2")]
+        pub units: i64,
+        #[prost(int32, tag = "3")]
+        pub nanos: i32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct GetQuoteRequest {
+        #[prost(message, optional, tag = "1")]
+        pub address: Option<Address>,
+        #[prost(message, repeated, tag = "2")]
+        pub items: Vec<CartItem>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct GetQuoteResponse {
+        #[prost(message, optional, tag = "1")]
+        pub cost_usd: Option<Money>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ShipOrderRequest {
+        #[prost(message, optional, tag = "1")]
+        pub address: Option<Address>,
+        #[prost(message, repeated, tag = "2")]
+        pub items: Vec<CartItem>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ShipOrderResponse {
+        #[prost(string, tag = "1")]
+        pub tracking_id: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct CurrencyConversionRequest {
+        #[prost(message, optional, tag = "1")]
+        pub from: Option<Money>,
+        #[prost(string, tag = "2")]
+        pub to_code: String,
+    }
+}
+
+impl From<pb::Address> for Address {
+    fn from(address: pb::Address) -> Self {
+        Address {
+            street_address: address.street_address,
+            city: address.city,
+            state: address.state,
+            country: address.country,
+            zip_code: address.zip_code,
+        }
+    }
+}
+
+impl From<Money> for pb::Money {
+    fn from(money: Money) -> Self {
+        pb::Money {
+            currency_code: money.currency_code,
+            units: money.units.try_into().unwrap_or(i64::MAX),
+            nanos: money.nanos as i32,
+        }
+    }
+}
+
+impl TryFrom<pb::Money> for Money {
+    type Error = String;
+
+    fn try_from(money: pb::Money) -> Result<Self, Self::Error> {
+        match (u64::try_from(money.units), u32::try_from(money.nanos)) {
+            (Ok(units), Ok(nanos)) => Ok(Money {
+                currency_code: money.currency_code,
+                units,
+                nanos,
+            }),
+            _ => Err(format!(
+                "negative amount {}.{:09} {}",
+                money.units, money.nanos, money.currency_code
+            )),
+        }
+    }
+}
+
+impl From<ShippingError> for Status {
+    fn from(err: ShippingError) -> Self {
+        Status::new(err.grpc_code(), err.into_message())
+    }
+}
+
+fn cart_items(items: Vec<pb::CartItem>) -> Result<Vec<CartItem>, ShippingError> {
+    items
+        .into_iter()
+        .map(|item| {
+            let quantity = u32::try_from(item.quantity).map_err(|_| {
+                ShippingError::InvalidItemCount(format!(
+                    "quantity of {:?} is negative",
+                    item.product_id
+                ))
+            })?;
+            Ok(CartItem {
+                product_id: item.product_id,
+                quantity,
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+async fn get_quote(
+    data: AppData,
+    req: pb::GetQuoteRequest,
+) -> Result<pb::GetQuoteResponse, Status> {
+    let config = &data.config;
+    validate_item_count(req.items.len(), config.max_items_in_request)
+        .map_err(ShippingError::TooManyItems)?;
+    let items = cart_items(req.items)?;
+    let count = ItemCount::total(&items).map_err(ShippingError::InvalidItemCount)?;
+    validate_item_quantity(count, config.max_item_count, config.instrumentation_level)
+        .map_err(ShippingError::TooManyItems)?;
+    check_zero_items(
+        count,
+        config.zero_items_policy,
+        config.instrumentation_level,
+    )
+    .map_err(ShippingError::NoItems)?;
+
+    let quote = create_quote_from_count(count, config, &data.quotes, &data.pricing.current())
+        .await
+        .map_err(|status| ShippingError::from_quote_failure(&status))?;
+    Ok(pb::GetQuoteResponse {
+        cost_usd: Some(quote_money(&quote).into()),
+    })
+}
+
+async fn ship_order(
+    data: AppData,
+    req: pb::ShipOrderRequest,
+) -> Result<pb::ShipOrderResponse, Status> {
+    let req = ShipOrderRequest {
+        items: cart_items(req.items)?,
+        address: req.address.map(Address::from),
+        ..Default::default()
+    };
+    let shipped = create_order(
+        req,
+        None,
+        &data.config,
+        &data.pricing.current(),
+        &data.quotes,
+        &data.orders,
+        &data.entropy,
+    )
+    .await?;
+    Ok(pb::ShipOrderResponse {
+        tracking_id: shipped.tracking_id,
+    })
+}
+
+/// The gRPC service, registered with a tonic server by [`serve`].
+#[derive(Clone)]
+pub struct ShippingGrpc {
+    data: AppData,
+    /// Runs the requests: the quote service client isn't `Send`, so they
+    /// can't run on tonic's tasks.
+    arbiter: ArbiterHandle,
+}
+
+impl ShippingGrpc {
+    /// Must be called from within the actix runtime, whose arbiter then runs
+    /// the requests.
+    pub fn new(data: AppData) -> Self {
+        ShippingGrpc {
+            data,
+            arbiter: Arbiter::current(),
+        }
+    }
+
+    /// Runs `handler` in a server span continuing the caller's trace.
+    async fn handle<Req, Resp, F, Fut>(
+        self,
+        method: &'static str,
+        request: tonic::Request<Req>,
+        handler: F,
+    ) -> Result<tonic::Response<Resp>, Status>
+    where
+        Req: Send + 'static,
+        Resp: Send + 'static,
+        F: FnOnce(AppData, Req) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<Resp, Status>> + 'static,
+    {
+        let parent = global::get_text_map_propagator(|propagator| {
+            propagator.extract(&MetadataExtractor(request.metadata()))
+        });
+        let tracer = global::tracer("otel_demo.shipping.grpc");
+        let span = tracer
+            .span_builder(format!("{SERVICE}/{method}"))
+            .with_kind(SpanKind::Server)
+            .with_attributes([
+                KeyValue::new("rpc.system", "grpc"),
+                KeyValue::new("rpc.service", SERVICE),
+                KeyValue::new("rpc.method", method),
+            ])
+            .start_with_context(&tracer, &parent);
+        let cx = parent.with_span(span);
+
+        let (tx, rx) = oneshot::channel();
+        let data = self.data;
+        let req = request.into_inner();
+        let work_cx = cx.clone();
+        let spawned = self.arbiter.spawn_fn(move || {
+            actix_web::rt::spawn(
+                async move {
+                    let _ = tx.send(handler(data, req).await);
+                }
+                .with_context(work_cx),
+            );
+        });
+        let result = if spawned {
+            rx.await
+                .unwrap_or_else(|_| Err(Status::internal("Request was dropped")))
+        } else {
+            Err(Status::unavailable("Shipping service is shutting down"))
+        };
+
+        let span = cx.span();
+        let code = match &result {
+            Ok(_) => tonic::Code::Ok,
+            Err(status) => status.code(),
+        };
+        span.set_attribute(KeyValue::new("rpc.grpc.status_code", code as i64));
+        if let Err(status) = &result {
+            span.set_status(SpanStatus::error(status.message().to_string()));
+        }
+        span.end();
+        result.map(tonic::Response::new)
+    }
+}
+
+impl<B> Service<http::Request<B>> for ShippingGrpc
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<GrpcBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let svc = self.clone();
+        let method = req
+            .uri()
+            .path()
+            .strip_prefix('/')
+            .and_then(|path| path.strip_prefix(SERVICE))
+            .and_then(|path| path.strip_prefix('/'));
+        match method {
+            Some("GetQuote") => unary(req, move |request| {
+                svc.clone().handle("GetQuote", request, get_quote)
+            }),
+            Some("ShipOrder") => unary(req, move |request| {
+                svc.clone().handle("ShipOrder", request, ship_order)
+            }),
+            _ => Box::pin(async { Ok(Status::unimplemented("Unknown method").into_http()) }),
+        }
+    }
+}
+
+impl NamedService for ShippingGrpc {
+    const NAME: &'static str = SERVICE;
+}
+
+/// Serves the gRPC API on `incoming` until `shutdown` resolves, then waits
+/// for the calls in flight. Must be called from within the actix runtime.
+pub async fn serve(
+    data: AppData,
+    incoming: TcpIncoming,
+    shutdown: impl Future<Output = ()>,
+) -> Result<(), tonic::transport::Error> {
+    Server::builder()
+        .add_service(ShippingGrpc::new(data))
+        .serve_with_incoming_shutdown(incoming, shutdown)
+        .await
+}
+
+/// Decodes a unary call from `req` and answers it with `handler`.
+pub(super) fn unary<B, Req, Resp, F, Fut>(
+    req: http::Request<B>,
+    handler: F,
+) -> BoxFuture<http::Response<GrpcBody>, Infallible>
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+    Req: prost::Message + Default + Send + 'static,
+    Resp: prost::Message + Send + 'static,
+    F: FnMut(tonic::Request<Req>) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<tonic::Response<Resp>, Status>> + Send + 'static,
+{
+    struct Unary<F>(F);
+
+    impl<Req, Resp, F, Fut> UnaryService<Req> for Unary<F>
+    where
+        F: FnMut(tonic::Request<Req>) -> Fut,
+        Fut: Future<Output = Result<tonic::Response<Resp>, Status>>,
+    {
+        type Response = Resp;
+        type Future = Fut;
+
+        fn call(&mut self, request: tonic::Request<Req>) -> Self::Future {
+            (self.0)(request)
+        }
+    }
+
+    Box::pin(async move {
+        let mut grpc = Grpc::new(ProstCodec::<Resp, Req>::default());
+        Ok(grpc.unary(Unary(handler), req).await)
+    })
+}
+
+/// Writes the trace context of an outgoing call into its metadata.
+pub struct MetadataInjector<'a>(pub &'a mut MetadataMap);
+
+impl Injector for MetadataInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(key), Ok(value)) = (
+            MetadataKey::from_bytes(key.as_bytes()),
+            MetadataValue::try_from(value),
+        ) {
+            self.0.insert(key, value);
+        }
+    }
+}
+
+/// Reads the trace context of a call from its metadata.
+struct MetadataExtractor<'a>(&'a MetadataMap);
+
+impl Extractor for MetadataExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0
+            .keys()
+            .filter_map(|key| match key {
+                KeyRef::Ascii(key) => Some(key.as_str()),
+                KeyRef::Binary(_) => None,
+            })
+            .collect()
+    }
+}
+
+/// Protobuf codec encoding `E` and decoding `D`.
+pub struct ProstCodec<E, D>(PhantomData<(E, D)>);
+
+impl<E, D> Default for ProstCodec<E, D> {
+    fn default() -> Self {
+        ProstCodec(PhantomData)
+    }
+}
+
+impl<E, D> Codec for ProstCodec<E, D>
+where
+    E: prost::Message + Send + 'static,
+    D: prost::Message + Default + Send + 'static,
+{
+    type Encode = E;
+    type Decode = D;
+    type Encoder = ProstCodec<E, ()>;
+    type Decoder = ProstCodec<(), D>;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        ProstCodec::default()
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        ProstCodec::default()
+    }
+}
+
+impl<E: prost::Message> Encoder for ProstCodec<E, ()> {
+    type Item = E;
+    type Error = Status;
+
+    fn encode(&mut self, item: E, dst: &mut EncodeBuf<'_>) -> Result<(), Status> {
+        item.encode(dst)
+            .map_err(|err| Status::internal(format!("Failed to encode message: {err}")))
+    }
+}
+
+impl<D: prost::Message + Default> Decoder for ProstCodec<(), D> {
+    type Item = D;
+    type Error = Status;
+
+    fn decode(&mut self, src: &mut DecodeBuf<'_>) -> Result<Option<D>, Status> {
+        D::decode(src)
+            .map(Some)
+            .map_err(|err| Status::invalid_argument(format!("Failed to decode message: {err}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use actix_web::{web, HttpRequest, HttpResponse};
+    use tonic::{client::Grpc as GrpcClient, codegen::http::uri::PathAndQuery, transport::Channel};
+
+    use super::*;
+    use crate::shipping_service::ShippingConfig;
+    use crate::test_support::{spawn_mock, spawn_quote_mock, test_spans};
+
+    const TRACE_ID: &str = "0af7651916cd43dd8448eb211c80319c";
+
+    async fn connect(config: ShippingConfig) -> GrpcClient<Channel> {
+        let incoming = TcpIncoming::bind(([127, 0, 0, 1], 0).into()).unwrap();
+        let addr = incoming.local_addr().unwrap();
+        actix_web::rt::spawn(serve(
+            AppData::new(config),
+            incoming,
+            std::future::pending(),
+        ));
+        let channel = Channel::from_shared(format!("http://{addr}"))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        GrpcClient::new(channel)
+    }
+
+    async fn call<Req, Resp>(
+        client: &mut GrpcClient<Channel>,
+        method: &'static str,
+        request: tonic::Request<Req>,
+    ) -> Result<Resp, Status>
+    where
+        Req: prost::Message + Send + Sync + 'static,
+        Resp: prost::Message + Default + Send + Sync + 'static,
+    {
+        client.ready().await.unwrap();
+        let path = PathAndQuery::try_from(format!("/{SERVICE}/{method}")).unwrap();
+        client
+            .unary(request, path, ProstCodec::default())
+            .await
+            .map(tonic::Response::into_inner)
+    }
+
+    #[actix_web::test]
+    async fn test_get_quote_continues_the_callers_trace() {
+        let spans = test_spans();
+        // Only a request for three items, made in the caller's trace, gets a
+        // price.
+        let quote_addr = spawn_mock(|cfg| {
+            cfg.route(
+                "/getquote",
+                web::post().to(
+                    |req: HttpRequest, body: web::Json<serde_json::Value>| async move {
+                        let traced = req
+                            .headers()
+                            .get("traceparent")
+                            .and_then(|value| value.to_str().ok())
+                            .is_some_and(|value| value.contains(TRACE_ID));
+                        if traced && body["numberOfItems"] == 3 {
+                            HttpResponse::Ok().body("10.99")
+                        } else {
+                            HttpResponse::BadRequest().finish()
+                        }
+                    },
+                ),
+            );
+        });
+        let mut client = connect(ShippingConfig {
+            quote_addr,
+            ..Default::default()
+        })
+        .await;
+
+        let mut request = tonic::Request::new(pb::GetQuoteRequest {
+            address: None,
+            items: vec![
+                pb::CartItem {
+                    product_id: "OLJCESPC7Z".into(),
+                    quantity: 2,
+                },
+                pb::CartItem {
+                    product_id: "66VCHSJNUP".into(),
+                    quantity: 1,
+                },
+            ],
+        });
+        request.metadata_mut().insert(
+            "traceparent",
+            format!("00-{TRACE_ID}-00f067aa0ba902b7-01")
+                .parse()
+                .unwrap(),
+        );
+        let resp: pb::GetQuoteResponse = call(&mut client, "GetQuote", request).await.unwrap();
+        assert_eq!(
+            resp.cost_usd,
+            Some(pb::Money {
+                currency_code: "USD".into(),
+                units: 10,
+                nanos: 990_000_000,
+            })
+        );
+
+        let traced: Vec<_> = spans
+            .get_finished_spans()
+            .unwrap()
+            .into_iter()
+            .filter(|span| span.span_context.trace_id().to_string() == TRACE_ID)
+            .collect();
+        let of_kind = |kind| {
+            traced
+                .iter()
+                .find(|span| span.span_kind == kind)
+                .expect("span was not exported")
+        };
+        let server = of_kind(SpanKind::Server);
+        assert_eq!(server.name, "oteldemo.ShippingService/GetQuote");
+        assert_eq!(
+            of_kind(SpanKind::Client).parent_span_id,
+            server.span_context.span_id()
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_server_stops_on_shutdown() {
+        let incoming = TcpIncoming::bind(([127, 0, 0, 1], 0).into()).unwrap();
+        let (stop, stopped) = oneshot::channel::<()>();
+        let server = actix_web::rt::spawn(serve(
+            AppData::new(ShippingConfig::default()),
+            incoming,
+            async {
+                let _ = stopped.await;
+            },
+        ));
+        stop.send(()).unwrap();
+        let served = actix_web::rt::time::timeout(Duration::from_secs(5), server).await;
+        assert!(matches!(served, Ok(Ok(Ok(())))));
+    }
+
+    #[actix_web::test]
+    async fn test_ship_order_returns_a_tracking_id() {
+        let mut client = connect(ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            ..Default::default()
+        })
+        .await;
+        let request = |quantity| {
+            tonic::Request::new(pb::ShipOrderRequest {
+                address: None,
+                items: vec![pb::CartItem {
+                    product_id: "OLJCESPC7Z".into(),
+                    quantity,
+                }],
+            })
+        };
+
+        let resp: pb::ShipOrderResponse = call(&mut client, "ShipOrder", request(1)).await.unwrap();
+        assert!(!resp.tracking_id.is_empty());
+
+        let err = call::<_, pb::ShipOrderResponse>(&mut client, "ShipOrder", request(-1))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    }
+}
@@ -0,0 +1,42 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::shipping_types::{CartItem, ShippingSpeed};
+
+/// Checks whether `items` may ship at `speed`, returning whether any of them
+/// is hazardous. Hazardous materials can't travel by air, so the speeds that
+/// need it are rejected.
+pub fn check_hazmat(items: &[CartItem], speed: ShippingSpeed) -> Result<bool, String> {
+    let hazmat = items.iter().any(|item| item.hazmat == Some(true));
+    if hazmat && speed == ShippingSpeed::Overnight {
+        return Err(format!(
+            "hazardous items can't ship at {} speed",
+            speed.as_str()
+        ));
+    }
+    Ok(hazmat)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(hazmat: Option<bool>) -> CartItem {
+        CartItem {
+            product_id: "OLJCESPC7Z".into(),
+            quantity: 1,
+            hazmat,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_check_hazmat() {
+        let items = [item(None), item(Some(false))];
+        assert_eq!(check_hazmat(&items, ShippingSpeed::Overnight), Ok(false));
+
+        let items = [item(None), item(Some(true))];
+        assert_eq!(check_hazmat(&items, ShippingSpeed::Express), Ok(true));
+        assert!(check_hazmat(&items, ShippingSpeed::Overnight).is_err());
+    }
+}
@@ -0,0 +1,103 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{self, HeaderValue},
+    middleware::Next,
+    web, Error,
+};
+
+use super::ShippingConfig;
+
+const CONTENT_SECURITY_POLICY: &str = "default-src 'self'; frame-ancestors 'none'";
+
+/// Middleware adding security headers to every response when
+/// `SECURITY_HEADERS_ENABLED` is set. JSON responses get `nosniff` and
+/// `X-Frame-Options`; HTML pages also get a `Content-Security-Policy`.
+pub async fn security_headers(
+    config: web::Data<ShippingConfig>,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let mut resp = next.call(req).await?;
+    if !config.security_headers_enabled {
+        return Ok(resp);
+    }
+
+    let is_html = resp
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"));
+    let headers = resp.headers_mut();
+    headers.insert(
+        header::X_CONTENT_TYPE_OPTIONS,
+        HeaderValue::from_static("nosniff"),
+    );
+    headers.insert(header::X_FRAME_OPTIONS, HeaderValue::from_static("DENY"));
+    if is_html {
+        headers.insert(
+            header::CONTENT_SECURITY_POLICY,
+            HeaderValue::from_static(CONTENT_SECURITY_POLICY),
+        );
+    }
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::StatusCode, middleware::from_fn, test, App, HttpResponse};
+
+    use super::*;
+    use crate::shipping_service::{get_order, AppData};
+
+    #[actix_web::test]
+    async fn test_headers_follow_flag_and_content_type() {
+        for enabled in [true, false] {
+            let config = ShippingConfig {
+                security_headers_enabled: enabled,
+                ..Default::default()
+            };
+            let app = test::init_service(
+                App::new()
+                    .configure(|cfg| AppData::new(config).register(cfg))
+                    .wrap(from_fn(security_headers))
+                    .service(get_order)
+                    .route(
+                        "/page",
+                        web::get().to(|| async {
+                            HttpResponse::Ok()
+                                .content_type("text/html")
+                                .body("<html></html>")
+                        }),
+                    ),
+            )
+            .await;
+
+            let req = test::TestRequest::get().uri("/order/missing").to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+            let headers = resp.headers();
+            assert_eq!(
+                headers.contains_key(header::X_CONTENT_TYPE_OPTIONS),
+                enabled
+            );
+            assert_eq!(headers.contains_key(header::X_FRAME_OPTIONS), enabled);
+            assert!(!headers.contains_key(header::CONTENT_SECURITY_POLICY));
+
+            let req = test::TestRequest::get().uri("/page").to_request();
+            let resp = test::call_service(&app, req).await;
+            let headers = resp.headers();
+            assert_eq!(
+                headers.contains_key(header::X_CONTENT_TYPE_OPTIONS),
+                enabled
+            );
+            assert_eq!(
+                headers.contains_key(header::CONTENT_SECURITY_POLICY),
+                enabled
+            );
+        }
+    }
+}
@@ -0,0 +1,26 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use std::time::Duration;
+
+/// Checks that the quote service answers within `timeout`. Any response
+/// short of a server error counts as ready; a timeout does not, so a slow
+/// dependency fails the probe instead of hanging it.
+pub async fn probe_quote_service(quote_addr: &str, timeout: Duration) -> Result<(), String> {
+    let response = awc::Client::new()
+        .get(format!("{}/", quote_addr))
+        .timeout(timeout)
+        .send()
+        .await
+        .map_err(|err| match err {
+            awc::error::SendRequestError::Timeout => {
+                format!("no response within {} ms", timeout.as_millis())
+            }
+            err => err.to_string(),
+        })?;
+
+    if response.status().is_server_error() {
+        return Err(format!("responded with {}", response.status()));
+    }
+    Ok(())
+}
@@ -0,0 +1,363 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{self, File, OpenOptions},
+    future::Future,
+    io::{self, Write},
+    path::Path,
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::sync::watch;
+use tracing::warn;
+
+/// How a keyed request was answered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Served {
+    /// The request did the work itself.
+    Created,
+    /// The request waited for a concurrent one with the same key.
+    Coalesced,
+    /// An earlier request with the same key had already completed.
+    Replayed,
+}
+
+impl Served {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Served::Created => "created",
+            Served::Coalesced => "coalesced",
+            Served::Replayed => "replayed",
+        }
+    }
+}
+
+/// Results of requests by idempotency key. Repeating a completed request
+/// replays its result; with coalescing on, a request arriving while another
+/// with the same key is in flight waits for that one's result instead of
+/// doing the work a second time.
+///
+/// Results live in memory, so replays are best-effort across restarts
+/// unless the store is opened with a journal file, which keeps every result
+/// and is read back on the next start. Either way a result is only replayed
+/// within its `Retention`.
+#[derive(Debug)]
+pub struct IdempotencyStore<T> {
+    inner: Mutex<Inner<T>>,
+    journal: Option<Mutex<File>>,
+    retention: Retention,
+}
+
+/// How long, and how many, results are kept for replay.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Retention {
+    /// Age past which a key is served afresh.
+    pub ttl: Duration,
+    /// Most results kept at once; the oldest are forgotten first.
+    pub max_keys: usize,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Retention {
+            ttl: Duration::from_secs(24 * 60 * 60),
+            max_keys: 10_000,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Inner<T> {
+    slots: HashMap<String, Slot<T>>,
+    /// Keys of completed results and when they completed, oldest first.
+    completed: VecDeque<(String, Instant)>,
+}
+
+impl<T> Default for Inner<T> {
+    fn default() -> Self {
+        Inner {
+            slots: HashMap::new(),
+            completed: VecDeque::new(),
+        }
+    }
+}
+
+impl<T> Inner<T> {
+    /// Keeps `result` for `key`, forgetting the oldest results past
+    /// `max_keys`.
+    fn complete(&mut self, key: String, result: T, max_keys: usize) {
+        let completed_at = Instant::now();
+        self.completed.push_back((key.clone(), completed_at));
+        self.slots.insert(key, Slot::Done(result, completed_at));
+        while self.completed.len() > max_keys {
+            let Some((oldest, at)) = self.completed.pop_front() else {
+                break;
+            };
+            // The key may have expired and completed again since.
+            if matches!(self.slots.get(&oldest), Some(Slot::Done(_, done_at)) if *done_at == at) {
+                self.slots.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// A line of the journal file.
+#[derive(Deserialize)]
+struct Record<T> {
+    key: String,
+    result: T,
+}
+
+#[derive(Serialize)]
+struct RecordRef<'a, T> {
+    key: &'a str,
+    result: &'a T,
+}
+
+#[derive(Debug)]
+enum Slot<T> {
+    InFlight(watch::Receiver<Option<T>>),
+    Done(T, Instant),
+}
+
+impl<T> Default for IdempotencyStore<T> {
+    fn default() -> Self {
+        IdempotencyStore {
+            inner: Mutex::default(),
+            journal: None,
+            retention: Retention::default(),
+        }
+    }
+}
+
+impl<T: DeserializeOwned> IdempotencyStore<T> {
+    /// Opens the store, replaying the journal at `path` if there is one.
+    /// Without a journal, results are lost on restart, which is logged.
+    /// Journaled results count their age from the start.
+    pub fn open(path: Option<&Path>, retention: Retention) -> anyhow::Result<Self> {
+        let Some(path) = path else {
+            warn!(
+                name = "IdempotencyBestEffort",
+                message = "Idempotency keys are kept in memory only; a request replayed after a restart is processed again"
+            );
+            return Ok(IdempotencyStore {
+                retention,
+                ..IdempotencyStore::default()
+            });
+        };
+
+        let mut inner = Inner::default();
+        let raw = match fs::read_to_string(path) {
+            Ok(raw) => raw,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => {
+                return Err(err).with_context(|| {
+                    format!("Failed to read idempotency journal {}", path.display())
+                })
+            }
+        };
+        for (line_no, line) in raw.lines().enumerate() {
+            match serde_json::from_str::<Record<T>>(line) {
+                Ok(record) => inner.complete(record.key, record.result, retention.max_keys),
+                // A line cut short by a crash mid-write is skipped; its
+                // request wasn't acknowledged.
+                Err(err) => warn!(
+                    name = "IdempotencyRecordSkipped",
+                    path = %path.display(),
+                    line = line_no + 1,
+                    error = %err,
+                    message = "Skipping unreadable idempotency record"
+                ),
+            }
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open idempotency journal {}", path.display()))?;
+        Ok(IdempotencyStore {
+            inner: Mutex::new(inner),
+            journal: Some(Mutex::new(file)),
+            retention,
+        })
+    }
+}
+
+impl<T> IdempotencyStore<T> {
+    /// Locks the slots, recovering them if a panicking request poisoned the
+    /// lock: every update leaves them consistent.
+    fn lock(&self) -> MutexGuard<'_, Inner<T>> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T: Clone + Serialize> IdempotencyStore<T> {
+    /// Runs `create` for `key` unless its result is already known and not
+    /// expired or, with `coalesce`, about to be. Failures aren't kept: the
+    /// waiting requests then retry, and one of them runs `create` in turn.
+    pub async fn run<E, Fut>(
+        &self,
+        key: &str,
+        coalesce: bool,
+        create: impl FnOnce() -> Fut,
+    ) -> (Result<T, E>, Served)
+    where
+        Fut: Future<Output = Result<T, E>>,
+    {
+        loop {
+            let role = {
+                let mut inner = self.lock();
+                match inner.slots.get(key) {
+                    Some(Slot::Done(result, completed_at))
+                        if completed_at.elapsed() < self.retention.ttl =>
+                    {
+                        return (Ok(result.clone()), Served::Replayed)
+                    }
+                    Some(Slot::InFlight(receiver)) if coalesce => Role::Wait(receiver.clone()),
+                    _ => {
+                        let (sender, receiver) = watch::channel(None);
+                        inner
+                            .slots
+                            .insert(key.to_string(), Slot::InFlight(receiver));
+                        Role::Lead(sender)
+                    }
+                }
+            };
+            let mut in_flight = match role {
+                Role::Lead(sender) => {
+                    return (self.lead(key, sender, create).await, Served::Created);
+                }
+                Role::Wait(receiver) => receiver,
+            };
+            let result = in_flight
+                .wait_for(Option::is_some)
+                .await
+                .map(|result| result.clone());
+            if let Ok(Some(result)) = result {
+                return (Ok(result), Served::Coalesced);
+            }
+        }
+    }
+
+    async fn lead<E, Fut>(
+        &self,
+        key: &str,
+        sender: watch::Sender<Option<T>>,
+        create: impl FnOnce() -> Fut,
+    ) -> Result<T, E>
+    where
+        Fut: Future<Output = Result<T, E>>,
+    {
+        // Clears the slot if `create` fails or the request is dropped midway,
+        // which also wakes the waiting requests to retry.
+        let _guard = InFlightGuard { store: self, key };
+        let result = create().await?;
+
+        self.persist(key, &result);
+        self.lock()
+            .complete(key.to_string(), result.clone(), self.retention.max_keys);
+        sender.send_replace(Some(result.clone()));
+        Ok(result)
+    }
+
+    /// Appends the result to the journal, if there is one. A failed write
+    /// only costs the replay after a restart, so the request still succeeds.
+    fn persist(&self, key: &str, result: &T) {
+        let Some(journal) = &self.journal else {
+            return;
+        };
+        let written = serde_json::to_string(&RecordRef { key, result })
+            .map_err(io::Error::from)
+            .and_then(|line| {
+                let mut file = journal.lock().unwrap_or_else(PoisonError::into_inner);
+                writeln!(file, "{line}")
+            });
+        if let Err(err) = written {
+            warn!(
+                name = "IdempotencyRecordNotSaved",
+                error = %err,
+                message = "Failed to save idempotency record"
+            );
+        }
+    }
+}
+
+enum Role<T> {
+    Lead(watch::Sender<Option<T>>),
+    Wait(watch::Receiver<Option<T>>),
+}
+
+/// Removes a slot left in flight by a request that didn't complete.
+struct InFlightGuard<'a, T> {
+    store: &'a IdempotencyStore<T>,
+    key: &'a str,
+}
+
+impl<T> Drop for InFlightGuard<'_, T> {
+    fn drop(&mut self) {
+        let mut inner = self.store.lock();
+        if matches!(inner.slots.get(self.key), Some(Slot::InFlight(_))) {
+            inner.slots.remove(self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_failures_are_not_replayed() {
+        let store = IdempotencyStore::default();
+        let (result, served) = store
+            .run("key", true, || async { Err::<u32, _>("down") })
+            .await;
+        assert_eq!((result, served), (Err("down"), Served::Created));
+
+        let (result, served) = store.run("key", true, || async { Ok::<_, &str>(7) }).await;
+        assert_eq!((result, served), (Ok(7), Served::Created));
+
+        let (result, served) = store.run("key", true, || async { Ok::<_, &str>(8) }).await;
+        assert_eq!((result, served), (Ok(7), Served::Replayed));
+    }
+
+    fn store(ttl: Duration, max_keys: usize) -> IdempotencyStore<u32> {
+        IdempotencyStore {
+            retention: Retention { ttl, max_keys },
+            ..IdempotencyStore::default()
+        }
+    }
+
+    async fn served(store: &IdempotencyStore<u32>, key: &str, value: u32) -> (u32, Served) {
+        let (result, served) = store.run(key, true, || async { Ok::<_, ()>(value) }).await;
+        (result.unwrap(), served)
+    }
+
+    #[actix_web::test]
+    async fn test_expired_keys_are_served_afresh() {
+        let store = store(Duration::from_millis(50), 10);
+        assert_eq!(served(&store, "key", 1).await, (1, Served::Created));
+        assert_eq!(served(&store, "key", 2).await, (1, Served::Replayed));
+
+        actix_web::rt::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(served(&store, "key", 3).await, (3, Served::Created));
+    }
+
+    #[actix_web::test]
+    async fn test_oldest_keys_are_evicted_past_the_cap() {
+        let store = store(Duration::from_secs(60), 2);
+        for (i, key) in ["a", "b", "c"].into_iter().enumerate() {
+            served(&store, key, i as u32).await;
+        }
+
+        assert_eq!(served(&store, "c", 9).await, (2, Served::Replayed));
+        assert_eq!(served(&store, "b", 9).await, (1, Served::Replayed));
+        assert_eq!(served(&store, "a", 9).await, (9, Served::Created));
+    }
+}
@@ -0,0 +1,107 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{fmt, str::FromStr};
+
+use opentelemetry::{trace::get_active_span, KeyValue};
+
+/// How much detail the handlers add to their spans, set by
+/// `INSTRUMENTATION_LEVEL`. Each attribute and event is tagged with the
+/// lowest level that records it: `Minimal` detail is always recorded, and
+/// the request's duration and status come from the tracing middleware
+/// whatever the level.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum InstrumentationLevel {
+    Minimal,
+    #[default]
+    Standard,
+    Verbose,
+}
+
+impl InstrumentationLevel {
+    /// Whether `detail` is recorded at this level. It never is when the active
+    /// span isn't recording, e.g. because sampling dropped it, so callers can
+    /// skip building attributes that would be thrown away.
+    pub fn records(self, detail: InstrumentationLevel) -> bool {
+        self >= detail && get_active_span(|span| span.is_recording())
+    }
+
+    /// Sets `attribute` on the active span if `detail` is recorded at this level.
+    pub fn set_attribute(self, detail: InstrumentationLevel, attribute: KeyValue) {
+        if self.records(detail) {
+            get_active_span(|span| span.set_attribute(attribute));
+        }
+    }
+
+    /// Adds an event to the active span if `detail` is recorded at this level.
+    pub fn add_event(
+        self,
+        detail: InstrumentationLevel,
+        name: &'static str,
+        attributes: Vec<KeyValue>,
+    ) {
+        if self.records(detail) {
+            get_active_span(|span| span.add_event(name, attributes));
+        }
+    }
+}
+
+impl FromStr for InstrumentationLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "minimal" => Ok(InstrumentationLevel::Minimal),
+            "standard" => Ok(InstrumentationLevel::Standard),
+            "verbose" => Ok(InstrumentationLevel::Verbose),
+            _ => Err(format!(
+                "unknown instrumentation level {s:?}, expected minimal, standard or verbose"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for InstrumentationLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            InstrumentationLevel::Minimal => "minimal",
+            InstrumentationLevel::Standard => "standard",
+            InstrumentationLevel::Verbose => "verbose",
+        };
+        f.write_str(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::{
+        trace::{TraceContextExt, TraceId, Tracer, TracerProvider},
+        Context,
+    };
+    use opentelemetry_sdk::trace::{Sampler, SdkTracerProvider};
+
+    use super::*;
+    use crate::telemetry::current_trace_context;
+
+    #[test]
+    fn test_parse_instrumentation_level() {
+        assert_eq!("Verbose".parse(), Ok(InstrumentationLevel::Verbose));
+        assert_eq!("minimal".parse(), Ok(InstrumentationLevel::Minimal));
+        assert!("loud".parse::<InstrumentationLevel>().is_err());
+    }
+
+    #[test]
+    fn test_unsampled_span_gets_no_span_work() {
+        let provider = SdkTracerProvider::builder()
+            .with_sampler(Sampler::AlwaysOff)
+            .build();
+        let span = provider.tracer("shipping-test").start("unsampled");
+        let _attached = Context::current_with_span(span).attach();
+
+        assert!(!InstrumentationLevel::Verbose.records(InstrumentationLevel::Minimal));
+        let trace = current_trace_context();
+        let (trace_id, span_id) = trace.as_fields();
+        assert_ne!(trace_id, TraceId::INVALID.to_string());
+        assert!(!trace_id.is_empty() && !span_id.is_empty());
+    }
+}
@@ -0,0 +1,78 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fmt;
+
+use super::shipping_types::CartItem;
+
+/// A number of items, as summed from cart quantities. Metrics and span
+/// attributes take it through `as_metric` and `as_attr`, which can't lose
+/// any value, instead of ad-hoc casts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ItemCount(u32);
+
+impl ItemCount {
+    #[cfg(test)]
+    pub const fn new(count: u32) -> Self {
+        ItemCount(count)
+    }
+
+    /// Sums the quantities of `items`, failing if the total overflows.
+    pub fn total<'a>(items: impl IntoIterator<Item = &'a CartItem>) -> Result<Self, String> {
+        items
+            .into_iter()
+            .try_fold(0u32, |total, item| total.checked_add(item.quantity))
+            .map(ItemCount)
+            .ok_or_else(|| format!("request has more than {} items", u32::MAX))
+    }
+
+    pub fn get(self) -> u32 {
+        self.0
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    /// The count as a counter increment.
+    pub fn as_metric(self) -> u64 {
+        u64::from(self.0)
+    }
+
+    /// The count as a span attribute value.
+    pub fn as_attr(self) -> i64 {
+        i64::from(self.0)
+    }
+}
+
+impl fmt::Display for ItemCount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(quantity: u32) -> CartItem {
+        CartItem {
+            product_id: "OLJCESPC7Z".into(),
+            quantity,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_total_is_checked_for_overflow() {
+        assert_eq!(ItemCount::total(&[item(2), item(3)]), Ok(ItemCount::new(5)));
+        assert_eq!(
+            ItemCount::total(&[item(u32::MAX), item(0)]),
+            Ok(ItemCount::new(u32::MAX))
+        );
+        assert_eq!(
+            ItemCount::total(&[item(u32::MAX), item(1)]),
+            Err("request has more than 4294967295 items".to_string())
+        );
+    }
+}
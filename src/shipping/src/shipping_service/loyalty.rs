@@ -0,0 +1,42 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::BTreeMap;
+
+use opentelemetry::{baggage::BaggageExt, Context};
+
+/// Baggage entry naming the shopper's loyalty tier, set by the frontend.
+const LOYALTY_TIER_KEY: &str = "user.loyalty_tier";
+
+/// The discount of the shopper's loyalty tier.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoyaltyDiscount {
+    pub tier: String,
+    /// Share of the base shipping cost taken off, 0 for tiers without a
+    /// configured discount.
+    pub rate: f64,
+}
+
+impl LoyaltyDiscount {
+    /// Looks up the tier in the current context's baggage. Returns `None`
+    /// when the baggage doesn't name one.
+    pub fn from_baggage(discounts: &BTreeMap<String, f64>) -> Option<Self> {
+        let cx = Context::current();
+        let tier = cx
+            .baggage()
+            .get(LOYALTY_TIER_KEY)?
+            .as_str()
+            .trim()
+            .to_ascii_lowercase();
+        if tier.is_empty() {
+            return None;
+        }
+        let rate = discounts.get(&tier).copied().unwrap_or(0.0);
+        Some(LoyaltyDiscount { tier, rate })
+    }
+
+    /// Cents taken off a base cost of `base_cents`.
+    pub fn discount_cents(&self, base_cents: u64) -> u64 {
+        (base_cents as f64 * self.rate).round() as u64
+    }
+}
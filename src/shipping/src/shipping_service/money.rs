@@ -0,0 +1,119 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::shipping_types::Money;
+
+const NANOS_PER_UNIT: u64 = 1_000_000_000;
+
+/// Digits after the decimal point in the usual notation of `currency_code`,
+/// per ISO 4217. Codes not listed use two.
+pub fn minor_units(currency_code: &str) -> u32 {
+    match currency_code {
+        "BIF" | "CLP" | "DJF" | "GNF" | "ISK" | "JPY" | "KMF" | "KRW" | "PYG" | "RWF" | "UGX"
+        | "VND" | "VUV" | "XAF" | "XOF" | "XPF" => 0,
+        "BHD" | "IQD" | "JOD" | "KWD" | "LYD" | "OMR" | "TND" => 3,
+        _ => 2,
+    }
+}
+
+/// Converts `amount` minor units of `currency_code`, e.g. cents of USD or
+/// yen of JPY, into `Money`.
+pub fn from_minor_units(amount: u64, currency_code: &str) -> Money {
+    let per_unit = 10u64.pow(minor_units(currency_code));
+    Money {
+        currency_code: currency_code.to_string(),
+        units: amount / per_unit,
+        nanos: ((amount % per_unit) * (NANOS_PER_UNIT / per_unit)) as u32,
+    }
+}
+
+/// The amount of `money` in minor units of its currency. A fraction of a
+/// minor unit is dropped.
+pub fn to_minor_units(money: &Money) -> u64 {
+    let per_unit = 10u64.pow(minor_units(&money.currency_code));
+    money.units * per_unit + u64::from(money.nanos) / (NANOS_PER_UNIT / per_unit)
+}
+
+/// The amount of `money` in billionths of its currency.
+pub fn to_nanos(money: &Money) -> u128 {
+    u128::from(money.units) * u128::from(NANOS_PER_UNIT) + u128::from(money.nanos)
+}
+
+/// Converts `nanos` billionths of `currency_code` into `Money`.
+pub fn from_nanos(nanos: u128, currency_code: &str) -> Money {
+    let per_unit = u128::from(NANOS_PER_UNIT);
+    Money {
+        currency_code: currency_code.to_string(),
+        units: u64::try_from(nanos / per_unit).unwrap_or(u64::MAX),
+        nanos: (nanos % per_unit) as u32,
+    }
+}
+
+/// Converts `money` into `currency_code` at `rate`, to the billionth.
+pub fn convert_at(money: &Money, rate: f64, currency_code: &str) -> Money {
+    from_nanos(
+        (to_nanos(money) as f64 * rate).round() as u128,
+        currency_code,
+    )
+}
+
+/// Writes `money` as an exact decimal string, e.g. `10.99` or `1200` for
+/// JPY. It shows at least the currency's minor-unit digits, and more only
+/// when the amount has a sub-unit fraction, e.g. `10.995`.
+pub fn decimal_amount(money: &Money) -> String {
+    let digits = minor_units(&money.currency_code) as usize;
+    let fraction = format!("{:09}", money.nanos);
+    let significant = fraction.trim_end_matches('0').len().max(digits);
+    if significant == 0 {
+        money.units.to_string()
+    } else {
+        format!("{}.{}", money.units, &fraction[..significant])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn money(currency_code: &str, units: u64, nanos: u32) -> Money {
+        Money {
+            currency_code: currency_code.into(),
+            units,
+            nanos,
+        }
+    }
+
+    #[test]
+    fn test_minor_units_follow_the_currency_exponent() {
+        assert_eq!(from_minor_units(1099, "USD"), money("USD", 10, 990_000_000));
+        assert_eq!(from_minor_units(1200, "JPY"), money("JPY", 1200, 0));
+        assert_eq!(from_minor_units(3250, "KWD"), money("KWD", 3, 250_000_000));
+
+        assert_eq!(to_minor_units(&money("USD", 10, 995_000_000)), 1099);
+        assert_eq!(to_minor_units(&money("JPY", 1200, 500_000_000)), 1200);
+        assert_eq!(to_minor_units(&money("KWD", 3, 250_000_000)), 3250);
+    }
+
+    #[test]
+    fn test_convert_at_keeps_billionths() {
+        assert_eq!(
+            convert_at(&money("USD", 10, 990_000_000), 0.9, "EUR"),
+            money("EUR", 9, 891_000_000)
+        );
+        assert_eq!(
+            convert_at(&money("USD", 2, 500_000_000), 150.0, "JPY"),
+            money("JPY", 375, 0)
+        );
+    }
+
+    #[test]
+    fn test_decimal_amount() {
+        assert_eq!(decimal_amount(&money("USD", 10, 990_000_000)), "10.99");
+        assert_eq!(decimal_amount(&money("USD", 0, 10_000_000)), "0.01");
+        assert_eq!(decimal_amount(&money("USD", 7, 0)), "7.00");
+        assert_eq!(decimal_amount(&money("USD", 10, 995_000_000)), "10.995");
+        assert_eq!(decimal_amount(&money("JPY", 1200, 0)), "1200");
+        assert_eq!(decimal_amount(&money("JPY", 1200, 500_000_000)), "1200.5");
+        assert_eq!(decimal_amount(&money("KWD", 3, 250_000_000)), "3.250");
+    }
+}
@@ -0,0 +1,155 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use chrono::{DateTime, Utc};
+use opentelemetry::trace::{get_active_span, SpanContext};
+
+use super::shipping_types::{
+    Address, CartItem, DeliveryStatus, OrderResponse, Package, ShippingQuote,
+};
+
+/// A shipped order, kept so follow-up requests can refer to it by order id
+/// or by the tracking id of any of its packages.
+#[derive(Debug, Clone)]
+pub struct Order {
+    pub order_id: String,
+    pub packages: Vec<Package>,
+    pub address: Option<Address>,
+    /// The quote computed at ship time, absent if the quote service failed.
+    pub quote: Option<ShippingQuote>,
+    pub carrier: String,
+    pub shipped_at: DateTime<Utc>,
+    pub estimated_delivery: DateTime<Utc>,
+    /// Span of the request that shipped the order, linked from the spans of
+    /// later operations on it.
+    pub origin: SpanContext,
+    /// When reconciliation found the order stuck in transit.
+    pub stuck_since: Option<DateTime<Utc>>,
+}
+
+impl Order {
+    /// Items of all packages, in package order.
+    pub fn items(&self) -> Vec<CartItem> {
+        self.packages
+            .iter()
+            .flat_map(|package| package.items.iter().cloned())
+            .collect()
+    }
+
+    /// Links the active span to the request that shipped the order.
+    pub fn link_origin(&self) {
+        if self.origin.is_valid() {
+            get_active_span(|span| span.add_link(self.origin.clone(), Vec::new()));
+        }
+    }
+
+    /// The order is delivered once all of its packages are.
+    pub fn status(&self) -> DeliveryStatus {
+        if self
+            .packages
+            .iter()
+            .all(|package| package.status == DeliveryStatus::Delivered)
+        {
+            DeliveryStatus::Delivered
+        } else {
+            DeliveryStatus::InTransit
+        }
+    }
+}
+
+impl From<&Order> for OrderResponse {
+    fn from(order: &Order) -> Self {
+        OrderResponse {
+            order_id: order.order_id.clone(),
+            status: order.status(),
+            carrier: order.carrier.clone(),
+            shipped_at: order.shipped_at,
+            estimated_delivery: order.estimated_delivery,
+            packages: order.packages.clone(),
+            stuck: order.stuck_since.is_some(),
+        }
+    }
+}
+
+/// In-memory store of shipped orders, keyed by order id.
+#[derive(Debug, Default)]
+pub struct OrderStore {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    orders: HashMap<String, Order>,
+    /// Order id of each package's tracking id.
+    order_ids: HashMap<String, String>,
+}
+
+impl OrderStore {
+    /// Locks the store, recovering it if a panicking request poisoned the
+    /// lock: every update leaves it consistent.
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn insert(&self, order: Order) {
+        let mut inner = self.lock();
+        for package in &order.packages {
+            inner
+                .order_ids
+                .insert(package.tracking_id.clone(), order.order_id.clone());
+        }
+        inner.orders.insert(order.order_id.clone(), order);
+    }
+
+    #[cfg(test)]
+    pub fn order_count(&self) -> usize {
+        self.lock().orders.len()
+    }
+
+    pub fn get(&self, order_id: &str) -> Option<Order> {
+        self.lock().orders.get(order_id).cloned()
+    }
+
+    pub fn find_by_tracking_id(&self, tracking_id: &str) -> Option<Order> {
+        let inner = self.lock();
+        let order_id = inner.order_ids.get(tracking_id)?;
+        inner.orders.get(order_id).cloned()
+    }
+
+    /// Flags the orders still in transit that shipped before `cutoff`,
+    /// returning the ones not flagged before.
+    pub fn flag_stuck(&self, cutoff: DateTime<Utc>, now: DateTime<Utc>) -> Vec<Order> {
+        let mut inner = self.lock();
+        inner
+            .orders
+            .values_mut()
+            .filter(|order| {
+                order.stuck_since.is_none()
+                    && order.shipped_at < cutoff
+                    && order.status() != DeliveryStatus::Delivered
+            })
+            .map(|order| {
+                order.stuck_since = Some(now);
+                order.clone()
+            })
+            .collect()
+    }
+
+    /// Sets the status of the package with `tracking_id`, returning its order.
+    pub fn set_package_status(&self, tracking_id: &str, status: DeliveryStatus) -> Option<Order> {
+        let mut inner = self.lock();
+        let order_id = inner.order_ids.get(tracking_id)?.clone();
+        let order = inner.orders.get_mut(&order_id)?;
+        for package in &mut order.packages {
+            if package.tracking_id == tracking_id {
+                package.status = status;
+            }
+        }
+        Some(order.clone())
+    }
+}
@@ -0,0 +1,201 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use opentelemetry::{global, KeyValue};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tracing::warn;
+
+use super::{determinism, InstrumentationLevel, ShippingConfig};
+use crate::telemetry::current_trace_context;
+
+/// Pricing terms negotiated with a partner, carried in the request as a
+/// signed `pricing_override` token. Only these parameters can be signed.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PricingOverride {
+    pub partner: String,
+    /// Share of the base shipping cost taken off, between 0 and 1.
+    pub discount_rate: f64,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Why a token was ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    Disabled,
+    Malformed,
+    InvalidSignature,
+    Expired,
+}
+
+impl Rejection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Rejection::Disabled => "disabled",
+            Rejection::Malformed => "malformed",
+            Rejection::InvalidSignature => "invalid_signature",
+            Rejection::Expired => "expired",
+        }
+    }
+}
+
+impl PricingOverride {
+    /// Verifies a `<payload>.<signature>` token, both parts unpadded
+    /// URL-safe base64: the payload is the override as JSON and the
+    /// signature its HMAC-SHA256 under `secret`.
+    pub fn verify(
+        token: &str,
+        secret: Option<&str>,
+        now: DateTime<Utc>,
+    ) -> Result<Self, Rejection> {
+        let secret = secret.ok_or(Rejection::Disabled)?;
+        let (payload, signature) = token.trim().split_once('.').ok_or(Rejection::Malformed)?;
+        let payload = URL_SAFE_NO_PAD
+            .decode(payload)
+            .map_err(|_| Rejection::Malformed)?;
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| Rejection::Malformed)?;
+
+        mac(secret, &payload)
+            .verify_slice(&signature)
+            .map_err(|_| Rejection::InvalidSignature)?;
+
+        let terms: PricingOverride =
+            serde_json::from_slice(&payload).map_err(|_| Rejection::Malformed)?;
+        if !(0.0..=1.0).contains(&terms.discount_rate) {
+            return Err(Rejection::Malformed);
+        }
+        if terms.expires_at <= now {
+            return Err(Rejection::Expired);
+        }
+        Ok(terms)
+    }
+
+    /// Cents taken off a base cost of `base_cents`.
+    pub fn discount_cents(&self, base_cents: u64) -> u64 {
+        (base_cents as f64 * self.discount_rate).round() as u64
+    }
+
+    /// Signs the override as a token, the way partners do.
+    #[cfg(test)]
+    pub fn sign(&self, secret: &str) -> String {
+        let payload = serde_json::to_vec(self).unwrap();
+        let signature = mac(secret, &payload).finalize().into_bytes();
+        format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(payload),
+            URL_SAFE_NO_PAD.encode(signature)
+        )
+    }
+}
+
+/// Verifies the request's `pricing_override` token, if any, recording the
+/// outcome as `app.shipping.pricing_override_applied`. Tokens that fail
+/// verification are logged, counted in `app.shipping.pricing_override.ignored`
+/// and otherwise ignored, so the quote proceeds at the normal price.
+pub fn accept_override(token: Option<&str>, config: &ShippingConfig) -> Option<PricingOverride> {
+    let token = token?;
+    let level = config.instrumentation_level;
+    let verified = PricingOverride::verify(
+        token,
+        config.pricing_override_secret.as_deref(),
+        determinism::now(config),
+    );
+    level.set_attribute(
+        InstrumentationLevel::Minimal,
+        KeyValue::new("app.shipping.pricing_override_applied", verified.is_ok()),
+    );
+
+    match verified {
+        Ok(terms) => {
+            level.set_attribute(
+                InstrumentationLevel::Standard,
+                KeyValue::new(
+                    "app.shipping.pricing_override.partner",
+                    terms.partner.clone(),
+                ),
+            );
+            Some(terms)
+        }
+        Err(rejection) => {
+            let trace = current_trace_context();
+            let (trace_id, span_id) = trace.as_fields();
+            warn!(
+                name = "PricingOverrideIgnored",
+                reason = rejection.as_str(),
+                trace_id,
+                span_id,
+                message = "Ignoring pricing override token"
+            );
+            let meter = global::meter("otel_demo.shipping.quote");
+            let counter = meter
+                .u64_counter("app.shipping.pricing_override.ignored")
+                .build();
+            counter.add(1, &[KeyValue::new("reason", rejection.as_str())]);
+            None
+        }
+    }
+}
+
+/// The HMAC of `payload` under `secret`, ready to sign or verify.
+fn mac(secret: &str, payload: &[u8]) -> Hmac<Sha256> {
+    <Hmac<Sha256> as Mac>::new_from_slice(secret.as_bytes())
+        .expect("HMAC takes keys of any length")
+        .chain_update(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use sha1::Sha1;
+
+    use super::*;
+
+    fn terms() -> PricingOverride {
+        PricingOverride {
+            partner: "acme".to_string(),
+            discount_rate: 0.25,
+            expires_at: "2030-01-01T00:00:00Z".parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_only_sha256_signatures_verify() {
+        let now = "2029-01-01T00:00:00Z".parse().unwrap();
+        let token = terms().sign("secret");
+        assert_eq!(
+            PricingOverride::verify(&token, Some("secret"), now),
+            Ok(terms())
+        );
+        assert_eq!(
+            PricingOverride::verify(&token, Some("other"), now),
+            Err(Rejection::InvalidSignature)
+        );
+
+        let payload = serde_json::to_vec(&terms()).unwrap();
+        let signature = <Hmac<Sha1> as Mac>::new_from_slice(b"secret")
+            .unwrap()
+            .chain_update(&payload)
+            .finalize()
+            .into_bytes();
+        let legacy = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(&payload),
+            URL_SAFE_NO_PAD.encode(signature)
+        );
+        assert_eq!(
+            PricingOverride::verify(&legacy, Some("secret"), now),
+            Err(Rejection::InvalidSignature)
+        );
+
+        let truncated = format!("{}.{}", URL_SAFE_NO_PAD.encode(&payload), "AAAA");
+        assert_eq!(
+            PricingOverride::verify(&truncated, Some("secret"), now),
+            Err(Rejection::InvalidSignature)
+        );
+    }
+}
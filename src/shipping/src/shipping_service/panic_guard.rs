@@ -0,0 +1,136 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    any::Any,
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    error::InternalError,
+    middleware::Next,
+    Error, HttpResponse,
+};
+use tracing::warn;
+
+use super::api_error;
+use crate::telemetry::current_trace_context;
+
+/// Middleware turning a panic in a handler into a `500 internal_error`
+/// response, so a bug fails the one request instead of dropping the
+/// connection. Register it inside `RequestTracing` so the response and the
+/// log carry the request's trace id.
+pub async fn catch_panics(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let path = req.path().to_string();
+
+    match CatchUnwind(Box::pin(async move { next.call(req).await })).await {
+        Ok(resp) => resp,
+        Err(payload) => {
+            let trace = current_trace_context();
+            let (trace_id, span_id) = trace.as_fields();
+            warn!(
+                name = "RequestPanicked",
+                path = path.as_str(),
+                panic = panic_message(payload.as_ref()),
+                trace_id,
+                span_id,
+                message = "Request handler panicked"
+            );
+            let body = api_error("internal_error", "Internal server error".to_string());
+            let resp = HttpResponse::InternalServerError().json(body);
+            // The request went down with the handler, so the response
+            // travels as an error rendered by the outer layers.
+            Err(InternalError::from_response("request handler panicked", resp).into())
+        }
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+/// Resolves to `Err` with the panic payload if polling the inner future
+/// panics.
+struct CatchUnwind<F>(Pin<Box<F>>);
+
+impl<F: Future> Future for CatchUnwind<F> {
+    type Output = Result<F::Output, Box<dyn Any + Send>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let inner = self.0.as_mut();
+        match panic::catch_unwind(AssertUnwindSafe(|| inner.poll(cx))) {
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(payload) => Poll::Ready(Err(payload)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use actix_web::{http::StatusCode, middleware::from_fn, web};
+
+    use super::*;
+    use crate::shipping_service::ApiError;
+    use crate::test_support::spawn_mock;
+
+    async fn panicking() -> &'static str {
+        actix_web::rt::time::sleep(Duration::from_millis(50)).await;
+        panic!("boom")
+    }
+
+    #[actix_web::test]
+    async fn test_panic_does_not_break_concurrent_requests() {
+        let url = spawn_mock(|cfg| {
+            cfg.service(
+                web::scope("")
+                    .wrap(from_fn(catch_panics))
+                    .route("/panic", web::get().to(panicking))
+                    .route(
+                        "/ok",
+                        web::get().to(|| async {
+                            actix_web::rt::time::sleep(Duration::from_millis(100)).await;
+                            "ok"
+                        }),
+                    ),
+            );
+        });
+
+        let send = |path: &'static str| {
+            let url = format!("{url}{path}");
+            actix_web::rt::spawn(async move {
+                let mut resp = awc::Client::new().get(url).send().await.unwrap();
+                (resp.status(), resp.body().await.unwrap())
+            })
+        };
+        let ok = [send("/ok"), send("/ok")];
+        let panicked = send("/panic");
+
+        let (status, body) = panicked.await.unwrap();
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        let error: ApiError = serde_json::from_slice(&body).unwrap();
+        assert_eq!(error.code, "internal_error");
+        for handle in ok {
+            let (status, body) = handle.await.unwrap();
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(body, "ok");
+        }
+
+        let (status, _) = send("/ok").await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+    }
+}
@@ -0,0 +1,295 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use actix_web::web;
+use arc_swap::ArcSwap;
+use chrono::{DateTime, Utc};
+use opentelemetry::{global, KeyValue};
+use sha1::{Digest, Sha1};
+use tracing::{info, warn};
+
+use super::config::PricingConfig;
+
+/// The pricing currently in effect. It can be swapped while requests are in
+/// flight; each request reads it once and keeps that copy throughout.
+#[derive(Debug)]
+pub struct PricingState {
+    current: ArcSwap<Versioned>,
+    /// Contents of the pricing file last looked at, valid or not, so an
+    /// unchanged file is neither reparsed nor reported again.
+    last_seen: Mutex<Option<String>>,
+}
+
+#[derive(Debug)]
+struct Versioned {
+    pricing: Arc<PricingConfig>,
+    version: PricingVersion,
+}
+
+/// Identifies the pricing that produced a quote, for auditing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PricingVersion {
+    /// 1 for the pricing loaded at startup, incremented by each reload
+    /// applied.
+    pub version: u64,
+    /// SHA-1 of the pricing file, unless pricing comes from the defaults
+    /// and environment alone.
+    pub file_sha1: Option<String>,
+    /// When the pricing, exchange rates included, took effect.
+    pub loaded_at: DateTime<Utc>,
+}
+
+impl PricingState {
+    /// Starts with `pricing`, loaded from the pricing `file` if any.
+    pub fn new(pricing: PricingConfig, file: Option<&Path>) -> Self {
+        let file_sha1 = file
+            .and_then(|path| fs::read(path).ok())
+            .map(|raw| sha1_hex(&raw));
+        PricingState {
+            current: ArcSwap::from_pointee(Versioned {
+                pricing: Arc::new(pricing),
+                version: PricingVersion {
+                    version: 1,
+                    file_sha1,
+                    loaded_at: Utc::now(),
+                },
+            }),
+            last_seen: Mutex::new(None),
+        }
+    }
+
+    pub fn current(&self) -> Arc<PricingConfig> {
+        self.current.load().pricing.clone()
+    }
+
+    /// The pricing in effect along with its version, read at once so that
+    /// a concurrent reload can't pair one with the other's predecessor.
+    pub fn current_versioned(&self) -> (Arc<PricingConfig>, PricingVersion) {
+        let current = self.current.load();
+        (current.pricing.clone(), current.version.clone())
+    }
+
+    /// Rereads the pricing file at `path` and swaps it in if it changed. An
+    /// invalid file is rejected and the pricing in effect is kept.
+    pub fn reload_if_changed(&self, path: &Path) -> anyhow::Result<bool> {
+        let raw = fs::read_to_string(path)?;
+        {
+            let mut last_seen = self
+                .last_seen
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            if last_seen.as_deref() == Some(raw.as_str()) {
+                return Ok(false);
+            }
+            *last_seen = Some(raw.clone());
+        }
+
+        let pricing = match PricingConfig::parse(&raw, path) {
+            Ok(pricing) => pricing.with_env_overrides(),
+            Err(err) => {
+                record_reload("rejected");
+                return Err(err);
+            }
+        };
+        let current = self.current.load_full();
+        if *current.pricing == pricing {
+            return Ok(false);
+        }
+        self.current.store(Arc::new(Versioned {
+            pricing: Arc::new(pricing),
+            version: PricingVersion {
+                version: current.version.version + 1,
+                file_sha1: Some(sha1_hex(raw.as_bytes())),
+                loaded_at: Utc::now(),
+            },
+        }));
+        record_reload("applied");
+        Ok(true)
+    }
+}
+
+fn sha1_hex(raw: &[u8]) -> String {
+    format!("{:x}", Sha1::digest(raw))
+}
+
+fn record_reload(result: &'static str) {
+    let meter = global::meter("otel_demo.shipping.config");
+    let counter = meter.u64_counter("app.shipping.config.reloads").build();
+    counter.add(1, &[KeyValue::new("result", result)]);
+}
+
+/// Checks the pricing file at `path` every `interval` for the life of the
+/// process.
+pub fn watch(state: web::Data<PricingState>, path: PathBuf, interval: Duration) {
+    actix_web::rt::spawn(async move {
+        let mut ticks = actix_web::rt::time::interval(interval);
+        loop {
+            ticks.tick().await;
+            match state.reload_if_changed(&path) {
+                Ok(true) => info!(
+                    name = "PricingConfigReloaded",
+                    path = %path.display(),
+                    message = "Reloaded pricing config"
+                ),
+                Ok(false) => {}
+                Err(err) => warn!(
+                    name = "PricingConfigRejected",
+                    path = %path.display(),
+                    error = format!("{err:#}").as_str(),
+                    message = "Keeping the current pricing config"
+                ),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, App};
+
+    use super::*;
+    use crate::shipping_service::{
+        get_quote, AppData, CartItem, GetQuoteRequest, GetQuoteResponse, ShippingConfig,
+        ShippingSpeed,
+    };
+    use crate::test_support::{env_lock, spawn_quote_mock, TestMetrics};
+
+    #[actix_web::test]
+    async fn test_reload_applies_valid_and_rejects_invalid_changes() {
+        let metrics = TestMetrics::install();
+        let path =
+            std::env::temp_dir().join(format!("shipping-reload-{}.json", std::process::id()));
+        fs::write(&path, r#"{"hazmat_surcharge": 15.5}"#).unwrap();
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            pricing: PricingConfig::from_file(&path).unwrap(),
+            ..Default::default()
+        };
+        let data = AppData::new(config);
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| data.register(cfg))
+                .service(get_quote),
+        )
+        .await;
+        let quote_cost = || async {
+            let req = test::TestRequest::post()
+                .uri("/get-quote")
+                .set_json(GetQuoteRequest {
+                    items: vec![CartItem {
+                        product_id: "HQTGWGPNH4".into(),
+                        quantity: 1,
+                        hazmat: Some(true),
+                        ..Default::default()
+                    }],
+                    speed: ShippingSpeed::Standard.into(),
+                    ..Default::default()
+                })
+                .to_request();
+            let quote: GetQuoteResponse = test::call_and_read_body_json(&app, req).await;
+            let cost = quote.cost_usd.unwrap();
+            (cost.units, cost.nanos)
+        };
+        assert_eq!(quote_cost().await, (26, 490_000_000));
+
+        fs::write(&path, r#"{"hazmat_surcharge": 5}"#).unwrap();
+        let reloaded = {
+            let _env = env_lock();
+            data.pricing.reload_if_changed(&path).unwrap()
+        };
+        assert!(reloaded);
+        assert_eq!(quote_cost().await, (15, 990_000_000));
+
+        fs::write(&path, r#"{"hazmat_surcharge": "lots"}"#).unwrap();
+        let rejected = {
+            let _env = env_lock();
+            data.pricing.reload_if_changed(&path)
+        };
+        assert!(rejected.is_err());
+        assert_eq!(quote_cost().await, (15, 990_000_000));
+
+        let reloads = |result: &'static str| {
+            metrics.counter(
+                "app.shipping.config.reloads",
+                &[KeyValue::new("result", result)],
+            )
+        };
+        assert_eq!((reloads("applied"), reloads("rejected")), (1, 1));
+    }
+
+    #[actix_web::test]
+    async fn test_provenance_reflects_the_active_pricing() {
+        let path =
+            std::env::temp_dir().join(format!("shipping-provenance-{}.json", std::process::id()));
+        fs::write(&path, r#"{"handling_fee": 1}"#).unwrap();
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            pricing: PricingConfig::from_file(&path).unwrap(),
+            pricing_config_file: Some(path.clone()),
+            ..Default::default()
+        };
+        let data = AppData::new(config);
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| data.register(cfg))
+                .service(get_quote),
+        )
+        .await;
+        let provenance = || async {
+            let req = test::TestRequest::post()
+                .uri("/get-quote")
+                .set_json(GetQuoteRequest {
+                    items: vec![CartItem {
+                        product_id: "OLJCESPC7Z".into(),
+                        quantity: 1,
+                        ..Default::default()
+                    }],
+                    include_provenance: true,
+                    ..Default::default()
+                })
+                .to_request();
+            let quote: GetQuoteResponse = test::call_and_read_body_json(&app, req).await;
+            quote.provenance.unwrap()
+        };
+
+        let first = provenance().await;
+        assert_eq!(first.pricing_version, 1);
+        assert_eq!(first.pricing_strategy, "quote_service");
+        assert_eq!(
+            first.rate_table_sha1.as_deref(),
+            Some(sha1_hex(br#"{"handling_fee": 1}"#).as_str())
+        );
+
+        fs::write(&path, r#"{"handling_fee": 2}"#).unwrap();
+        let reloaded = {
+            let _env = env_lock();
+            data.pricing.reload_if_changed(&path).unwrap()
+        };
+        assert!(reloaded);
+        let second = provenance().await;
+        assert_eq!(second.pricing_version, 2);
+        assert_eq!(
+            second.rate_table_sha1.as_deref(),
+            Some(sha1_hex(br#"{"handling_fee": 2}"#).as_str())
+        );
+        assert!(second.exchange_rates_as_of >= first.exchange_rates_as_of);
+    }
+
+    #[actix_web::test]
+    async fn test_unchanged_file_is_not_reloaded() {
+        let path =
+            std::env::temp_dir().join(format!("shipping-unchanged-{}.json", std::process::id()));
+        fs::write(&path, "{}").unwrap();
+        let _env = env_lock();
+        let state = PricingState::new(PricingConfig::default(), None);
+        assert!(!state.reload_if_changed(&path).unwrap());
+        assert!(!state.reload_if_changed(&path).unwrap());
+    }
+}
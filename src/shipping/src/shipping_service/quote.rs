@@ -4,97 +4,2045 @@
 use core::fmt;
 use opentelemetry::global;
 use opentelemetry_instrumentation_actix_web::ClientExt;
-use std::{collections::HashMap, env};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    future::Future,
+    iter,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    task::{self, Poll},
+    time::{Duration, Instant},
+};
+use tokio::sync::OnceCell;
 
+use actix_tls::connect::{
+    ConnectError, ConnectInfo, Connector as TcpConnector, ConnectorService, Resolver,
+};
+use actix_web::{dev::Service, HttpMessage};
 use anyhow::{Context, Result};
-use opentelemetry::{trace::get_active_span, KeyValue};
-use tracing::info;
+use awc::{
+    error::SendRequestError,
+    http::{StatusCode, Uri},
+    middleware::Transform,
+    ConnectRequest, ConnectResponse,
+};
+use futures_util::future::LocalBoxFuture;
+use opentelemetry::{baggage::BaggageExt, metrics::Histogram, KeyValue};
+use tracing::{error, info, warn};
 
-use super::shipping_types::Quote;
+use super::backoff::backoff_delay;
+use super::breaker::{BreakerScope, BreakerSnapshot, CircuitBreaker, Health, Permit};
+use super::config::{PricingConfig, QuotePoolConfig};
+use super::determinism::{self, Entropy};
+use super::events::QuoteEvent;
+use super::fees::handling_fee_cents;
+use super::items::ItemCount;
+use super::shipping_types::{
+    Address, CartItem, Charge, Quote, QuoteConfidence, QuoteServiceRequest, QuoteServiceResponse,
+    QuoteSource, ShippingQuote, ShippingSpeed,
+};
+use super::weight::billable_weight;
+use super::zones::{zone_for, ShippingZone, DEFAULT_ZONE};
+use super::{InstrumentationLevel, ShippingConfig};
+use crate::telemetry::current_trace_context;
 
-pub async fn create_quote_from_count(count: u32) -> Result<Quote, tonic::Status> {
-    let f = match request_quote(count).await {
-        Ok(float) => float,
+/// Bucket bounds, in milliseconds, of the quote latency histograms: tight
+/// around the usual tens of milliseconds, up to the default quote timeout.
+const LATENCY_BUCKETS_MS: [f64; 12] = [
+    1.0, 2.5, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0,
+];
+
+/// Baggage entries set by the frontend that are recorded on the span of a
+/// quote request, as `app.shipping.baggage.<key>`.
+const RECORDED_BAGGAGE: [&str; 2] = ["session.id", "user.tier"];
+
+/// Histogram `name` of quote path latencies in milliseconds.
+pub fn latency_histogram(name: &'static str) -> Histogram<u64> {
+    global::meter("otel_demo.shipping.quote")
+        .u64_histogram(name)
+        .with_unit("ms")
+        .with_boundaries(LATENCY_BUCKETS_MS.to_vec())
+        .build()
+}
+
+/// `success` or `error`, the outcome attribute of the latency histograms.
+pub fn outcome(success: bool) -> KeyValue {
+    KeyValue::new("outcome", if success { "success" } else { "error" })
+}
+
+/// State of the quote path shared by all requests.
+#[derive(Debug)]
+pub struct QuoteState {
+    /// Guards every backend under the global breaker scope.
+    breaker: CircuitBreaker,
+    backends: Vec<Backend>,
+    scope: BreakerScope,
+    /// Backend the next quote request starts from.
+    next_backend: AtomicUsize,
+    /// Draws the retry backoff jitter and the injected timeouts.
+    jitter: Entropy,
+    cache: QuoteCache,
+}
+
+/// A quote service replica, with the breaker guarding it under the
+/// per-backend scope.
+#[derive(Debug)]
+struct Backend {
+    addr: String,
+    breaker: CircuitBreaker,
+}
+
+impl QuoteState {
+    pub fn new(config: &ShippingConfig) -> Self {
+        let breaker =
+            || CircuitBreaker::new(config.breaker.failure_threshold, config.breaker.open_for);
+        QuoteState {
+            breaker: breaker(),
+            backends: iter::once(&config.quote_addr)
+                .chain(&config.quote_replica_addrs)
+                .map(|addr| Backend {
+                    addr: addr.clone(),
+                    breaker: breaker(),
+                })
+                .collect(),
+            scope: config.breaker.scope,
+            next_backend: AtomicUsize::new(0),
+            jitter: Entropy::new(config),
+            cache: QuoteCache::default(),
+        }
+    }
+
+    /// Picks the backend for a quote request, taking turns between them,
+    /// with the permit of its breaker. Under the per-backend scope, backends
+    /// whose breaker is open are skipped. Returns `None` when the breakers
+    /// turn the request away.
+    fn route(&self, level: InstrumentationLevel) -> Option<(Route<'_>, Permit<'_>)> {
+        let start = self.next_backend.fetch_add(1, Ordering::Relaxed);
+        let mut backends =
+            (0..self.backends.len()).map(|i| &self.backends[(start + i) % self.backends.len()]);
+        match self.scope {
+            BreakerScope::Global => {
+                let route = Route {
+                    addr: &backends.next()?.addr,
+                    breaker_label: "global",
+                    breaker: &self.breaker,
+                    level,
+                };
+                let permit = route.update(CircuitBreaker::try_acquire).ok()?;
+                Some((route, permit))
+            }
+            BreakerScope::PerBackend => backends
+                .map(|backend| Route {
+                    addr: &backend.addr,
+                    breaker_label: &backend.addr,
+                    breaker: &backend.breaker,
+                    level,
+                })
+                .find_map(|route| {
+                    let permit = route.update(CircuitBreaker::try_acquire).ok()?;
+                    Some((route, permit))
+                }),
+        }
+    }
+
+    /// Closes every breaker, returning how many were open or half-open.
+    pub fn reset_breakers(&self, level: InstrumentationLevel) -> usize {
+        let global = Route {
+            addr: &self.backends[0].addr,
+            breaker_label: "global",
+            breaker: &self.breaker,
+            level,
+        };
+        let backends = self.backends.iter().map(|backend| Route {
+            addr: &backend.addr,
+            breaker_label: &backend.addr,
+            breaker: &backend.breaker,
+            level,
+        });
+        iter::once(global)
+            .chain(backends)
+            .filter(|route| route.update(CircuitBreaker::reset))
+            .count()
+    }
+
+    /// Forgets every cached price, returning how many there were.
+    pub fn clear_cache(&self) -> usize {
+        self.cache.clear()
+    }
+
+    /// State of the breaker that turns requests away: the global one, or
+    /// the per-backend one that probes its backend again first.
+    pub fn breaker_snapshot(&self) -> BreakerSnapshot {
+        match self.scope {
+            BreakerScope::Global => self.breaker.snapshot(),
+            BreakerScope::PerBackend => self
+                .backends
+                .iter()
+                .map(|backend| backend.breaker.snapshot())
+                .min_by_key(|snapshot| snapshot.cooldown_remaining_ms)
+                .unwrap_or_else(|| self.breaker.snapshot()),
+        }
+    }
+}
+
+/// The backend a quote request goes to, and the breaker guarding it.
+struct Route<'a> {
+    addr: &'a str,
+    /// `global`, or the backend's address under the per-backend scope.
+    breaker_label: &'a str,
+    breaker: &'a CircuitBreaker,
+    level: InstrumentationLevel,
+}
+
+impl<'a> Route<'a> {
+    /// Applies `update` to the breaker. A change of state is marked on the
+    /// active span, counted in `app.shipping.quote.breaker_state_changes`
+    /// and recorded in the `app.shipping.quote.circuit_state` gauge.
+    fn update<T>(&self, update: impl FnOnce(&'a CircuitBreaker) -> T) -> T {
+        let before = self.breaker.snapshot().state;
+        let result = update(self.breaker);
+        let after = self.breaker.snapshot().state;
+        if after != before {
+            let backend = KeyValue::new("backend", self.breaker_label.to_string());
+            let meter = global::meter("otel_demo.shipping.quote");
+            meter
+                .u64_counter("app.shipping.quote.breaker_state_changes")
+                .build()
+                .add(
+                    1,
+                    &[backend.clone(), KeyValue::new("state", after.as_str())],
+                );
+            meter
+                .u64_gauge("app.shipping.quote.circuit_state")
+                .with_description("0 when closed, 1 when open, 2 when half-open")
+                .build()
+                .record(after.gauge_value(), &[backend]);
+            QuoteEvent::circuit_changed(self.breaker_label, after).emit(self.level);
+        }
+        result
+    }
+}
+
+/// Whether a quote came from the quote service or from the cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Served {
+    Fetched,
+    Cached,
+    /// Fetched although a cached price may have been fresh enough.
+    Refreshed,
+}
+
+impl Served {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Served::Fetched => "miss",
+            Served::Cached => "hit",
+            Served::Refreshed => "bypass",
+        }
+    }
+}
+
+/// Whether a quote may reuse a cached price.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CachePolicy {
+    #[default]
+    Reuse,
+    /// Asks the quote service, and caches its price for later quotes.
+    Refresh,
+    /// Leaves the quote service and the cache alone and prices by the
+    /// fallback formula, as `X-Debug-Force-Fallback` asks.
+    Fallback,
+}
+
+/// Prices from the quote service by item count, which is all they depend
+/// on. A request for a count being fetched waits for that fetch instead of
+/// making its own.
+#[derive(Debug, Default)]
+struct QuoteCache {
+    entries: Mutex<HashMap<u32, Arc<OnceCell<CachedPrice>>>>,
+}
+
+/// A price and when it was fetched.
+type CachedPrice = (f64, Instant);
+
+impl QuoteCache {
+    /// The price of `count` fetched less than `ttl` ago, or the one `fetch`
+    /// gets, which `Refresh` always asks for. Failures aren't cached.
+    async fn get_or_fetch<Fut>(
+        &self,
+        count: ItemCount,
+        ttl: Duration,
+        policy: CachePolicy,
+        fetch: impl FnOnce() -> Fut,
+    ) -> Result<(f64, Served), tonic::Status>
+    where
+        Fut: Future<Output = Result<f64, tonic::Status>>,
+    {
+        let entry = {
+            let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+            let entry = entries.entry(count.get()).or_default();
+            let expired = entry
+                .get()
+                .is_some_and(|(_, fetched_at)| fetched_at.elapsed() >= ttl);
+            if expired || policy == CachePolicy::Refresh {
+                *entry = Arc::default();
+            }
+            entry.clone()
+        };
+
+        let mut served = Served::Cached;
+        let (f, _) = entry
+            .get_or_try_init(|| {
+                served = match policy {
+                    CachePolicy::Reuse | CachePolicy::Fallback => Served::Fetched,
+                    CachePolicy::Refresh => Served::Refreshed,
+                };
+                async { fetch().await.map(|f| (f, Instant::now())) }
+            })
+            .await?;
+        Ok((*f, served))
+    }
+
+    /// Forgets every price, returning how many were fetched.
+    fn clear(&self) -> usize {
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        let fetched = entries.values().filter(|entry| entry.initialized()).count();
+        entries.clear();
+        fetched
+    }
+}
+
+/// Prices `items` shipped to `destination`: the quote service's price for
+/// their count, scaled by the multiplier of the destination's zone. Their
+/// weight is charged separately, by `per_kg_rate`. The quote service is
+/// given no longer than the caller's `deadline` leaves.
+pub async fn create_quote_from_items(
+    items: &[CartItem],
+    destination: Option<&Address>,
+    config: &ShippingConfig,
+    state: &QuoteState,
+    zones: &[ShippingZone],
+    cache: CachePolicy,
+    deadline: Option<Instant>,
+) -> Result<ShippingQuote, tonic::Status> {
+    let count = ItemCount::total(items).map_err(tonic::Status::invalid_argument)?;
+    let level = config.instrumentation_level;
+    if let Some(weight) = billable_weight(items, None) {
+        level.set_attribute(
+            InstrumentationLevel::Standard,
+            KeyValue::new(
+                "app.shipping.total_weight_kg",
+                round_to_grams(weight.actual_kg),
+            ),
+        );
+    }
+    let zone = zone_for(destination, zones);
+    level.set_attribute(
+        InstrumentationLevel::Standard,
+        KeyValue::new(
+            "app.shipping.zone",
+            zone.map_or(DEFAULT_ZONE, |zone| zone.name.as_str())
+                .to_string(),
+        ),
+    );
+
+    let mut quote = quote_count(count, config, state, cache, deadline).await?;
+    if let Some(zone) = zone {
+        quote.total_cents = (quote.total_cents as f64 * zone.multiplier).round() as u64;
+    }
+    Ok(quote)
+}
+
+/// Rounds `kg` to whole grams, dropping the float error of summing weights.
+fn round_to_grams(kg: f64) -> f64 {
+    (kg * 1000.0).round() / 1000.0
+}
+
+/// Prices `count` items at standard speed, adding the handling fee of
+/// `pricing` as its own line. The fee is added in whole cents to the
+/// rounded carrier price, so it never moves the carrier price's rounding.
+pub async fn create_quote_from_count(
+    count: ItemCount,
+    config: &ShippingConfig,
+    state: &QuoteState,
+    pricing: &PricingConfig,
+) -> Result<ShippingQuote, tonic::Status> {
+    let mut quote = quote_count(count, config, state, CachePolicy::Reuse, None).await?;
+    let speed = ShippingSpeed::Standard;
+    let multiplier = pricing.speed_multipliers.get(speed);
+    quote.total_cents = (quote.total_cents as f64 * multiplier).round() as u64;
+    // A negative fee set through the environment rounds to nothing.
+    let handling = handling_fee_cents(pricing);
+    let level = config.instrumentation_level;
+    level.set_attribute(
+        InstrumentationLevel::Minimal,
+        KeyValue::new("app.shipping.speed", speed.as_str()),
+    );
+    level.set_attribute(
+        InstrumentationLevel::Standard,
+        KeyValue::new(
+            "app.shipping.cost.carrier",
+            quote.total_cents as f64 / 100.0,
+        ),
+    );
+    level.set_attribute(
+        InstrumentationLevel::Standard,
+        KeyValue::new("app.shipping.cost.handling", handling as f64 / 100.0),
+    );
+    if handling > 0 {
+        quote.add_charge("Handling fee", handling);
+    }
+    Ok(quote)
+}
+
+async fn quote_count(
+    count: ItemCount,
+    config: &ShippingConfig,
+    state: &QuoteState,
+    cache: CachePolicy,
+    deadline: Option<Instant>,
+) -> Result<ShippingQuote, tonic::Status> {
+    // Nothing to ship costs nothing; `ZERO_ITEMS_POLICY` callers that
+    // reject empty requests never get here.
+    if count.is_zero() {
+        return Ok(service_quote(0, config));
+    }
+    if cache == CachePolicy::Fallback {
+        let forced =
+            tonic::Status::unavailable("The fallback was forced by X-Debug-Force-Fallback");
+        return Ok(fallback_quote(count, config, &forced));
+    }
+
+    let meter = global::meter("otel_demo.shipping.quote");
+    // Whether the price was cached is only known, and counted, when caching.
+    let fetched = if config.quote_cache_ttl.is_zero() {
+        fetch_quote(count, config, state, deadline)
+            .await
+            .map(|f| (f, None))
+    } else {
+        // The fetch is shared by every caller waiting for the count, so it
+        // runs under `QUOTE_TIMEOUT_MS` alone; each caller's deadline only
+        // ends its own wait, leaving the fetch to the next waiter.
+        let fetch = state
+            .cache
+            .get_or_fetch(count, config.quote_cache_ttl, cache, || {
+                fetch_quote(count, config, state, None)
+            });
+        let fetched = match deadline {
+            Some(deadline) => {
+                let left = deadline.saturating_duration_since(Instant::now());
+                actix_web::rt::time::timeout(left, fetch)
+                    .await
+                    .unwrap_or_else(|_| {
+                        meter
+                            .u64_counter("app.shipping.quote.errors")
+                            .build()
+                            .add(1, &[KeyValue::new("reason", "deadline")]);
+                        Err(tonic::Status::deadline_exceeded(
+                            "The request deadline passed before the quote service answered",
+                        ))
+                    })
+            }
+            None => fetch.await,
+        };
+        fetched.map(|(f, served)| (f, Some(served)))
+    };
+    let (f, served) = match fetched {
+        Ok(fetched) => fetched,
+        Err(status) if config.fallback.enabled => {
+            return Ok(fallback_quote(count, config, &status))
+        }
+        Err(status) => return Err(status),
+    };
+    if let Some(served) = served {
+        config.instrumentation_level.set_attribute(
+            InstrumentationLevel::Standard,
+            KeyValue::new("app.shipping.quote.cache", served.as_str()),
+        );
+    }
+    match served {
+        Some(Served::Cached) => {
+            meter
+                .u64_counter("app.shipping.quote.cache_hits")
+                .build()
+                .add(1, &[]);
+            QuoteEvent::cache_hit(count).emit(config.instrumentation_level);
+        }
+        Some(Served::Fetched) => {
+            meter
+                .u64_counter("app.shipping.quote.cache_misses")
+                .build()
+                .add(1, &[]);
+        }
+        Some(Served::Refreshed) => {
+            meter
+                .u64_counter("app.shipping.quote.cache_bypasses")
+                .build()
+                .add(1, &[]);
+        }
+        None => {}
+    }
+
+    let counter = meter.u64_counter("app.shipping.items_count").build();
+    counter.add(count.as_metric(), &[]);
+
+    let level = config.instrumentation_level;
+    level.set_attribute(
+        InstrumentationLevel::Minimal,
+        KeyValue::new("app.shipping.quote.source", "quote_service"),
+    );
+    let q = create_quote_from_float(f);
+    QuoteEvent::received(&q, count).emit(level);
+    level.set_attribute(
+        InstrumentationLevel::Minimal,
+        KeyValue::new("app.shipping.cost.total", format!("{}", q)),
+    );
+
+    if let Some(threshold) = config.quote_warn_above {
+        if f > threshold {
+            flag_high_value(&q, threshold, level);
+        }
+    }
+
+    Ok(service_quote(q.dollars * 100 + q.cents as u64, config))
+}
+
+/// Asks a quote service backend to price `count` items, unless the circuit
+/// breakers turn the request away.
+async fn fetch_quote(
+    count: ItemCount,
+    config: &ShippingConfig,
+    state: &QuoteState,
+    deadline: Option<Instant>,
+) -> Result<f64, tonic::Status> {
+    let meter = global::meter("otel_demo.shipping.quote");
+    let errors = meter.u64_counter("app.shipping.quote.errors").build();
+
+    let Some((route, _permit)) = state.route(config.instrumentation_level) else {
+        errors.add(1, &[KeyValue::new("reason", "breaker_open")]);
+        return Err(tonic::Status::unavailable(
+            "Quote service circuit breaker is open",
+        ));
+    };
+    config.instrumentation_level.set_attribute(
+        InstrumentationLevel::Standard,
+        KeyValue::new("app.shipping.quote.backend", route.addr.to_string()),
+    );
+
+    match request_quote_with_retries(count, route.addr, config, &state.jitter, deadline).await {
+        Ok(float) => {
+            record_health(route.update(CircuitBreaker::record_success), config);
+            Ok(float)
+        }
         Err(err) => {
+            let timeout = err.downcast_ref::<QuoteTimeout>();
+            // Giving up at the caller's deadline is no failure of the quote
+            // service, and mustn't let impatient callers open the breaker.
+            if !timeout.is_some_and(|timeout| timeout.by_deadline) {
+                record_health(route.update(CircuitBreaker::record_failure), config);
+            }
             let msg = format!("{}", err);
-            return Err(tonic::Status::unknown(msg));
+            if let Some(timeout) = timeout {
+                let reason = if timeout.by_deadline {
+                    "deadline"
+                } else {
+                    "timeout"
+                };
+                errors.add(1, &[KeyValue::new("reason", reason)]);
+                let trace = current_trace_context();
+                let (trace_id, span_id) = trace.as_fields();
+                error!(
+                    name = "QuoteTimedOut",
+                    duration_ms = timeout.after.as_millis() as u64,
+                    trace_id,
+                    span_id,
+                    message = "Quote service did not answer in time"
+                );
+                return Err(tonic::Status::deadline_exceeded(msg));
+            }
+            errors.add(1, &[KeyValue::new("reason", "upstream")]);
+            Err(tonic::Status::unknown(msg))
         }
+    }
+}
+
+/// Prices `count` items by the `FALLBACK_*` formula after the quote service
+/// failed with `status`, so that checkout goes on at an estimated price.
+fn fallback_quote(
+    count: ItemCount,
+    config: &ShippingConfig,
+    status: &tonic::Status,
+) -> ShippingQuote {
+    let fallback = &config.fallback;
+    let q = create_quote_from_float(
+        fallback.base_fee_usd + fallback.per_item_usd * f64::from(count.get()),
+    );
+
+    let trace = current_trace_context();
+    let (trace_id, span_id) = trace.as_fields();
+    warn!(
+        name = "QuoteFallback",
+        items = count.get(),
+        quote_total = %q,
+        error = status.message(),
+        trace_id,
+        span_id,
+        message = "Quote service failed, pricing with the fallback formula"
+    );
+    global::meter("otel_demo.shipping.quote")
+        .u64_counter("app.shipping.quote.fallbacks")
+        .build()
+        .add(1, &[KeyValue::new("code", format!("{:?}", status.code()))]);
+    let level = config.instrumentation_level;
+    level.set_attribute(
+        InstrumentationLevel::Minimal,
+        KeyValue::new("app.shipping.quote.source", "fallback"),
+    );
+    level.set_attribute(
+        InstrumentationLevel::Minimal,
+        KeyValue::new("app.shipping.cost.total", q.to_string()),
+    );
+
+    ShippingQuote {
+        source: QuoteSource::Fallback,
+        confidence: QuoteConfidence::Estimated,
+        ..service_quote(q.dollars * 100 + q.cents as u64, config)
+    }
+}
+
+fn service_quote(total_cents: u64, config: &ShippingConfig) -> ShippingQuote {
+    ShippingQuote {
+        total_cents,
+        charges: vec![],
+        currency: "USD".to_string(),
+        source: QuoteSource::QuoteService,
+        confidence: QuoteConfidence::Exact,
+        quoted_at: determinism::now(config),
+    }
+}
+
+/// Marks a change in the quote service's health on the active span and in
+/// the `app.shipping.quote.health_transitions` counter.
+fn record_health(transition: Option<Health>, config: &ShippingConfig) {
+    let Some(health) = transition else {
+        return;
     };
 
+    let trace = current_trace_context();
+    let (trace_id, span_id) = trace.as_fields();
+    warn!(
+        name = "QuoteServiceHealthChanged",
+        health = health.as_str(),
+        trace_id,
+        span_id,
+        message = "Quote service health changed"
+    );
+
     let meter = global::meter("otel_demo.shipping.quote");
-    let counter = meter.u64_counter("app.shipping.items_count").build();
-    counter.add(count as u64, &[]);
+    let counter = meter
+        .u64_counter("app.shipping.quote.health_transitions")
+        .build();
+    counter.add(1, &[KeyValue::new("health", health.as_str())]);
+
+    QuoteEvent::health_changed(health).emit(config.instrumentation_level);
+}
+
+/// Records a quote above the `QUOTE_WARN_ABOVE` threshold, which may point to
+/// a pricing bug or abuse. Unlike a hard limit, the quote is still returned.
+fn flag_high_value(q: &Quote, threshold: f64, level: InstrumentationLevel) {
+    let trace = current_trace_context();
+    let (trace_id, span_id) = trace.as_fields();
+    warn!(
+        name = "HighValueQuote",
+        quote_total = %q,
+        threshold = threshold,
+        trace_id,
+        span_id,
+        message = "Quote exceeds the warning threshold"
+    );
+
+    let meter = global::meter("otel_demo.shipping.quote");
+    let counter = meter.u64_counter("app.shipping.quote.high_value").build();
+    counter.add(1, &[]);
 
-    Ok(get_active_span(|span| {
-        let q = create_quote_from_float(f);
-        span.add_event(
-            "Received Quote".to_string(),
-            vec![KeyValue::new("app.shipping.cost.total", format!("{}", q))],
+    QuoteEvent::high_value(q, threshold).emit(level);
+}
+
+/// Marks quote request failures worth retrying: the connection failed or
+/// the service answered 5xx or 429.
+#[derive(Debug)]
+struct Transient;
+
+impl fmt::Display for Transient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("transient quote service failure")
+    }
+}
+
+impl std::error::Error for Transient {}
+
+fn is_transient(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<Transient>().is_some()
+}
+
+/// The quote service didn't answer within `after`. Not retried: a hung
+/// service would hold the request for the timeout once per attempt.
+#[derive(Debug)]
+struct QuoteTimeout {
+    after: Duration,
+    /// Whether `after` was cut short of `QUOTE_TIMEOUT_MS` by the caller's
+    /// deadline, which says nothing of the quote service's health.
+    by_deadline: bool,
+}
+
+impl fmt::Display for QuoteTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Quote service did not answer within {} ms",
+            self.after.as_millis()
+        )
+    }
+}
+
+impl std::error::Error for QuoteTimeout {}
+
+/// Requests a quote, retrying transient failures with exponential backoff
+/// as `QUOTE_MAX_RETRIES` and `QUOTE_RETRY_BUDGET_MS` allow. Returns the
+/// last error, with the number of attempts, once out of retries or budget.
+/// No attempt outlasts the caller's `deadline`.
+async fn request_quote_with_retries(
+    count: ItemCount,
+    quote_addr: &str,
+    config: &ShippingConfig,
+    jitter: &Entropy,
+    deadline: Option<Instant>,
+) -> Result<f64> {
+    let retry = &config.retry;
+    let started = Instant::now();
+    let mut attempt = 1;
+    loop {
+        let until_deadline =
+            deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        let remaining = retry
+            .budget
+            .map(|budget| budget.saturating_sub(started.elapsed()))
+            .into_iter()
+            .chain(until_deadline)
+            .min();
+        let timeout = remaining.map_or(config.quote_timeout, |remaining| {
+            remaining.min(config.quote_timeout)
+        });
+        let by_deadline =
+            until_deadline.is_some_and(|left| left == timeout && left < config.quote_timeout);
+        let inject_timeout = config.quote_inject_timeout_rate > 0.0
+            && jitter.fraction() < config.quote_inject_timeout_rate;
+        let attempt_started = Instant::now();
+        let injected_delay = inject_timeout.then(|| injected_delay(timeout));
+        let mut result = request_quote(count, quote_addr, config, timeout, injected_delay).await;
+        if let Some(timeout) = result
+            .as_mut()
+            .err()
+            .and_then(|err| err.downcast_mut::<QuoteTimeout>())
+        {
+            timeout.by_deadline = by_deadline;
+        }
+        latency_histogram("app.shipping.quote.upstream_duration_ms").record(
+            attempt_started.elapsed().as_millis() as u64,
+            &[outcome(result.is_ok())],
+        );
+
+        let backoff = backoff_delay(retry.backoff, attempt - 1, retry.jitter, jitter);
+        let out_of_budget = retry
+            .budget
+            .is_some_and(|budget| started.elapsed() + backoff >= budget)
+            || deadline.is_some_and(|deadline| Instant::now() + backoff >= deadline);
+        let err = match result {
+            Err(err) if is_transient(&err) && attempt <= retry.max_retries && !out_of_budget => err,
+            result => {
+                config.instrumentation_level.set_attribute(
+                    InstrumentationLevel::Standard,
+                    KeyValue::new("app.shipping.quote.attempts", attempt as i64),
+                );
+                return result.map_err(|err| {
+                    let attempts = if attempt == 1 { "attempt" } else { "attempts" };
+                    let msg = format!("{err} (after {attempt} {attempts})");
+                    err.context(msg)
+                });
+            }
+        };
+
+        let trace = current_trace_context();
+        let (trace_id, span_id) = trace.as_fields();
+        warn!(
+            name = "RetryingQuote",
+            attempt = attempt,
+            backoff_ms = backoff.as_millis() as u64,
+            error = %err,
+            trace_id,
+            span_id,
+            message = "Retrying failed quote request"
         );
-        span.set_attribute(KeyValue::new("app.shipping.cost.total", format!("{}", q)));
-        q
-    }))
+        actix_web::rt::time::sleep(backoff).await;
+        attempt += 1;
+    }
 }
 
-async fn request_quote(count: u32) -> Result<f64, anyhow::Error> {
-    let client = awc::Client::new();
-    let quote_service_addr: String = format!(
-        "{}{}",
-        env::var("QUOTE_ADDR")
-            .unwrap_or_else(|_| "http://quote:8090".to_string())
-            .parse::<String>()
-            .expect("Invalid quote service address"),
-        "/getquote"
+/// The delay put in front of a quote request to make it hang, past the
+/// `timeout` of the attempt so that the client's timeout gives up on it.
+fn injected_delay(timeout: Duration) -> Duration {
+    let trace = current_trace_context();
+    let (trace_id, span_id) = trace.as_fields();
+    warn!(
+        name = "InjectingQuoteTimeout",
+        timeout_ms = timeout.as_millis() as u64,
+        trace_id,
+        span_id,
+        message = "Injecting a quote service timeout"
     );
+    timeout * 2
+}
+
+tokio::task_local! {
+    /// How long the quote request being sent took to connect, unset while
+    /// it reuses a pooled connection.
+    static CONNECT_TIME: Cell<Option<Duration>>;
 
+    /// How long `InjectedDelay` holds the quote request being sent back.
+    static INJECTED_DELAY: Option<Duration>;
+}
+
+thread_local! {
+    /// The worker's quote service client, with the pool settings it was
+    /// built with. `awc` clients can't be shared between threads.
+    static QUOTE_CLIENT: RefCell<Option<(QuotePoolConfig, awc::Client)>> =
+        const { RefCell::new(None) };
+}
+
+/// The worker's quote service client, built on first use, whose pool keeps
+/// connections open across quote requests. It is rebuilt, dropping the
+/// pool, if `pool` differs from the settings it was built with.
+fn quote_client(pool: &QuotePoolConfig) -> awc::Client {
+    QUOTE_CLIENT.with_borrow_mut(|cached| match cached {
+        Some((built_with, client)) if built_with == pool => client.clone(),
+        _ => {
+            let connector = TimedConnector {
+                inner: TcpConnector::new(Resolver::default()).service(),
+            };
+            let client = awc::Client::builder()
+                .wrap(InjectedDelay)
+                .connector(
+                    awc::Connector::new()
+                        .connector(connector)
+                        .limit(pool.max_connections)
+                        .conn_keep_alive(pool.keep_alive),
+                )
+                .finish();
+            *cached = Some((pool.clone(), client.clone()));
+            client
+        }
+    })
+}
+
+/// Connector timing how long the client takes to connect, so that the
+/// connect phase can be told apart from the wait for the quote service.
+/// The pool connects inside the task of the request needing a connection,
+/// which finds the time in `CONNECT_TIME`.
+#[derive(Clone)]
+struct TimedConnector {
+    inner: ConnectorService,
+}
+
+impl Service<ConnectInfo<Uri>> for TimedConnector {
+    type Response = <ConnectorService as Service<ConnectInfo<Uri>>>::Response;
+    type Error = ConnectError;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, ConnectError>>;
+
+    fn poll_ready(&self, cx: &mut task::Context<'_>) -> Poll<Result<(), ConnectError>> {
+        Service::<ConnectInfo<Uri>>::poll_ready(&self.inner, cx)
+    }
+
+    fn call(&self, req: ConnectInfo<Uri>) -> Self::Future {
+        let started = Instant::now();
+        let connecting = self.inner.call(req);
+        Box::pin(async move {
+            let connection = connecting.await;
+            let _ = CONNECT_TIME.try_with(|connect| connect.set(Some(started.elapsed())));
+            connection
+        })
+    }
+}
+
+/// Client middleware holding quote requests back for their
+/// `INJECTED_DELAY` before sending them. The client's timeout runs
+/// meanwhile, so an injected timeout fails like a hung quote service does.
+struct InjectedDelay;
+
+impl<S> Transform<S, ConnectRequest> for InjectedDelay
+where
+    S: Service<ConnectRequest, Response = ConnectResponse, Error = SendRequestError> + 'static,
+{
+    type Transform = InjectedDelayService<S>;
+
+    fn new_transform(self, service: S) -> Self::Transform {
+        InjectedDelayService {
+            inner: Rc::new(service),
+        }
+    }
+}
+
+struct InjectedDelayService<S> {
+    inner: Rc<S>,
+}
+
+impl<S> Service<ConnectRequest> for InjectedDelayService<S>
+where
+    S: Service<ConnectRequest, Response = ConnectResponse, Error = SendRequestError> + 'static,
+{
+    type Response = ConnectResponse;
+    type Error = SendRequestError;
+    type Future = LocalBoxFuture<'static, Result<ConnectResponse, SendRequestError>>;
+
+    fn poll_ready(&self, cx: &mut task::Context<'_>) -> Poll<Result<(), SendRequestError>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&self, req: ConnectRequest) -> Self::Future {
+        let delay = INJECTED_DELAY.try_with(|delay| *delay).ok().flatten();
+        let inner = Rc::clone(&self.inner);
+        Box::pin(async move {
+            if let Some(delay) = delay {
+                actix_web::rt::time::sleep(delay).await;
+            }
+            inner.call(req).await
+        })
+    }
+}
+
+/// Records a phase of a quote service call on the active span, in
+/// fractional milliseconds like the handler phase timings.
+fn record_upstream_phase(level: InstrumentationLevel, phase: &'static str, duration: Duration) {
+    level.set_attribute(
+        InstrumentationLevel::Standard,
+        KeyValue::new(
+            format!("app.shipping.quote.upstream.{phase}_ms"),
+            duration.as_secs_f64() * 1000.0,
+        ),
+    );
+}
+
+/// Records the `RECORDED_BAGGAGE` entries of the current context, which
+/// `trace_request` propagates to the quote service along with the trace.
+fn record_baggage(level: InstrumentationLevel) {
+    let cx = opentelemetry::Context::current();
+    for key in RECORDED_BAGGAGE {
+        if let Some(value) = cx.baggage().get(key) {
+            level.set_attribute(
+                InstrumentationLevel::Standard,
+                KeyValue::new(
+                    format!("app.shipping.baggage.{key}"),
+                    value.as_str().to_string(),
+                ),
+            );
+        }
+    }
+}
+
+/// Requests a quote, giving up after `timeout`, once `injected_delay` has
+/// passed if one is given. The connect,
+/// time-to-first-byte and body-read phases of the call, and their total,
+/// are recorded on the active span, connect being 0 on a reused connection;
+/// a retried call keeps those of its last attempt.
+async fn request_quote(
+    count: ItemCount,
+    quote_addr: &str,
+    config: &ShippingConfig,
+    timeout: Duration,
+    injected_delay: Option<Duration>,
+) -> Result<f64, anyhow::Error> {
+    let level = config.instrumentation_level;
+    let client = quote_client(&config.quote_pool);
+    let quote_service_addr: String = format!("{}{}", quote_addr, "/getquote");
+
+    let trace = current_trace_context();
+    let (trace_id, span_id) = trace.as_fields();
     info!(
         name = "RequestingQuote",
         quote_service_addr = quote_service_addr.as_str(),
+        trace_id,
+        span_id,
         message = "Requesting quote"
     );
+    record_baggage(level);
 
-    let mut reqbody = HashMap::new();
-    reqbody.insert("numberOfItems", count);
+    let reqbody = QuoteServiceRequest {
+        number_of_items: count.get(),
+    };
 
-    let mut response = client
-        .post(quote_service_addr)
-        .trace_request()
-        .send_json(&reqbody)
-        .await
-        .map_err(|err| anyhow::anyhow!("Failed to call quote service: {err}"))?;
+    let started = Instant::now();
+    let (sent, connected) = CONNECT_TIME
+        .scope(Cell::new(None), async {
+            let sending = async {
+                client
+                    .post(quote_service_addr)
+                    .timeout(timeout)
+                    .trace_request()
+                    .send_json(&reqbody)
+                    .await
+            };
+            let sent = INJECTED_DELAY.scope(injected_delay, sending).await;
+            (sent, CONNECT_TIME.with(Cell::get))
+        })
+        .await;
+    level.set_attribute(
+        InstrumentationLevel::Standard,
+        KeyValue::new("app.shipping.quote.connection_reused", connected.is_none()),
+    );
+    let connect = connected.unwrap_or_default();
+    record_upstream_phase(level, "connect", connect);
+    record_upstream_phase(level, "ttfb", started.elapsed().saturating_sub(connect));
+    let mut response = sent.map_err(|err| match err {
+        SendRequestError::Timeout => anyhow::Error::new(QuoteTimeout {
+            after: timeout,
+            by_deadline: false,
+        }),
+        err => {
+            anyhow::Error::new(Transient).context(format!("Failed to call quote service: {err}"))
+        }
+    })?;
 
-    let bytes = response
-        .body()
-        .await
-        .context("Failed to read response body from quote service")?;
+    let status = response.status();
+    let json = is_json(response.content_type());
+    if !status.is_success() {
+        let msg = format!("Quote service answered {status}");
+        return Err(
+            if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+                anyhow::Error::new(Transient).context(msg)
+            } else {
+                anyhow::Error::msg(msg)
+            },
+        );
+    }
+
+    let body_started = Instant::now();
+    let body = response.body().await;
+    record_upstream_phase(level, "body", body_started.elapsed());
+    record_upstream_phase(level, "total", started.elapsed());
+    let bytes = body.map_err(|err| {
+        anyhow::Error::new(Transient).context(format!(
+            "Failed to read response body from quote service: {err}"
+        ))
+    })?;
 
     let resp = std::str::from_utf8(&bytes)
         .context("Failed to parse quote service response as UTF-8")?
         .to_owned();
 
-    let f = resp
+    if json {
+        parse_quote_json(&resp)
+    } else {
+        parse_quote_value(&resp, config.quote_decimal_separator)
+    }
+}
+
+fn is_json(content_type: &str) -> bool {
+    let content_type = content_type.to_ascii_lowercase();
+    content_type == "application/json" || content_type.ends_with("+json")
+}
+
+/// Parses a JSON quote service response, which the decimal separator
+/// doesn't apply to.
+fn parse_quote_json(raw: &str) -> Result<f64, anyhow::Error> {
+    let quote = serde_json::from_str::<QuoteServiceResponse>(raw)
+        .with_context(|| format!("Failed to parse quote service response {raw:?} as JSON"))?
+        .quote();
+    if !quote.is_finite() {
+        anyhow::bail!("Quote value in {raw:?} is not a finite number");
+    }
+    Ok(quote)
+}
+
+/// Parses a quote value written with `decimal_separator`. Values with more
+/// than one separator, or with a `.` when another separator is configured,
+/// are rejected rather than guessed at, since `1,000,99` or `1.000,99` could
+/// be read either way.
+fn parse_quote_value(raw: &str, decimal_separator: char) -> Result<f64, anyhow::Error> {
+    let raw = raw.trim();
+    if raw.matches(decimal_separator).count() > 1 || (decimal_separator != '.' && raw.contains('.'))
+    {
+        anyhow::bail!("Ambiguous quote value {raw:?}");
+    }
+
+    let f = raw
+        .replace(decimal_separator, ".")
         .parse::<f64>()
-        .context("Failed to parse quote value as f64")?;
+        .with_context(|| format!("Failed to parse quote value {raw:?} as f64"))?;
+    if !f.is_finite() {
+        anyhow::bail!("Quote value {raw:?} is not a finite number");
+    }
 
     Ok(f)
 }
 
+/// Rounds `value` to the nearest cent. Negative values, which no quote
+/// should have, are clamped to zero.
 pub fn create_quote_from_float(value: f64) -> Quote {
+    let total_cents = (value.max(0.0) * 100.0).round() as u64;
     Quote {
-        dollars: value.floor() as u64,
-        cents: ((value * 100_f64) as u32) % 100,
+        dollars: total_cents / 100,
+        cents: (total_cents % 100) as u32,
+    }
+}
+
+impl ShippingQuote {
+    /// Adds a charge, the total saturating rather than overflowing.
+    pub fn add_charge(&mut self, label: &'static str, cents: u64) {
+        self.total_cents = self.total_cents.saturating_add(cents);
+        self.charges.push(Charge { label, cents });
+    }
+
+    /// The shipping cost before any charges.
+    pub fn base_cents(&self) -> u64 {
+        let charges = self
+            .charges
+            .iter()
+            .fold(0u64, |total, charge| total.saturating_add(charge.cents));
+        self.total_cents.saturating_sub(charges)
+    }
+}
+
+impl From<&ShippingQuote> for Quote {
+    fn from(quote: &ShippingQuote) -> Self {
+        Quote {
+            dollars: quote.total_cents / 100,
+            cents: (quote.total_cents % 100) as u32,
+        }
     }
 }
 
 impl fmt::Display for Quote {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}.{}", self.dollars, self.cents)
+        write!(f, "{}.{:02}", self.dollars, self.cents)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::{
+        rc::Rc,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use actix_web::{web, HttpResponse};
+
+    use super::super::breaker::BreakerState;
+    use super::super::config::{BreakerConfig, FallbackConfig, RetryConfig};
+    use crate::test_support::{
+        in_test_span, spawn_mock, spawn_quote_mock, CapturedLogs, TestMetrics,
+    };
+
+    async fn quote_with_warn_threshold(threshold: f64) -> (u64, bool) {
+        let metrics = TestMetrics::install();
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            quote_warn_above: Some(threshold),
+            ..Default::default()
+        };
+
+        let state = QuoteState::new(&config);
+        let (quote, span) = in_test_span(
+            "get-quote",
+            create_quote_from_count(ItemCount::new(3), &config, &state, &config.pricing),
+        )
+        .await;
+        assert_eq!(quote.unwrap().total_cents, 1099);
+
+        let flagged = span
+            .events
+            .iter()
+            .any(|event| event.name == "High Value Quote");
+        (
+            metrics.counter("app.shipping.quote.high_value", &[]),
+            flagged,
+        )
+    }
+
+    #[test]
+    fn test_charges_saturate_the_total() {
+        let mut quote = ShippingQuote {
+            total_cents: 1099,
+            charges: Vec::new(),
+            currency: "USD".to_string(),
+            source: QuoteSource::QuoteService,
+            confidence: QuoteConfidence::Exact,
+            quoted_at: chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+        };
+        quote.add_charge("Weight charge", u64::MAX);
+        quote.add_charge("Handling fee", 250);
+        assert_eq!(quote.total_cents, u64::MAX);
+        assert_eq!(quote.base_cents(), 0);
+    }
+
+    #[actix_web::test]
+    async fn test_quote_below_warn_threshold_is_not_flagged() {
+        assert_eq!(quote_with_warn_threshold(20.0).await, (0, false));
+    }
+
+    #[actix_web::test]
+    async fn test_quote_above_warn_threshold_is_flagged() {
+        assert_eq!(quote_with_warn_threshold(5.0).await, (1, true));
+    }
+
+    #[actix_web::test]
+    async fn test_quote_metrics() {
+        let metrics = TestMetrics::install();
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            ..Default::default()
+        };
+        let state = QuoteState::new(&config);
+        create_quote_from_count(ItemCount::new(3), &config, &state, &config.pricing)
+            .await
+            .unwrap();
+        create_quote_from_count(ItemCount::new(2), &config, &state, &config.pricing)
+            .await
+            .unwrap();
+        assert_eq!(metrics.counter("app.shipping.items_count", &[]), 5);
+        assert_eq!(metrics.counter("app.shipping.quote.errors", &[]), 0);
+
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("not a number"),
+            fallback: no_fallback(),
+            ..Default::default()
+        };
+        let state = QuoteState::new(&config);
+        assert!(
+            create_quote_from_count(ItemCount::new(4), &config, &state, &config.pricing)
+                .await
+                .is_err()
+        );
+        assert_eq!(metrics.counter("app.shipping.items_count", &[]), 5);
+        assert_eq!(
+            metrics.counter(
+                "app.shipping.quote.errors",
+                &[KeyValue::new("reason", "upstream")]
+            ),
+            1
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_outage_start_and_end_are_marked_once() {
+        let metrics = TestMetrics::install();
+        const ANSWERS: [&str; 5] = ["10.99", "not a number", "not a number", "10.99", "10.99"];
+        let calls = Arc::new(AtomicUsize::new(0));
+        let config = ShippingConfig {
+            quote_addr: spawn_mock(move |cfg| {
+                let calls = calls.clone();
+                cfg.route(
+                    "/getquote",
+                    web::post().to(move || {
+                        let call = calls.fetch_add(1, Ordering::SeqCst);
+                        async move { ANSWERS[call.min(ANSWERS.len() - 1)] }
+                    }),
+                );
+            }),
+            ..Default::default()
+        };
+        let state = QuoteState::new(&config);
+
+        let ((), span) = in_test_span("quotes", async {
+            for _ in ANSWERS {
+                let _ =
+                    create_quote_from_count(ItemCount::new(1), &config, &state, &config.pricing)
+                        .await;
+            }
+        })
+        .await;
+
+        let transitions: Vec<_> = span
+            .events
+            .iter()
+            .filter(|event| event.name.starts_with("Quote Service"))
+            .map(|event| event.name.as_ref())
+            .collect();
+        assert_eq!(
+            transitions,
+            ["Quote Service Unhealthy", "Quote Service Recovered"]
+        );
+        for health in ["healthy", "unhealthy"] {
+            assert_eq!(
+                metrics.counter(
+                    "app.shipping.quote.health_transitions",
+                    &[KeyValue::new("health", health)]
+                ),
+                1
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_retries_stop_at_the_time_budget() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let hits = attempts.clone();
+        let config = ShippingConfig {
+            quote_addr: spawn_mock(move |cfg| {
+                let hits = hits.clone();
+                cfg.route(
+                    "/getquote",
+                    web::post().to(move || {
+                        hits.fetch_add(1, Ordering::SeqCst);
+                        async {
+                            actix_web::rt::time::sleep(Duration::from_millis(100)).await;
+                            HttpResponse::ServiceUnavailable().finish()
+                        }
+                    }),
+                );
+            }),
+            retry: RetryConfig {
+                max_retries: 10,
+                backoff: Duration::from_millis(20),
+                budget: Some(Duration::from_millis(320)),
+                ..Default::default()
+            },
+            fallback: no_fallback(),
+            ..Default::default()
+        };
+        let state = QuoteState::new(&config);
+
+        let started = Instant::now();
+        assert!(
+            create_quote_from_count(ItemCount::new(1), &config, &state, &config.pricing)
+                .await
+                .is_err()
+        );
+        let elapsed = started.elapsed();
+
+        // Two full attempts and their backoff take about 260 ms, so a third
+        // starts and is cut off at the budget rather than running 100 ms.
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        assert!(elapsed >= Duration::from_millis(300), "{elapsed:?}");
+        assert!(elapsed < Duration::from_millis(400), "{elapsed:?}");
+    }
+
+    /// Outcomes of four quotes from a failing backend and a healthy
+    /// replica, with breakers treating the first failure as an outage.
+    async fn quotes_with_one_failing_backend(scope: BreakerScope) -> Vec<Option<tonic::Code>> {
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("not a number"),
+            quote_replica_addrs: vec![spawn_quote_mock("10.99")],
+            breaker: BreakerConfig {
+                failure_threshold: 1,
+                open_for: Duration::from_secs(60),
+                scope,
+            },
+            fallback: no_fallback(),
+            ..Default::default()
+        };
+        let state = QuoteState::new(&config);
+        let mut outcomes = Vec::new();
+        for _ in 0..4 {
+            let quote =
+                create_quote_from_count(ItemCount::new(1), &config, &state, &config.pricing).await;
+            outcomes.push(quote.err().map(|status| status.code()));
+        }
+        outcomes
+    }
+
+    #[actix_web::test]
+    async fn test_per_backend_breakers_keep_serving_from_healthy_backend() {
+        let metrics = TestMetrics::install();
+        assert_eq!(
+            quotes_with_one_failing_backend(BreakerScope::PerBackend).await,
+            [Some(tonic::Code::Unknown), None, None, None]
+        );
+        assert_eq!(
+            metrics.counter(
+                "app.shipping.quote.breaker_state_changes",
+                &[KeyValue::new("state", "open")]
+            ),
+            1
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_global_breaker_trips_for_every_backend() {
+        let metrics = TestMetrics::install();
+        let unavailable = Some(tonic::Code::Unavailable);
+        assert_eq!(
+            quotes_with_one_failing_backend(BreakerScope::Global).await,
+            [
+                Some(tonic::Code::Unknown),
+                unavailable,
+                unavailable,
+                unavailable
+            ]
+        );
+        assert_eq!(
+            metrics.counter(
+                "app.shipping.quote.breaker_state_changes",
+                &[
+                    KeyValue::new("backend", "global"),
+                    KeyValue::new("state", "open")
+                ]
+            ),
+            1
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_upstream_phases_add_up_to_the_call() {
+        let quote_addr = spawn_mock(|cfg| {
+            cfg.route(
+                "/getquote",
+                web::post().to(|| async {
+                    actix_web::rt::time::sleep(Duration::from_millis(50)).await;
+                    "10.99"
+                }),
+            );
+        });
+        let config = ShippingConfig {
+            quote_addr,
+            ..Default::default()
+        };
+        let state = QuoteState::new(&config);
+
+        let (quote, span) = in_test_span(
+            "quote",
+            create_quote_from_count(ItemCount::new(1), &config, &state, &config.pricing),
+        )
+        .await;
+        assert!(quote.is_ok());
+        let phase_ms = |phase: &str| {
+            let key = format!("app.shipping.quote.upstream.{phase}_ms");
+            match span
+                .attributes
+                .iter()
+                .find(|kv| kv.key.as_str() == key)
+                .map(|kv| &kv.value)
+            {
+                Some(opentelemetry::Value::F64(ms)) => *ms,
+                other => panic!("{key} is {other:?}"),
+            }
+        };
+
+        let (connect, ttfb, body) = (phase_ms("connect"), phase_ms("ttfb"), phase_ms("body"));
+        assert!(connect > 0.0, "connect took {connect} ms");
+        assert!(ttfb >= 50.0, "ttfb took {ttfb} ms");
+        let total = phase_ms("total");
+        let unaccounted = total - (connect + ttfb + body);
+        assert!(
+            (0.0..1.0).contains(&unaccounted),
+            "{unaccounted} ms of {total} ms"
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_sequential_quotes_reuse_a_connection() {
+        let peers = Arc::new(Mutex::new(Vec::new()));
+        let seen = peers.clone();
+        let quote_addr = spawn_mock(move |cfg| {
+            let seen = seen.clone();
+            cfg.route(
+                "/getquote",
+                web::post().to(move |req: actix_web::HttpRequest| {
+                    seen.lock().unwrap().push(req.peer_addr());
+                    async { "10.99" }
+                }),
+            );
+        });
+        let config = ShippingConfig {
+            quote_addr,
+            ..Default::default()
+        };
+        let state = QuoteState::new(&config);
+
+        let mut reused = Vec::new();
+        for _ in 0..20 {
+            let (quote, span) = in_test_span(
+                "quote",
+                create_quote_from_count(ItemCount::new(1), &config, &state, &config.pricing),
+            )
+            .await;
+            assert!(quote.is_ok());
+            reused.extend(span.attributes.into_iter().find_map(|kv| {
+                (kv.key.as_str() == "app.shipping.quote.connection_reused").then_some(kv.value)
+            }));
+        }
+
+        let mut peers = peers.lock().unwrap().clone();
+        assert_eq!(peers.len(), 20);
+        peers.dedup();
+        assert_eq!(peers.len(), 1, "connected from {peers:?}");
+        assert_eq!(reused.len(), 20);
+        assert_eq!(reused.first(), Some(&opentelemetry::Value::Bool(false)));
+        assert!(reused[1..]
+            .iter()
+            .all(|value| *value == opentelemetry::Value::Bool(true)));
+    }
+
+    #[actix_web::test]
+    async fn test_breaker_opens_then_probes_and_closes() {
+        let metrics = TestMetrics::install();
+        let (quote_addr, calls) = spawn_flaky_mock(2, StatusCode::BAD_REQUEST);
+        let config = ShippingConfig {
+            quote_addr,
+            breaker: BreakerConfig {
+                failure_threshold: 2,
+                open_for: Duration::from_millis(50),
+                ..Default::default()
+            },
+            fallback: no_fallback(),
+            ..Default::default()
+        };
+        let state = QuoteState::new(&config);
+        let circuit_state = || {
+            metrics.gauge(
+                "app.shipping.quote.circuit_state",
+                &[KeyValue::new("backend", "global")],
+            )
+        };
+
+        let (codes, span) = in_test_span("quotes", async {
+            let mut codes = Vec::new();
+            for _ in 0..2 {
+                let quote =
+                    create_quote_from_count(ItemCount::new(1), &config, &state, &config.pricing)
+                        .await;
+                codes.push(quote.err().map(|status| status.code()));
+            }
+            assert_eq!(circuit_state(), Some(1));
+
+            let rejected =
+                create_quote_from_count(ItemCount::new(1), &config, &state, &config.pricing).await;
+            codes.push(rejected.err().map(|status| status.code()));
+
+            tokio::time::sleep(Duration::from_millis(60)).await;
+            let probe =
+                create_quote_from_count(ItemCount::new(1), &config, &state, &config.pricing).await;
+            codes.push(probe.err().map(|status| status.code()));
+            codes
+        })
+        .await;
+
+        assert_eq!(
+            codes,
+            [
+                Some(tonic::Code::Unknown),
+                Some(tonic::Code::Unknown),
+                Some(tonic::Code::Unavailable),
+                None
+            ]
+        );
+        // The rejected call never reached the quote service.
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(circuit_state(), Some(0));
+        let transitions: Vec<_> = span
+            .events
+            .iter()
+            .map(|event| event.name.as_ref())
+            .filter(|name| name.starts_with("Quote Circuit"))
+            .collect();
+        assert_eq!(
+            transitions,
+            [
+                "Quote Circuit Opened",
+                "Quote Circuit Half-Opened",
+                "Quote Circuit Closed"
+            ]
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_cancelled_probe_lets_the_next_request_probe() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let hits = calls.clone();
+        let quote_addr = spawn_mock(move |cfg| {
+            let hits = hits.clone();
+            cfg.route(
+                "/getquote",
+                web::post().to(move || {
+                    let call = hits.fetch_add(1, Ordering::SeqCst);
+                    async move {
+                        match call {
+                            0 => HttpResponse::BadRequest().finish(),
+                            1 => {
+                                actix_web::rt::time::sleep(Duration::from_millis(500)).await;
+                                HttpResponse::Ok().body("10.99")
+                            }
+                            _ => HttpResponse::Ok().body("10.99"),
+                        }
+                    }
+                }),
+            );
+        });
+        let config = ShippingConfig {
+            quote_addr,
+            breaker: BreakerConfig {
+                failure_threshold: 1,
+                open_for: Duration::from_millis(20),
+                ..Default::default()
+            },
+            fallback: no_fallback(),
+            ..Default::default()
+        };
+        let state = QuoteState::new(&config);
+        let quote = || create_quote_from_count(ItemCount::new(1), &config, &state, &config.pricing);
+
+        assert!(quote().await.is_err());
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        let cancelled = tokio::time::timeout(Duration::from_millis(50), quote()).await;
+        assert!(cancelled.is_err());
+
+        assert_eq!(quote().await.unwrap().total_cents, 1099);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    /// Quote service failing its first `failures` calls with `status`,
+    /// and the number of calls it got.
+    fn spawn_flaky_mock(failures: usize, status: StatusCode) -> (String, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let hits = calls.clone();
+        let addr = spawn_mock(move |cfg| {
+            let hits = hits.clone();
+            cfg.route(
+                "/getquote",
+                web::post().to(move || {
+                    let call = hits.fetch_add(1, Ordering::SeqCst);
+                    async move {
+                        if call < failures {
+                            HttpResponse::build(status).finish()
+                        } else {
+                            HttpResponse::Ok().body("10.99")
+                        }
+                    }
+                }),
+            );
+        });
+        (addr, calls)
+    }
+
+    fn quick_retries(quote_addr: String) -> ShippingConfig {
+        ShippingConfig {
+            quote_addr,
+            retry: RetryConfig {
+                backoff: Duration::from_millis(10),
+                ..Default::default()
+            },
+            fallback: no_fallback(),
+            ..Default::default()
+        }
+    }
+
+    fn no_fallback() -> FallbackConfig {
+        FallbackConfig {
+            enabled: false,
+            ..Default::default()
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_failed_quotes_fall_back_to_the_local_formula() {
+        let (logs, _guard) = CapturedLogs::install();
+        let mut config = quick_retries(spawn_quote_mock("not a number"));
+        config.fallback = FallbackConfig {
+            enabled: true,
+            base_fee_usd: 5.0,
+            per_item_usd: 0.5,
+        };
+        let state = QuoteState::new(&config);
+
+        let (quote, span) = in_test_span(
+            "quote",
+            create_quote_from_count(ItemCount::new(3), &config, &state, &config.pricing),
+        )
+        .await;
+        let quote = quote.unwrap();
+        assert_eq!(quote.total_cents, 650);
+        assert_eq!(quote.source, QuoteSource::Fallback);
+        assert_eq!(quote.confidence, QuoteConfidence::Estimated);
+        assert!(span
+            .attributes
+            .contains(&KeyValue::new("app.shipping.quote.source", "fallback")));
+        let fallbacks = logs.named("QuoteFallback");
+        assert_eq!(fallbacks.len(), 1);
+        assert_eq!(fallbacks[0]["quote_total"], "6.50");
+    }
+
+    #[actix_web::test]
+    async fn test_disabled_fallback_keeps_the_error() {
+        let config = quick_retries(spawn_quote_mock("not a number"));
+        let state = QuoteState::new(&config);
+
+        let (quote, span) = in_test_span(
+            "quote",
+            create_quote_from_count(ItemCount::new(3), &config, &state, &config.pricing),
+        )
+        .await;
+        assert_eq!(quote.unwrap_err().code(), tonic::Code::Unknown);
+        assert!(!span
+            .attributes
+            .iter()
+            .any(|kv| kv.key.as_str() == "app.shipping.quote.source"));
+    }
+
+    #[actix_web::test]
+    async fn test_transient_failures_are_retried() {
+        let (logs, _guard) = CapturedLogs::install();
+        let (addr, calls) = spawn_flaky_mock(2, StatusCode::SERVICE_UNAVAILABLE);
+        let config = quick_retries(addr);
+        let state = QuoteState::new(&config);
+
+        let quote =
+            create_quote_from_count(ItemCount::new(1), &config, &state, &config.pricing).await;
+        assert_eq!(quote.unwrap().total_cents, 1099);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        let retries = logs.named("RetryingQuote");
+        let attempts: Vec<_> = retries.iter().map(|log| log["attempt"].clone()).collect();
+        let delays: Vec<_> = retries
+            .iter()
+            .map(|log| log["backoff_ms"].clone())
+            .collect();
+        assert_eq!(attempts, ["1", "2"]);
+        assert_eq!(delays, ["10", "20"]);
+    }
+
+    #[actix_web::test]
+    async fn test_concurrent_quotes_for_one_count_share_a_fetch() {
+        let metrics = TestMetrics::install();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let hits = calls.clone();
+        let config = Rc::new(ShippingConfig {
+            quote_addr: spawn_mock(move |cfg| {
+                let hits = hits.clone();
+                cfg.route(
+                    "/getquote",
+                    web::post().to(move || {
+                        hits.fetch_add(1, Ordering::SeqCst);
+                        async {
+                            actix_web::rt::time::sleep(Duration::from_millis(50)).await;
+                            "10.99"
+                        }
+                    }),
+                );
+            }),
+            quote_cache_ttl: Duration::from_secs(60),
+            ..Default::default()
+        });
+        let state = Rc::new(QuoteState::new(&config));
+
+        let tasks: Vec<_> = (0..10)
+            .map(|_| {
+                let (config, state) = (config.clone(), state.clone());
+                actix_web::rt::spawn(async move {
+                    create_quote_from_count(ItemCount::new(3), &config, &state, &config.pricing)
+                        .await
+                })
+            })
+            .collect();
+        for task in tasks {
+            assert_eq!(task.await.unwrap().unwrap().total_cents, 1099);
+        }
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(metrics.counter("app.shipping.quote.cache_misses", &[]), 1);
+        assert_eq!(metrics.counter("app.shipping.quote.cache_hits", &[]), 9);
+    }
+
+    #[actix_web::test]
+    async fn test_cached_quote_expires_and_zero_ttl_bypasses_cache() {
+        let (addr, calls) = spawn_flaky_mock(0, StatusCode::OK);
+        for (ttl, expected_calls) in [(Duration::ZERO, 3), (Duration::from_millis(100), 2)] {
+            calls.store(0, Ordering::SeqCst);
+            let config = ShippingConfig {
+                quote_addr: addr.clone(),
+                quote_cache_ttl: ttl,
+                ..Default::default()
+            };
+            let state = QuoteState::new(&config);
+
+            let ((), span) = in_test_span("quotes", async {
+                for wait in [0, 0, 150] {
+                    actix_web::rt::time::sleep(Duration::from_millis(wait)).await;
+                    create_quote_from_count(ItemCount::new(1), &config, &state, &config.pricing)
+                        .await
+                        .unwrap();
+                }
+            })
+            .await;
+            let cache_hits = span
+                .events
+                .iter()
+                .filter(|event| event.name == "Quote Cache Hit")
+                .count();
+            assert_eq!(calls.load(Ordering::SeqCst), expected_calls, "{ttl:?}");
+            assert_eq!(cache_hits, 3 - expected_calls, "{ttl:?}");
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_slow_quote_service_exceeds_deadline() {
+        let (logs, _guard) = CapturedLogs::install();
+        let config = ShippingConfig {
+            quote_addr: spawn_mock(|cfg| {
+                cfg.route(
+                    "/getquote",
+                    web::post().to(|| async {
+                        actix_web::rt::time::sleep(Duration::from_secs(5)).await;
+                        "10.99"
+                    }),
+                );
+            }),
+            quote_timeout: Duration::from_millis(100),
+            fallback: no_fallback(),
+            ..Default::default()
+        };
+        let state = QuoteState::new(&config);
+
+        let started = Instant::now();
+        let err = create_quote_from_count(ItemCount::new(1), &config, &state, &config.pricing)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::DeadlineExceeded);
+        assert!(started.elapsed() < Duration::from_secs(1));
+        let timeouts = logs.named("QuoteTimedOut");
+        assert_eq!(timeouts.len(), 1);
+        assert_eq!(timeouts[0]["duration_ms"], "100");
+    }
+
+    /// Config of a quote service taking 100 ms to answer, behind a breaker
+    /// opening on its first failure.
+    fn slow_quote_service(quote_cache_ttl: Duration) -> ShippingConfig {
+        ShippingConfig {
+            quote_addr: spawn_mock(|cfg| {
+                cfg.route(
+                    "/getquote",
+                    web::post().to(|| async {
+                        actix_web::rt::time::sleep(Duration::from_millis(100)).await;
+                        "10.99"
+                    }),
+                );
+            }),
+            breaker: BreakerConfig {
+                failure_threshold: 1,
+                open_for: Duration::from_secs(30),
+                ..Default::default()
+            },
+            quote_cache_ttl,
+            fallback: no_fallback(),
+            ..Default::default()
+        }
+    }
+
+    async fn quote_by(
+        config: &ShippingConfig,
+        state: &QuoteState,
+        deadline: Option<Duration>,
+    ) -> Result<ShippingQuote, tonic::Status> {
+        let items = [CartItem {
+            product_id: "OLJCESPC7Z".into(),
+            quantity: 1,
+            ..Default::default()
+        }];
+        let deadline = deadline.map(|left| Instant::now() + left);
+        create_quote_from_items(
+            &items,
+            None,
+            config,
+            state,
+            &[],
+            CachePolicy::Reuse,
+            deadline,
+        )
+        .await
+    }
+
+    #[actix_web::test]
+    async fn test_short_deadlines_dont_open_the_breaker() {
+        let metrics = TestMetrics::install();
+        let config = slow_quote_service(Duration::ZERO);
+        let state = QuoteState::new(&config);
+
+        for _ in 0..3 {
+            let err = quote_by(&config, &state, Some(Duration::from_millis(10)))
+                .await
+                .unwrap_err();
+            assert_eq!(err.code(), tonic::Code::DeadlineExceeded);
+        }
+        assert_eq!(state.breaker_snapshot().state, BreakerState::Closed);
+        assert_eq!(
+            metrics.counter(
+                "app.shipping.quote.errors",
+                &[KeyValue::new("reason", "deadline")]
+            ),
+            3
+        );
+        assert!(quote_by(&config, &state, None).await.is_ok());
+    }
+
+    #[actix_web::test]
+    async fn test_impatient_caller_leaves_the_shared_fetch_to_other_waiters() {
+        let config = slow_quote_service(Duration::from_secs(60));
+        let state = QuoteState::new(&config);
+
+        let (impatient, patient) = futures_util::future::join(
+            quote_by(&config, &state, Some(Duration::from_millis(20))),
+            quote_by(&config, &state, None),
+        )
+        .await;
+        assert_eq!(impatient.unwrap_err().code(), tonic::Code::DeadlineExceeded);
+        assert_eq!(patient.unwrap().total_cents, 1099);
+        assert_eq!(state.breaker_snapshot().state, BreakerState::Closed);
+    }
+
+    #[actix_web::test]
+    async fn test_injected_timeouts_take_the_timeout_path() {
+        let metrics = TestMetrics::install();
+        let (addr, calls) = spawn_flaky_mock(0, StatusCode::OK);
+        let config = ShippingConfig {
+            quote_timeout: Duration::from_millis(20),
+            quote_inject_timeout_rate: 1.0,
+            ..quick_retries(addr)
+        };
+        let state = QuoteState::new(&config);
+
+        for _ in 0..3 {
+            let err = create_quote_from_count(ItemCount::new(1), &config, &state, &config.pricing)
+                .await
+                .unwrap_err();
+            assert_eq!(err.code(), tonic::Code::DeadlineExceeded);
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        assert_eq!(
+            metrics.counter(
+                "app.shipping.quote.errors",
+                &[KeyValue::new("reason", "timeout")]
+            ),
+            3
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_client_errors_are_not_retried() {
+        let (addr, calls) = spawn_flaky_mock(usize::MAX, StatusCode::BAD_REQUEST);
+        let config = quick_retries(addr);
+        let state = QuoteState::new(&config);
+
+        let err = create_quote_from_count(ItemCount::new(1), &config, &state, &config.pricing)
+            .await
+            .unwrap_err();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(err.message().ends_with("(after 1 attempt)"), "{err}");
+    }
+
+    #[actix_web::test]
+    async fn test_final_failure_counts_attempts() {
+        let (addr, calls) = spawn_flaky_mock(usize::MAX, StatusCode::TOO_MANY_REQUESTS);
+        let config = quick_retries(addr);
+        let state = QuoteState::new(&config);
+
+        let err = create_quote_from_count(ItemCount::new(1), &config, &state, &config.pricing)
+            .await
+            .unwrap_err();
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+        assert!(err.message().ends_with("(after 4 attempts)"), "{err}");
+    }
+
+    #[test]
+    fn test_parse_quote_value_with_comma_separator() {
+        assert_eq!(parse_quote_value("10,99", ',').unwrap(), 10.99);
+        assert_eq!(parse_quote_value(" 7 ", ',').unwrap(), 7.0);
+        assert!(parse_quote_value("10.99", ',').is_err());
+    }
+
+    #[test]
+    fn test_parse_quote_value_rejects_ambiguous_values() {
+        assert!(parse_quote_value("1,000,99", ',').is_err());
+        assert!(parse_quote_value("1.000,99", ',').is_err());
+        assert!(parse_quote_value("10,99", '.').is_err());
+        assert!(parse_quote_value("1.000.99", '.').is_err());
+        assert!(parse_quote_value("NaN", '.').is_err());
+        assert_eq!(parse_quote_value("10.99", '.').unwrap(), 10.99);
+    }
+
+    #[test]
+    fn test_json_quote_responses() {
+        assert_eq!(parse_quote_json(r#"{"quote": 12.34}"#).unwrap(), 12.34);
+        assert_eq!(parse_quote_json("12.34").unwrap(), 12.34);
+        for malformed in [r#"{"price": 12.34}"#, r#"{"quote": "12.34"}"#, "12,34", ""] {
+            let err = parse_quote_json(malformed).unwrap_err();
+            assert!(
+                format!("{err}").contains(&format!("{malformed:?}")),
+                "{err}"
+            );
+        }
+        assert!(is_json("application/json"));
+        assert!(is_json("application/vnd.quote+json"));
+        assert!(!is_json("text/plain"));
+    }
+
+    #[actix_web::test]
+    async fn test_quote_service_answers_json_or_bare_floats() {
+        let json_addr = spawn_mock(|cfg| {
+            cfg.route(
+                "/getquote",
+                web::post().to(|| async {
+                    HttpResponse::Ok().json(serde_json::json!({ "quote": 12.34 }))
+                }),
+            );
+        });
+        // A body that parses neither as a bare float nor as JSON.
+        let malformed_addr = spawn_mock(|cfg| {
+            cfg.route(
+                "/getquote",
+                web::post().to(|| async {
+                    HttpResponse::Ok()
+                        .content_type("application/json")
+                        .body("quote=12.34")
+                }),
+            );
+        });
+        let quote = |quote_addr| async move {
+            let config = ShippingConfig {
+                quote_addr,
+                fallback: no_fallback(),
+                ..Default::default()
+            };
+            let state = QuoteState::new(&config);
+            create_quote_from_count(ItemCount::new(1), &config, &state, &config.pricing).await
+        };
+
+        assert_eq!(quote(json_addr).await.unwrap().total_cents, 1234);
+        assert_eq!(
+            quote(spawn_quote_mock("10.99")).await.unwrap().total_cents,
+            1099
+        );
+        let err = quote(malformed_addr).await.unwrap_err();
+        assert!(err.message().contains(r#""quote=12.34""#), "{err}");
+        let err = quote(spawn_quote_mock("quote=12.34")).await.unwrap_err();
+        assert!(err.message().contains(r#""quote=12.34""#), "{err}");
+    }
+
+    #[actix_web::test]
+    async fn test_comma_separated_quote_from_service() {
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10,99"),
+            quote_decimal_separator: ',',
+            ..Default::default()
+        };
+
+        let state = QuoteState::new(&config);
+        let quote = create_quote_from_count(ItemCount::new(1), &config, &state, &config.pricing)
+            .await
+            .unwrap();
+        assert_eq!(quote.total_cents, 1099);
+    }
 
     #[test]
     fn test_create_quote_from_float() {
@@ -112,17 +2060,166 @@ mod tests {
     }
 
     #[test]
-    fn test_quote_display() {
-        let quote = Quote {
-            dollars: 10,
-            cents: 99,
+    fn test_create_quote_from_float_rounds_to_the_nearest_cent() {
+        for (value, dollars, cents) in [
+            (10.995, 11, 0),
+            (0.299, 0, 30),
+            (0.29, 0, 29),
+            (2.675, 2, 68),
+            (-1.5, 0, 0),
+        ] {
+            let quote = create_quote_from_float(value);
+            assert_eq!((quote.dollars, quote.cents), (dollars, cents), "{value}");
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_quote_from_items_scales_by_zone() {
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.00"),
+            ..Default::default()
+        };
+        let state = QuoteState::new(&config);
+        let zones = [ShippingZone {
+            name: "remote".to_string(),
+            zip_prefixes: vec!["995".to_string()],
+            multiplier: 1.5,
+        }];
+        let items = [CartItem {
+            product_id: "OLJCESPC7Z".into(),
+            quantity: 3,
+            weight_kg: Some(0.1),
+            ..Default::default()
+        }];
+        let to_zip = |zip_code: &str| Address {
+            zip_code: zip_code.to_string(),
+            ..Default::default()
         };
-        assert_eq!(format!("{}", quote), "10.99");
 
-        let quote = Quote {
-            dollars: 0,
-            cents: 1,
+        for (zip, zone, cents) in [("99501", "remote", 1500), ("94107", DEFAULT_ZONE, 1000)] {
+            let destination = to_zip(zip);
+            let (quote, span) = in_test_span(
+                "get-quote",
+                create_quote_from_items(
+                    &items,
+                    Some(&destination),
+                    &config,
+                    &state,
+                    &zones,
+                    CachePolicy::Reuse,
+                    None,
+                ),
+            )
+            .await;
+            assert_eq!(quote.unwrap().total_cents, cents, "{zip}");
+            assert!(span
+                .attributes
+                .contains(&KeyValue::new("app.shipping.zone", zone)));
+            assert!(span
+                .attributes
+                .contains(&KeyValue::new("app.shipping.total_weight_kg", 0.3)));
+        }
+    }
+
+    #[test]
+    fn test_quote_display() {
+        for (dollars, cents, expected) in [
+            (10, 99, "10.99"),
+            (0, 1, "0.01"),
+            (5, 0, "5.00"),
+            (5, 5, "5.05"),
+            (0, 9, "0.09"),
+            (0, 99, "0.99"),
+        ] {
+            assert_eq!(format!("{}", Quote { dollars, cents }), expected);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_handling_fee_is_added_to_count_quotes() {
+        for (carrier, fee, carrier_cents, handling_cents, total) in [
+            ("10.00", 2.5, 1000, 250, (12, 50)),
+            ("10.004", 2.5, 1000, 250, (12, 50)),
+            ("10.006", 2.5, 1001, 250, (12, 51)),
+            ("10.00", 2.005, 1000, 201, (12, 1)),
+            ("10.00", -1.0, 1000, 0, (10, 0)),
+        ] {
+            let config = ShippingConfig {
+                quote_addr: spawn_quote_mock(carrier),
+                pricing: PricingConfig {
+                    handling_fee: fee,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let state = QuoteState::new(&config);
+            let (quote, span) = in_test_span(
+                "get-quote",
+                create_quote_from_count(ItemCount::new(1), &config, &state, &config.pricing),
+            )
+            .await;
+            let quote = quote.unwrap();
+            let shown = Quote::from(&quote);
+            assert_eq!((shown.dollars, shown.cents), total, "{carrier} + {fee}");
+            assert_eq!(quote.base_cents(), carrier_cents);
+            assert!(span.attributes.contains(&KeyValue::new(
+                "app.shipping.cost.carrier",
+                carrier_cents as f64 / 100.0
+            )));
+            assert!(span.attributes.contains(&KeyValue::new(
+                "app.shipping.cost.handling",
+                handling_cents as f64 / 100.0
+            )));
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_baggage_is_propagated_to_the_quote_service() {
+        let sent = Arc::new(Mutex::new(None));
+        let captured = sent.clone();
+        let config = ShippingConfig {
+            quote_addr: spawn_mock(move |cfg| {
+                let captured = captured.clone();
+                cfg.route(
+                    "/getquote",
+                    web::post().to(move |req: actix_web::HttpRequest| {
+                        let baggage = req
+                            .headers()
+                            .get("baggage")
+                            .and_then(|value| value.to_str().ok())
+                            .map(str::to_string);
+                        *captured.lock().unwrap() = baggage;
+                        async { "10.99" }
+                    }),
+                );
+            }),
+            ..Default::default()
         };
-        assert_eq!(format!("{}", quote), "0.1");
+        let state = QuoteState::new(&config);
+        let cx = opentelemetry::Context::current_with_baggage([
+            KeyValue::new("session.id", "s-42"),
+            KeyValue::new("user.tier", "gold"),
+        ]);
+
+        let (quote, span) = opentelemetry::trace::FutureExt::with_context(
+            in_test_span(
+                "get-quote",
+                create_quote_from_count(ItemCount::new(1), &config, &state, &config.pricing),
+            ),
+            cx,
+        )
+        .await;
+        assert!(quote.is_ok());
+
+        let baggage = sent.lock().unwrap().clone().expect("no baggage header");
+        let mut members: Vec<_> = baggage.split(',').map(str::trim).collect();
+        members.sort_unstable();
+        assert_eq!(members, ["session.id=s-42", "user.tier=gold"]);
+        assert!(span
+            .attributes
+            .contains(&KeyValue::new("app.shipping.baggage.session.id", "s-42")));
+        assert!(span
+            .attributes
+            .contains(&KeyValue::new("app.shipping.baggage.user.tier", "gold")));
     }
 }
@@ -0,0 +1,212 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+use actix_web::HttpResponse;
+
+use super::api_error;
+use super::determinism::Entropy;
+use super::items::ItemCount;
+use super::shipping_types::ShippingQuote;
+
+/// Most tokens kept at once; the oldest are forgotten first.
+const MAX_TOKENS: usize = 10_000;
+
+/// How long a token ships orders at its quote once issued.
+const TOKEN_TTL: Duration = Duration::from_secs(30 * 60);
+
+/// Why a quote token can't be redeemed.
+#[derive(Debug, PartialEq)]
+pub enum TokenRejection {
+    /// Never issued, expired, or forgotten to make room for newer tokens.
+    Unknown,
+    /// Already presented as many times as allowed.
+    Exhausted { max_uses: u32 },
+    /// Issued for a quote of a different number of items.
+    ItemsChanged { quoted: u32 },
+}
+
+impl TokenRejection {
+    pub fn response(&self) -> HttpResponse {
+        match self {
+            TokenRejection::Unknown => HttpResponse::BadRequest().json(api_error(
+                "unknown_quote_token",
+                "The quote token is unknown or expired".to_string(),
+            )),
+            TokenRejection::Exhausted { max_uses } => HttpResponse::Conflict().json(api_error(
+                "quote_token_exhausted",
+                format!("The quote token can be used {max_uses} times"),
+            )),
+            TokenRejection::ItemsChanged { quoted } => HttpResponse::Conflict().json(api_error(
+                "quote_token_mismatch",
+                format!("The quote token was issued for {quoted} items"),
+            )),
+        }
+    }
+}
+
+/// Quotes handed out by `get-quote`, each under a token that ships an order
+/// at the quoted price instead of requoting it.
+#[derive(Debug, Default)]
+pub struct QuoteTokens {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    issued: HashMap<String, Issued>,
+    /// Tokens in the order they were issued.
+    age: VecDeque<String>,
+}
+
+#[derive(Debug)]
+struct Issued {
+    quote: ShippingQuote,
+    /// Items quoted, which the order must ship.
+    quantity: ItemCount,
+    issued_at: Instant,
+    uses: u32,
+}
+
+impl QuoteTokens {
+    /// Locks the store, recovering it if a panicking request poisoned the
+    /// lock: every update leaves it consistent.
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Stores `quote` of `quantity` items, returning its token. Expired
+    /// tokens are forgotten on the way.
+    pub fn issue(&self, quote: ShippingQuote, quantity: ItemCount, entropy: &Entropy) -> String {
+        let token = entropy.uuid().simple().to_string();
+        let mut inner = self.lock();
+        while inner.age.len() >= MAX_TOKENS || inner.oldest_expired() {
+            if let Some(oldest) = inner.age.pop_front() {
+                inner.issued.remove(&oldest);
+            }
+        }
+        inner.age.push_back(token.clone());
+        inner.issued.insert(
+            token.clone(),
+            Issued {
+                quote,
+                quantity,
+                issued_at: Instant::now(),
+                uses: 0,
+            },
+        );
+        token
+    }
+
+    /// Counts a use of `token` for an order of `quantity` items, returning
+    /// its quote while it has been used at most `max_uses` times. A use of
+    /// an order that then fails to ship is handed back with `refund`.
+    pub fn redeem(
+        &self,
+        token: &str,
+        quantity: ItemCount,
+        max_uses: u32,
+    ) -> Result<ShippingQuote, TokenRejection> {
+        let mut inner = self.lock();
+        let issued = inner
+            .issued
+            .get_mut(token)
+            .filter(|issued| issued.issued_at.elapsed() < TOKEN_TTL)
+            .ok_or(TokenRejection::Unknown)?;
+        if issued.quantity != quantity {
+            return Err(TokenRejection::ItemsChanged {
+                quoted: issued.quantity.get(),
+            });
+        }
+        if issued.uses >= max_uses {
+            return Err(TokenRejection::Exhausted { max_uses });
+        }
+        issued.uses += 1;
+        Ok(issued.quote.clone())
+    }
+
+    /// Hands back the use of `token` counted for an order that didn't ship.
+    pub fn refund(&self, token: &str) {
+        if let Some(issued) = self.lock().issued.get_mut(token) {
+            issued.uses = issued.uses.saturating_sub(1);
+        }
+    }
+}
+
+impl Inner {
+    fn oldest_expired(&self) -> bool {
+        self.age
+            .front()
+            .and_then(|oldest| self.issued.get(oldest))
+            .is_some_and(|issued| issued.issued_at.elapsed() >= TOKEN_TTL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+
+    use super::*;
+    use crate::shipping_service::{QuoteConfidence, QuoteSource, ShippingConfig};
+
+    fn quote() -> ShippingQuote {
+        ShippingQuote {
+            total_cents: 1099,
+            charges: Vec::new(),
+            currency: "USD".to_string(),
+            source: QuoteSource::QuoteService,
+            confidence: QuoteConfidence::Exact,
+            quoted_at: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+        }
+    }
+
+    fn issue(tokens: &QuoteTokens) -> String {
+        tokens.issue(
+            quote(),
+            ItemCount::new(2),
+            &Entropy::new(&ShippingConfig::default()),
+        )
+    }
+
+    #[test]
+    fn test_redeem_counts_uses() {
+        let tokens = QuoteTokens::default();
+        let token = issue(&tokens);
+        let two = ItemCount::new(2);
+
+        assert_eq!(tokens.redeem(&token, two, 2), Ok(quote()));
+        assert_eq!(tokens.redeem(&token, two, 2), Ok(quote()));
+        assert_eq!(
+            tokens.redeem(&token, two, 2),
+            Err(TokenRejection::Exhausted { max_uses: 2 })
+        );
+        tokens.refund(&token);
+        assert_eq!(tokens.redeem(&token, two, 2), Ok(quote()));
+        assert_eq!(
+            tokens.redeem("missing", two, 2),
+            Err(TokenRejection::Unknown)
+        );
+    }
+
+    #[test]
+    fn test_redeem_checks_items_and_age() {
+        let tokens = QuoteTokens::default();
+        let token = issue(&tokens);
+
+        assert_eq!(
+            tokens.redeem(&token, ItemCount::new(1), 1),
+            Err(TokenRejection::ItemsChanged { quoted: 2 })
+        );
+        tokens.lock().issued.get_mut(&token).unwrap().issued_at =
+            Instant::now().checked_sub(TOKEN_TTL).unwrap();
+        assert_eq!(
+            tokens.redeem(&token, ItemCount::new(2), 1),
+            Err(TokenRejection::Unknown)
+        );
+    }
+}
@@ -0,0 +1,184 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header,
+    middleware::Next,
+    web, Error,
+};
+use opentelemetry::{global, KeyValue};
+use tracing::warn;
+
+use super::{error_response, ShippingError};
+use crate::telemetry::current_trace_context;
+
+/// Token bucket holding the quote endpoints of the process to
+/// `QUOTE_RATE_LIMIT_RPS` requests a second, in bursts of as many, so that
+/// a spike isn't all forwarded to the quote service.
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// Zero leaves the endpoints unlimited.
+    rps: u32,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(rps: u32) -> Self {
+        RateLimiter {
+            rps,
+            bucket: Mutex::new(Bucket {
+                tokens: f64::from(rps),
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes a token at `now`, or returns how long until one is available.
+    fn acquire(&self, now: Instant) -> Result<(), Duration> {
+        if self.rps == 0 {
+            return Ok(());
+        }
+        let rps = f64::from(self.rps);
+        let mut bucket = self.bucket.lock().unwrap_or_else(PoisonError::into_inner);
+        let elapsed = now.saturating_duration_since(bucket.refilled_at);
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * rps).min(rps);
+        bucket.refilled_at = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rps))
+        }
+    }
+}
+
+/// Middleware answering requests over the rate of [`RateLimiter`] with a
+/// 429 and a `Retry-After` of the seconds until the next token, counted in
+/// `app.shipping.rate_limited`.
+pub async fn rate_limit_quotes(
+    limiter: web::Data<RateLimiter>,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let Err(wait) = limiter.acquire(Instant::now()) else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+    let retry_after_secs = wait.as_secs_f64().ceil().max(1.0) as u64;
+
+    let trace = current_trace_context();
+    let (trace_id, span_id) = trace.as_fields();
+    warn!(
+        name = "RateLimited",
+        path = req.path(),
+        retry_after_secs = retry_after_secs,
+        trace_id,
+        span_id,
+        message = "Rejecting quote request over the rate limit"
+    );
+    global::meter("otel_demo.shipping.rate_limit")
+        .u64_counter("app.shipping.rate_limited")
+        .build()
+        .add(1, &[KeyValue::new("path", req.path().to_string())]);
+
+    let mut resp = error_response(
+        ShippingError::RateLimited(format!(
+            "More than {} quote requests a second, retry later",
+            limiter.rps
+        )),
+        None,
+    );
+    resp.headers_mut()
+        .insert(header::RETRY_AFTER, retry_after_secs.into());
+    Ok(req.into_response(resp).map_into_right_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::StatusCode, test, App};
+
+    use super::*;
+    use crate::shipping_service::{
+        get_quote, ApiError, AppData, CartItem, GetQuoteRequest, ShippingConfig,
+    };
+    use crate::test_support::{spawn_quote_mock, TestMetrics};
+
+    #[actix_web::test]
+    async fn test_bucket_refills_at_the_rate() {
+        let limiter = RateLimiter::new(2);
+        let start = Instant::now();
+        assert_eq!(limiter.acquire(start), Ok(()));
+        assert_eq!(limiter.acquire(start), Ok(()));
+        assert_eq!(limiter.acquire(start), Err(Duration::from_millis(500)));
+        assert_eq!(limiter.acquire(start + Duration::from_millis(500)), Ok(()));
+        // An idle bucket holds no more than a second's worth of tokens.
+        let later = start + Duration::from_secs(60);
+        assert_eq!(limiter.acquire(later), Ok(()));
+        assert_eq!(limiter.acquire(later), Ok(()));
+        assert!(limiter.acquire(later).is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_zero_rate_is_unlimited() {
+        let limiter = RateLimiter::new(0);
+        let now = Instant::now();
+        assert!((0..1000).all(|_| limiter.acquire(now).is_ok()));
+    }
+
+    #[actix_web::test]
+    async fn test_burst_past_the_limit_is_answered_429() {
+        let metrics = TestMetrics::install();
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            quote_rate_limit_rps: 3,
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(get_quote),
+        )
+        .await;
+        let quote = || {
+            test::TestRequest::post()
+                .uri("/get-quote")
+                .set_json(GetQuoteRequest {
+                    items: vec![CartItem {
+                        quantity: 1,
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                })
+                .to_request()
+        };
+
+        for _ in 0..3 {
+            let resp = test::call_service(&app, quote()).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+        }
+        let resp = test::call_service(&app, quote()).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers().get(header::RETRY_AFTER).unwrap(), "1");
+        let err: ApiError = test::read_body_json(resp).await;
+        assert_eq!(err.code, "rate_limited");
+        assert_eq!(
+            metrics.counter(
+                "app.shipping.rate_limited",
+                &[KeyValue::new("path", "/get-quote")]
+            ),
+            1
+        );
+    }
+}
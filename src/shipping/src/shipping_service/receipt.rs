@@ -0,0 +1,92 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use core::fmt;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::money::decimal_amount;
+use super::orders::Order;
+use super::shipping_types::{Address, CartItem, Money, QuoteLine};
+use super::{quote_lines, quote_money};
+
+/// A customer-facing summary of a shipped order.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Receipt {
+    pub order_id: String,
+    pub tracking_ids: Vec<String>,
+    pub carrier: String,
+    pub shipped_at: DateTime<Utc>,
+    pub estimated_delivery: DateTime<Utc>,
+    pub ship_to: Option<Address>,
+    pub items: Vec<CartItem>,
+    pub breakdown: Vec<QuoteLine>,
+    pub total: Option<Money>,
+}
+
+impl From<&Order> for Receipt {
+    fn from(order: &Order) -> Self {
+        let breakdown = order.quote.iter().flat_map(quote_lines).collect();
+
+        Receipt {
+            order_id: order.order_id.clone(),
+            tracking_ids: order
+                .packages
+                .iter()
+                .map(|package| package.tracking_id.clone())
+                .collect(),
+            carrier: order.carrier.clone(),
+            shipped_at: order.shipped_at,
+            estimated_delivery: order.estimated_delivery,
+            ship_to: order.address.clone(),
+            items: order.items(),
+            breakdown,
+            total: order.quote.as_ref().map(quote_money),
+        }
+    }
+}
+
+impl fmt::Display for Receipt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Receipt for order {}", self.order_id)?;
+        writeln!(f, "Tracking: {}", self.tracking_ids.join(", "))?;
+        writeln!(f, "Carrier: {}", self.carrier)?;
+        writeln!(
+            f,
+            "Shipped: {}",
+            self.shipped_at.to_rfc3339_opts(SecondsFormat::Secs, true)
+        )?;
+        writeln!(
+            f,
+            "Estimated delivery: {}",
+            self.estimated_delivery.format("%Y-%m-%d")
+        )?;
+        if let Some(address) = &self.ship_to {
+            writeln!(
+                f,
+                "Ship to: {}, {} {} {}, {}",
+                address.street_address,
+                address.city,
+                address.state,
+                address.zip_code,
+                address.country
+            )?;
+        }
+        writeln!(f, "Items:")?;
+        for item in &self.items {
+            writeln!(f, "  {} x {}", item.quantity, item.product_id)?;
+        }
+        for line in &self.breakdown {
+            writeln!(f, "{}: {}", line.label, format_money(&line.amount))?;
+        }
+        match &self.total {
+            Some(total) => writeln!(f, "Total: {}", format_money(total)),
+            None => writeln!(f, "Total: unavailable"),
+        }
+    }
+}
+
+fn format_money(money: &Money) -> String {
+    format!("{} {}", decimal_amount(money), money.currency_code)
+}
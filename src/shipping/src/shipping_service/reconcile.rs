@@ -0,0 +1,104 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use std::time::Duration;
+
+use actix_web::web;
+use chrono::{DateTime, Utc};
+use opentelemetry::{global, KeyValue};
+use tracing::warn;
+
+use super::determinism;
+use super::orders::OrderStore;
+use super::ShippingConfig;
+
+/// Flags the orders in transit for longer than `max_age` at `now`. Each is
+/// logged once, with the trace of the request that shipped it, and counted
+/// in `app.shipping.orders.stuck`. Returns how many were flagged.
+pub fn flag_stuck_orders(orders: &OrderStore, max_age: Duration, now: DateTime<Utc>) -> usize {
+    let Ok(max_age) = chrono::Duration::from_std(max_age) else {
+        return 0;
+    };
+    let stuck = orders.flag_stuck(now - max_age, now);
+    if stuck.is_empty() {
+        return 0;
+    }
+
+    for order in &stuck {
+        let origin = &order.origin;
+        warn!(
+            name = "OrderStuck",
+            order_id = order.order_id.as_str(),
+            shipped_at = %order.shipped_at,
+            origin.trace_id = %origin.trace_id(),
+            origin.span_id = %origin.span_id(),
+            message = "Order has been in transit longer than expected"
+        );
+    }
+
+    let meter = global::meter("otel_demo.shipping.orders");
+    let counter = meter.u64_counter("app.shipping.orders.stuck").build();
+    counter.add(stuck.len() as u64, &[KeyValue::new("status", "in_transit")]);
+    stuck.len()
+}
+
+/// Looks for stuck orders every `STUCK_ORDER_SCAN_INTERVAL_MS` for the life
+/// of the process.
+pub fn watch(orders: web::Data<OrderStore>, config: web::Data<ShippingConfig>, max_age: Duration) {
+    actix_web::rt::spawn(async move {
+        let mut ticks = actix_web::rt::time::interval(config.stuck_order_scan_interval);
+        loop {
+            ticks.tick().await;
+            flag_stuck_orders(&orders, max_age, determinism::now(&config));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use opentelemetry::trace::SpanContext;
+
+    use super::*;
+    use crate::shipping_service::orders::Order;
+    use crate::shipping_service::{DeliveryStatus, OrderResponse, Package};
+    use crate::test_support::TestMetrics;
+
+    #[test]
+    fn test_order_past_max_age_is_flagged_once() {
+        let metrics = TestMetrics::install();
+        let shipped_at = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        let orders = OrderStore::default();
+        orders.insert(Order {
+            order_id: "order-1".into(),
+            packages: vec![Package {
+                tracking_id: "track-1".into(),
+                items: vec![],
+                status: DeliveryStatus::InTransit,
+            }],
+            address: None,
+            quote: None,
+            carrier: "carrier".into(),
+            shipped_at,
+            estimated_delivery: shipped_at + chrono::Duration::days(5),
+            origin: SpanContext::empty_context(),
+            stuck_since: None,
+        });
+        let max_age = Duration::from_secs(7 * 24 * 3600);
+        let at = |days| shipped_at + chrono::Duration::days(days);
+
+        assert_eq!(flag_stuck_orders(&orders, max_age, at(6)), 0);
+        assert_eq!(flag_stuck_orders(&orders, max_age, at(8)), 1);
+        assert_eq!(flag_stuck_orders(&orders, max_age, at(9)), 0);
+
+        let order = OrderResponse::from(&orders.get("order-1").unwrap());
+        assert!(order.stuck);
+        assert_eq!(
+            metrics.counter(
+                "app.shipping.orders.stuck",
+                &[KeyValue::new("status", "in_transit")]
+            ),
+            1
+        );
+    }
+}
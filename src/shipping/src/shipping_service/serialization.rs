@@ -0,0 +1,179 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use actix_web::HttpResponse;
+use opentelemetry::{
+    global,
+    trace::{get_active_span, Status},
+    KeyValue,
+};
+use serde::Serialize;
+use tracing::error;
+
+use super::api_error;
+use super::shipping_types::{GetQuoteResponse, ShipOrderResponse};
+use crate::telemetry::current_trace_context;
+
+/// A response body that can tell which of its fields breaks serialization,
+/// so that a failure is traced to it rather than to the whole response.
+pub trait ResponseBody: Serialize {
+    /// Name of the first field that fails to serialize on its own.
+    fn failing_field(&self) -> Option<&'static str>;
+}
+
+fn fails<T: Serialize>(value: &T) -> bool {
+    serde_json::to_value(value).is_err()
+}
+
+/// First of `fields` that failed to serialize.
+fn first_failing<const N: usize>(fields: [(&'static str, bool); N]) -> Option<&'static str> {
+    fields
+        .into_iter()
+        .find_map(|(name, failed)| failed.then_some(name))
+}
+
+impl ResponseBody for GetQuoteResponse {
+    fn failing_field(&self) -> Option<&'static str> {
+        first_failing([
+            ("cost_usd", fails(&self.cost_usd)),
+            ("amount_decimal", fails(&self.amount_decimal)),
+            ("quoted_at", fails(&self.quoted_at)),
+            ("served_at", fails(&self.served_at)),
+            ("breakdown", fails(&self.breakdown)),
+            ("tax", fails(&self.tax)),
+            ("freight", fails(&self.freight)),
+            ("quote_token", fails(&self.quote_token)),
+            ("provenance", fails(&self.provenance)),
+            ("trace_id", fails(&self.trace_id)),
+            ("conversion", fails(&self.conversion)),
+            ("estimated_co2_kg", fails(&self.estimated_co2_kg)),
+        ])
+    }
+}
+
+impl ResponseBody for ShipOrderResponse {
+    fn failing_field(&self) -> Option<&'static str> {
+        first_failing([
+            ("order_id", fails(&self.order_id)),
+            ("tracking_id", fails(&self.tracking_id)),
+            ("package_tracking_ids", fails(&self.package_tracking_ids)),
+            ("trace_id", fails(&self.trace_id)),
+        ])
+    }
+}
+
+/// Serializes `body`, the `response` of a handler. A failure is recorded
+/// on the active span, counted in `app.shipping.serialization_errors` and
+/// answered with a 500 `ApiError` carrying the trace id.
+pub fn to_json<T: ResponseBody>(body: &T, response: &'static str) -> Result<Vec<u8>, HttpResponse> {
+    let err = match serde_json::to_vec(body) {
+        Ok(bytes) => return Ok(bytes),
+        Err(err) => err,
+    };
+    let field = body.failing_field().unwrap_or("unknown");
+
+    let trace = current_trace_context();
+    let (trace_id, span_id) = trace.as_fields();
+    error!(
+        name = "SerializationFailed",
+        response = response,
+        field = field,
+        error = %err,
+        trace_id,
+        span_id,
+        message = "Failed to serialize response"
+    );
+    get_active_span(|span| {
+        span.add_event(
+            "Serialization Failed",
+            vec![
+                KeyValue::new("app.shipping.serialization.response", response),
+                KeyValue::new("app.shipping.serialization.field", field),
+                KeyValue::new("exception.message", err.to_string()),
+            ],
+        );
+        span.set_status(Status::error(format!("Failed to serialize {response}")));
+    });
+    global::meter("otel_demo.shipping.serialization")
+        .u64_counter("app.shipping.serialization_errors")
+        .build()
+        .add(
+            1,
+            &[
+                KeyValue::new("response", response),
+                KeyValue::new("field", field),
+            ],
+        );
+
+    Err(HttpResponse::InternalServerError().json(api_error(
+        "serialization_failed",
+        format!("Failed to serialize {response}: {field}"),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{body::to_bytes, http::StatusCode};
+    use serde::Serializer;
+
+    use super::*;
+    use crate::shipping_service::ApiError;
+    use crate::test_support::{in_test_span, TestMetrics};
+
+    struct Unserializable;
+
+    impl Serialize for Unserializable {
+        fn serialize<S: Serializer>(&self, _serializer: S) -> Result<S::Ok, S::Error> {
+            Err(serde::ser::Error::custom("not serializable"))
+        }
+    }
+
+    #[derive(Serialize)]
+    struct Body {
+        fine: u32,
+        broken: Unserializable,
+    }
+
+    impl ResponseBody for Body {
+        fn failing_field(&self) -> Option<&'static str> {
+            first_failing([("fine", fails(&self.fine)), ("broken", fails(&self.broken))])
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_serialization_failure_is_traced_and_counted() {
+        let metrics = TestMetrics::install();
+        let body = Body {
+            fine: 1,
+            broken: Unserializable,
+        };
+
+        let (result, span) = in_test_span("serialize", async { to_json(&body, "test body") }).await;
+        let resp = result.unwrap_err();
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let err: ApiError =
+            serde_json::from_slice(&to_bytes(resp.into_body()).await.unwrap()).unwrap();
+        assert_eq!(err.code, "serialization_failed");
+        assert_eq!(err.message, "Failed to serialize test body: broken");
+        assert_eq!(err.trace_id, span.span_context.trace_id().to_string());
+
+        let event = span
+            .events
+            .iter()
+            .find(|event| event.name == "Serialization Failed")
+            .unwrap();
+        assert!(event
+            .attributes
+            .contains(&KeyValue::new("app.shipping.serialization.field", "broken")));
+        assert_eq!(
+            metrics.counter(
+                "app.shipping.serialization_errors",
+                &[
+                    KeyValue::new("response", "test body"),
+                    KeyValue::new("field", "broken"),
+                ]
+            ),
+            1
+        );
+    }
+}
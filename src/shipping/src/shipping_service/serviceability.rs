@@ -0,0 +1,168 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use serde::Serialize;
+
+use super::shipping_types::Address;
+
+/// Why a destination was turned away, reported in the error's `details`
+/// when `SUGGEST_ALTERNATIVES` is on.
+#[derive(Debug, Serialize)]
+pub struct Unserviceable {
+    pub reason: String,
+    pub suggestions: Vec<Suggestion>,
+}
+
+/// A destination the customer could ship to instead.
+#[derive(Debug, Serialize)]
+pub struct Suggestion {
+    pub kind: &'static str,
+    pub country: String,
+    /// The region it shares with the unserviceable destination.
+    pub region: &'static str,
+}
+
+/// ISO 3166 countries by continent, the regions suggestions are drawn from.
+const REGIONS: &[(&str, &[&str])] = &[
+    (
+        "africa",
+        &[
+            "AO", "BF", "BI", "BJ", "BW", "CD", "CF", "CG", "CI", "CM", "CV", "DJ", "DZ", "EG",
+            "EH", "ER", "ET", "GA", "GH", "GM", "GN", "GQ", "GW", "KE", "KM", "LR", "LS", "LY",
+            "MA", "MG", "ML", "MR", "MU", "MW", "MZ", "NA", "NE", "NG", "RE", "RW", "SC", "SD",
+            "SH", "SL", "SN", "SO", "SS", "ST", "SZ", "TD", "TG", "TN", "TZ", "UG", "YT", "ZA",
+            "ZM", "ZW",
+        ],
+    ),
+    ("antarctica", &["AQ", "BV", "GS", "HM", "TF"]),
+    (
+        "asia",
+        &[
+            "AE", "AF", "AM", "AZ", "BD", "BH", "BN", "BT", "CN", "CY", "GE", "HK", "ID", "IL",
+            "IN", "IQ", "IR", "JO", "JP", "KG", "KH", "KP", "KR", "KW", "KZ", "LA", "LB", "LK",
+            "MM", "MN", "MO", "MV", "MY", "NP", "OM", "PH", "PK", "PS", "QA", "SA", "SG", "SY",
+            "TH", "TJ", "TL", "TM", "TR", "TW", "UZ", "VN", "YE",
+        ],
+    ),
+    (
+        "europe",
+        &[
+            "AD", "AL", "AT", "AX", "BA", "BE", "BG", "BY", "CH", "CZ", "DE", "DK", "EE", "ES",
+            "FI", "FO", "FR", "GB", "GG", "GI", "GR", "HR", "HU", "IE", "IM", "IS", "IT", "JE",
+            "LI", "LT", "LU", "LV", "MC", "MD", "ME", "MK", "MT", "NL", "NO", "PL", "PT", "RO",
+            "RS", "RU", "SE", "SI", "SJ", "SK", "SM", "UA", "VA", "XK",
+        ],
+    ),
+    (
+        "north_america",
+        &[
+            "AG", "AI", "AW", "BB", "BL", "BM", "BQ", "BS", "BZ", "CA", "CR", "CU", "CW", "DM",
+            "DO", "GD", "GL", "GP", "GT", "HN", "HT", "JM", "KN", "KY", "LC", "MF", "MQ", "MS",
+            "MX", "NI", "PA", "PM", "PR", "SV", "SX", "TC", "TT", "US", "VC", "VG", "VI",
+        ],
+    ),
+    (
+        "oceania",
+        &[
+            "AS", "AU", "CK", "FJ", "FM", "GU", "KI", "MH", "MP", "NC", "NF", "NR", "NU", "NZ",
+            "PF", "PG", "PN", "PW", "SB", "TK", "TO", "TV", "UM", "VU", "WF", "WS",
+        ],
+    ),
+    (
+        "south_america",
+        &[
+            "AR", "BO", "BR", "CL", "CO", "EC", "FK", "GF", "GY", "PE", "PY", "SR", "UY", "VE",
+        ],
+    ),
+];
+
+/// The region of `country`, an ISO code in any case.
+fn region_of(country: &str) -> Option<&'static str> {
+    REGIONS
+        .iter()
+        .find(|(_, countries)| {
+            countries
+                .iter()
+                .any(|code| code.eq_ignore_ascii_case(country.trim()))
+        })
+        .map(|(region, _)| *region)
+}
+
+/// Checks `address` against the `serviceable` countries, an empty list
+/// meaning every country is served. Addresses without a country pass, since
+/// there is nothing to check.
+pub fn check_serviceable(address: &Address, serviceable: &[String]) -> Result<(), String> {
+    let country = address.country.trim();
+    if serviceable.is_empty()
+        || country.is_empty()
+        || serviceable
+            .iter()
+            .any(|served| served.eq_ignore_ascii_case(country))
+    {
+        return Ok(());
+    }
+    Err(format!("we don't ship to {:?}", country))
+}
+
+/// Alternatives to shipping to `address`: the served countries in its
+/// region, in configuration order. Destinations without a served country
+/// nearby get none.
+pub fn suggest_alternatives(
+    reason: String,
+    address: &Address,
+    serviceable: &[String],
+) -> Unserviceable {
+    let suggestions = match region_of(&address.country) {
+        Some(region) => serviceable
+            .iter()
+            .filter(|country| region_of(country) == Some(region))
+            .map(|country| Suggestion {
+                kind: "serviceable_country",
+                country: country.clone(),
+                region,
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+    Unserviceable {
+        reason,
+        suggestions,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to(country: &str) -> Address {
+        Address {
+            country: country.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_suggestions_are_served_countries_in_the_same_region() {
+        let serviceable = ["US", "de", "CA", "FR"].map(String::from);
+        let suggested = |country| {
+            suggest_alternatives(String::new(), &to(country), &serviceable)
+                .suggestions
+                .into_iter()
+                .map(|suggestion| (suggestion.country, suggestion.region))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            suggested("mx"),
+            [
+                ("US".to_string(), "north_america"),
+                ("CA".to_string(), "north_america")
+            ]
+        );
+        assert_eq!(
+            suggested("AT"),
+            [("de".to_string(), "europe"), ("FR".to_string(), "europe")]
+        );
+        assert!(suggested("JP").is_empty());
+        assert!(suggested("ZZ").is_empty());
+    }
+}
@@ -1,46 +1,799 @@
 // Copyright The OpenTelemetry Authors
 // SPDX-License-Identifier: Apache-2.0
 
+use actix_web::http::StatusCode;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize, Serialize)]
+// Serialized types use structs, or `BTreeMap` where a map is unavoidable,
+// never `HashMap`: responses must be byte-stable for ETags and snapshots.
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct CartItem {
+    #[serde(default)]
+    pub product_id: String,
     pub quantity: u32,
+    /// Declares the item as hazardous materials.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hazmat: Option<bool>,
+    /// Weight of one unit, in kilograms.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight_kg: Option<f64>,
+    /// Length, width and height of one unit, in centimeters.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dimensions_cm: Option<[f64; 3]>,
+    /// Ships free whatever else is in the order: the item counts toward
+    /// neither the billed items nor the billed weight.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub free_shipping: Option<bool>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct Address {
+    #[serde(default)]
+    pub street_address: String,
+    #[serde(default)]
+    pub city: String,
+    #[serde(default)]
+    pub state: String,
+    #[serde(default)]
+    pub country: String,
+    #[serde(default)]
     pub zip_code: String,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct GetQuoteRequest {
     pub items: Vec<CartItem>,
     pub address: Option<Address>,
+    #[serde(default)]
+    pub speed: RequestedSpeed,
+    /// Declared value of the goods, used to estimate duties on
+    /// international shipments.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub customs_value: Option<Money>,
+    /// Currency the shopper pays in. It selects the market rules the request
+    /// must follow, and `cost_usd` is converted to it by the currency
+    /// service, staying in dollars when the conversion fails. Also accepted
+    /// as `currency_code`.
+    #[serde(
+        default,
+        alias = "currency_code",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub currency: Option<String>,
+    /// Adds the tax owed at the destination to the response.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub include_tax: bool,
+    /// Adds the pricing that produced the quote to the response.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub include_provenance: bool,
+    /// Asks the quote service rather than reusing a cached price, as does
+    /// `Cache-Control: no-cache`. The fresh price is cached for others.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub fresh: bool,
+    /// Signed partner pricing terms, see `PRICING_OVERRIDE_SECRET`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pricing_override: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+/// Query of `GET /get-quote`: a number of items of no particular product.
+#[derive(Debug, Deserialize)]
+pub struct GetQuoteQuery {
+    pub items: u32,
+    #[serde(default)]
+    pub speed: RequestedSpeed,
+    #[serde(default, alias = "currency_code")]
+    pub currency: Option<String>,
+    #[serde(default)]
+    pub include_tax: bool,
+    #[serde(default)]
+    pub include_provenance: bool,
+    #[serde(default)]
+    pub fresh: bool,
+}
+
+impl From<GetQuoteQuery> for GetQuoteRequest {
+    fn from(query: GetQuoteQuery) -> Self {
+        GetQuoteRequest {
+            items: vec![CartItem {
+                quantity: query.items,
+                ..Default::default()
+            }],
+            speed: query.speed,
+            currency: query.currency,
+            include_tax: query.include_tax,
+            include_provenance: query.include_provenance,
+            fresh: query.fresh,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShippingSpeed {
+    #[default]
+    Standard,
+    Express,
+    /// Moves by air.
+    Overnight,
+}
+
+impl ShippingSpeed {
+    pub const ALL: [ShippingSpeed; 3] = [
+        ShippingSpeed::Standard,
+        ShippingSpeed::Express,
+        ShippingSpeed::Overnight,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ShippingSpeed::Standard => "standard",
+            ShippingSpeed::Express => "express",
+            ShippingSpeed::Overnight => "overnight",
+        }
+    }
+}
+
+/// The `speed` of a quote request as sent. One naming no speed is kept, so
+/// that it is answered `invalid_speed` rather than as a malformed request.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum RequestedSpeed {
+    Known(ShippingSpeed),
+    Unknown(String),
+}
+
+impl Default for RequestedSpeed {
+    fn default() -> Self {
+        RequestedSpeed::Known(ShippingSpeed::default())
+    }
+}
+
+impl From<ShippingSpeed> for RequestedSpeed {
+    fn from(speed: ShippingSpeed) -> Self {
+        RequestedSpeed::Known(speed)
+    }
+}
+
+impl RequestedSpeed {
+    /// The speed requested, whose name is matched regardless of case.
+    pub fn speed(&self) -> Result<ShippingSpeed, ShippingError> {
+        match self {
+            RequestedSpeed::Known(speed) => Ok(*speed),
+            RequestedSpeed::Unknown(name) => ShippingSpeed::ALL
+                .into_iter()
+                .find(|speed| name.trim().eq_ignore_ascii_case(speed.as_str()))
+                .ok_or_else(|| {
+                    ShippingError::InvalidSpeed(format!(
+                        "unknown shipping speed {name:?}, expected standard, express or overnight"
+                    ))
+                }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct Money {
     pub currency_code: String,
     pub units: u64,
     pub nanos: u32,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct GetQuoteResponse {
     pub cost_usd: Option<Money>,
+    /// `cost_usd` as a decimal string, e.g. `"10.99"`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub amount_decimal: Option<String>,
+    /// When the quote was computed, which predates `served_at` when the quote
+    /// is reused.
+    pub quoted_at: DateTime<Utc>,
+    pub served_at: DateTime<Utc>,
+    /// Speed the shipment was quoted at.
+    #[serde(default)]
+    pub speed: ShippingSpeed,
+    /// Itemized cost, present when the quote has charges beyond shipping.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub breakdown: Vec<QuoteLine>,
+    /// Tax owed at the destination, present when the request asks for it.
+    /// `cost_usd` stays tax-exclusive either way.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tax: Option<QuoteTax>,
+    /// Delivery estimate of freight quotes, which don't follow the parcel
+    /// transit times.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub freight: Option<FreightEstimate>,
+    /// Ships an order at this price when passed to `ship-order`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quote_token: Option<String>,
+    /// Pricing that produced the quote, present when the request asks for
+    /// it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<QuoteProvenance>,
+    /// Trace of the request, see `INCLUDE_TRACE_ID_IN_RESPONSE`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+    /// How `cost_usd` was converted when the currency service couldn't
+    /// convert it; absent otherwise.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conversion: Option<ConversionFallback>,
+    /// Kilograms of CO2 the shipment is estimated to emit, see
+    /// `INCLUDE_CARBON`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub estimated_co2_kg: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConversionFallback {
+    /// Converted at the rate of the last successful conversion.
+    StaleRate,
+    /// Left in dollars.
+    UsdFallback,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct QuoteProvenance {
+    /// Version of the pricing config in effect, see `PricingVersion`.
+    pub pricing_version: u64,
+    /// `quote_service` for parcels, `freight` for freight rate tables.
+    pub pricing_strategy: String,
+    /// SHA-1 of the pricing file, absent when there is none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_table_sha1: Option<String>,
+    /// When the exchange rates used for fees took effect.
+    pub exchange_rates_as_of: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FreightEstimate {
+    pub transit_days: u32,
+    pub estimated_delivery: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct QuoteTax {
+    pub rate: f64,
+    pub amount: Money,
+    pub total_exclusive: Money,
+    /// Exactly `total_exclusive` plus `amount`.
+    pub total_inclusive: Money,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct BatchQuoteRequest {
+    pub requests: Vec<GetQuoteRequest>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BatchQuoteResponse {
+    /// One result per request, in request order.
+    pub results: Vec<BatchQuoteResult>,
+}
+
+/// Outcome of one request of a batch, as `get-quote` would have answered
+/// it.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BatchQuoteResult {
+    /// Position of the request in the batch.
+    pub index: usize,
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quote: Option<GetQuoteResponse>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ApiError>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CompareCarriersResponse {
+    /// One quote per configured carrier, in configuration order.
+    pub carriers: Vec<CarrierQuote>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CarrierQuote {
+    pub carrier: String,
+    #[serde(flatten)]
+    pub quote: GetQuoteResponse,
+    pub estimated_delivery: DateTime<Utc>,
+    /// Product ids of the items the carrier can't price, left out of the
+    /// quote.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unpriceable_items: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct QuoteLine {
+    pub label: String,
+    pub amount: Money,
+}
+
+/// Body of every error response. Requests that don't parse, or whose
+/// fields are out of their accepted range or length, are answered 400, e.g.
+/// `malformed_request`, `invalid_address` or `address_required`. Well-formed
+/// requests breaking a business rule are answered 422, e.g.
+/// `unserviceable_destination` or `hazmat_speed_unavailable`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ApiError {
+    pub code: String,
+    pub message: String,
+    pub trace_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+}
+
+/// Why `get-quote` or `ship-order` failed, each variant holding the message
+/// for the client. Its `code` is what clients branch on, and never changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShippingError {
+    MalformedRequest(String),
+    /// More items, or item entries, than the configured maximum.
+    TooManyItems(String),
+    /// A quantity out of range, such as a negative one or a total too
+    /// large to count.
+    InvalidItemCount(String),
+    /// Nothing to ship, under the `zero_items_policy` forbidding it.
+    NoItems(String),
+    InvalidAddress(String),
+    /// An item weighs nothing, less, or not a number.
+    InvalidWeight(String),
+    /// The requested shipping speed is none of those offered.
+    InvalidSpeed(String),
+    /// The market of the currency requires a destination.
+    AddressRequired(String),
+    UnserviceableDestination(String),
+    HazmatSpeedUnavailable(String),
+    InvalidCustomsValue(String),
+    /// The quote service is down, or its circuit breaker is open.
+    QuoteServiceUnavailable(String),
+    /// The quote service didn't answer in time.
+    DeadlineExceeded(String),
+    QuoteFailed(String),
+    CurrencyUnavailable(String),
+    /// The endpoint is serving as many requests as it is allowed to.
+    EndpointSaturated(String),
+    /// More requests than `QUOTE_RATE_LIMIT_RPS` allows.
+    RateLimited(String),
+}
+
+impl ShippingError {
+    /// Maps a failed call to the quote service.
+    pub fn from_quote_failure(status: &tonic::Status) -> Self {
+        let message = format!("Failed to get quote: {}", status.message());
+        match status.code() {
+            tonic::Code::Unavailable => ShippingError::QuoteServiceUnavailable(message),
+            tonic::Code::DeadlineExceeded => ShippingError::DeadlineExceeded(message),
+            _ => ShippingError::QuoteFailed(message),
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ShippingError::MalformedRequest(_) => "malformed_request",
+            ShippingError::TooManyItems(_) => "too_many_items",
+            ShippingError::InvalidItemCount(_) => "invalid_item_count",
+            ShippingError::NoItems(_) => "no_items",
+            ShippingError::InvalidAddress(_) => "invalid_address",
+            ShippingError::InvalidWeight(_) => "invalid_weight",
+            ShippingError::InvalidSpeed(_) => "invalid_speed",
+            ShippingError::AddressRequired(_) => "address_required",
+            ShippingError::UnserviceableDestination(_) => "unserviceable_destination",
+            ShippingError::HazmatSpeedUnavailable(_) => "hazmat_speed_unavailable",
+            ShippingError::InvalidCustomsValue(_) => "invalid_customs_value",
+            ShippingError::QuoteServiceUnavailable(_) => "quote_service_unavailable",
+            ShippingError::DeadlineExceeded(_) => "quote_timeout",
+            ShippingError::QuoteFailed(_) => "quote_failed",
+            ShippingError::CurrencyUnavailable(_) => "currency_unavailable",
+            ShippingError::EndpointSaturated(_) => "endpoint_saturated",
+            ShippingError::RateLimited(_) => "rate_limited",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ShippingError::MalformedRequest(_)
+            | ShippingError::TooManyItems(_)
+            | ShippingError::InvalidItemCount(_)
+            | ShippingError::InvalidAddress(_)
+            | ShippingError::InvalidWeight(_)
+            | ShippingError::InvalidSpeed(_)
+            | ShippingError::NoItems(_)
+            | ShippingError::AddressRequired(_) => StatusCode::BAD_REQUEST,
+            ShippingError::UnserviceableDestination(_)
+            | ShippingError::HazmatSpeedUnavailable(_)
+            | ShippingError::InvalidCustomsValue(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ShippingError::QuoteServiceUnavailable(_)
+            | ShippingError::CurrencyUnavailable(_)
+            | ShippingError::EndpointSaturated(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ShippingError::DeadlineExceeded(_) => StatusCode::GATEWAY_TIMEOUT,
+            ShippingError::QuoteFailed(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ShippingError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+
+    /// Code of the error on the gRPC API: requests answered 400 over HTTP
+    /// are invalid arguments, and those answered 422 failed preconditions.
+    pub fn grpc_code(&self) -> tonic::Code {
+        match self {
+            ShippingError::MalformedRequest(_)
+            | ShippingError::TooManyItems(_)
+            | ShippingError::InvalidItemCount(_)
+            | ShippingError::InvalidAddress(_)
+            | ShippingError::InvalidWeight(_)
+            | ShippingError::InvalidSpeed(_)
+            | ShippingError::NoItems(_)
+            | ShippingError::AddressRequired(_) => tonic::Code::InvalidArgument,
+            ShippingError::UnserviceableDestination(_)
+            | ShippingError::HazmatSpeedUnavailable(_)
+            | ShippingError::InvalidCustomsValue(_) => tonic::Code::FailedPrecondition,
+            ShippingError::QuoteServiceUnavailable(_) | ShippingError::CurrencyUnavailable(_) => {
+                tonic::Code::Unavailable
+            }
+            ShippingError::DeadlineExceeded(_) => tonic::Code::DeadlineExceeded,
+            ShippingError::QuoteFailed(_) => tonic::Code::Internal,
+            ShippingError::EndpointSaturated(_) | ShippingError::RateLimited(_) => {
+                tonic::Code::ResourceExhausted
+            }
+        }
+    }
+
+    pub fn into_message(self) -> String {
+        match self {
+            ShippingError::MalformedRequest(message)
+            | ShippingError::TooManyItems(message)
+            | ShippingError::InvalidItemCount(message)
+            | ShippingError::NoItems(message)
+            | ShippingError::InvalidAddress(message)
+            | ShippingError::InvalidWeight(message)
+            | ShippingError::InvalidSpeed(message)
+            | ShippingError::AddressRequired(message)
+            | ShippingError::UnserviceableDestination(message)
+            | ShippingError::HazmatSpeedUnavailable(message)
+            | ShippingError::InvalidCustomsValue(message)
+            | ShippingError::QuoteServiceUnavailable(message)
+            | ShippingError::DeadlineExceeded(message)
+            | ShippingError::QuoteFailed(message)
+            | ShippingError::CurrencyUnavailable(message)
+            | ShippingError::EndpointSaturated(message)
+            | ShippingError::RateLimited(message) => message,
+        }
+    }
+}
+
+/// Where a quote's price came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuoteSource {
+    QuoteService,
+    /// Priced locally from a carrier's rate table.
+    RateTable,
+    /// Priced locally by the `FALLBACK_*` formula, the quote service having
+    /// failed.
+    Fallback,
 }
 
-#[derive(Debug, Default)]
+/// How closely a quote reflects what the carrier will charge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuoteConfidence {
+    Exact,
+    /// An approximation the carrier's price may differ from.
+    Estimated,
+}
+
+/// A computed shipping quote, kept in integer minor units so it can be
+/// converted to `Money` without loss.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShippingQuote {
+    /// Total including `charges`.
+    pub total_cents: u64,
+    /// Charges added on top of the base shipping cost.
+    pub charges: Vec<Charge>,
+    pub currency: String,
+    pub source: QuoteSource,
+    pub confidence: QuoteConfidence,
+    pub quoted_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Charge {
+    pub label: &'static str,
+    pub cents: u64,
+}
+
+/// Dollars-and-cents view of a quote, used for display only.
+#[derive(Debug, Clone, Default)]
 pub struct Quote {
     pub dollars: u64,
     pub cents: u32,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-pub struct ShipOrderRequest {}
+/// Body of the request sent to the quote service.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuoteServiceRequest {
+    pub number_of_items: u32,
+}
 
-#[derive(Debug, Deserialize, Serialize)]
+/// Body of a quote service answering `application/json`: either the
+/// quote on its own or an object holding it.
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum QuoteServiceResponse {
+    Object { quote: f64 },
+    Bare(f64),
+}
+
+impl QuoteServiceResponse {
+    pub fn quote(&self) -> f64 {
+        match self {
+            QuoteServiceResponse::Object { quote } | QuoteServiceResponse::Bare(quote) => *quote,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ShipOrderRequest {
+    #[serde(default)]
+    pub items: Vec<CartItem>,
+    pub address: Option<Address>,
+    /// Splits the order into several packages. When empty, all `items` ship
+    /// as a single package; otherwise `items` is ignored.
+    #[serde(default)]
+    pub packages: Vec<PackageRequest>,
+    /// Token of an earlier quote to ship at, instead of quoting the order
+    /// again.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quote_token: Option<String>,
+    /// Key replaying the result of an earlier request with the same key,
+    /// for clients that can't set the `Idempotency-Key` header. The header
+    /// wins when both are set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+}
+
+impl ShipOrderRequest {
+    /// The items shipped: those of the packages, or `items` when there are
+    /// none.
+    pub fn shipped_items(&self) -> impl Iterator<Item = &CartItem> {
+        let items = self.packages.is_empty().then_some(&self.items);
+        items
+            .into_iter()
+            .flatten()
+            .chain(self.packages.iter().flat_map(|package| &package.items))
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct PackageRequest {
+    #[serde(default)]
+    pub items: Vec<CartItem>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ShipOrderResponse {
+    pub order_id: String,
+    /// Tracking id of the first package, kept for single-package clients.
     pub tracking_id: String,
+    pub package_tracking_ids: Vec<String>,
+    /// Trace of the request, see `INCLUDE_TRACE_ID_IN_RESPONSE`. Replays
+    /// of an idempotent request carry the replay's trace.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    InTransit,
+    Delivered,
+}
+
+/// One package of an order, with its own tracking id.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Package {
+    pub tracking_id: String,
+    pub items: Vec<CartItem>,
+    pub status: DeliveryStatus,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PackageStatusUpdate {
+    pub status: DeliveryStatus,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct OrderResponse {
+    pub order_id: String,
+    /// `delivered` once every package is, `in_transit` until then.
+    pub status: DeliveryStatus,
+    pub carrier: String,
+    pub shipped_at: DateTime<Utc>,
+    pub estimated_delivery: DateTime<Utc>,
+    pub packages: Vec<Package>,
+    /// Set once reconciliation finds the order in transit for too long.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stuck: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_get_quote_response_serialization_is_stable() {
+        let quoted_at = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        let response = GetQuoteResponse {
+            cost_usd: Some(Money {
+                currency_code: "USD".into(),
+                units: 10,
+                nanos: 990_000_000,
+            }),
+            amount_decimal: Some("10.99".into()),
+            quoted_at,
+            served_at: quoted_at,
+            speed: ShippingSpeed::Standard,
+            breakdown: vec![],
+            tax: None,
+            freight: None,
+            quote_token: None,
+            provenance: None,
+            trace_id: None,
+            conversion: None,
+            estimated_co2_kg: None,
+        };
+
+        let expected = concat!(
+            r#"{"cost_usd":{"currency_code":"USD","units":10,"nanos":990000000},"#,
+            r#""amount_decimal":"10.99","#,
+            r#""quoted_at":"2024-05-01T12:00:00Z","served_at":"2024-05-01T12:00:00Z","#,
+            r#""speed":"standard"}"#
+        );
+        for _ in 0..3 {
+            assert_eq!(serde_json::to_string(&response).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_error_codes_and_statuses_are_stable() {
+        let message = || "message".to_string();
+        for (err, code, status, grpc_code) in [
+            (
+                ShippingError::MalformedRequest(message()),
+                "malformed_request",
+                StatusCode::BAD_REQUEST,
+                tonic::Code::InvalidArgument,
+            ),
+            (
+                ShippingError::TooManyItems(message()),
+                "too_many_items",
+                StatusCode::BAD_REQUEST,
+                tonic::Code::InvalidArgument,
+            ),
+            (
+                ShippingError::InvalidItemCount(message()),
+                "invalid_item_count",
+                StatusCode::BAD_REQUEST,
+                tonic::Code::InvalidArgument,
+            ),
+            (
+                ShippingError::NoItems(message()),
+                "no_items",
+                StatusCode::BAD_REQUEST,
+                tonic::Code::InvalidArgument,
+            ),
+            (
+                ShippingError::InvalidAddress(message()),
+                "invalid_address",
+                StatusCode::BAD_REQUEST,
+                tonic::Code::InvalidArgument,
+            ),
+            (
+                ShippingError::InvalidWeight(message()),
+                "invalid_weight",
+                StatusCode::BAD_REQUEST,
+                tonic::Code::InvalidArgument,
+            ),
+            (
+                ShippingError::InvalidSpeed(message()),
+                "invalid_speed",
+                StatusCode::BAD_REQUEST,
+                tonic::Code::InvalidArgument,
+            ),
+            (
+                ShippingError::AddressRequired(message()),
+                "address_required",
+                StatusCode::BAD_REQUEST,
+                tonic::Code::InvalidArgument,
+            ),
+            (
+                ShippingError::UnserviceableDestination(message()),
+                "unserviceable_destination",
+                StatusCode::UNPROCESSABLE_ENTITY,
+                tonic::Code::FailedPrecondition,
+            ),
+            (
+                ShippingError::HazmatSpeedUnavailable(message()),
+                "hazmat_speed_unavailable",
+                StatusCode::UNPROCESSABLE_ENTITY,
+                tonic::Code::FailedPrecondition,
+            ),
+            (
+                ShippingError::InvalidCustomsValue(message()),
+                "invalid_customs_value",
+                StatusCode::UNPROCESSABLE_ENTITY,
+                tonic::Code::FailedPrecondition,
+            ),
+            (
+                ShippingError::QuoteServiceUnavailable(message()),
+                "quote_service_unavailable",
+                StatusCode::SERVICE_UNAVAILABLE,
+                tonic::Code::Unavailable,
+            ),
+            (
+                ShippingError::DeadlineExceeded(message()),
+                "quote_timeout",
+                StatusCode::GATEWAY_TIMEOUT,
+                tonic::Code::DeadlineExceeded,
+            ),
+            (
+                ShippingError::QuoteFailed(message()),
+                "quote_failed",
+                StatusCode::INTERNAL_SERVER_ERROR,
+                tonic::Code::Internal,
+            ),
+            (
+                ShippingError::CurrencyUnavailable(message()),
+                "currency_unavailable",
+                StatusCode::SERVICE_UNAVAILABLE,
+                tonic::Code::Unavailable,
+            ),
+            (
+                ShippingError::EndpointSaturated(message()),
+                "endpoint_saturated",
+                StatusCode::SERVICE_UNAVAILABLE,
+                tonic::Code::ResourceExhausted,
+            ),
+            (
+                ShippingError::RateLimited(message()),
+                "rate_limited",
+                StatusCode::TOO_MANY_REQUESTS,
+                tonic::Code::ResourceExhausted,
+            ),
+        ] {
+            assert_eq!(err.code(), code);
+            assert_eq!(err.status(), status, "{code}");
+            assert_eq!(err.grpc_code(), grpc_code, "{code}");
+            assert_eq!(err.into_message(), "message");
+        }
+    }
+
+    #[test]
+    fn test_quote_failures_keep_their_kind() {
+        for (status, code) in [
+            (
+                tonic::Status::unavailable("down"),
+                "quote_service_unavailable",
+            ),
+            (tonic::Status::deadline_exceeded("slow"), "quote_timeout"),
+            (tonic::Status::internal("broken"), "quote_failed"),
+        ] {
+            assert_eq!(ShippingError::from_quote_failure(&status).code(), code);
+        }
+        assert_eq!(
+            ShippingError::from_quote_failure(&tonic::Status::unavailable("down")),
+            ShippingError::QuoteServiceUnavailable("Failed to get quote: down".to_string())
+        );
+    }
+
+    #[test]
+    fn test_quote_service_request_serialization() {
+        let body = QuoteServiceRequest { number_of_items: 3 };
+        assert_eq!(
+            serde_json::to_string(&body).unwrap(),
+            r#"{"numberOfItems":3}"#
+        );
+    }
 }
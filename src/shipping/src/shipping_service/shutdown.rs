@@ -0,0 +1,110 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    pin::pin,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+    web, Error,
+};
+use futures_util::future::select;
+use tracing::info;
+
+/// Number of requests being served, kept by [`count_in_flight`].
+#[derive(Debug, Default)]
+pub struct InFlight(AtomicUsize);
+
+impl InFlight {
+    pub fn get(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Uncounts a request when dropped, whether it completed or was cancelled.
+struct Counted<'a>(&'a InFlight);
+
+impl Drop for Counted<'_> {
+    fn drop(&mut self) {
+        self.0 .0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Middleware counting the requests being served in the app's `InFlight`,
+/// so that shutdown can tell how many it drains. Register it outermost.
+pub async fn count_in_flight(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let Some(in_flight) = req.app_data::<web::Data<InFlight>>().cloned() else {
+        return next.call(req).await;
+    };
+    in_flight.0.fetch_add(1, Ordering::SeqCst);
+    let _counted = Counted(&in_flight);
+    next.call(req).await
+}
+
+/// Resolves on SIGTERM or Ctrl-C, logging how many requests the server is
+/// left to drain within `grace`.
+pub async fn stop_signal(in_flight: web::Data<InFlight>, grace: Duration) {
+    terminated().await;
+    info!(
+        name = "ShutdownStarted",
+        in_flight = in_flight.get(),
+        grace_secs = grace.as_secs(),
+        message = "Draining in-flight requests"
+    );
+}
+
+#[cfg(unix)]
+async fn terminated() {
+    use actix_rt::signal::unix::{signal, SignalKind};
+
+    match signal(SignalKind::terminate()) {
+        Ok(mut term) => {
+            select(pin!(term.recv()), pin!(actix_rt::signal::ctrl_c())).await;
+        }
+        Err(_) => {
+            let _ = actix_rt::signal::ctrl_c().await;
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn terminated() {
+    let _ = actix_rt::signal::ctrl_c().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{middleware::from_fn, test, App, HttpResponse};
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_requests_are_counted_while_served() {
+        let in_flight = web::Data::new(InFlight::default());
+        let app = test::init_service(
+            App::new()
+                .app_data(in_flight.clone())
+                .wrap(from_fn(count_in_flight))
+                .route(
+                    "/",
+                    web::get().to(|in_flight: web::Data<InFlight>| async move {
+                        HttpResponse::Ok().body(in_flight.get().to_string())
+                    }),
+                ),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/").to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        assert_eq!(body, "1");
+        assert_eq!(in_flight.get(), 0);
+    }
+}
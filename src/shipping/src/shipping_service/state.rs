@@ -0,0 +1,130 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use actix_web::web;
+
+use super::concurrency::ConcurrencyLimits;
+use super::currency::StaleRates;
+use super::determinism::Entropy;
+use super::idempotency::IdempotencyStore;
+use super::orders::OrderStore;
+use super::pricing::{self, PricingState};
+use super::quote::QuoteState;
+use super::quote_tokens::QuoteTokens;
+use super::rate_limit::RateLimiter;
+use super::reconcile;
+use super::shutdown::InFlight;
+use super::{malformed_request, ShipOrderResponse, ShippingConfig};
+use crate::prometheus::PrometheusReader;
+use crate::telemetry::TelemetryStatus;
+
+/// Shared state of the handlers. It is built once per process and registered
+/// on every worker's `App`, so all workers see the same stores.
+#[derive(Clone)]
+pub struct AppData {
+    pub config: web::Data<ShippingConfig>,
+    pub quotes: web::Data<QuoteState>,
+    pub orders: web::Data<OrderStore>,
+    pub pricing: web::Data<PricingState>,
+    pub entropy: web::Data<Entropy>,
+    pub shipments: web::Data<IdempotencyStore<ShipOrderResponse>>,
+    pub quote_tokens: web::Data<QuoteTokens>,
+    pub stale_rates: web::Data<StaleRates>,
+    pub telemetry: web::Data<TelemetryStatus>,
+    pub in_flight: web::Data<InFlight>,
+    pub concurrency: web::Data<ConcurrencyLimits>,
+    pub quote_rate_limit: web::Data<RateLimiter>,
+    /// Collects the metrics `/metrics` serves, when it is enabled.
+    pub prometheus: Option<web::Data<PrometheusReader>>,
+}
+
+impl AppData {
+    #[cfg(test)]
+    pub fn new(config: ShippingConfig) -> Self {
+        AppData::try_new(config).expect("Failed to build app data")
+    }
+
+    /// Builds the state, failing if the idempotency journal can't be read.
+    pub fn try_new(config: ShippingConfig) -> anyhow::Result<Self> {
+        let shipments = IdempotencyStore::open(
+            config.idempotency_store_file.as_deref(),
+            config.idempotency_retention,
+        )?;
+        Ok(AppData {
+            quotes: web::Data::new(QuoteState::new(&config)),
+            orders: web::Data::new(OrderStore::default()),
+            pricing: web::Data::new(PricingState::new(
+                config.pricing.clone(),
+                config.pricing_config_file.as_deref(),
+            )),
+            entropy: web::Data::new(Entropy::new(&config)),
+            shipments: web::Data::new(shipments),
+            quote_tokens: web::Data::new(QuoteTokens::default()),
+            stale_rates: web::Data::new(StaleRates::default()),
+            telemetry: web::Data::new(TelemetryStatus::default()),
+            in_flight: web::Data::new(InFlight::default()),
+            concurrency: web::Data::new(ConcurrencyLimits::new(&config.concurrency)),
+            quote_rate_limit: web::Data::new(RateLimiter::new(config.quote_rate_limit_rps)),
+            prometheus: None,
+            config: web::Data::new(config),
+        })
+    }
+
+    /// Records which telemetry signals failed to start, for `/ready`.
+    pub fn with_telemetry(self, telemetry: TelemetryStatus) -> Self {
+        AppData {
+            telemetry: web::Data::new(telemetry),
+            ..self
+        }
+    }
+
+    /// Serves the metrics `reader` collects on `/metrics`.
+    pub fn with_prometheus(self, reader: Option<PrometheusReader>) -> Self {
+        AppData {
+            prometheus: reader.map(web::Data::new),
+            ..self
+        }
+    }
+
+    /// Registers each store on its own and, for handlers that need most of
+    /// them, the whole state.
+    pub fn register(&self, cfg: &mut web::ServiceConfig) {
+        cfg.app_data(web::Data::new(self.clone()))
+            .app_data(web::JsonConfig::default().error_handler(|err, _| malformed_request(err)))
+            .app_data(web::QueryConfig::default().error_handler(|err, _| malformed_request(err)))
+            .app_data(self.config.clone())
+            .app_data(self.quotes.clone())
+            .app_data(self.orders.clone())
+            .app_data(self.pricing.clone())
+            .app_data(self.entropy.clone())
+            .app_data(self.shipments.clone())
+            .app_data(self.quote_tokens.clone())
+            .app_data(self.stale_rates.clone())
+            .app_data(self.telemetry.clone())
+            .app_data(self.in_flight.clone())
+            .app_data(self.concurrency.clone())
+            .app_data(self.quote_rate_limit.clone());
+        if let Some(reader) = &self.prometheus {
+            cfg.app_data(reader.clone());
+        }
+    }
+
+    /// Starts watching the pricing file for changes, if hot reload is on.
+    /// Must be called from within the actix runtime.
+    pub fn spawn_pricing_watcher(&self) {
+        if let (Some(path), Some(interval)) = (
+            &self.config.pricing_config_file,
+            self.config.pricing_reload_interval,
+        ) {
+            pricing::watch(self.pricing.clone(), path.clone(), interval);
+        }
+    }
+
+    /// Starts looking for stuck orders, if `STUCK_ORDER_MAX_AGE_SECS` is set.
+    /// Must be called from within the actix runtime.
+    pub fn spawn_order_reconciler(&self) {
+        if let Some(max_age) = self.config.stuck_order_max_age {
+            reconcile::watch(self.orders.clone(), self.config.clone(), max_age);
+        }
+    }
+}
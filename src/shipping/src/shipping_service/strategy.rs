@@ -0,0 +1,371 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{fmt, str::FromStr};
+
+use opentelemetry::{
+    global,
+    trace::{get_active_span, TraceContextExt, Tracer},
+    KeyValue,
+};
+use serde::Deserialize;
+
+use super::config::{CompositeRates, PricingConfig, Warehouse};
+use super::determinism::Entropy;
+use super::shipping_types::{Address, CartItem, ShippingQuote};
+use super::weight::billable_weight;
+use super::InstrumentationLevel;
+
+/// Locally computed pricing strategies. The quote service stays the source
+/// of the price clients get; these run alongside it as canaries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PricingStrategy {
+    /// A flat `per_item_rate` for every item.
+    PerItem,
+    /// The rate of each product in `sku_rates`. Products missing from the
+    /// table can't be priced.
+    PerSku,
+    /// The sum of the item count, declared weight and distance components
+    /// priced from the `composite` rates.
+    Composite,
+}
+
+/// Base price of some items under a strategy.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Priced {
+    pub cents: u64,
+    /// Product ids of the items left out of `cents`.
+    pub unpriceable: Vec<String>,
+}
+
+impl PricingStrategy {
+    pub fn price(
+        self,
+        items: &[CartItem],
+        destination: Option<&Address>,
+        pricing: &PricingConfig,
+    ) -> Priced {
+        match self {
+            PricingStrategy::PerItem => price_items(items, |_| Some(pricing.per_item_rate)),
+            PricingStrategy::PerSku => price_items(items, |item| {
+                pricing.sku_rates.get(&item.product_id).copied()
+            }),
+            PricingStrategy::Composite => Priced {
+                cents: price_composite(items, destination, &pricing.composite),
+                unpriceable: Vec::new(),
+            },
+        }
+    }
+}
+
+/// Prices every item at its `rate`, leaving out the items without one.
+fn price_items(items: &[CartItem], rate: impl Fn(&CartItem) -> Option<f64>) -> Priced {
+    let mut priced = Priced::default();
+    for item in items {
+        match rate(item) {
+            Some(rate) => priced.cents += (rate * 100.0).round() as u64 * item.quantity as u64,
+            None => priced.unpriceable.push(item.product_id.clone()),
+        }
+    }
+    priced
+}
+
+/// Prices each component in its own span, under a span for the whole
+/// strategy, so traces show where pricing time goes.
+fn price_composite(
+    items: &[CartItem],
+    destination: Option<&Address>,
+    rates: &CompositeRates,
+) -> u64 {
+    let tracer = global::tracer("otel_demo.shipping.pricing");
+    let component = |name: &'static str, price: &dyn Fn() -> u64| {
+        tracer.in_span(format!("shipping.price.{name}"), |cx| {
+            let cents = price();
+            cx.span().set_attribute(KeyValue::new(
+                "app.shipping.price.component_cents",
+                cents as i64,
+            ));
+            cents
+        })
+    };
+
+    tracer.in_span("shipping.price.composite", |cx| {
+        let cents = component("count", &|| {
+            price_items(items, |_| Some(rates.per_item_rate)).cents
+        }) + component("weight", &|| {
+            billable_weight(items, None).map_or(0, |weight| {
+                (weight.actual_kg * rates.per_kg_rate * 100.0).round() as u64
+            })
+        }) + component("distance", &|| {
+            let country = destination.map(|address| address.country.trim().to_ascii_uppercase());
+            let rate = match country {
+                Some(country) if rates.warehouses.is_empty() => {
+                    rates.distance_rates.get(&country).copied()
+                }
+                Some(country) => warehouse_rate(&rates.warehouses, &country),
+                None => None,
+            };
+            rate.map_or(0, |rate| (rate * 100.0).round() as u64)
+        });
+        cx.span().set_attribute(KeyValue::new(
+            "app.shipping.price.total_cents",
+            cents as i64,
+        ));
+        cents
+    })
+}
+
+/// Rate to `country` from the nearest warehouse with capacity, recorded on
+/// the active span along with whether a nearer one was at capacity. When
+/// all are, the nearest ships anyway.
+fn warehouse_rate(warehouses: &[Warehouse], country: &str) -> Option<f64> {
+    let mut serving: Vec<(&Warehouse, f64)> = warehouses
+        .iter()
+        .filter_map(|warehouse| {
+            let rate = warehouse.distance_rates.get(country)?;
+            Some((warehouse, *rate))
+        })
+        .collect();
+    serving.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+    let nearest = serving.first()?;
+    let (warehouse, rate) = serving
+        .iter()
+        .find(|(warehouse, _)| !warehouse.at_capacity)
+        .unwrap_or(nearest);
+    get_active_span(|span| {
+        span.set_attribute(KeyValue::new(
+            "app.shipping.warehouse",
+            warehouse.name.clone(),
+        ));
+        span.set_attribute(KeyValue::new(
+            "app.shipping.warehouse_fallback",
+            !std::ptr::eq(*warehouse, nearest.0),
+        ));
+    });
+    Some(*rate)
+}
+
+impl FromStr for PricingStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "per_item" => Ok(PricingStrategy::PerItem),
+            "per_sku" => Ok(PricingStrategy::PerSku),
+            "composite" => Ok(PricingStrategy::Composite),
+            _ => Err(format!(
+                "unknown pricing strategy {s:?}, expected per_item, per_sku or composite"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for PricingStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PricingStrategy::PerItem => f.write_str("per_item"),
+            PricingStrategy::PerSku => f.write_str("per_sku"),
+            PricingStrategy::Composite => f.write_str("composite"),
+        }
+    }
+}
+
+/// Whether to run the canary for this request, for a `rate` between 0 and 1.
+pub fn sample_canary(rate: f64, entropy: &Entropy) -> bool {
+    entropy.fraction() < rate
+}
+
+/// Prices `items` with the canary `strategy` and records how far it is from
+/// `primary`, which is what the client gets. Only the base shipping cost is
+/// compared, since charges don't depend on the strategy.
+pub fn compare_canary(
+    strategy: PricingStrategy,
+    items: &[CartItem],
+    destination: Option<&Address>,
+    primary: &ShippingQuote,
+    pricing: &PricingConfig,
+    level: InstrumentationLevel,
+) {
+    let candidate_cents = strategy.price(items, destination, pricing).cents;
+    let delta_cents = candidate_cents as i64 - primary.base_cents() as i64;
+
+    let meter = global::meter("otel_demo.shipping.quote");
+    let attributes = [KeyValue::new("strategy", strategy.to_string())];
+    meter
+        .u64_counter("app.shipping.canary.comparisons")
+        .build()
+        .add(1, &attributes);
+    meter
+        .f64_histogram("app.shipping.canary.delta_cents")
+        .build()
+        .record(delta_cents as f64, &attributes);
+
+    for attribute in [
+        KeyValue::new("app.shipping.canary.strategy", strategy.to_string()),
+        KeyValue::new("app.shipping.canary.total_cents", candidate_cents as i64),
+        KeyValue::new("app.shipping.canary.delta_cents", delta_cents),
+    ] {
+        level.set_attribute(InstrumentationLevel::Minimal, attribute);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::Value;
+
+    use super::*;
+    use crate::test_support::{in_test_span, test_spans};
+
+    #[test]
+    fn test_sample_canary_bounds() {
+        let entropy = Entropy::default();
+        assert!((0..100).all(|_| sample_canary(1.0, &entropy)));
+        assert!((0..100).all(|_| !sample_canary(0.0, &entropy)));
+    }
+
+    #[test]
+    fn test_per_sku_leaves_out_unknown_products() {
+        let pricing = PricingConfig {
+            sku_rates: [("OLJCESPC7Z".to_string(), 2.5)].into(),
+            ..Default::default()
+        };
+        let item = |product_id: &str| CartItem {
+            product_id: product_id.into(),
+            quantity: 2,
+            ..Default::default()
+        };
+        let priced =
+            PricingStrategy::PerSku.price(&[item("OLJCESPC7Z"), item("UNKNOWN")], None, &pricing);
+        assert_eq!(
+            priced,
+            Priced {
+                cents: 500,
+                unpriceable: vec!["UNKNOWN".to_string()],
+            }
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_composite_prices_each_component_in_its_own_span() {
+        let pricing = PricingConfig {
+            composite: CompositeRates {
+                per_item_rate: 3.99,
+                per_kg_rate: 2.0,
+                distance_rates: [("DE".to_string(), 12.5)].into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let items = [CartItem {
+            product_id: "OLJCESPC7Z".into(),
+            quantity: 2,
+            weight_kg: Some(1.5),
+            ..Default::default()
+        }];
+        let destination = Address {
+            country: "de".into(),
+            ..Default::default()
+        };
+
+        let (priced, parent) = in_test_span("composite-pricing", async {
+            PricingStrategy::Composite.price(&items, Some(&destination), &pricing)
+        })
+        .await;
+        assert_eq!(priced.cents, 798 + 600 + 1250);
+
+        let spans: Vec<_> = test_spans()
+            .get_finished_spans()
+            .unwrap()
+            .into_iter()
+            .filter(|span| span.span_context.trace_id() == parent.span_context.trace_id())
+            .collect();
+        let composite = spans
+            .iter()
+            .find(|span| span.name == "shipping.price.composite")
+            .expect("composite span was not exported");
+        assert_eq!(composite.parent_span_id, parent.span_context.span_id());
+
+        let mut components: Vec<_> = spans
+            .iter()
+            .filter(|span| span.parent_span_id == composite.span_context.span_id())
+            .map(|span| {
+                let cents = span
+                    .attributes
+                    .iter()
+                    .find(|kv| kv.key.as_str() == "app.shipping.price.component_cents")
+                    .map(|kv| kv.value.clone());
+                (span.name.to_string(), cents)
+            })
+            .collect();
+        components.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            components,
+            [
+                ("shipping.price.count".to_string(), Some(Value::I64(798))),
+                (
+                    "shipping.price.distance".to_string(),
+                    Some(Value::I64(1250))
+                ),
+                ("shipping.price.weight".to_string(), Some(Value::I64(600))),
+            ]
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_full_nearest_warehouse_falls_back_to_the_next() {
+        let warehouse = |name: &str, rate: f64, at_capacity: bool| Warehouse {
+            name: name.into(),
+            distance_rates: [("DE".to_string(), rate)].into(),
+            at_capacity,
+        };
+        let destination = Address {
+            country: "DE".into(),
+            ..Default::default()
+        };
+        for (near_at_capacity, expected) in [
+            (false, (1250, Some("near".into()), Some(Value::Bool(false)))),
+            (true, (2000, Some("far".into()), Some(Value::Bool(true)))),
+        ] {
+            let pricing = PricingConfig {
+                composite: CompositeRates {
+                    per_item_rate: 0.0,
+                    warehouses: vec![
+                        warehouse("far", 20.0, false),
+                        warehouse("near", 12.5, near_at_capacity),
+                    ],
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let (priced, parent) = in_test_span("warehouse-pricing", async {
+                PricingStrategy::Composite.price(&[], Some(&destination), &pricing)
+            })
+            .await;
+            let span = test_spans()
+                .get_finished_spans()
+                .unwrap()
+                .into_iter()
+                .find(|span| {
+                    span.span_context.trace_id() == parent.span_context.trace_id()
+                        && span.name == "shipping.price.distance"
+                })
+                .expect("distance span was not exported");
+            let attribute = |key: &str| {
+                span.attributes
+                    .iter()
+                    .find(|kv| kv.key.as_str() == key)
+                    .map(|kv| kv.value.clone())
+            };
+            assert_eq!(
+                (
+                    priced.cents,
+                    attribute("app.shipping.warehouse"),
+                    attribute("app.shipping.warehouse_fallback"),
+                ),
+                expected
+            );
+        }
+    }
+}
@@ -0,0 +1,60 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::BTreeMap;
+
+use super::shipping_types::Address;
+
+/// A quote's total with and without tax, in cents.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TaxedTotal {
+    pub rate: f64,
+    pub exclusive_cents: u64,
+    pub tax_cents: u64,
+    pub inclusive_cents: u64,
+}
+
+impl TaxedTotal {
+    /// Taxes `exclusive_cents` at the rate of the destination's country.
+    /// Destinations missing from `tax_rates` are untaxed.
+    pub fn for_destination(
+        exclusive_cents: u64,
+        address: Option<&Address>,
+        tax_rates: &BTreeMap<String, f64>,
+    ) -> Self {
+        let rate = address
+            .and_then(|address| tax_rates.get(&address.country.trim().to_ascii_uppercase()))
+            .copied()
+            .unwrap_or(0.0);
+        let tax_cents = (exclusive_cents as f64 * rate).round() as u64;
+        TaxedTotal {
+            rate,
+            exclusive_cents,
+            tax_cents,
+            // Summed in cents, so the inclusive total is exactly the
+            // exclusive one plus the tax line.
+            inclusive_cents: exclusive_cents + tax_cents,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tax_uses_destination_rate() {
+        let rates = BTreeMap::from([("DE".to_string(), 0.19)]);
+        let to = |country: &str| Address {
+            country: country.into(),
+            ..Default::default()
+        };
+
+        let taxed = TaxedTotal::for_destination(1099, Some(&to(" de ")), &rates);
+        assert_eq!((taxed.tax_cents, taxed.inclusive_cents), (209, 1308));
+
+        let untaxed = TaxedTotal::for_destination(1099, Some(&to("US")), &rates);
+        assert_eq!((untaxed.tax_cents, untaxed.inclusive_cents), (0, 1099));
+        assert_eq!(TaxedTotal::for_destination(1099, None, &rates), untaxed);
+    }
+}
@@ -0,0 +1,61 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use std::time::Duration;
+
+use opentelemetry::KeyValue;
+
+use super::InstrumentationLevel;
+
+/// Durations of the phases of a request. Every phase is recorded on the
+/// active span, and the same values feed the optional `Server-Timing` header.
+#[derive(Debug, Default)]
+pub struct PhaseTimings {
+    level: InstrumentationLevel,
+    phases: Vec<(&'static str, Duration)>,
+}
+
+impl PhaseTimings {
+    pub fn new(level: InstrumentationLevel) -> Self {
+        PhaseTimings {
+            level,
+            phases: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, phase: &'static str, duration: Duration) {
+        self.level.set_attribute(
+            InstrumentationLevel::Standard,
+            KeyValue::new(
+                format!("app.shipping.timing.{phase}_ms"),
+                duration.as_secs_f64() * 1000.0,
+            ),
+        );
+        self.phases.push((phase, duration));
+    }
+
+    /// Formats the phases as a `Server-Timing` header value, e.g.
+    /// `quote;dur=12.345, total;dur=13.001`.
+    pub fn header_value(&self) -> String {
+        self.phases
+            .iter()
+            .map(|(phase, duration)| {
+                format!("{};dur={:.3}", phase, duration.as_secs_f64() * 1000.0)
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_value() {
+        let mut timings = PhaseTimings::default();
+        timings.record("quote", Duration::from_micros(12_345));
+        timings.record("total", Duration::from_millis(13));
+        assert_eq!(timings.header_value(), "quote;dur=12.345, total;dur=13.000");
+    }
+}
@@ -1,9 +1,447 @@
 // Copyright The OpenTelemetry Authors
 // SPDX-License-Identifier: Apache-2.0
 
-use uuid::Uuid;
+use std::{fmt, str::FromStr};
+
+use uuid::{Builder, Uuid};
+
+use super::determinism::Entropy;
+
+/// Bytes of randomness in a tracking id.
+const ID_LEN: usize = 16;
+
+/// Decoded tracking id: the random bytes followed by their checksum.
+pub type Payload = [u8; ID_LEN + 2];
+
+/// A text encoding of tracking ids. Each encoding writes every payload with
+/// the same number of characters, so that downstreams can rely on a fixed
+/// length.
+pub trait IdEncoding {
+    fn encoded_len(&self) -> usize;
+    fn encode(&self, payload: &Payload) -> String;
+    /// Decodes `id`, or returns `None` if it isn't in this encoding.
+    fn decode(&self, id: &str) -> Option<Payload>;
+}
+
+/// Lowercase hexadecimal.
+pub struct Hex;
+
+/// RFC 4648 base32, uppercase and without padding.
+pub struct Base32;
+
+/// Base58 with the Bitcoin alphabet, left-padded to a fixed width.
+pub struct Base58;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+const BASE58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+impl IdEncoding for Hex {
+    fn encoded_len(&self) -> usize {
+        2 * std::mem::size_of::<Payload>()
+    }
+
+    fn encode(&self, payload: &Payload) -> String {
+        payload.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+
+    fn decode(&self, id: &str) -> Option<Payload> {
+        if id.len() != self.encoded_len() || !id.is_ascii() {
+            return None;
+        }
+        let mut payload = Payload::default();
+        for (byte, pair) in payload.iter_mut().zip(id.as_bytes().chunks(2)) {
+            *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+        }
+        Some(payload)
+    }
+}
+
+impl IdEncoding for Base32 {
+    fn encoded_len(&self) -> usize {
+        (8 * std::mem::size_of::<Payload>()).div_ceil(5)
+    }
+
+    fn encode(&self, payload: &Payload) -> String {
+        let (mut buffer, mut bits) = (0u32, 0);
+        let mut id = String::with_capacity(self.encoded_len());
+        for byte in payload {
+            buffer = (buffer << 8) | *byte as u32;
+            bits += 8;
+            while bits >= 5 {
+                bits -= 5;
+                id.push(BASE32_ALPHABET[(buffer >> bits) as usize & 31] as char);
+            }
+        }
+        if bits > 0 {
+            id.push(BASE32_ALPHABET[(buffer << (5 - bits)) as usize & 31] as char);
+        }
+        id
+    }
+
+    fn decode(&self, id: &str) -> Option<Payload> {
+        if id.len() != self.encoded_len() {
+            return None;
+        }
+        let mut payload = Payload::default();
+        let (mut buffer, mut bits, mut len) = (0u32, 0, 0);
+        for c in id.bytes() {
+            let digit = BASE32_ALPHABET.iter().position(|&a| a == c)? as u32;
+            buffer = (buffer << 5) | digit;
+            bits += 5;
+            if bits >= 8 {
+                bits -= 8;
+                *payload.get_mut(len)? = (buffer >> bits) as u8;
+                len += 1;
+            }
+        }
+        // The padding bits of the last character must be zero, so that each
+        // payload has a single encoding.
+        let padding = buffer & ((1 << bits) - 1);
+        (len == payload.len() && padding == 0).then_some(payload)
+    }
+}
+
+impl IdEncoding for Base58 {
+    fn encoded_len(&self) -> usize {
+        // The fewest digits whose range, 58^n, covers 2^(8 * payload length).
+        ((8 * std::mem::size_of::<Payload>()) as f64 / 58f64.log2()).ceil() as usize
+    }
+
+    fn encode(&self, payload: &Payload) -> String {
+        let mut number = *payload;
+        let mut digits = vec![0u8; self.encoded_len()];
+        for digit in digits.iter_mut().rev() {
+            let mut remainder = 0u32;
+            for byte in number.iter_mut() {
+                let value = (remainder << 8) | *byte as u32;
+                *byte = (value / 58) as u8;
+                remainder = value % 58;
+            }
+            *digit = BASE58_ALPHABET[remainder as usize];
+        }
+        String::from_utf8(digits).expect("base58 digits are ASCII")
+    }
+
+    fn decode(&self, id: &str) -> Option<Payload> {
+        if id.len() != self.encoded_len() {
+            return None;
+        }
+        let mut payload = Payload::default();
+        for c in id.bytes() {
+            let mut carry = BASE58_ALPHABET.iter().position(|&a| a == c)? as u32;
+            for byte in payload.iter_mut().rev() {
+                let value = *byte as u32 * 58 + carry;
+                *byte = value as u8;
+                carry = value >> 8;
+            }
+            if carry != 0 {
+                return None;
+            }
+        }
+        Some(payload)
+    }
+}
+
+/// Encoding of tracking ids, set by `TRACKING_ID_ENCODING`. Ids are plain
+/// v4 UUIDs unless one of the checksummed encodings is picked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TrackingIdEncoding {
+    #[default]
+    Uuid,
+    Hex,
+    Base32,
+    Base58,
+}
+
+impl TrackingIdEncoding {
+    /// The checksummed encoding, or `None` for UUIDs.
+    fn encoding(self) -> Option<&'static dyn IdEncoding> {
+        match self {
+            TrackingIdEncoding::Uuid => None,
+            TrackingIdEncoding::Hex => Some(&Hex),
+            TrackingIdEncoding::Base32 => Some(&Base32),
+            TrackingIdEncoding::Base58 => Some(&Base58),
+        }
+    }
+}
+
+impl FromStr for TrackingIdEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "uuid" => Ok(TrackingIdEncoding::Uuid),
+            "hex" => Ok(TrackingIdEncoding::Hex),
+            "base32" => Ok(TrackingIdEncoding::Base32),
+            "base58" => Ok(TrackingIdEncoding::Base58),
+            _ => Err(format!(
+                "unknown tracking id encoding {s:?}, expected uuid, hex, base32 or base58"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for TrackingIdEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            TrackingIdEncoding::Uuid => "uuid",
+            TrackingIdEncoding::Hex => "hex",
+            TrackingIdEncoding::Base32 => "base32",
+            TrackingIdEncoding::Base58 => "base58",
+        })
+    }
+}
+
+/// Characters of the check digit, and of tracking id prefixes. They cover
+/// every encoding's alphabet.
+const CHECK_ALPHABET: &[u8; 64] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz-_";
+
+/// Text written before tracking ids, e.g. a carrier code, set by
+/// `TRACKING_ID_PREFIX`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrackingIdPrefix(String);
+
+impl FromStr for TrackingIdPrefix {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.bytes().find(|c| !CHECK_ALPHABET.contains(c)) {
+            Some(c) => Err(format!(
+                "tracking id prefix {s:?} contains {:?}, expected letters, digits, - or _",
+                c as char
+            )),
+            None => Ok(TrackingIdPrefix(s.to_string())),
+        }
+    }
+}
+
+/// How tracking ids are written.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrackingIdFormat {
+    pub encoding: TrackingIdEncoding,
+    pub prefix: TrackingIdPrefix,
+    /// Appends a check digit over the whole id, prefix included, which
+    /// `validate_check_digit` verifies without knowing the encoding. Set by
+    /// `TRACKING_ID_CHECKSUM`.
+    pub check_digit: bool,
+}
+
+/// Luhn mod 64 sum of `id`, doubling from the last character when
+/// `double_last`. `None` if `id` has characters outside `CHECK_ALPHABET`.
+fn luhn_sum(id: &str, double_last: bool) -> Option<usize> {
+    let n = CHECK_ALPHABET.len();
+    id.bytes().rev().enumerate().try_fold(0, |sum, (i, c)| {
+        let code = CHECK_ALPHABET.iter().position(|&a| a == c)?;
+        let factor = if (i % 2 == 0) == double_last { 2 } else { 1 };
+        let addend = code * factor;
+        Some(sum + addend / n + addend % n)
+    })
+}
+
+/// The Luhn mod 64 check digit of `id`.
+fn check_digit(id: &str) -> Option<char> {
+    let n = CHECK_ALPHABET.len();
+    let sum = luhn_sum(id, true)?;
+    Some(CHECK_ALPHABET[(n - sum % n) % n] as char)
+}
+
+/// Whether the last character of `id` is the check digit of the rest.
+pub fn validate_check_digit(id: &str) -> bool {
+    !id.is_empty() && luhn_sum(id, false).is_some_and(|sum| sum % CHECK_ALPHABET.len() == 0)
+}
+
+/// Fletcher-16 checksum, which catches mistyped and swapped characters.
+fn checksum(bytes: &[u8]) -> [u8; 2] {
+    let (mut low, mut high) = (0u16, 0u16);
+    for byte in bytes {
+        low = (low + *byte as u16) % 255;
+        high = (high + low) % 255;
+    }
+    [high as u8, low as u8]
+}
+
+/// Encodes the random `bytes` of a tracking id along with their checksum,
+/// or as a hyphenated v4 UUID.
+pub fn encode_tracking_id(bytes: [u8; ID_LEN], encoding: TrackingIdEncoding) -> String {
+    let Some(encoding) = encoding.encoding() else {
+        return Builder::from_random_bytes(bytes).into_uuid().to_string();
+    };
+    let mut payload = Payload::default();
+    payload[..ID_LEN].copy_from_slice(&bytes);
+    payload[ID_LEN..].copy_from_slice(&checksum(&bytes));
+    encoding.encode(&payload)
+}
+
+/// Writes the random `bytes` of a tracking id in `format`.
+pub fn format_tracking_id(bytes: [u8; ID_LEN], format: &TrackingIdFormat) -> String {
+    let mut id = format.prefix.0.clone();
+    id.push_str(&encode_tracking_id(bytes, format.encoding));
+    if format.check_digit {
+        // The prefix and every encoding stay within the check alphabet.
+        id.extend(check_digit(&id));
+    }
+    id
+}
+
+/// Whether `id` is a tracking id in `format` with matching checksums.
+pub fn validate_tracking_id(id: &str, format: &TrackingIdFormat) -> bool {
+    let id = if format.check_digit {
+        if !validate_check_digit(id) {
+            return false;
+        }
+        &id[..id.len() - 1]
+    } else {
+        id
+    };
+    let Some(encoded) = id.strip_prefix(format.prefix.0.as_str()) else {
+        return false;
+    };
+    match format.encoding.encoding() {
+        Some(encoding) => encoding
+            .decode(encoded)
+            .is_some_and(|payload| payload[ID_LEN..] == checksum(&payload[..ID_LEN])),
+        None => encoded.len() == 36 && Uuid::try_parse(encoded).is_ok(),
+    }
+}
 
 /// returns a tracking ID
-pub fn create_tracking_id() -> String {
-    Uuid::new_v4().to_string()
+pub fn create_tracking_id(entropy: &Entropy, format: &TrackingIdFormat) -> String {
+    format_tracking_id(entropy.bytes(), format)
+}
+
+/// returns an order ID
+pub fn create_order_id(entropy: &Entropy) -> String {
+    entropy.uuid().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The encodings with a checksum.
+    const ENCODINGS: [TrackingIdEncoding; 3] = [
+        TrackingIdEncoding::Hex,
+        TrackingIdEncoding::Base32,
+        TrackingIdEncoding::Base58,
+    ];
+
+    fn plain(encoding: TrackingIdEncoding) -> TrackingIdFormat {
+        TrackingIdFormat {
+            encoding,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_encodings_round_trip_with_checksum() {
+        for hash in [[0u8; ID_LEN], [0xff; ID_LEN], *b"0123456789abcdef"] {
+            for encoding in ENCODINGS {
+                let id = encode_tracking_id(hash, encoding);
+                let encoded_len = encoding.encoding().unwrap().encoded_len();
+                assert_eq!(id.len(), encoded_len, "{encoding}");
+                assert!(
+                    validate_tracking_id(&id, &plain(encoding)),
+                    "{encoding}: {id}"
+                );
+                let payload = encoding.encoding().unwrap().decode(&id).unwrap();
+                assert_eq!(payload[..ID_LEN], hash);
+            }
+        }
+    }
+
+    #[test]
+    fn test_corrupted_ids_are_rejected() {
+        for encoding in ENCODINGS {
+            let id = encode_tracking_id(*b"0123456789abcdef", encoding);
+            let mut chars: Vec<char> = id.chars().collect();
+            chars.swap(0, 1);
+            let swapped: String = chars.into_iter().collect();
+            assert!(
+                !validate_tracking_id(&swapped, &plain(encoding)),
+                "{encoding}"
+            );
+            assert!(
+                !validate_tracking_id(&id[1..], &plain(encoding)),
+                "{encoding}"
+            );
+        }
+        assert!(!validate_tracking_id(
+            "not-a-tracking-id",
+            &TrackingIdFormat::default()
+        ));
+    }
+
+    #[test]
+    fn test_ids_are_uuids_by_default() {
+        let format = TrackingIdFormat::default();
+        let id = format_tracking_id(*b"0123456789abcdef", &format);
+        let uuid = Uuid::try_parse(&id).unwrap();
+        assert_eq!(uuid.get_version_num(), 4);
+        assert_eq!(id, uuid.hyphenated().to_string());
+        assert!(validate_tracking_id(&id, &format));
+        assert!(!validate_tracking_id(&uuid.simple().to_string(), &format));
+        assert!(!validate_tracking_id(&id[1..], &format));
+
+        let format = TrackingIdFormat {
+            prefix: "UPS-".parse().unwrap(),
+            check_digit: true,
+            ..Default::default()
+        };
+        let id = format_tracking_id(*b"0123456789abcdef", &format);
+        assert_eq!(id.len(), 4 + 36 + 1);
+        assert!(validate_tracking_id(&id, &format), "{id}");
+    }
+
+    #[test]
+    fn test_default_format_is_the_bare_encoding() {
+        let hash = *b"0123456789abcdef";
+        for encoding in ENCODINGS {
+            assert_eq!(
+                format_tracking_id(hash, &plain(encoding)),
+                encode_tracking_id(hash, encoding)
+            );
+        }
+    }
+
+    #[test]
+    fn test_prefixed_ids_round_trip_with_and_without_check_digit() {
+        for check_digit in [false, true] {
+            for encoding in ENCODINGS {
+                let format = TrackingIdFormat {
+                    encoding,
+                    prefix: "UPS-".parse().unwrap(),
+                    check_digit,
+                };
+                let id = format_tracking_id(*b"0123456789abcdef", &format);
+                assert!(id.starts_with("UPS-"), "{id}");
+                assert_eq!(
+                    id.len(),
+                    4 + encoding.encoding().unwrap().encoded_len() + check_digit as usize
+                );
+                assert!(validate_tracking_id(&id, &format), "{format:?}: {id}");
+                assert_eq!(validate_check_digit(&id), check_digit, "{id}");
+                assert!(!validate_tracking_id(&id[4..], &format), "{id}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_check_digit_catches_single_character_errors() {
+        let format = TrackingIdFormat {
+            check_digit: true,
+            ..Default::default()
+        };
+        let id = format_tracking_id(*b"0123456789abcdef", &format);
+        assert!(validate_check_digit(&id));
+        for i in 0..id.len() {
+            let mut chars: Vec<char> = id.chars().collect();
+            chars[i] = if chars[i] == 'z' { 'y' } else { 'z' };
+            let mistyped: String = chars.into_iter().collect();
+            assert!(!validate_check_digit(&mistyped), "{mistyped}");
+            assert!(!validate_tracking_id(&mistyped, &format), "{mistyped}");
+        }
+        assert!(!validate_check_digit(""));
+        assert!("UPS!".parse::<TrackingIdPrefix>().is_err());
+    }
 }
@@ -0,0 +1,210 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{borrow::Cow, fmt, str::FromStr};
+
+use opentelemetry::KeyValue;
+
+use super::config::AddressLimits;
+use super::items::ItemCount;
+use super::shipping_types::Address;
+use super::InstrumentationLevel;
+
+/// Longest value, in characters, written to a log line or span attribute for
+/// a single untrusted field. Applied regardless of the validation limits.
+pub const LOG_FIELD_MAX_LEN: usize = 64;
+
+/// Rejects an address whose fields exceed the configured maximum lengths,
+/// returning a message naming the first offending field.
+pub fn validate_address(address: &Address, limits: &AddressLimits) -> Result<(), String> {
+    let fields = [
+        (
+            "street_address",
+            &address.street_address,
+            limits.street_address,
+        ),
+        ("city", &address.city, limits.city),
+        ("state", &address.state, limits.state),
+        ("country", &address.country, limits.country),
+        ("zip_code", &address.zip_code, limits.zip_code),
+    ];
+
+    for (name, value, max) in fields {
+        let len = value.chars().count();
+        if len > max {
+            return Err(format!(
+                "address.{name} is {len} characters long, the maximum is {max}"
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Rejects a request listing more than `max` items, before any work is done
+/// per item. This bounds the number of entries, not their quantities.
+pub fn validate_item_count(count: usize, max: usize) -> Result<(), String> {
+    if count > max {
+        return Err(format!("request has {count} items, the maximum is {max}"));
+    }
+    Ok(())
+}
+
+/// Rejects a request for more than `max` items in total, before the quote
+/// service is asked to price them, recording the count on the active span.
+pub fn validate_item_quantity(
+    quantity: ItemCount,
+    max: u32,
+    level: InstrumentationLevel,
+) -> Result<(), String> {
+    if quantity.get() <= max {
+        return Ok(());
+    }
+    level.add_event(
+        InstrumentationLevel::Minimal,
+        "Item Count Rejected",
+        vec![
+            KeyValue::new("app.shipping.items.count", quantity.as_attr()),
+            KeyValue::new("app.shipping.items.max", i64::from(max)),
+        ],
+    );
+    Err(format!(
+        "request has {quantity} items in total, the maximum is {max}"
+    ))
+}
+
+/// Rule of a market, named by `ADDRESS_REQUIRED_CURRENCIES`, that only
+/// allows tax-inclusive quotes for a known destination.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressRequired {
+    pub currency: String,
+}
+
+impl AddressRequired {
+    /// The rule for quotes in `currency`, if its market has one.
+    pub fn for_currency(currency: Option<&str>, required_currencies: &[String]) -> Option<Self> {
+        let currency = currency.map(str::trim)?;
+        required_currencies
+            .iter()
+            .any(|required| required.eq_ignore_ascii_case(currency))
+            .then(|| AddressRequired {
+                currency: currency.to_ascii_uppercase(),
+            })
+    }
+
+    pub fn name(&self) -> String {
+        format!("address_required:{}", self.currency)
+    }
+
+    pub fn check(&self, address: Option<&Address>) -> Result<(), String> {
+        match address {
+            Some(_) => Ok(()),
+            None => Err(format!(
+                "an address is required to quote in {}, whose market needs it for tax-inclusive prices",
+                self.currency
+            )),
+        }
+    }
+}
+
+/// What to do with a request for zero items, set by `ZERO_ITEMS_POLICY`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ZeroItemsPolicy {
+    /// Answer with a free quote, without calling the quote service.
+    #[default]
+    ZeroQuote,
+    /// Reject the request with a 400.
+    Reject,
+}
+
+impl FromStr for ZeroItemsPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "zero_quote" => Ok(ZeroItemsPolicy::ZeroQuote),
+            "reject" => Ok(ZeroItemsPolicy::Reject),
+            _ => Err(format!(
+                "unknown zero items policy {s:?}, expected zero_quote or reject"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ZeroItemsPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ZeroItemsPolicy::ZeroQuote => "zero_quote",
+            ZeroItemsPolicy::Reject => "reject",
+        })
+    }
+}
+
+/// Applies `policy` to a request for `quantity` items in total, recording
+/// the decision on the active span. Fails only for zero items under
+/// `ZeroItemsPolicy::Reject`.
+pub fn check_zero_items(
+    quantity: ItemCount,
+    policy: ZeroItemsPolicy,
+    level: InstrumentationLevel,
+) -> Result<(), String> {
+    if !quantity.is_zero() {
+        return Ok(());
+    }
+    level.set_attribute(
+        InstrumentationLevel::Minimal,
+        KeyValue::new("app.shipping.zero_items.policy", policy.to_string()),
+    );
+    match policy {
+        ZeroItemsPolicy::ZeroQuote => Ok(()),
+        ZeroItemsPolicy::Reject => Err("request has no items to ship".to_string()),
+    }
+}
+
+/// Shortens an untrusted value to `LOG_FIELD_MAX_LEN` characters so it can be
+/// safely recorded in logs and spans.
+pub fn truncate_for_log(value: &str) -> Cow<'_, str> {
+    match value.char_indices().nth(LOG_FIELD_MAX_LEN) {
+        Some((idx, _)) => Cow::Owned(format!("{}...", &value[..idx])),
+        None => Cow::Borrowed(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_address_rejects_long_city() {
+        let limits = AddressLimits::default();
+        let address = Address {
+            city: "a".repeat(limits.city + 1),
+            ..Default::default()
+        };
+
+        let err = validate_address(&address, &limits).unwrap_err();
+        assert!(err.contains("address.city"));
+    }
+
+    #[test]
+    fn test_truncate_for_log() {
+        assert_eq!(truncate_for_log("Mountain View"), "Mountain View");
+
+        let long = "é".repeat(LOG_FIELD_MAX_LEN + 10);
+        let truncated = truncate_for_log(&long);
+        assert_eq!(truncated.chars().count(), LOG_FIELD_MAX_LEN + 3);
+        assert!(truncated.ends_with("..."));
+    }
+
+    #[test]
+    fn test_address_required_for_listed_currencies() {
+        let required = ["EUR".to_string()];
+        let rule = AddressRequired::for_currency(Some("eur"), &required).unwrap();
+        assert_eq!(rule.name(), "address_required:EUR");
+        assert!(rule.check(None).unwrap_err().contains("EUR"));
+        assert!(rule.check(Some(&Address::default())).is_ok());
+
+        assert_eq!(AddressRequired::for_currency(Some("USD"), &required), None);
+        assert_eq!(AddressRequired::for_currency(None, &required), None);
+    }
+}
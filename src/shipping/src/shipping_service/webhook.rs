@@ -0,0 +1,165 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use opentelemetry::{
+    context::FutureExt,
+    global,
+    trace::{Link, SpanKind, TraceContextExt, Tracer},
+    Context, KeyValue,
+};
+use opentelemetry_instrumentation_actix_web::ClientExt;
+use tracing::{info, warn};
+
+use super::orders::Order;
+use super::shipping_types::OrderResponse;
+use crate::telemetry::current_trace_context;
+
+/// Posts `order` to the `ORDER_WEBHOOK_URL` from a background task, so the
+/// request that changed it doesn't wait on the receiver. The delivery gets
+/// its own trace, linked to the one that shipped the order.
+pub fn notify(url: String, order: &Order) {
+    let body = OrderResponse::from(order);
+    let mut links = Vec::new();
+    if order.origin.is_valid() {
+        links.push(Link::with_context(order.origin.clone()));
+    }
+    let tracer = global::tracer("otel_demo.shipping.webhook");
+    let span = tracer
+        .span_builder("order-webhook")
+        .with_kind(SpanKind::Producer)
+        .with_links(links)
+        .with_attributes([KeyValue::new(
+            "app.shipping.order_id",
+            body.order_id.clone(),
+        )])
+        .start_with_context(&tracer, &Context::new());
+    let cx = Context::new().with_span(span);
+
+    actix_web::rt::spawn(
+        async move {
+            let result = awc::Client::new()
+                .post(url)
+                .trace_request()
+                .send_json(&body)
+                .await;
+
+            let trace = current_trace_context();
+            let (trace_id, span_id) = trace.as_fields();
+            match result {
+                Ok(resp) if resp.status().is_success() => info!(
+                    name = "OrderWebhookDelivered",
+                    order_id = body.order_id.as_str(),
+                    trace_id,
+                    span_id,
+                    message = "Order webhook delivered"
+                ),
+                Ok(resp) => warn!(
+                    name = "OrderWebhookFailed",
+                    order_id = body.order_id.as_str(),
+                    status = resp.status().as_u16(),
+                    trace_id,
+                    span_id,
+                    message = "Order webhook rejected"
+                ),
+                Err(err) => warn!(
+                    name = "OrderWebhookFailed",
+                    order_id = body.order_id.as_str(),
+                    error = %err,
+                    trace_id,
+                    span_id,
+                    message = "Order webhook failed"
+                ),
+            }
+            Context::current().span().end();
+        }
+        .with_context(cx),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use actix_web::{test, web, App, HttpResponse};
+    use opentelemetry_sdk::trace::SpanData;
+
+    use crate::shipping_service::{
+        ship_order, update_package_status, AppData, CartItem, DeliveryStatus, PackageStatusUpdate,
+        ShipOrderRequest, ShipOrderResponse, ShippingConfig,
+    };
+    use crate::test_support::{in_test_span, spawn_mock, spawn_quote_mock, test_spans};
+
+    /// Waits for the background delivery of the webhook for `order_id`.
+    async fn webhook_span(order_id: &str) -> SpanData {
+        let exporter = test_spans();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let found = exporter
+                .get_finished_spans()
+                .unwrap()
+                .into_iter()
+                .find(|span| {
+                    span.name == "order-webhook"
+                        && span.attributes.iter().any(|kv| {
+                            kv.key.as_str() == "app.shipping.order_id"
+                                && kv.value.as_str() == order_id
+                        })
+                });
+            if let Some(span) = found {
+                return span;
+            }
+            assert!(Instant::now() < deadline, "webhook span was not exported");
+            actix_web::rt::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_webhook_links_to_order_origin() {
+        let webhook_url = spawn_mock(|cfg| {
+            cfg.route("/orders", web::post().to(HttpResponse::Ok));
+        });
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            order_webhook_url: Some(format!("{webhook_url}/orders")),
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| AppData::new(config).register(cfg))
+                .service(ship_order)
+                .service(update_package_status),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/ship-order")
+            .set_json(ShipOrderRequest {
+                items: vec![CartItem {
+                    product_id: "OLJCESPC7Z".into(),
+                    quantity: 1,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            })
+            .to_request();
+        let (shipped, origin) =
+            in_test_span("ship-order", test::call_and_read_body_json(&app, req)).await;
+        let shipped: ShipOrderResponse = shipped;
+
+        let req = test::TestRequest::put()
+            .uri(&format!("/package/{}/status", shipped.tracking_id))
+            .set_json(PackageStatusUpdate {
+                status: DeliveryStatus::Delivered,
+            })
+            .to_request();
+        let (resp, _) = in_test_span("update-status", test::call_service(&app, req)).await;
+        assert!(resp.status().is_success());
+
+        let span = webhook_span(&shipped.order_id).await;
+        assert_ne!(span.span_context.trace_id(), origin.span_context.trace_id());
+        assert!(span
+            .links
+            .iter()
+            .any(|link| link.span_context.trace_id() == origin.span_context.trace_id()));
+    }
+}
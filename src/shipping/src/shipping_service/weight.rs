@@ -0,0 +1,99 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::shipping_types::CartItem;
+
+/// Slack for float error when dividing a weight by the increment, so an
+/// exact multiple such as 1.1 kg in 0.1 kg steps isn't rounded up.
+const INCREMENT_EPSILON: f64 = 1e-9;
+
+/// Weight of a shipment as declared, and as the carrier bills it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BilledWeight {
+    pub actual_kg: f64,
+    pub billed_kg: f64,
+}
+
+/// Rejects an item declaring a weight that isn't a positive, finite number
+/// of kilograms, which would cancel out or overflow the weight charge.
+pub fn validate_weights(items: &[CartItem]) -> Result<(), String> {
+    match items.iter().find(|item| {
+        item.weight_kg
+            .is_some_and(|kg| !(kg.is_finite() && kg > 0.0))
+    }) {
+        Some(item) => Err(format!(
+            "weight_kg of {:?} must be a positive number",
+            item.product_id
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Totals the declared weight of `items`, rounding it up to the carrier's
+/// billing `increment`. Returns `None` when no item declares a weight.
+pub fn billable_weight(items: &[CartItem], increment: Option<f64>) -> Option<BilledWeight> {
+    let actual_kg = items
+        .iter()
+        .filter_map(|item| item.weight_kg.map(|kg| kg * item.quantity as f64))
+        .reduce(|total, kg| total + kg)?;
+    let billed_kg = match increment {
+        Some(increment) => round_up(actual_kg, increment),
+        None => actual_kg,
+    };
+    Some(BilledWeight {
+        actual_kg,
+        billed_kg,
+    })
+}
+
+/// Rounds `weight` up to the next multiple of `increment`.
+fn round_up(weight: f64, increment: f64) -> f64 {
+    ((weight / increment) - INCREMENT_EPSILON).ceil() * increment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(weight_kg: f64, quantity: u32) -> CartItem {
+        CartItem {
+            product_id: "OLJCESPC7Z".into(),
+            quantity,
+            weight_kg: Some(weight_kg),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_weights_must_be_positive_and_finite() {
+        assert!(validate_weights(&[item(1.2, 1), CartItem::default()]).is_ok());
+        for kg in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(
+                validate_weights(&[item(1.2, 1), item(kg, 1)]).is_err(),
+                "{kg}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_weight_rounds_up_to_increment() {
+        let billed = billable_weight(&[item(1.2, 1)], Some(0.5)).unwrap();
+        assert_eq!(billed.actual_kg, 1.2);
+        assert_eq!(billed.billed_kg, 1.5);
+    }
+
+    #[test]
+    fn test_exact_multiple_is_unchanged() {
+        let billed = billable_weight(&[item(0.75, 2)], Some(0.5)).unwrap();
+        assert_eq!(billed.billed_kg, 1.5);
+        assert!((round_up(1.1, 0.1) - 1.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_no_declared_weight() {
+        let items = [CartItem::default()];
+        assert_eq!(billable_weight(&items, Some(0.5)), None);
+        let billed = billable_weight(&[item(1.2, 1)], None).unwrap();
+        assert_eq!(billed.billed_kg, 1.2);
+    }
+}
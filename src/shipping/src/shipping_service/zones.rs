@@ -0,0 +1,99 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use serde::Deserialize;
+
+use super::shipping_types::Address;
+
+/// Zone of destinations matching none of the configured zones. It is priced
+/// at the quote service's price.
+pub const DEFAULT_ZONE: &str = "default";
+
+/// Destinations priced at `multiplier` times the quote service's price,
+/// chosen by the start of their zip code.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ShippingZone {
+    pub name: String,
+    pub zip_prefixes: Vec<String>,
+    pub multiplier: f64,
+}
+
+impl ShippingZone {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !self.multiplier.is_finite() || self.multiplier <= 0.0 {
+            anyhow::bail!(
+                "multiplier must be a positive factor, got {}",
+                self.multiplier
+            );
+        }
+        if self.zip_prefixes.iter().any(String::is_empty) {
+            anyhow::bail!("zip_prefixes must not be empty strings");
+        }
+        Ok(())
+    }
+}
+
+/// Zone of `destination`: the one with the longest zip prefix matching its
+/// zip code, or `None` when none matches or there is no destination.
+pub fn zone_for<'a>(
+    destination: Option<&Address>,
+    zones: &'a [ShippingZone],
+) -> Option<&'a ShippingZone> {
+    let zip = destination?.zip_code.trim();
+    zones
+        .iter()
+        .filter_map(|zone| {
+            zone.zip_prefixes
+                .iter()
+                .filter(|prefix| zip.starts_with(prefix.as_str()))
+                .map(String::len)
+                .max()
+                .map(|len| (len, zone))
+        })
+        .max_by_key(|(len, _)| *len)
+        .map(|(_, zone)| zone)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zone(name: &str, zip_prefixes: &[&str], multiplier: f64) -> ShippingZone {
+        ShippingZone {
+            name: name.to_string(),
+            zip_prefixes: zip_prefixes.iter().map(|p| p.to_string()).collect(),
+            multiplier,
+        }
+    }
+
+    fn to_zip(zip_code: &str) -> Address {
+        Address {
+            zip_code: zip_code.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_longest_matching_prefix_picks_the_zone() {
+        let zones = [
+            zone("west", &["9"], 1.2),
+            zone("bay_area", &["940", "941"], 1.1),
+            zone("east", &["0", "1"], 1.3),
+        ];
+
+        let zone_of = |zip| zone_for(Some(&to_zip(zip)), &zones).map(|z| z.name.as_str());
+        assert_eq!(zone_of("94107"), Some("bay_area"));
+        assert_eq!(zone_of("98101"), Some("west"));
+        assert_eq!(zone_of(" 10001"), Some("east"));
+        assert_eq!(zone_of("60601"), None);
+        assert_eq!(zone_for(None, &zones), None);
+    }
+
+    #[test]
+    fn test_multiplier_must_be_positive() {
+        assert!(zone("west", &["9"], 1.2).validate().is_ok());
+        assert!(zone("west", &["9"], 0.0).validate().is_err());
+        assert!(zone("west", &[""], 1.2).validate().is_err());
+    }
+}
@@ -0,0 +1,84 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::BTreeMap;
+
+use opentelemetry::trace::get_active_span;
+use serde::Serialize;
+
+/// Signals whose exporter couldn't be built at startup, with why. The
+/// service runs without them: their global providers stay no-ops.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TelemetryStatus {
+    pub failed_signals: BTreeMap<&'static str, String>,
+}
+
+impl TelemetryStatus {
+    pub fn is_degraded(&self) -> bool {
+        !self.failed_signals.is_empty()
+    }
+}
+
+/// Hex trace and span ids of a span, for correlating log lines with traces.
+/// Both are empty when there is no valid span, so logs don't carry all-zero
+/// ids.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TraceContext {
+    pub trace_id: String,
+    pub span_id: String,
+}
+
+impl TraceContext {
+    /// The ids to pass as the `trace_id` and `span_id` fields of a log event.
+    pub fn as_fields(&self) -> (&str, &str) {
+        (&self.trace_id, &self.span_id)
+    }
+}
+
+/// The ids of the active span.
+pub fn current_trace_context() -> TraceContext {
+    get_active_span(|span| {
+        let cx = span.span_context();
+        if !cx.is_valid() {
+            return TraceContext::default();
+        }
+        TraceContext {
+            trace_id: cx.trace_id().to_string(),
+            span_id: cx.span_id().to_string(),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::trace::{TraceContextExt, Tracer, TracerProvider};
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+
+    use super::*;
+
+    #[test]
+    fn test_trace_context_is_empty_without_span() {
+        assert_eq!(current_trace_context(), TraceContext::default());
+        assert_eq!(current_trace_context().as_fields(), ("", ""));
+    }
+
+    #[test]
+    fn test_trace_context_has_the_ids_of_the_active_span() {
+        let tracer = SdkTracerProvider::builder().build().tracer("trace-context");
+        let (trace, span_cx) = tracer.in_span("handler", |cx| {
+            (current_trace_context(), cx.span().span_context().clone())
+        });
+
+        let is_hex = |id: &str, len| {
+            id.len() == len
+                && id
+                    .bytes()
+                    .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+                && id.bytes().any(|b| b != b'0')
+        };
+        assert!(is_hex(&trace.trace_id, 32), "{}", trace.trace_id);
+        assert!(is_hex(&trace.span_id, 16), "{}", trace.span_id);
+        assert_eq!(trace.trace_id, span_cx.trace_id().to_string());
+        assert_eq!(trace.span_id, span_cx.span_id().to_string());
+    }
+}
@@ -1,17 +1,29 @@
 // Copyright The OpenTelemetry Authors
 // SPDX-License-Identifier: Apache-2.0
 
-use anyhow::Result;
-use opentelemetry::global;
+use std::env;
+
+use opentelemetry::{global, propagation::TextMapCompositePropagator};
 use opentelemetry_appender_tracing::layer::OpenTelemetryTracingBridge;
+use opentelemetry_otlp::{ExporterBuildError, WithExportConfig};
 use tracing_subscriber::prelude::*;
 use tracing_subscriber::EnvFilter;
 
 use opentelemetry_resource_detectors::{OsResourceDetector, ProcessResourceDetector};
 use opentelemetry_sdk::{
-    propagation::TraceContextPropagator, resource::ResourceDetector, Resource,
+    error::OTelSdkError,
+    logs::SdkLoggerProvider,
+    metrics::SdkMeterProvider,
+    propagation::{BaggagePropagator, TraceContextPropagator},
+    resource::ResourceDetector,
+    trace::{BatchSpanProcessor, Config, SdkTracerProvider},
+    Resource,
 };
 
+use crate::error_sampling::{KeepErrors, RecordDropped};
+use crate::prometheus::PrometheusReader;
+use crate::telemetry::TelemetryStatus;
+
 fn get_resource() -> Resource {
     let detectors: Vec<Box<dyn ResourceDetector>> = vec![
         Box::new(OsResourceDetector),
@@ -21,46 +33,76 @@ fn get_resource() -> Resource {
     Resource::builder().with_detectors(&detectors).build()
 }
 
-fn init_tracer_provider() {
-    global::set_text_map_propagator(TraceContextPropagator::new());
+/// Points an exporter at `endpoint` when given, instead of the one of the
+/// `OTEL_EXPORTER_OTLP_*ENDPOINT` variables.
+fn with_endpoint<B: WithExportConfig>(builder: B, endpoint: Option<&str>) -> B {
+    match endpoint {
+        Some(endpoint) => builder.with_endpoint(endpoint),
+        None => builder,
+    }
+}
 
-    let tracer_provider = opentelemetry_sdk::trace::SdkTracerProvider::builder()
-        .with_resource(get_resource())
-        .with_batch_exporter(
-            opentelemetry_otlp::SpanExporter::builder()
-                .with_tonic()
-                .build()
-                .expect("Failed to initialize tracing provider"),
-        )
-        .build();
+fn init_tracer_provider(endpoint: Option<&str>) -> Result<SdkTracerProvider, ExporterBuildError> {
+    // Baggage carries business context set upstream, such as the loyalty
+    // tier quotes are discounted by.
+    global::set_text_map_propagator(TextMapCompositePropagator::new(vec![
+        Box::new(TraceContextPropagator::new()),
+        Box::new(BaggagePropagator::new()),
+    ]));
 
-    global::set_tracer_provider(tracer_provider);
+    let exporter = with_endpoint(
+        opentelemetry_otlp::SpanExporter::builder().with_tonic(),
+        endpoint,
+    )
+    .build()?;
+    let builder = SdkTracerProvider::builder().with_resource(get_resource());
+    // Failed requests are exported even when head sampling drops them.
+    let sample_errors_always = env::var("SAMPLE_ERRORS_ALWAYS").is_ok_and(|value| value == "true");
+    let tracer_provider = if sample_errors_always {
+        builder
+            .with_sampler(RecordDropped::new(Config::default().sampler))
+            .with_span_processor(KeepErrors::new(
+                BatchSpanProcessor::builder(exporter).build(),
+            ))
+            .build()
+    } else {
+        builder.with_batch_exporter(exporter).build()
+    };
+
+    global::set_tracer_provider(tracer_provider.clone());
+    Ok(tracer_provider)
 }
 
-fn init_meter_provider() -> opentelemetry_sdk::metrics::SdkMeterProvider {
-    let meter_provider = opentelemetry_sdk::metrics::SdkMeterProvider::builder()
+fn init_meter_provider(
+    endpoint: Option<&str>,
+    prometheus: Option<&PrometheusReader>,
+) -> Result<SdkMeterProvider, ExporterBuildError> {
+    let exporter = with_endpoint(
+        opentelemetry_otlp::MetricExporter::builder().with_tonic(),
+        endpoint,
+    )
+    .build()?;
+    let mut builder = SdkMeterProvider::builder()
         .with_resource(get_resource())
-        .with_periodic_exporter(
-            opentelemetry_otlp::MetricExporter::builder()
-                .with_tonic()
-                .build()
-                .expect("Failed to initialize metric exporter"),
-        )
-        .build();
+        .with_periodic_exporter(exporter);
+    // Also served on `/metrics`, for scrapers that can't take OTLP.
+    if let Some(reader) = prometheus {
+        builder = builder.with_reader(reader.clone());
+    }
+    let meter_provider = builder.build();
     global::set_meter_provider(meter_provider.clone());
-
-    meter_provider
+    Ok(meter_provider)
 }
 
-fn init_logger_provider() {
-    let logger_provider = opentelemetry_sdk::logs::SdkLoggerProvider::builder()
+fn init_logger_provider(endpoint: Option<&str>) -> Result<SdkLoggerProvider, ExporterBuildError> {
+    let exporter = with_endpoint(
+        opentelemetry_otlp::LogExporter::builder().with_tonic(),
+        endpoint,
+    )
+    .build()?;
+    let logger_provider = SdkLoggerProvider::builder()
         .with_resource(get_resource())
-        .with_batch_exporter(
-            opentelemetry_otlp::LogExporter::builder()
-                .with_tonic()
-                .build()
-                .expect("Failed to initialize logger provider"),
-        )
+        .with_batch_exporter(exporter)
         .build();
 
     let otel_layer = OpenTelemetryTracingBridge::new(&logger_provider);
@@ -68,11 +110,186 @@ fn init_logger_provider() {
     let otel_layer = otel_layer.with_filter(filter_otel);
 
     tracing_subscriber::registry().with(otel_layer).init();
+    Ok(logger_provider)
+}
+
+/// The providers set up by [`init_otel`], kept to flush them at exit.
+#[derive(Debug, Default)]
+pub struct Telemetry {
+    pub status: TelemetryStatus,
+    /// Set when `PROMETHEUS_ENABLED` is true and metrics started.
+    pub prometheus: Option<PrometheusReader>,
+    tracer: Option<SdkTracerProvider>,
+    meter: Option<SdkMeterProvider>,
+    logger: Option<SdkLoggerProvider>,
+}
+
+impl Telemetry {
+    /// Exports what the providers still buffer and shuts them down, logs
+    /// last so that the other signals can log until then. Returns the
+    /// signals that failed to.
+    pub fn shutdown(&self) -> Vec<(&'static str, OTelSdkError)> {
+        [
+            (
+                "traces",
+                self.tracer.as_ref().map(SdkTracerProvider::shutdown),
+            ),
+            (
+                "metrics",
+                self.meter.as_ref().map(SdkMeterProvider::shutdown),
+            ),
+            (
+                "logs",
+                self.logger.as_ref().map(SdkLoggerProvider::shutdown),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(signal, result)| Some((signal, result?.err()?)))
+        .collect()
+    }
 }
 
-pub fn init_otel() -> Result<()> {
-    init_logger_provider();
-    init_tracer_provider();
-    init_meter_provider();
-    Ok(())
+/// The provider of `signal`, recording in `status` why it failed to start.
+fn started<T>(
+    status: &mut TelemetryStatus,
+    signal: &'static str,
+    result: Result<T, ExporterBuildError>,
+) -> Option<T> {
+    result
+        .map_err(|err| status.failed_signals.insert(signal, err.to_string()))
+        .ok()
+}
+
+/// Sets up the exporters of logs, traces and metrics, sending them to
+/// `endpoint` if given and where `OTEL_EXPORTER_OTLP_*ENDPOINT` say
+/// otherwise. A signal whose exporter can't be built, such as
+/// for an invalid endpoint, is left out rather than failing startup, and
+/// reported in the status.
+pub fn init_otel(endpoint: Option<&str>) -> Telemetry {
+    let mut status = TelemetryStatus::default();
+    let logger = started(&mut status, "logs", init_logger_provider(endpoint));
+    let tracer = started(&mut status, "traces", init_tracer_provider(endpoint));
+    let prometheus = env::var("PROMETHEUS_ENABLED")
+        .is_ok_and(|value| value == "true")
+        .then(PrometheusReader::default);
+    let meter = started(
+        &mut status,
+        "metrics",
+        init_meter_provider(endpoint, prometheus.as_ref()),
+    );
+    Telemetry {
+        status,
+        prometheus: prometheus.filter(|_| meter.is_some()),
+        tracer,
+        meter,
+        logger,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use actix_web::{http::StatusCode, test, App};
+    use opentelemetry::{
+        metrics::MeterProvider,
+        trace::{Tracer, TracerProvider},
+    };
+    use opentelemetry_sdk::{
+        error::OTelSdkResult,
+        metrics::InMemoryMetricExporter,
+        trace::{SpanData, SpanExporter},
+    };
+
+    use super::*;
+    use crate::shipping_service::{get_quote, ready, AppData, GetQuoteRequest, ShippingConfig};
+    use crate::test_support::spawn_quote_mock;
+
+    /// Keeps the spans it exported after shutdown, unlike the in-memory
+    /// exporter of the SDK.
+    #[derive(Debug, Clone, Default)]
+    struct KeptSpans(Arc<Mutex<Vec<SpanData>>>);
+
+    impl SpanExporter for KeptSpans {
+        async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+            self.0.lock().unwrap().extend(batch);
+            Ok(())
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_shutdown_exports_buffered_spans_and_metrics() {
+        let spans = KeptSpans::default();
+        let metrics = InMemoryMetricExporter::default();
+        let telemetry = Telemetry {
+            tracer: Some(
+                SdkTracerProvider::builder()
+                    .with_batch_exporter(spans.clone())
+                    .build(),
+            ),
+            meter: Some(
+                SdkMeterProvider::builder()
+                    .with_periodic_exporter(metrics.clone())
+                    .build(),
+            ),
+            ..Default::default()
+        };
+        let tracer = telemetry.tracer.as_ref().unwrap().tracer("shutdown");
+        tracer.in_span("in-flight quote", |_| {});
+        let meter = telemetry.meter.as_ref().unwrap().meter("shutdown");
+        meter.u64_counter("quotes").build().add(1, &[]);
+        assert!(spans.0.lock().unwrap().is_empty());
+
+        assert!(telemetry.shutdown().is_empty());
+        let exported = spans.0.lock().unwrap();
+        assert_eq!(exported.len(), 1);
+        assert_eq!(exported[0].name, "in-flight quote");
+        let exports = metrics.get_finished_metrics().unwrap();
+        assert!(exports
+            .iter()
+            .flat_map(|export| export.scope_metrics())
+            .flat_map(|scope| scope.metrics())
+            .any(|metric| metric.name() == "quotes"));
+    }
+
+    #[actix_web::test]
+    async fn test_broken_exporters_leave_the_service_serving() {
+        let telemetry = init_otel(Some("not a valid endpoint")).status;
+        assert!(telemetry.is_degraded());
+        assert_eq!(
+            telemetry.failed_signals.keys().copied().collect::<Vec<_>>(),
+            ["logs", "metrics", "traces"]
+        );
+
+        let config = ShippingConfig {
+            quote_addr: spawn_quote_mock("10.99"),
+            ..Default::default()
+        };
+        let data = AppData::new(config).with_telemetry(telemetry);
+        let app = test::init_service(
+            App::new()
+                .configure(|cfg| data.register(cfg))
+                .service(get_quote)
+                .service(ready),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/get-quote")
+            .set_json(GetQuoteRequest::default())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let req = test::TestRequest::get().uri("/ready").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["status"], "ready");
+        assert_eq!(body["observability"], "degraded");
+        assert!(body["telemetry"]["failed_signals"]["traces"]
+            .as_str()
+            .unwrap()
+            .contains("not a valid endpoint"));
+    }
 }
@@ -0,0 +1,302 @@
+// Copyright The OpenTelemetry Authors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Helpers shared by the unit tests of the shipping service.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt,
+    future::Future,
+    sync::{Arc, Mutex, MutexGuard, Once, OnceLock},
+};
+
+use actix_web::{web, App, HttpServer};
+use opentelemetry::{
+    context::FutureExt,
+    global,
+    metrics::{Meter, MeterProvider},
+    propagation::TextMapCompositePropagator,
+    trace::{TraceContextExt, Tracer},
+    Context, InstrumentationScope, KeyValue,
+};
+use opentelemetry_sdk::{
+    metrics::{
+        data::{AggregatedMetrics, MetricData},
+        reader::MetricReader,
+        InMemoryMetricExporter, MeterProviderBuilder, SdkMeterProvider,
+    },
+    propagation::{BaggagePropagator, TraceContextPropagator},
+    trace::{InMemorySpanExporter, SdkTracerProvider, SpanData},
+};
+use tracing::{
+    field::{Field, Visit},
+    subscriber::DefaultGuard,
+    Event, Subscriber,
+};
+use tracing_subscriber::{layer, prelude::*, Layer};
+
+/// Serializes the tests that set environment variables, or read ones that
+/// other tests set, since the environment is shared by the whole process.
+pub fn env_lock() -> MutexGuard<'static, ()> {
+    static LOCK: Mutex<()> = Mutex::new(());
+    LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// The fields of a single captured log event.
+pub type LogFields = HashMap<String, String>;
+
+/// Collects every log event emitted on the current thread while the returned
+/// guard is alive.
+#[derive(Clone, Default)]
+pub struct CapturedLogs {
+    events: Arc<Mutex<Vec<LogFields>>>,
+}
+
+impl CapturedLogs {
+    pub fn install() -> (Self, DefaultGuard) {
+        let logs = CapturedLogs::default();
+        let subscriber = tracing_subscriber::registry().with(logs.clone());
+        let guard = tracing::subscriber::set_default(subscriber);
+        (logs, guard)
+    }
+
+    /// Returns the captured events whose `name` field equals `name`.
+    pub fn named(&self, name: &str) -> Vec<LogFields> {
+        self.events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|fields| fields.get("name").map(String::as_str) == Some(name))
+            .cloned()
+            .collect()
+    }
+}
+
+impl<S: Subscriber> Layer<S> for CapturedLogs {
+    fn on_event(&self, event: &Event<'_>, _ctx: layer::Context<'_, S>) {
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+        self.events.lock().unwrap().push(visitor.0);
+    }
+}
+
+#[derive(Default)]
+struct FieldVisitor(LogFields);
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value));
+    }
+}
+
+/// Installs, once per test binary, a global tracer provider exporting every
+/// span to memory and the W3C trace-context and baggage propagators, as the
+/// service does. Tests share the exporter, so they should filter the
+/// finished spans by their own trace id.
+pub fn test_spans() -> InMemorySpanExporter {
+    static EXPORTER: OnceLock<InMemorySpanExporter> = OnceLock::new();
+    EXPORTER
+        .get_or_init(|| {
+            let exporter = InMemorySpanExporter::default();
+            let provider = SdkTracerProvider::builder()
+                .with_simple_exporter(exporter.clone())
+                .build();
+            global::set_tracer_provider(provider);
+            global::set_text_map_propagator(TextMapCompositePropagator::new(vec![
+                Box::new(TraceContextPropagator::new()),
+                Box::new(BaggagePropagator::new()),
+            ]));
+            exporter
+        })
+        .clone()
+}
+
+/// Runs `fut` inside a new span from the global test tracer and returns its
+/// output along with the finished span.
+pub async fn in_test_span<F: Future>(name: &'static str, fut: F) -> (F::Output, SpanData) {
+    let exporter = test_spans();
+    let span = global::tracer("shipping-test").start(name);
+    let cx = Context::current_with_span(span);
+    let span_id = cx.span().span_context().span_id();
+
+    let output = fut.with_context(cx.clone()).await;
+    cx.span().end();
+
+    let span = exporter
+        .get_finished_spans()
+        .unwrap()
+        .into_iter()
+        .find(|span| span.span_context.span_id() == span_id)
+        .expect("test span was not exported");
+    (output, span)
+}
+
+thread_local! {
+    static THREAD_METER_PROVIDER: RefCell<Option<SdkMeterProvider>> = const { RefCell::new(None) };
+}
+
+/// Global meter provider handing out meters from the provider installed on
+/// the calling thread, so tests running in parallel each see only their own
+/// measurements.
+struct ThreadMeterProvider {
+    fallback: SdkMeterProvider,
+}
+
+impl MeterProvider for ThreadMeterProvider {
+    fn meter_with_scope(&self, scope: InstrumentationScope) -> Meter {
+        THREAD_METER_PROVIDER.with(|provider| match &*provider.borrow() {
+            Some(provider) => provider.meter_with_scope(scope),
+            None => self.fallback.meter_with_scope(scope),
+        })
+    }
+}
+
+/// In-memory metrics recorded through `global::meter` on the current thread
+/// while this value is alive.
+pub struct TestMetrics {
+    provider: SdkMeterProvider,
+    exporter: InMemoryMetricExporter,
+}
+
+impl TestMetrics {
+    pub fn install() -> Self {
+        TestMetrics::install_with(SdkMeterProvider::builder())
+    }
+
+    /// Like [`TestMetrics::install`], with `reader` collecting from the
+    /// thread's provider as well.
+    pub fn install_with_reader(reader: impl MetricReader) -> Self {
+        TestMetrics::install_with(SdkMeterProvider::builder().with_reader(reader))
+    }
+
+    fn install_with(builder: MeterProviderBuilder) -> Self {
+        static GLOBAL: Once = Once::new();
+        GLOBAL.call_once(|| {
+            global::set_meter_provider(ThreadMeterProvider {
+                fallback: SdkMeterProvider::builder().build(),
+            })
+        });
+
+        let exporter = InMemoryMetricExporter::default();
+        let provider = builder.with_periodic_exporter(exporter.clone()).build();
+        THREAD_METER_PROVIDER.with(|p| *p.borrow_mut() = Some(provider.clone()));
+        TestMetrics { provider, exporter }
+    }
+
+    /// Returns the current value of the `u64` counter `name`, summed over the
+    /// data points whose attributes include all of `attrs`.
+    pub fn counter(&self, name: &str, attrs: &[KeyValue]) -> u64 {
+        self.provider.force_flush().unwrap();
+        let exports = self.exporter.get_finished_metrics().unwrap();
+        let Some(latest) = exports.last() else {
+            return 0;
+        };
+
+        latest
+            .scope_metrics()
+            .flat_map(|scope| scope.metrics())
+            .filter(|metric| metric.name() == name)
+            .map(|metric| match metric.data() {
+                AggregatedMetrics::U64(MetricData::Sum(sum)) => sum
+                    .data_points()
+                    .filter(|point| {
+                        attrs
+                            .iter()
+                            .all(|attr| point.attributes().any(|kv| kv == attr))
+                    })
+                    .map(|point| point.value())
+                    .sum(),
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Returns the last value of the `u64` gauge `name` at the data point
+    /// whose attributes include all of `attrs`, if one was recorded.
+    pub fn gauge(&self, name: &str, attrs: &[KeyValue]) -> Option<u64> {
+        self.provider.force_flush().unwrap();
+        let exports = self.exporter.get_finished_metrics().unwrap();
+        let latest = exports.last()?;
+
+        let value = latest
+            .scope_metrics()
+            .flat_map(|scope| scope.metrics())
+            .filter(|metric| metric.name() == name)
+            .find_map(|metric| match metric.data() {
+                AggregatedMetrics::U64(MetricData::Gauge(gauge)) => gauge
+                    .data_points()
+                    .find(|point| {
+                        attrs
+                            .iter()
+                            .all(|attr| point.attributes().any(|kv| kv == attr))
+                    })
+                    .map(|point| point.value()),
+                _ => None,
+            });
+        value
+    }
+
+    /// Returns the number of values recorded in the `u64` histogram `name`,
+    /// over the data points whose attributes include all of `attrs`.
+    pub fn histogram_count(&self, name: &str, attrs: &[KeyValue]) -> u64 {
+        self.provider.force_flush().unwrap();
+        let exports = self.exporter.get_finished_metrics().unwrap();
+        let Some(latest) = exports.last() else {
+            return 0;
+        };
+
+        latest
+            .scope_metrics()
+            .flat_map(|scope| scope.metrics())
+            .filter(|metric| metric.name() == name)
+            .map(|metric| match metric.data() {
+                AggregatedMetrics::U64(MetricData::Histogram(histogram)) => histogram
+                    .data_points()
+                    .filter(|point| {
+                        attrs
+                            .iter()
+                            .all(|attr| point.attributes().any(|kv| kv == attr))
+                    })
+                    .map(|point| point.count())
+                    .sum(),
+                _ => 0,
+            })
+            .sum()
+    }
+}
+
+impl Drop for TestMetrics {
+    fn drop(&mut self) {
+        THREAD_METER_PROVIDER.with(|p| *p.borrow_mut() = None);
+    }
+}
+
+/// Starts an HTTP server on an ephemeral local port with the services set up
+/// by `configure` and returns its base URL. The server lives as long as the
+/// test's actix system.
+pub fn spawn_mock<F>(configure: F) -> String
+where
+    F: Fn(&mut web::ServiceConfig) + Clone + Send + 'static,
+{
+    let server = HttpServer::new(move || App::new().configure(configure.clone()))
+        .workers(1)
+        .disable_signals()
+        .bind(("127.0.0.1", 0))
+        .expect("Failed to bind mock server");
+    let addr = server.addrs()[0];
+    actix_web::rt::spawn(server.run());
+    format!("http://{addr}")
+}
+
+/// Starts a mock quote service answering every `/getquote` call with `body`.
+pub fn spawn_quote_mock(body: &'static str) -> String {
+    spawn_mock(move |cfg| {
+        cfg.route("/getquote", web::post().to(move || async move { body }));
+    })
+}